@@ -0,0 +1,78 @@
+// benches/fixed.rs - Compare the fixed-point engine with the float one
+//
+// Run with: cargo bench --bench fixed --features fixed-point
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use fm_synth::fixed::{from_q15, FixedSynth};
+use fm_synth::synth_core::{FMParams, FMSynth, Waveform};
+use fm_synth::synth_data::get_presets;
+
+const SAMPLE_RATE: f32 = 48000.0;
+const SECONDS: usize = 2;
+
+/// Play a held note and release it halfway, returning the largest and RMS
+/// difference between the two engines
+fn compare(params: &FMParams) -> (f32, f32) {
+    let mut float = FMSynth::new(SAMPLE_RATE, params.clone());
+    let mut fixed = FixedSynth::new(SAMPLE_RATE, params.clone());
+    float.note_on();
+    fixed.note_on();
+
+    let frames = SAMPLE_RATE as usize * SECONDS;
+    let (mut max, mut sum) = (0.0f32, 0.0f64);
+    for i in 0..frames {
+        if i == frames / 2 {
+            float.note_off();
+            fixed.note_off();
+        }
+        let error = (float.next_sample() - from_q15(fixed.next_sample())).abs();
+        max = max.max(error);
+        sum += (error * error) as f64;
+    }
+    (max, (sum / frames as f64).sqrt() as f32)
+}
+
+/// Nanoseconds per sample for each engine
+fn speed(params: &FMParams) -> (f64, f64) {
+    let frames = SAMPLE_RATE as usize * SECONDS * 16;
+
+    let mut float = FMSynth::new(SAMPLE_RATE, params.clone());
+    float.note_on();
+    let start = Instant::now();
+    for _ in 0..frames {
+        black_box(float.next_sample());
+    }
+    let float_ns = start.elapsed().as_nanos() as f64 / frames as f64;
+
+    let mut fixed = FixedSynth::new(SAMPLE_RATE, params.clone());
+    fixed.note_on();
+    let start = Instant::now();
+    for _ in 0..frames {
+        black_box(fixed.next_sample());
+    }
+    let fixed_ns = start.elapsed().as_nanos() as f64 / frames as f64;
+
+    (float_ns, fixed_ns)
+}
+
+fn main() {
+    println!("Fixed-point vs float, {} s at {} Hz", SECONDS, SAMPLE_RATE);
+    for (name, preset) in get_presets() {
+        // Square and saw aren't band-limited in fixed point, so only the
+        // sine operators are expected to track closely
+        let sine = preset.carrier_wave == Waveform::Sine && preset.modulator_wave == Waveform::Sine;
+        let (max, rms) = compare(&preset.for_note(440.0));
+        let (float_ns, fixed_ns) = speed(&preset.for_note(440.0));
+        println!(
+            "  {:<16} max error {:.2e}  rms {:.2e}  float {:>6.2} ns  fixed {:>6.2} ns{}",
+            name,
+            max,
+            rms,
+            float_ns,
+            fixed_ns,
+            if sine { "" } else { "  (naive square/saw)" }
+        );
+    }
+}
@@ -0,0 +1,104 @@
+// benches/sine.rs - Compare the exact sine path with lookup tables
+//
+// Run with: cargo bench --bench sine
+
+use std::f32::consts::PI;
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::Instant;
+
+use fm_synth::synth_core::{FMOscillator, FMParams, FMSynth, SineTable};
+
+const SAMPLE_RATE: f32 = 48000.0;
+const SECONDS: usize = 10;
+const VOICES: usize = 16;
+
+/// A benchmark run with either the exact sine or a lookup table
+type Bench = fn(Option<Arc<SineTable>>) -> f64;
+
+/// Render `VOICES` synths for `SECONDS` and return nanoseconds per sample
+fn bench_voices(table: Option<Arc<SineTable>>) -> f64 {
+    let mut voices: Vec<FMSynth> = (0..VOICES)
+        .map(|i| {
+            let freq = 220.0 * (1.0 + i as f32 / VOICES as f32);
+            let mut synth = FMSynth::new(SAMPLE_RATE, FMParams::default().for_note(freq));
+            synth.set_sine_table(table.clone());
+            synth.note_on();
+            synth
+        })
+        .collect();
+
+    let frames = SAMPLE_RATE as usize * SECONDS;
+    let start = Instant::now();
+    for _ in 0..frames {
+        let mix: f32 = voices.iter_mut().map(|v| v.next_sample()).sum();
+        black_box(mix);
+    }
+    start.elapsed().as_nanos() as f64 / (frames * VOICES) as f64
+}
+
+/// Evaluate sines over a sweep of phases and return nanoseconds per call
+fn bench_raw(table: Option<Arc<SineTable>>) -> f64 {
+    let phases: Vec<f32> = (0..4096).map(|i| i as f32 / 4096.0 * 3.7).collect();
+    let rounds = SAMPLE_RATE as usize * SECONDS * VOICES / phases.len();
+
+    let start = Instant::now();
+    for _ in 0..rounds {
+        let sum: f32 = match &table {
+            Some(table) => phases.iter().map(|&p| table.lookup(black_box(p))).sum(),
+            None => phases.iter().map(|&p| (2.0 * PI * black_box(p)).sin()).sum(),
+        };
+        black_box(sum);
+    }
+    start.elapsed().as_nanos() as f64 / (rounds * phases.len()) as f64
+}
+
+/// Render a bare oscillator (no envelope) and return nanoseconds per sample
+fn bench_oscillator(table: Option<Arc<SineTable>>) -> f64 {
+    let mut osc = FMOscillator::new(SAMPLE_RATE, FMParams::default());
+    osc.set_sine_table(table);
+
+    let frames = SAMPLE_RATE as usize * SECONDS * VOICES;
+    let start = Instant::now();
+    for _ in 0..frames {
+        black_box(osc.next_sample());
+    }
+    start.elapsed().as_nanos() as f64 / frames as f64
+}
+
+/// Largest absolute error of a table against `f32::sin` over one period
+fn max_error(table: &SineTable) -> f32 {
+    (0..100_000)
+        .map(|i| {
+            let phase = i as f32 / 100_000.0;
+            (table.lookup(phase) - (2.0 * PI * phase).sin()).abs()
+        })
+        .fold(0.0, f32::max)
+}
+
+fn main() {
+    let benches: [(&str, Bench); 3] = [
+        ("Sine evaluation", bench_raw),
+        ("Oscillator only", bench_oscillator),
+        ("FMSynth voices", bench_voices),
+    ];
+
+    for (name, bench) in benches {
+        println!("{}: {} voices, {} s at {} Hz", name, VOICES, SECONDS, SAMPLE_RATE);
+        let exact = bench(None);
+        println!("  {:<12} {:>8.2} ns/sample", "exact sin", exact);
+
+        for size in [256, 1024, 4096, 16384] {
+            let table = Arc::new(SineTable::new(size));
+            let error = max_error(&table);
+            let time = bench(Some(table));
+            println!(
+                "  {:<12} {:>8.2} ns/sample  max error {:.2e}  ({:.2}x)",
+                format!("table {}", size),
+                time,
+                error,
+                exact / time
+            );
+        }
+    }
+}
@@ -0,0 +1,5 @@
+fn main() {
+    // Link flags a Node addon needs (undefined N-API symbols on macOS)
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
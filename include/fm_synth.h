@@ -0,0 +1,252 @@
+#ifndef FM_SYNTH_H
+#define FM_SYNTH_H
+
+/* Generated by cbindgen from src/ffi.rs and src/mobile.rs; don't edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// A polyphonic synth playing the built-in presets through the device's
+// default output. Opaque to C.
+typedef struct FmPlayer FmPlayer;
+
+// An engine instance: a polyphonic synth playing one of the built-in
+// presets. Opaque to C.
+typedef struct FmSynth FmSynth;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create an engine rendering at `sample_rate` with up to `max_voices`
+// notes at once (0 for the default of 8, at most 256), playing the first
+// preset.
+// Returns null if `sample_rate` isn't positive. Free it with
+// `fm_synth_free`.
+struct FmSynth *fm_synth_new(float sample_rate, uint32_t max_voices);
+
+// Free an engine created by `fm_synth_new`. Null is ignored.
+//
+// # Safety
+//
+// `synth` must be null or a pointer from `fm_synth_new` that hasn't been
+// freed yet; it can't be used afterwards.
+void fm_synth_free(struct FmSynth *synth);
+
+// Number of built-in presets
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+uint32_t fm_synth_preset_count(const struct FmSynth *synth);
+
+// Name of preset `index` (0-based) as a NUL-terminated string, or null if
+// there is no such preset. The string lives as long as the engine.
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+const char *fm_synth_preset_name(const struct FmSynth *synth, uint32_t index);
+
+// Choose the preset (0-based) for notes started afterwards; sounding
+// notes keep theirs. Returns false, changing nothing, if there is no such
+// preset.
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+bool fm_synth_set_preset(struct FmSynth *synth, uint32_t index);
+
+// Start MIDI note `note` (60 = C4) at `velocity` (1 - 127; 0 releases the
+// note, as in MIDI). Velocity scales the amplitude and, by the preset's
+// `velocity_index`, the modulation index.
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+void fm_synth_note_on(struct FmSynth *synth, uint8_t note, uint8_t velocity);
+
+// Release MIDI note `note`; it fades out over the preset's release
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+void fm_synth_note_off(struct FmSynth *synth, uint8_t note);
+
+// Release every held note
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+void fm_synth_all_notes_off(struct FmSynth *synth);
+
+// Number of voices still sounding, releases included
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+uint32_t fm_synth_active_voices(const struct FmSynth *synth);
+
+// Most notes that can sound at once
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+uint32_t fm_synth_max_voices(const struct FmSynth *synth);
+
+// Change the most notes that can sound at once, as for `fm_synth_new`.
+// Lowering it cuts off the oldest notes beyond the new limit. Allocates
+// when raising it, so call it outside the audio callback.
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+void fm_synth_set_max_voices(struct FmSynth *synth, uint32_t max_voices);
+
+// Follow a change of output rate; sounding notes keep their pitch
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`.
+void fm_synth_set_sample_rate(struct FmSynth *synth, float sample_rate);
+
+// Render the next `frames` mono samples into `out`, overwriting it. Doesn't
+// allocate or lock, so it can be called from an audio callback.
+//
+// # Safety
+//
+// `synth` must be a live pointer from `fm_synth_new`, and `out` must point
+// to at least `frames` writable floats (it may be null if `frames` is 0).
+void fm_synth_process(struct FmSynth *synth, float *out, size_t frames);
+
+// Open the default output and start a player with up to `max_voices`
+// notes at once (0 for the default of 8). `buffer_frames` requests a
+// callback size, which trades latency for robustness on slower phones; 0
+// leaves it to the system (AAudio's burst size, or the iOS session's IO
+// buffer duration), as does a size the device rejects. Returns null if no
+// output could be opened. Free it with `fm_player_free`.
+//
+// On Android, call `fm_player_android_init` first.
+struct FmPlayer *fm_player_new(uint32_t max_voices, uint32_t buffer_frames);
+
+#if defined(__ANDROID__)
+// Hand cpal the JVM and the application `Context` (a global JNI
+// reference) it needs to reach AAudio, e.g. from `JNI_OnLoad` and the
+// app's `onCreate`. Call once, before `fm_player_new`.
+//
+// # Safety
+//
+// `vm` must be the process's `JavaVM*` and `context` a global reference
+// to an `android.content.Context` that outlives every player.
+void fm_player_android_init(void *vm, void *context);
+#endif
+
+// Fade out, close the output, and free a player. Null is ignored.
+//
+// # Safety
+//
+// `player` must be null or a pointer from `fm_player_new` that hasn't
+// been freed yet; it can't be used afterwards.
+void fm_player_free(struct FmPlayer *player);
+
+// Number of built-in presets
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+uint32_t fm_player_preset_count(const struct FmPlayer *player);
+
+// Name of preset `index` (0-based) as a NUL-terminated string, or null if
+// there is no such preset. The string lives as long as the player.
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+const char *fm_player_preset_name(const struct FmPlayer *player, uint32_t index);
+
+// Choose the preset (0-based) for notes started afterwards. Returns
+// false, changing nothing, if there is no such preset.
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+bool fm_player_set_preset(struct FmPlayer *player, uint32_t index);
+
+// Start MIDI note `note` (60 = C4) at `velocity` (1 - 127; 0 releases the
+// note). Notes played while suspended are ignored.
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+void fm_player_note_on(struct FmPlayer *player, uint8_t note, uint8_t velocity);
+
+// Release MIDI note `note`
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+void fm_player_note_off(struct FmPlayer *player, uint8_t note);
+
+// Release every held note
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+void fm_player_all_notes_off(struct FmPlayer *player);
+
+// Number of voices still sounding, releases included
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+uint32_t fm_player_active_voices(const struct FmPlayer *player);
+
+// Most notes that can sound at once
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+uint32_t fm_player_max_voices(const struct FmPlayer *player);
+
+// Change the most notes that can sound at once, as for `fm_player_new`;
+// lowering it cuts off the oldest notes beyond the new limit
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+void fm_player_set_max_voices(struct FmPlayer *player, uint32_t max_voices);
+
+// Fade out, release held notes, and give up the audio device. Call it
+// when an audio session interruption begins (iOS
+// `AVAudioSessionInterruptionTypeBegan`, Android `AUDIOFOCUS_LOSS`) and
+// when the app goes to the background without background audio. Blocks
+// for the fade (about 100 ms).
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+void fm_player_suspend(struct FmPlayer *player);
+
+// Reopen the audio device after `fm_player_suspend`, when the
+// interruption ends or the app returns to the foreground. The device
+// opens in the background within about half a second; on iOS, activate
+// the audio session first.
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+void fm_player_resume(struct FmPlayer *player);
+
+// Whether the player is suspended
+//
+// # Safety
+//
+// `player` must be a live pointer from `fm_player_new`.
+bool fm_player_is_suspended(const struct FmPlayer *player);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* FM_SYNTH_H */
@@ -0,0 +1,305 @@
+use std::f64::consts::PI;
+
+use crate::render::render_note;
+use crate::synth_core::FMParams;
+use crate::synth_data::note_freq;
+
+/// Notes sampled across the keyboard by `analyze_preset`
+pub const KEYBOARD_NOTES: [&str; 6] = ["C3", "A3", "C4", "A4", "C5", "A5"];
+
+/// FFT size used for spectrum measurements
+const FFT_SIZE: usize = 16384;
+
+/// Bins either side of a sideband that count towards its energy (Hann main lobe)
+const SIDEBAND_BINS: isize = 3;
+
+/// Sidebands weaker than this (relative to the strongest) are ignored
+const SIDEBAND_FLOOR: f64 = 1e-3;
+
+/// Bessel function of the first kind, J_n(x), by numerical integration of
+/// Bessel's integral (accurate for the index ranges used in FM synthesis)
+pub fn bessel_j(n: i32, x: f64) -> f64 {
+    let steps = 2000;
+    let h = PI / steps as f64;
+    let f = |tau: f64| (n as f64 * tau - x * tau.sin()).cos();
+    // Simpson's rule over [0, pi]
+    let mut sum = f(0.0) + f(PI);
+    for i in 1..steps {
+        let weight = if i % 2 == 1 { 4.0 } else { 2.0 };
+        sum += weight * f(i as f64 * h);
+    }
+    sum * h / 3.0 / PI
+}
+
+/// A single spectral component predicted by FM theory
+#[derive(Clone, Debug)]
+pub struct Sideband {
+    /// Sideband order k (component at carrier + k * modulator); the lowest
+    /// of them where several land on the same frequency
+    pub order: i32,
+    /// Frequency in Hz after reflection around 0 Hz (0 Hz is a DC offset)
+    pub freq: f64,
+    /// Amplitude relative to the unmodulated carrier
+    pub amplitude: f64,
+}
+
+/// Effective modulation index (beta) of a set of parameters.
+///
+/// The oscillator deviates the carrier by `modulation_index * carrier_freq`,
+/// so beta is that deviation over the modulator frequency. Carrier and
+/// modulator scale together, so it only varies across notes with key scaling.
+pub fn beta(params: &FMParams) -> f64 {
+    if params.modulator_freq <= 0.0 {
+        return 0.0;
+    }
+    (params.modulation_index * params.carrier_freq / params.modulator_freq) as f64
+}
+
+/// Predict the FM sidebands of a preset playing `freq`, combining components
+/// that land on the same frequency. Components above `nyquist` are dropped.
+pub fn fm_sidebands(params: &FMParams, freq: f32, nyquist: f64) -> Vec<Sideband> {
+    let note = params.for_note(freq);
+    let b = beta(&note);
+    sidebands_with(note.carrier_freq as f64, note.modulator_freq as f64, b, nyquist, |k| bessel_j(k, b))
+}
+
+/// The sidebands of carrier `fc` modulated by `fm` at depth `b`, as
+/// `fm_sidebands`, with `bessel(k)` giving J_k(b) (from a table, say).
+/// Orders up to `b + 20` either way are asked for.
+pub fn sidebands_with(fc: f64, fm: f64, b: f64, nyquist: f64, bessel: impl Fn(i32) -> f64) -> Vec<Sideband> {
+    let max_order = (b + 20.0).ceil() as i32;
+
+    // s(t) = sin(wc t + b - b cos(wm t)) = Im(e^{ib} sum_k (-i)^k J_k(b) e^{i(wc + k wm)t})
+    let mut components: Vec<(i32, f64, f64, f64)> = Vec::new();
+    for k in -max_order..=max_order {
+        let j = bessel(k);
+        let phase = b - k as f64 * PI / 2.0;
+        let (mut re, im) = (j * phase.cos(), j * phase.sin());
+        let mut f = fc + k as f64 * fm;
+        if f < 0.0 {
+            // A negative frequency reflects to |f| as -conj(c)
+            f = -f;
+            re = -re;
+        }
+        if f >= nyquist {
+            continue;
+        }
+        match components.iter_mut().find(|c| (c.1 - f).abs() < 1e-6) {
+            Some(c) => {
+                if k.abs() < c.0.abs() {
+                    c.0 = k;
+                }
+                c.2 += re;
+                c.3 += im;
+            }
+            None => components.push((k, f, re, im)),
+        }
+    }
+
+    let mut sidebands: Vec<Sideband> = components
+        .into_iter()
+        .map(|(order, freq, re, im)| {
+            // The signal is the imaginary part, so a 0 Hz term is a plain offset
+            let amplitude = if freq == 0.0 { im.abs() } else { (re * re + im * im).sqrt() };
+            Sideband { order, freq, amplitude }
+        })
+        .collect();
+    let strongest = sidebands.iter().fold(0.0f64, |m, s| m.max(s.amplitude));
+    sidebands.retain(|s| s.amplitude >= strongest * SIDEBAND_FLOOR);
+    sidebands.sort_by(|a, b| a.freq.total_cmp(&b.freq));
+    sidebands
+}
+
+/// In-place iterative radix-2 FFT over (re, im) pairs
+pub fn fft(data: &mut [(f64, f64)]) {
+    let n = data.len();
+    assert!(n.is_power_of_two(), "FFT size must be a power of two");
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (wr, wi) = ((angle * k as f64).cos(), (angle * k as f64).sin());
+                let (ar, ai) = data[start + k];
+                let (br, bi) = data[start + k + len / 2];
+                let (tr, ti) = (br * wr - bi * wi, br * wi + bi * wr);
+                data[start + k] = (ar + tr, ai + ti);
+                data[start + k + len / 2] = (ar - tr, ai - ti);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Hann-windowed power spectrum of the first `FFT_SIZE` samples
+pub fn power_spectrum(samples: &[f32]) -> Vec<f64> {
+    windowed_power_spectrum(samples, FFT_SIZE)
+}
+
+/// Hann-windowed power spectrum of the first `size` samples (a power of
+/// two), zero-padded if there are fewer
+pub fn windowed_power_spectrum(samples: &[f32], size: usize) -> Vec<f64> {
+    let mut data: Vec<(f64, f64)> = (0..size)
+        .map(|i| {
+            let window = 0.5 - 0.5 * (2.0 * PI * i as f64 / size as f64).cos();
+            (samples.get(i).copied().unwrap_or(0.0) as f64 * window, 0.0)
+        })
+        .collect();
+    fft(&mut data);
+    data[..size / 2].iter().map(|(re, im)| re * re + im * im).collect()
+}
+
+/// Measured spectral quality of one note
+#[derive(Clone, Debug)]
+pub struct NoteAnalysis {
+    pub note: &'static str,
+    pub freq: f32,
+    /// Effective modulation index at this note
+    pub beta: f64,
+    /// Number of predicted in-band sidebands
+    pub sidebands: usize,
+    /// Energy away from every predicted sideband, relative to the total (dB)
+    pub aliasing_db: f64,
+    /// Deviation of the measured sideband amplitudes from the Bessel
+    /// prediction, relative to the predicted spectrum (dB)
+    pub thd_db: f64,
+}
+
+fn to_db(ratio: f64) -> f64 {
+    10.0 * ratio.max(1e-20).log10()
+}
+
+/// Power spectrum of a preset holding `freq`, measured during the sustain
+/// stage, after attack and decay have settled
+fn sustain_spectrum(params: &FMParams, freq: f32, sample_rate: f32) -> Vec<f64> {
+    let gate_ms = 250 + (FFT_SIZE as u64 * 1000 / sample_rate as u64);
+    let (samples, _) = render_note(sample_rate, params, freq, gate_ms);
+    let start = (sample_rate * 0.2) as usize;
+    power_spectrum(&samples[start..])
+}
+
+/// Render one note of a preset and compare its spectrum with FM theory
+pub fn analyze_note(params: &FMParams, note: &'static str, sample_rate: f32) -> NoteAnalysis {
+    let freq = note_freq(note);
+    let spectrum = sustain_spectrum(params, freq, sample_rate);
+
+    let bin_hz = sample_rate as f64 / FFT_SIZE as f64;
+    let predicted = fm_sidebands(params, freq, sample_rate as f64 / 2.0);
+
+    // Sidebands closer than a window main lobe can't be told apart, so they
+    // are measured and compared as one group
+    let mut groups: Vec<Vec<&Sideband>> = Vec::new();
+    for sideband in &predicted {
+        match groups.last_mut() {
+            Some(group) if (sideband.freq - group[group.len() - 1].freq) / bin_hz <= 2.0 * SIDEBAND_BINS as f64 => {
+                group.push(sideband)
+            }
+            _ => groups.push(vec![sideband]),
+        }
+    }
+
+    let mut claimed = vec![false; spectrum.len()];
+    let mut measured = Vec::with_capacity(groups.len());
+    let mut expected = Vec::with_capacity(groups.len());
+    for group in &groups {
+        let first = (group[0].freq / bin_hz).round() as isize - SIDEBAND_BINS;
+        let last = (group[group.len() - 1].freq / bin_hz).round() as isize + SIDEBAND_BINS;
+        let mut energy = 0.0;
+        for bin in first.max(0)..=last.min(spectrum.len() as isize - 1) {
+            claimed[bin as usize] = true;
+            energy += spectrum[bin as usize];
+        }
+        // A DC offset isn't split between positive and negative bins like a sinusoid
+        let amplitude = if group[0].freq == 0.0 { energy.sqrt() / 2.0 } else { energy.sqrt() };
+        measured.push(amplitude);
+        expected.push(group.iter().map(|s| s.amplitude * s.amplitude).sum::<f64>().sqrt());
+    }
+
+    let total: f64 = spectrum.iter().sum();
+    let stray: f64 = spectrum.iter().zip(&claimed).filter(|(_, &c)| !c).map(|(e, _)| e).sum();
+
+    // Compare normalised amplitude distributions so overall level doesn't matter
+    let norm = |v: &[f64]| v.iter().map(|a| a * a).sum::<f64>().sqrt().max(1e-20);
+    let (m_norm, e_norm) = (norm(&measured), norm(&expected));
+    let error: f64 = measured.iter()
+        .zip(&expected)
+        .map(|(m, e)| (m / m_norm - e / e_norm).powi(2))
+        .sum();
+
+    NoteAnalysis {
+        note,
+        freq,
+        beta: beta(&params.for_note(freq)),
+        sidebands: predicted.len(),
+        aliasing_db: to_db(stray / total.max(1e-20)),
+        thd_db: to_db(error),
+    }
+}
+
+/// Analyze a preset at each of `KEYBOARD_NOTES`
+pub fn analyze_preset(params: &FMParams, sample_rate: f32) -> Vec<NoteAnalysis> {
+    KEYBOARD_NOTES.iter().map(|&note| analyze_note(params, note, sample_rate)).collect()
+}
+
+/// A predicted sideband and the level it was measured at
+#[derive(Clone, Debug)]
+pub struct SidebandLevel {
+    pub sideband: Sideband,
+    /// Measured amplitude, scaled so the measured sidebands carry the same
+    /// total energy as the predicted ones (so the preset's level and
+    /// envelope don't matter)
+    pub measured: f64,
+}
+
+/// The sidebands FM theory predicts for a preset playing `freq`, each with
+/// the amplitude measured in a render at `sample_rate`, lowest first. Each
+/// sideband's energy is taken from the bins around it, up to halfway to
+/// its neighbours.
+pub fn measure_sidebands(params: &FMParams, freq: f32, sample_rate: f32) -> Vec<SidebandLevel> {
+    let spectrum = sustain_spectrum(params, freq, sample_rate);
+    let bin_hz = sample_rate as f64 / FFT_SIZE as f64;
+    let predicted = fm_sidebands(params, freq, sample_rate as f64 / 2.0);
+    let bins: Vec<isize> = predicted.iter().map(|s| (s.freq / bin_hz).round() as isize).collect();
+
+    let amplitudes: Vec<f64> = predicted.iter()
+        .enumerate()
+        .map(|(i, sideband)| {
+            let low = i.checked_sub(1).map_or(isize::MIN, |prev| (bins[prev] + bins[i]) / 2 + 1);
+            let high = bins.get(i + 1).map_or(isize::MAX, |next| (bins[i] + next) / 2);
+            let first = (bins[i] - SIDEBAND_BINS).max(low).max(0);
+            let last = (bins[i] + SIDEBAND_BINS).min(high).min(spectrum.len() as isize - 1);
+            let energy: f64 = (first..=last).map(|bin| spectrum[bin as usize]).sum();
+            // A DC offset isn't split between positive and negative bins like a sinusoid
+            if sideband.freq == 0.0 { energy.sqrt() / 2.0 } else { energy.sqrt() }
+        })
+        .collect();
+    let predicted_energy: f64 = predicted.iter().map(|s| s.amplitude * s.amplitude).sum();
+    let measured_energy: f64 = amplitudes.iter().map(|a| a * a).sum();
+    let scale = (predicted_energy / measured_energy.max(1e-20)).sqrt();
+    predicted.into_iter()
+        .zip(amplitudes)
+        .map(|(sideband, measured)| SidebandLevel { sideband, measured: measured * scale })
+        .collect()
+}
+
+/// Bandwidth in Hz holding about 98% of the energy of a preset playing
+/// `freq`, by Carson's rule: twice the peak deviation plus the modulator
+/// frequency
+pub fn carson_bandwidth(params: &FMParams, freq: f32) -> f64 {
+    let note = params.for_note(freq);
+    2.0 * (beta(&note) + 1.0) * note.modulator_freq as f64
+}
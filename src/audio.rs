@@ -0,0 +1,975 @@
+// src/audio.rs - Native audio output (cpal)
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample};
+
+use crate::external::ExternalReader;
+use crate::input;
+use crate::resample::{Resampled, Resampler, OUTPUT_CHANNELS};
+use crate::synth_core::{SampleSource, MAX_OUTPUTS};
+
+/// How often the output supervisor checks for a failed or replaced device
+const SUPERVISOR_POLL: Duration = Duration::from_millis(500);
+
+/// Length of the ramp to silence used by `AudioOutput::fade_out`
+pub const FADE_OUT: Duration = Duration::from_millis(50);
+
+/// Length of the ramp up from silence every stream starts with, so opening
+/// a device (at startup, or on switching to another) doesn't click
+pub const FADE_IN: Duration = Duration::from_millis(20);
+
+/// Longest `AudioOutput::close_when_idle` waits for releases to ring out
+const RELEASE_WAIT: Duration = Duration::from_secs(10);
+
+/// How often `AudioOutput::close_when_idle` checks for idle voices
+const IDLE_POLL: Duration = Duration::from_millis(10);
+
+/// Set by `silence_all`: every stream ramps to silence
+static SILENCING: AtomicBool = AtomicBool::new(false);
+
+/// Set once a stream has reported being refused realtime priority, so it's
+/// only reported once
+static REALTIME_REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Where output streams send what they play while the master output is
+/// being recorded, and whether anything is listening
+static TAP: Mutex<Option<mpsc::Sender<TapBlock>>> = Mutex::new(None);
+static TAPPING: AtomicBool = AtomicBool::new(false);
+
+/// Identifies each stream's blocks on the tap
+static NEXT_STREAM: AtomicU64 = AtomicU64::new(0);
+
+/// One callback's worth of output from one stream, after volume and fades
+pub struct TapBlock {
+    pub stream: u64,
+    pub sample_rate: u32,
+    /// Left and right, whatever the device's channel count
+    pub frames: Vec<[f32; 2]>,
+}
+
+/// Start copying every output stream's frames to the returned receiver,
+/// replacing any earlier tap
+pub fn start_tap() -> mpsc::Receiver<TapBlock> {
+    let (sender, receiver) = mpsc::channel();
+    *TAP.lock().unwrap() = Some(sender);
+    TAPPING.store(true, Ordering::SeqCst);
+    receiver
+}
+
+/// Fade every output stream to silence and wait for the ramps, for exiting
+/// the process without dropping the outputs first
+pub fn silence_all() {
+    SILENCING.store(true, Ordering::SeqCst);
+    std::thread::sleep(FADE_OUT * 2);
+}
+
+/// Stop the tap; its receiver sees the end once queued blocks are read
+pub fn stop_tap() {
+    TAPPING.store(false, Ordering::SeqCst);
+    *TAP.lock().unwrap() = None;
+}
+
+/// User choices for the output stream; `None` fields use the host defaults
+#[derive(Clone, Debug, Default)]
+pub struct OutputConfig {
+    /// Audio host (backend) by name, e.g. `alsa`, `jack`, `asio`
+    pub host: Option<String>,
+    /// Output device, by list index or (part of) its name
+    pub device: Option<String>,
+    /// Requested stream sample rate in Hz
+    pub sample_rate: Option<u32>,
+    /// Fixed rate the synth runs at, resampled to the stream's; `None` runs
+    /// it at the stream's rate
+    pub engine_rate: Option<u32>,
+    /// Requested buffer size in frames (smaller is lower latency)
+    pub buffer_size: Option<u32>,
+    /// Master volume applied to the output (0.0 - 1.0, default 1.0)
+    pub volume: Option<f32>,
+    /// Device channels for each of a source's outputs; without one, the
+    /// first output plays on every pair of channels
+    pub channel_map: Option<ChannelMap>,
+    /// Ask for realtime scheduling of the thread running the stream's
+    /// callbacks, so a loaded system doesn't starve it
+    pub realtime: bool,
+}
+
+impl OutputConfig {
+    pub fn with_host(mut self, host: &str) -> Self {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    pub fn with_device(mut self, device: &str) -> Self {
+        self.device = Some(device.to_string());
+        self
+    }
+
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    pub fn with_engine_rate(mut self, sample_rate: u32) -> Self {
+        self.engine_rate = Some(sample_rate);
+        self
+    }
+
+    pub fn with_buffer_size(mut self, frames: u32) -> Self {
+        self.buffer_size = Some(frames);
+        self
+    }
+
+    pub fn with_volume(mut self, volume: f32) -> Self {
+        self.volume = Some(volume);
+        self
+    }
+
+    pub fn with_channel_map(mut self, channel_map: ChannelMap) -> Self {
+        self.channel_map = Some(channel_map);
+        self
+    }
+
+    pub fn with_realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+}
+
+/// Where each output of a source (`SampleSource::next_outputs`) plays on a
+/// device with more than two channels: its left and right device channels,
+/// counting from 0, or one channel for both, which gets them mixed. Several
+/// outputs may share channels; channels no output uses stay silent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMap {
+    routes: Vec<[usize; 2]>,
+}
+
+impl ChannelMap {
+    /// Parse a comma-separated list of channel pairs or single channels,
+    /// one per output and counting from 1, such as `1-2,3-4` for a second
+    /// output on channels 3 and 4, or `1,2,3,4` for four mono outputs
+    pub fn parse(s: &str) -> Option<Self> {
+        let channel = |c: &str| c.trim().parse::<usize>().ok().filter(|&c| c >= 1).map(|c| c - 1);
+        let routes = s.split(',')
+            .map(|route| match route.split_once('-') {
+                Some((left, right)) => Some([channel(left)?, channel(right)?]),
+                None => channel(route).map(|c| [c, c]),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        (!routes.is_empty() && routes.len() <= MAX_OUTPUTS).then_some(Self { routes })
+    }
+
+    /// Highest device channel used, counting from 1
+    pub fn channels(&self) -> usize {
+        self.routes.iter().flatten().max().map_or(0, |&c| c + 1)
+    }
+
+    /// Sample for device `channel` from every output routed to it
+    fn mix(&self, channel: usize, outputs: &[[f32; 2]; MAX_OUTPUTS]) -> f32 {
+        self.routes.iter().zip(outputs).fold(0.0, |sum, (&[left, right], &[l, r])| match (left == channel, right == channel) {
+            (true, true) => sum + 0.5 * (l + r),
+            (true, false) => sum + l,
+            (false, true) => sum + r,
+            (false, false) => sum,
+        })
+    }
+}
+
+impl std::fmt::Display for ChannelMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let routes: Vec<String> = self.routes.iter()
+            .map(|&[left, right]| if left == right { format!("{}", left + 1) } else { format!("{}-{}", left + 1, right + 1) })
+            .collect();
+        write!(f, "{}", routes.join(","))
+    }
+}
+
+/// What an output stream is really running with, as opened and as its
+/// callbacks report, for working out its latency
+#[derive(Clone, Debug, Default)]
+pub struct OutputStats {
+    pub device: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Fixed buffer size the stream was opened with; `None` when it runs
+    /// at the backend's default
+    pub buffer_size: Option<u32>,
+    /// Frames the latest callback filled: the buffer the backend really uses
+    pub callback_frames: u32,
+    /// Time from the latest callback to its first frame playing, as the
+    /// backend reports it; `None` when it doesn't
+    pub stream_latency: Option<Duration>,
+    /// Callbacks counted since the stream opened
+    pub callbacks: u64,
+    /// Outcome of asking for realtime priority, once the first callback
+    /// has: `None` if it wasn't asked for (or not yet), otherwise why not
+    pub realtime: Option<Result<(), String>>,
+}
+
+impl OutputStats {
+    /// Length of one callback's buffer
+    pub fn buffer_latency(&self) -> Duration {
+        Duration::from_secs_f64(self.callback_frames as f64 / self.sample_rate.max(1) as f64)
+    }
+
+    /// Time from rendering a frame to hearing it: the backend's figure when
+    /// it gives one, otherwise one buffer
+    pub fn latency(&self) -> Duration {
+        self.stream_latency.unwrap_or_else(|| self.buffer_latency())
+    }
+}
+
+/// What an output stream's callbacks share with whoever opened it
+#[derive(Clone, Default)]
+pub struct StreamShared {
+    /// Set by the stream's first error
+    pub failed: Arc<AtomicBool>,
+    /// Once set, the output ramps to silence over `FADE_OUT`
+    pub fading: Arc<AtomicBool>,
+    /// As `fading`, but for this stream alone, before it's replaced
+    pub closing: Arc<AtomicBool>,
+    /// The stream's configuration, and what its callbacks report
+    pub stats: Arc<Mutex<OutputStats>>,
+}
+
+/// Supported configuration range of an output device
+#[derive(Clone, Debug)]
+pub struct ConfigInfo {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub sample_format: SampleFormat,
+    pub buffer_size: cpal::SupportedBufferSize,
+}
+
+/// An output device and everything it supports
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub configs: Vec<ConfigInfo>,
+}
+
+/// Output devices available through one audio host
+#[derive(Clone, Debug)]
+pub struct HostInfo {
+    pub name: &'static str,
+    pub is_default: bool,
+    pub devices: Vec<DeviceInfo>,
+}
+
+/// Cargo feature that offers a host, for hosts behind one
+fn host_feature(id: cpal::HostId) -> Option<&'static str> {
+    match id.name() {
+        "ALSA" => Some("alsa"),
+        "JACK" => Some("jack"),
+        "ASIO" => Some("asio"),
+        "WASAPI" => Some("wasapi-exclusive"),
+        _ => None,
+    }
+}
+
+/// Host features this build was compiled with
+const HOST_FEATURES: &[&str] = &[
+    #[cfg(feature = "alsa")]
+    "alsa",
+    #[cfg(feature = "jack")]
+    "jack",
+    #[cfg(feature = "asio")]
+    "asio",
+    #[cfg(feature = "wasapi-exclusive")]
+    "wasapi-exclusive",
+];
+
+/// Whether this build offers a host: those behind a feature need it on
+fn host_compiled_in(id: cpal::HostId) -> bool {
+    host_feature(id).is_none_or(|feature| HOST_FEATURES.contains(&feature))
+}
+
+/// Hosts available on this system and compiled into this build
+fn compiled_hosts() -> Vec<cpal::HostId> {
+    cpal::available_hosts().into_iter().filter(|&id| host_compiled_in(id)).collect()
+}
+
+/// The host used without `--host`: cpal's default when it's compiled in,
+/// otherwise the first one that is
+fn default_host_id() -> Option<cpal::HostId> {
+    let default = cpal::default_host().id();
+    let hosts = compiled_hosts();
+    hosts.iter().copied().find(|&id| id == default).or_else(|| hosts.first().copied())
+}
+
+/// Enumerate every available host and its output devices
+pub fn list_output_devices() -> Vec<HostInfo> {
+    let default_host = default_host_id();
+    compiled_hosts()
+        .into_iter()
+        .filter_map(|id| cpal::host_from_id(id).ok())
+        .map(|host| {
+            let default_name = host.default_output_device().and_then(|d| d.name().ok());
+            let devices = host.output_devices()
+                .map(|devices| devices.map(|device| describe_device(&device, default_name.as_deref())).collect())
+                .unwrap_or_default();
+            HostInfo {
+                name: host.id().name(),
+                is_default: Some(host.id()) == default_host,
+                devices,
+            }
+        })
+        .collect()
+}
+
+fn describe_device(device: &cpal::Device, default_name: Option<&str>) -> DeviceInfo {
+    let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+    let configs = device.supported_output_configs()
+        .map(|configs| {
+            configs
+                .map(|c| ConfigInfo {
+                    channels: c.channels(),
+                    min_sample_rate: c.min_sample_rate().0,
+                    max_sample_rate: c.max_sample_rate().0,
+                    sample_format: c.sample_format(),
+                    buffer_size: *c.buffer_size(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    DeviceInfo {
+        is_default: default_name == Some(name.as_str()),
+        name,
+        configs,
+    }
+}
+
+/// The host named in `output`, or the platform default. ALSA, JACK, ASIO,
+/// and WASAPI are only available when built with their cargo feature.
+pub fn select_host(output: &OutputConfig) -> anyhow::Result<cpal::Host> {
+    let hosts = compiled_hosts();
+    let names: Vec<&str> = hosts.iter().map(|id| id.name()).collect();
+    let Some(wanted) = &output.host else {
+        return match default_host_id() {
+            Some(id) => Ok(cpal::host_from_id(id)?),
+            None => anyhow::bail!("No audio host is compiled in (build with e.g. --features alsa)"),
+        };
+    };
+    if let Some(&id) = hosts.iter().find(|id| id.name().eq_ignore_ascii_case(wanted)) {
+        return Ok(cpal::host_from_id(id)?);
+    }
+    // Known to cpal on this platform, but left out of this build
+    let missing = cpal::available_hosts().into_iter().find(|id| id.name().eq_ignore_ascii_case(wanted));
+    match missing.and_then(host_feature) {
+        Some(feature) => anyhow::bail!(
+            "Audio host '{}' isn't compiled in (build with --features {}; available: {})",
+            wanted,
+            feature,
+            names.join(", ")
+        ),
+        None => anyhow::bail!("Audio host '{}' not available (available: {})", wanted, names.join(", ")),
+    }
+}
+
+/// Find an output device by list index (as shown by `list_output_devices`)
+/// or case-insensitive name match
+fn find_device(host: &cpal::Host, wanted: &str) -> anyhow::Result<cpal::Device> {
+    let devices: Vec<cpal::Device> = host.output_devices()?.collect();
+    if let Ok(index) = wanted.parse::<usize>() {
+        if index > 0 && index <= devices.len() {
+            return Ok(devices[index - 1].clone());
+        }
+    }
+
+    let wanted_lower = wanted.to_lowercase();
+    let names: Vec<String> = devices.iter().map(|d| d.name().unwrap_or_default()).collect();
+    let found = names.iter()
+        .position(|n| n.to_lowercase() == wanted_lower)
+        .or_else(|| names.iter().position(|n| n.to_lowercase().contains(&wanted_lower)));
+    match found {
+        Some(i) => Ok(devices[i].clone()),
+        None => anyhow::bail!("Output device '{}' not found. Use 'devices' to list them.", wanted),
+    }
+}
+
+/// Output device and configuration to use for `output` on `host` (see
+/// `select_host`). A sample rate the device can't run at falls back to its
+/// default with a warning.
+pub fn open_output(host: &cpal::Host, output: &OutputConfig) -> anyhow::Result<(cpal::Device, cpal::SupportedStreamConfig)> {
+    let device = match &output.device {
+        Some(name) => find_device(host, name)?,
+        None => host.default_output_device()
+            .ok_or_else(|| anyhow::anyhow!("No output device available"))?,
+    };
+    let default = device.default_output_config()?;
+    let config = match output.sample_rate {
+        Some(rate) if rate != default.sample_rate().0 => match config_with_rate(&device, &default, rate) {
+            Some(config) => config,
+            None => {
+                eprintln!(
+                    "Warning: output device doesn't support {} Hz, using {} Hz",
+                    rate,
+                    default.sample_rate().0
+                );
+                default
+            }
+        },
+        _ => default,
+    };
+    Ok((device, config))
+}
+
+/// A supported config running at `rate`, preferring the default's channel
+/// count and sample format
+fn config_with_rate(
+    device: &cpal::Device,
+    default: &cpal::SupportedStreamConfig,
+    rate: u32,
+) -> Option<cpal::SupportedStreamConfig> {
+    device.supported_output_configs().ok()?
+        .filter(|c| c.min_sample_rate().0 <= rate && rate <= c.max_sample_rate().0)
+        .max_by_key(|c| (c.channels() == default.channels(), c.sample_format() == default.sample_format()))
+        .map(|c| c.with_sample_rate(cpal::SampleRate(rate)))
+}
+
+/// Build a stream on `device` that renders `synth`, converting to whatever
+/// sample format the device config uses and resampling from the engine
+/// rate, if `output` sets one. A requested buffer size outside
+/// the device's range, or one the backend rejects, falls back to the default.
+/// The first stream error is reported and sets `shared.failed`; later ones
+/// are quiet.
+pub fn build_output_stream<S: SampleSource + 'static>(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    output: &OutputConfig,
+    synth: Arc<Mutex<S>>,
+    shared: StreamShared,
+) -> anyhow::Result<cpal::Stream> {
+    let buffer_size = output.buffer_size;
+    let mut stream_config = config.config();
+    if let Some(frames) = buffer_size {
+        match *config.buffer_size() {
+            cpal::SupportedBufferSize::Range { min, max } if (min..=max).contains(&frames) => {
+                stream_config.buffer_size = cpal::BufferSize::Fixed(frames);
+            }
+            cpal::SupportedBufferSize::Range { min, max } => {
+                eprintln!("Warning: buffer size {} outside device range {}-{}, using default", frames, min, max);
+            }
+            cpal::SupportedBufferSize::Unknown => {
+                stream_config.buffer_size = cpal::BufferSize::Fixed(frames);
+            }
+        }
+    }
+
+    let format = config.sample_format();
+    match build_format(device, &stream_config, format, output, Arc::clone(&synth), shared.clone()) {
+        Err(err) if stream_config.buffer_size != cpal::BufferSize::Default => {
+            eprintln!("Warning: couldn't open stream with buffer size {:?} ({}), using default", buffer_size, err);
+            stream_config.buffer_size = cpal::BufferSize::Default;
+            build_format(device, &stream_config, format, output, synth, shared)
+        }
+        result => result,
+    }
+}
+
+fn build_format<S: SampleSource + 'static>(
+    device: &cpal::Device,
+    stream_config: &cpal::StreamConfig,
+    format: SampleFormat,
+    output: &OutputConfig,
+    synth: Arc<Mutex<S>>,
+    shared: StreamShared,
+) -> anyhow::Result<cpal::Stream> {
+    match format {
+        SampleFormat::F32 => build::<f32, S>(device, stream_config, output, synth, shared),
+        SampleFormat::F64 => build::<f64, S>(device, stream_config, output, synth, shared),
+        SampleFormat::I8 => build::<i8, S>(device, stream_config, output, synth, shared),
+        SampleFormat::I16 => build::<i16, S>(device, stream_config, output, synth, shared),
+        SampleFormat::I32 => build::<i32, S>(device, stream_config, output, synth, shared),
+        SampleFormat::U8 => build::<u8, S>(device, stream_config, output, synth, shared),
+        SampleFormat::U16 => build::<u16, S>(device, stream_config, output, synth, shared),
+        SampleFormat::U32 => build::<u32, S>(device, stream_config, output, synth, shared),
+        format => anyhow::bail!("Unsupported sample format {}", format),
+    }
+}
+
+fn build<T, S>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    output: &OutputConfig,
+    synth: Arc<Mutex<S>>,
+    shared: StreamShared,
+) -> anyhow::Result<cpal::Stream>
+where
+    T: SizedSample + FromSample<f32>,
+    S: SampleSource + 'static,
+{
+    let StreamShared { failed, fading, closing, stats } = shared;
+    let channels = config.channels as usize;
+    let gain = output.volume.unwrap_or(1.0).clamp(0.0, 1.0);
+    let channel_map = output.channel_map.clone();
+    if let Some(map) = channel_map.as_ref().filter(|map| map.channels() > channels) {
+        eprintln!("Warning: channel map {} needs {} channels, the device has {}; the rest are dropped", map, map.channels(), channels);
+    }
+    let fade_out_step = 1.0 / (FADE_OUT.as_secs_f32() * config.sample_rate.0 as f32);
+    let fade_in_step = 1.0 / (FADE_IN.as_secs_f32() * config.sample_rate.0 as f32);
+    // Every stream fades in, whatever it opens on
+    let mut level = 0.0f32;
+    let stream_id = NEXT_STREAM.fetch_add(1, Ordering::Relaxed);
+    let sample_rate = config.sample_rate.0;
+    let mut tapped = Vec::new();
+    let mut external = ExternalReader::new();
+    let mut external_block = Vec::new();
+    let mut resampler = Resampler::<OUTPUT_CHANNELS>::new(output.engine_rate.unwrap_or(sample_rate), sample_rate);
+    // Asked for from the first callback, which runs on the stream's thread
+    let mut promote = output.realtime;
+    {
+        let mut stats = stats.lock().unwrap();
+        stats.sample_rate = sample_rate;
+        stats.channels = config.channels;
+        stats.buffer_size = match config.buffer_size {
+            cpal::BufferSize::Fixed(frames) => Some(frames),
+            cpal::BufferSize::Default => None,
+        };
+    }
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+            if std::mem::take(&mut promote) {
+                let result = promote_current_thread();
+                stats.lock().unwrap().realtime = Some(result);
+            }
+            let frames = data.len() / channels.max(1);
+            let modulator = external.fill(&mut external_block, frames, sample_rate as f32).then_some(&external_block[..]);
+            let mut locked = synth.lock().unwrap();
+            let mut synth = Resampled::new(&mut *locked, &mut resampler);
+            synth.set_input_level(input::level());
+            let tapping = TAPPING.load(Ordering::Relaxed);
+            let mut tap = tapping.then_some(&mut tapped);
+            let silent = fading.load(Ordering::Relaxed) || closing.load(Ordering::Relaxed) || SILENCING.load(Ordering::Relaxed);
+            let target = if silent { 0.0 } else { 1.0 };
+            if level != target {
+                // Ramp one frame at a time so the fade has no steps
+                for (i, frame) in data.chunks_mut(channels.max(1)).enumerate() {
+                    level = match silent {
+                        true => (level - fade_out_step).max(0.0),
+                        false => (level + fade_in_step).min(1.0),
+                    };
+                    let modulator = modulator.and_then(|m| m.get(i..=i));
+                    write_frames(frame, channels, gain * level, &mut synth, tap.as_deref_mut(), modulator, channel_map.as_ref());
+                }
+            } else {
+                write_frames(data, channels, gain * level, &mut synth, tap, modulator, channel_map.as_ref());
+            }
+            drop(locked);
+
+            // Never wait on the tap: a block lost to a busy lock is a tiny gap
+            // in the recording, a blocked callback is a glitch in the output
+            if tapping {
+                if let Ok(tap) = TAP.try_lock() {
+                    if let Some(sender) = tap.as_ref() {
+                        let frames = std::mem::take(&mut tapped);
+                        let _ = sender.send(TapBlock { stream: stream_id, sample_rate, frames });
+                    }
+                }
+                tapped.clear();
+            }
+            // Like the tap, skipped rather than waited for while it's read
+            if let Ok(mut stats) = stats.try_lock() {
+                let timestamp = info.timestamp();
+                stats.callback_frames = frames as u32;
+                stats.stream_latency = timestamp.playback.duration_since(&timestamp.callback);
+                stats.callbacks += 1;
+            }
+        },
+        move |err| {
+            if !failed.swap(true, Ordering::SeqCst) {
+                eprintln!("Error in audio stream: {}", err);
+            }
+        },
+        None,
+    )?;
+    Ok(stream)
+}
+
+/// Keeps `synth` playing for as long as the value lives. A supervisor thread
+/// owns the stream and rebuilds it when the device fails or, without an
+/// explicit `--device`, when the system default output changes (on desktop
+/// systems; Android and iOS reroute the stream themselves).
+pub struct AudioOutput {
+    stop: Arc<AtomicBool>,
+    fading: Arc<AtomicBool>,
+    /// Device released until `resume`, e.g. during an audio session
+    /// interruption
+    suspended: Arc<AtomicBool>,
+    /// Reported by whichever stream is playing
+    stats: Arc<Mutex<OutputStats>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AudioOutput {
+    /// Open the output and start playing; the synth is switched to the
+    /// engine rate, or without one the stream's sample rate (again on every
+    /// reconnect)
+    pub fn start<S: SampleSource + 'static>(output: OutputConfig, synth: Arc<Mutex<S>>) -> anyhow::Result<Self> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let fading = Arc::new(AtomicBool::new(false));
+        let suspended = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(Mutex::new(OutputStats::default()));
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = {
+            let stop = Arc::clone(&stop);
+            let fading = Arc::clone(&fading);
+            let suspended = Arc::clone(&suspended);
+            let stats = Arc::clone(&stats);
+            std::thread::spawn(move || supervise(output, synth, stop, fading, suspended, stats, ready_tx))
+        };
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self { stop, fading, suspended, stats, thread: Some(thread) }),
+            Ok(Err(err)) => {
+                let _ = thread.join();
+                Err(err)
+            }
+            Err(_) => anyhow::bail!("Audio output thread exited unexpectedly"),
+        }
+    }
+
+    /// Ramp the output down to silence and wait for the ramp to finish, so
+    /// dropping the output afterwards doesn't click
+    pub fn fade_out(&self) {
+        self.fading.store(true, Ordering::SeqCst);
+        // Allow for a buffer already queued ahead of the ramp
+        std::thread::sleep(FADE_OUT * 2);
+    }
+
+    /// Fade out and release the device, keeping the synth as it is, for
+    /// when the system takes the audio away (a call on a phone, another
+    /// app taking audio focus) or the app goes to the background
+    pub fn suspend(&self) {
+        if !self.suspended.load(Ordering::SeqCst) {
+            self.fade_out();
+            self.suspended.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Reopen the device after `suspend`, within `SUPERVISOR_POLL`
+    pub fn resume(&self) {
+        self.fading.store(false, Ordering::SeqCst);
+        self.suspended.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::SeqCst)
+    }
+
+    /// The current stream's configuration and latency; a stream just
+    /// opened hasn't reported a callback yet
+    pub fn stats(&self) -> OutputStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Keep playing until `idle` reports every voice has finished its
+    /// release (or `RELEASE_WAIT` passes), then fade out and close, so an
+    /// ending is never cut short
+    pub fn close_when_idle(self, idle: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !idle() && start.elapsed() < RELEASE_WAIT {
+            std::thread::sleep(IDLE_POLL);
+        }
+    }
+}
+
+impl Drop for AudioOutput {
+    fn drop(&mut self) {
+        // Whatever is still playing ramps down rather than stopping dead
+        if !self.fading.load(Ordering::SeqCst) {
+            self.fade_out();
+        }
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A playing stream and what it was opened on
+struct RunningStream {
+    _stream: cpal::Stream,
+    device_name: String,
+    failed: Arc<AtomicBool>,
+    closing: Arc<AtomicBool>,
+}
+
+impl RunningStream {
+    /// Ramp this stream alone to silence before it's dropped, unless it
+    /// has failed and nothing would be heard anyway
+    fn fade_out(&self) {
+        if !self.failed.load(Ordering::SeqCst) {
+            self.closing.store(true, Ordering::SeqCst);
+            std::thread::sleep(FADE_OUT * 2);
+        }
+    }
+}
+
+fn start_stream<S: SampleSource + 'static>(
+    host: &cpal::Host,
+    output: &OutputConfig,
+    synth: &Arc<Mutex<S>>,
+    fading: &Arc<AtomicBool>,
+    stats: &Arc<Mutex<OutputStats>>,
+) -> anyhow::Result<RunningStream> {
+    let (device, config) = open_output(host, output)?;
+    let engine_rate = output.engine_rate.unwrap_or(config.sample_rate().0);
+    synth.lock().unwrap().set_sample_rate(engine_rate as f32);
+    let failed = Arc::new(AtomicBool::new(false));
+    let device_name = device.name().unwrap_or_default();
+    *stats.lock().unwrap() = OutputStats { device: device_name.clone(), ..OutputStats::default() };
+    let closing = Arc::new(AtomicBool::new(false));
+    let shared = StreamShared {
+        failed: Arc::clone(&failed),
+        fading: Arc::clone(fading),
+        closing: Arc::clone(&closing),
+        stats: Arc::clone(stats),
+    };
+    let stream = build_output_stream(&device, &config, output, Arc::clone(synth), shared)?;
+    stream.play()?;
+    Ok(RunningStream {
+        _stream: stream,
+        device_name,
+        failed,
+        closing,
+    })
+}
+
+/// Name of the current default output device of `host`
+fn default_device_name(host: &cpal::Host) -> Option<String> {
+    host.default_output_device().and_then(|d| d.name().ok())
+}
+
+/// Supervisor thread body: the stream is created, watched, and dropped here
+/// because cpal streams can't move between threads on every platform
+fn supervise<S: SampleSource + 'static>(
+    output: OutputConfig,
+    synth: Arc<Mutex<S>>,
+    stop: Arc<AtomicBool>,
+    fading: Arc<AtomicBool>,
+    suspended: Arc<AtomicBool>,
+    stats: Arc<Mutex<OutputStats>>,
+    ready: mpsc::Sender<anyhow::Result<()>>,
+) {
+    // One host for the supervisor's lifetime: opening one can be costly
+    // (JACK registers a new client each time)
+    let host = match select_host(&output) {
+        Ok(host) => host,
+        Err(err) => {
+            let _ = ready.send(Err(err));
+            return;
+        }
+    };
+    let mut current = match start_stream(&host, &output, &synth, &fading, &stats) {
+        Ok(stream) => {
+            let _ = ready.send(Ok(()));
+            Some(stream)
+        }
+        Err(err) => {
+            let _ = ready.send(Err(err));
+            return;
+        }
+    };
+
+    // A named device that disappears is replaced by the default one. Mobile
+    // systems move a stream to new routes (headphones, Bluetooth) themselves.
+    let fallback = OutputConfig { device: None, ..output.clone() };
+    let follow_default = output.device.is_none() && !cfg!(any(target_os = "android", target_os = "ios"));
+    let mut reported = false;
+    let mut resuming = false;
+    while !stop.load(Ordering::SeqCst) {
+        std::thread::sleep(SUPERVISOR_POLL);
+
+        if let Some(Err(reason)) = &stats.lock().unwrap().realtime {
+            if !REALTIME_REPORTED.swap(true, Ordering::SeqCst) {
+                eprintln!("Warning: couldn't get realtime priority for the audio thread ({})", reason);
+            }
+        }
+        if suspended.load(Ordering::SeqCst) {
+            current = None;
+            resuming = true;
+            continue;
+        }
+        let needs_rebuild = match &current {
+            Some(running) => {
+                running.failed.load(Ordering::SeqCst)
+                    || (follow_default && default_device_name(&host).is_some_and(|name| name != running.device_name))
+            }
+            None => true,
+        };
+        if !needs_rebuild {
+            continue;
+        }
+
+        // Release the old device before opening its replacement, fading out
+        // first if it's still playing (the default output changed)
+        if let Some(running) = current.take() {
+            running.fade_out();
+        }
+        match start_stream(&host, &output, &synth, &fading, &stats).or_else(|_| start_stream(&host, &fallback, &synth, &fading, &stats)) {
+            Ok(running) => {
+                if !resuming {
+                    println!("Audio output switched to '{}'", running.device_name);
+                }
+                current = Some(running);
+                reported = false;
+                resuming = false;
+            }
+            Err(err) => {
+                if !reported {
+                    eprintln!("Audio output unavailable ({}), retrying...", err);
+                    reported = true;
+                }
+            }
+        }
+    }
+}
+
+/// Give the calling thread realtime scheduling: `SCHED_FIFO` halfway up its
+/// range on Unix-like systems (which takes `rtprio` permission on Linux),
+/// time-critical priority on Windows
+#[cfg(unix)]
+fn promote_current_thread() -> Result<(), String> {
+    // Only the current thread's scheduling changes; other fields of
+    // `sched_param`, where a platform has any, stay zero
+    let result = unsafe {
+        let min = libc::sched_get_priority_min(libc::SCHED_FIFO);
+        let max = libc::sched_get_priority_max(libc::SCHED_FIFO);
+        let mut param: libc::sched_param = std::mem::zeroed();
+        param.sched_priority = min + (max - min) / 2;
+        libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+    };
+    match result {
+        0 => Ok(()),
+        err => Err(std::io::Error::from_raw_os_error(err).to_string()),
+    }
+}
+
+#[cfg(windows)]
+fn promote_current_thread() -> Result<(), String> {
+    use windows_sys::Win32::System::Threading::{GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL};
+    // The current thread's pseudo-handle needs no closing
+    match unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) } {
+        0 => Err(std::io::Error::last_os_error().to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn promote_current_thread() -> Result<(), String> {
+    Err("not supported on this platform".to_string())
+}
+
+/// Fill an interleaved output buffer, rendering one synth frame per frame
+/// and scaling it by `gain`. With a channel `map`, each of the synth's
+/// outputs goes to the channels it names. Otherwise mono outputs get the
+/// mono sample, and with more channels even ones get the left sample and odd
+/// ones the right. With `tap`, each scaled frame (every output mixed) is
+/// also appended there as left and right. With `external`, its samples are
+/// handed to the synth frame by frame.
+pub fn write_frames<T, S: SampleSource>(
+    data: &mut [T],
+    channels: usize,
+    gain: f32,
+    synth: &mut S,
+    mut tap: Option<&mut Vec<[f32; 2]>>,
+    external: Option<&[f32]>,
+    map: Option<&ChannelMap>,
+) where
+    T: Sample + FromSample<f32>,
+{
+    let mut external = external.map(|samples| samples.iter());
+    let mut next_external = |synth: &mut S| {
+        if let Some(sample) = external.as_mut().and_then(Iterator::next) {
+            synth.set_external(*sample);
+        }
+    };
+    if let Some(map) = map {
+        for frame in data.chunks_mut(channels.max(1)) {
+            next_external(synth);
+            let outputs = synth.next_outputs();
+            if let Some(tap) = tap.as_deref_mut() {
+                let [left, right] = outputs.iter().fold([0.0; 2], |[l, r], [left, right]| [l + left, r + right]);
+                tap.push([left * gain, right * gain]);
+            }
+            for (channel, out) in frame.iter_mut().enumerate() {
+                *out = T::from_sample(map.mix(channel, &outputs) * gain);
+            }
+        }
+        return;
+    }
+    if channels <= 1 {
+        for out in data.iter_mut() {
+            next_external(synth);
+            let sample = synth.next_sample() * gain;
+            if let Some(tap) = tap.as_deref_mut() {
+                tap.push([sample, sample]);
+            }
+            *out = T::from_sample(sample);
+        }
+        return;
+    }
+    for frame in data.chunks_mut(channels) {
+        next_external(synth);
+        let [left, right] = synth.next_frame().map(|s| s * gain);
+        if let Some(tap) = tap.as_deref_mut() {
+            tap.push([left, right]);
+        }
+        let (left, right) = (T::from_sample(left), T::from_sample(right));
+        for (channel, out) in frame.iter_mut().enumerate() {
+            *out = if channel % 2 == 0 { left } else { right };
+        }
+    }
+}
+
+/// An output with no device behind it: it pulls buffers from a source
+/// through `write_frames`, locking it per buffer as a stream's callback
+/// does, but as fast as the source can render and into a buffer nobody
+/// hears. For benchmarks, and anywhere a source has to run without
+/// hardware.
+pub struct NullOutput {
+    sample_rate: u32,
+    channels: usize,
+    buffer: Vec<f32>,
+}
+
+impl NullOutput {
+    /// An output of `channels` at `sample_rate`, rendering `buffer_frames`
+    /// per callback
+    pub fn new(sample_rate: u32, channels: u16, buffer_frames: usize) -> Self {
+        let channels = channels.max(1) as usize;
+        Self { sample_rate, channels, buffer: vec![0.0; buffer_frames.max(1) * channels] }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Pull `frames` frames from `synth`, switching it to the output's rate
+    /// first, and return how long rendering took
+    pub fn run<S: SampleSource>(&mut self, synth: &Mutex<S>, frames: u64) -> Duration {
+        synth.lock().unwrap().set_sample_rate(self.sample_rate as f32);
+        let buffer_frames = (self.buffer.len() / self.channels) as u64;
+        let start = Instant::now();
+        let mut left = frames;
+        while left > 0 {
+            let block = left.min(buffer_frames) as usize * self.channels;
+            let mut synth = synth.lock().unwrap();
+            write_frames(&mut self.buffer[..block], self.channels, 1.0, &mut *synth, None, None, None);
+            left -= (block / self.channels) as u64;
+        }
+        let elapsed = start.elapsed();
+        // Keep the output from being optimized away
+        std::hint::black_box(&self.buffer);
+        elapsed
+    }
+}
@@ -0,0 +1,406 @@
+// src/bin/fm_synth_gui.rs - Desktop GUI: knobs, preset browser, keyboard, and scope
+
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+use eframe::egui::{self, Color32, Key, Pos2, Rect, Sense, Stroke, StrokeKind, Vec2};
+
+use fm_synth::analysis::power_spectrum;
+use fm_synth::audio::AudioOutput;
+use fm_synth::config::{self, Config};
+use fm_synth::macros::preset_macros;
+use fm_synth::render::DEFAULT_RENDER_RATE;
+use fm_synth::synth_core::{FMParams, PolySynth, SampleSource, Waveform, MAX_MODULATION_INDEX};
+use fm_synth::synth_data::{self, get_presets, midi_to_freq, note_name, preset_meta, PresetMeta};
+
+/// Voices available to the keyboard
+const VOICES: usize = 16;
+
+/// Samples kept for the scope and spectrum (a power of two for the FFT)
+const SCOPE_LEN: usize = 16384;
+
+/// Samples drawn by the scope
+const SCOPE_WINDOW: usize = 1024;
+
+/// First key of the on-screen keyboard (C4) and its length in keys
+const KEYBOARD_LOW: i32 = 60;
+const KEYBOARD_KEYS: i32 = 25;
+
+/// Computer keys playing the keyboard from its first C, piano style
+const KEY_MAP: [(Key, i32); 13] = [
+    (Key::A, 0), (Key::W, 1), (Key::S, 2), (Key::E, 3), (Key::D, 4), (Key::F, 5), (Key::T, 6),
+    (Key::G, 7), (Key::Y, 8), (Key::H, 9), (Key::U, 10), (Key::J, 11), (Key::K, 12),
+];
+
+/// The synth the audio thread plays, with a ring buffer of what it played
+struct Engine {
+    synth: PolySynth,
+    scope: Vec<f32>,
+    pos: usize,
+}
+
+impl Engine {
+    /// The last `SCOPE_LEN` samples, oldest first
+    fn snapshot(&self) -> Vec<f32> {
+        let mut samples = self.scope[self.pos..].to_vec();
+        samples.extend_from_slice(&self.scope[..self.pos]);
+        samples
+    }
+}
+
+impl SampleSource for Engine {
+    fn next_sample(&mut self) -> f32 {
+        let sample = self.synth.next_sample();
+        self.scope[self.pos] = sample;
+        self.pos = (self.pos + 1) % SCOPE_LEN;
+        sample
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.synth.set_sample_rate(sample_rate);
+    }
+}
+
+fn is_black(note: i32) -> bool {
+    matches!(note.rem_euclid(12), 1 | 3 | 6 | 8 | 10)
+}
+
+/// A rotary control: drag up or down to turn it
+fn knob(ui: &mut egui::Ui, label: &str, value: &mut f32, range: RangeInclusive<f32>) -> egui::Response {
+    let (start, end) = (*range.start(), *range.end());
+    let (rect, mut response) = ui.allocate_exact_size(Vec2::new(56.0, 72.0), Sense::click_and_drag());
+    if response.dragged() {
+        let step = (end - start) / 200.0;
+        *value = (*value - response.drag_delta().y * step).clamp(start, end);
+        response.mark_changed();
+    }
+
+    let painter = ui.painter();
+    let visuals = ui.style().interact(&response);
+    let center = rect.center_top() + Vec2::new(0.0, 24.0);
+    let radius = 18.0;
+    painter.circle(center, radius, visuals.bg_fill, visuals.fg_stroke);
+    // Sweeps 270 degrees, from lower left through the top to lower right
+    let t = ((*value - start) / (end - start)).clamp(0.0, 1.0);
+    let angle = (t - 0.5) * 1.5 * PI;
+    let tip = center + Vec2::new(angle.sin(), -angle.cos()) * (radius - 3.0);
+    painter.line_segment([center, tip], Stroke::new(2.5, visuals.fg_stroke.color));
+    painter.text(
+        rect.center_bottom() - Vec2::new(0.0, 16.0),
+        egui::Align2::CENTER_BOTTOM,
+        format!("{:.2}", value),
+        egui::FontId::monospace(10.0),
+        visuals.text_color(),
+    );
+    painter.text(
+        rect.center_bottom(),
+        egui::Align2::CENTER_BOTTOM,
+        label,
+        egui::FontId::proportional(11.0),
+        visuals.text_color(),
+    );
+    response
+}
+
+struct SynthApp {
+    engine: Arc<Mutex<Engine>>,
+    output: AudioOutput,
+    presets: Vec<(String, FMParams, PresetMeta)>,
+    /// Patch played by the keyboard, edited by the knobs
+    patch: FMParams,
+    patch_name: String,
+    /// Notes held by the computer keyboard and the mouse
+    keys_held: Vec<i32>,
+    mouse_note: Option<i32>,
+}
+
+impl SynthApp {
+    fn new(config: &Config) -> anyhow::Result<Self> {
+        let engine = Arc::new(Mutex::new(Engine {
+            synth: PolySynth::new(DEFAULT_RENDER_RATE, VOICES),
+            scope: vec![0.0; SCOPE_LEN],
+            pos: 0,
+        }));
+        let output = AudioOutput::start(config.output(), Arc::clone(&engine))?;
+
+        let mut presets: Vec<(String, FMParams, PresetMeta)> = get_presets()
+            .into_iter()
+            .map(|(name, params)| (name.to_string(), params, preset_meta(name)))
+            .collect();
+        if let Some(dir) = config.preset_dir().filter(|dir| dir.exists()) {
+            match config::load_presets(&dir) {
+                Ok(loaded) => presets.extend(loaded),
+                Err(err) => eprintln!("Warning: couldn't read preset directory {}: {}", dir.display(), err),
+            }
+        }
+        let (patch_name, patch, _) = presets[0].clone();
+
+        Ok(Self {
+            engine,
+            output,
+            presets,
+            patch,
+            patch_name,
+            keys_held: Vec::new(),
+            mouse_note: None,
+        })
+    }
+
+    fn note_on(&self, note: i32) {
+        let freq = midi_to_freq(note);
+        self.engine.lock().unwrap().synth.note_on(freq, &self.patch);
+    }
+
+    fn note_off(&self, note: i32) {
+        self.engine.lock().unwrap().synth.note_off(midi_to_freq(note));
+    }
+
+    fn preset_browser(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Presets");
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (name, params, meta) in &self.presets {
+                let mut label = ui.selectable_label(*name == self.patch_name, name);
+                if !meta.description.is_empty() {
+                    label = label.on_hover_text(&meta.description);
+                }
+                if label.clicked() {
+                    self.patch = params.clone();
+                    self.patch_name = name.clone();
+                }
+            }
+        });
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        // Generic macros are centred on the preset as loaded
+        let macros = match self.presets.iter().find(|(name, _, _)| *name == self.patch_name) {
+            Some((_, params, meta)) => preset_macros(&meta.macros, params),
+            None => preset_macros(&[], &self.patch),
+        };
+        let p = &mut self.patch;
+        ui.heading(format!("Patch: {}", self.patch_name));
+
+        ui.label("Macros");
+        ui.horizontal(|ui| {
+            for control in &macros {
+                let mut position = control.position(p);
+                if knob(ui, &control.name, &mut position, 0.0..=1.0).changed() {
+                    control.apply(p, position);
+                }
+            }
+        });
+
+        ui.label("FM");
+        ui.horizontal(|ui| {
+            let mut ratio = p.modulator_freq / p.carrier_freq;
+            if knob(ui, "Ratio", &mut ratio, 0.25..=8.0).changed() {
+                p.modulator_freq = p.carrier_freq * ratio;
+            }
+            knob(ui, "Index", &mut p.modulation_index, 0.0..=MAX_MODULATION_INDEX);
+            knob(ui, "Level dB", &mut p.level_db, -60.0..=0.0);
+            knob(ui, "Key scale", &mut p.key_scaling, 0.0..=1.0);
+            ui.vertical(|ui| {
+                wave_picker(ui, "Carrier", &mut p.carrier_wave);
+                wave_picker(ui, "Modulator", &mut p.modulator_wave);
+            });
+        });
+
+        ui.label("Envelope");
+        ui.horizontal(|ui| {
+            knob(ui, "Attack", &mut p.attack, 0.0..=2.0);
+            knob(ui, "Decay", &mut p.decay, 0.0..=2.0);
+            knob(ui, "Sustain", &mut p.sustain, 0.0..=1.0);
+            knob(ui, "Release", &mut p.release, 0.0..=5.0);
+        });
+
+        ui.checkbox(&mut p.index_env, "Index envelope");
+        ui.add_enabled_ui(p.index_env, |ui| {
+            ui.horizontal(|ui| {
+                knob(ui, "Attack", &mut p.index_attack, 0.0..=2.0);
+                knob(ui, "Decay", &mut p.index_decay, 0.0..=2.0);
+                knob(ui, "Sustain", &mut p.index_sustain, 0.0..=1.0);
+                knob(ui, "Release", &mut p.index_release, 0.0..=5.0);
+            });
+        });
+    }
+
+    /// Two octaves of piano keys, played with the mouse
+    fn keyboard(&mut self, ui: &mut egui::Ui) {
+        let whites: Vec<i32> = (KEYBOARD_LOW..KEYBOARD_LOW + KEYBOARD_KEYS).filter(|n| !is_black(*n)).collect();
+        let size = Vec2::new(ui.available_width(), 96.0);
+        let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+        let rect = response.rect;
+        let white_width = rect.width() / whites.len() as f32;
+
+        // Rectangle of each key; black keys sit over the boundary after their white key
+        let key_rect = |note: i32| -> Rect {
+            let white_index = whites.iter().filter(|&&w| w < note).count() as f32;
+            if is_black(note) {
+                let x = rect.left() + white_index * white_width - white_width * 0.3;
+                Rect::from_min_size(Pos2::new(x, rect.top()), Vec2::new(white_width * 0.6, rect.height() * 0.6))
+            } else {
+                Rect::from_min_size(Pos2::new(rect.left() + white_index * white_width, rect.top()),
+                    Vec2::new(white_width, rect.height()))
+            }
+        };
+        let held = |note: i32| self.keys_held.contains(&note) || self.mouse_note == Some(note);
+
+        for &note in &whites {
+            let fill = if held(note) { Color32::LIGHT_BLUE } else { Color32::WHITE };
+            painter.rect(key_rect(note).shrink(1.0), 2.0, fill, Stroke::new(1.0, Color32::GRAY), StrokeKind::Inside);
+        }
+        let blacks = (KEYBOARD_LOW..KEYBOARD_LOW + KEYBOARD_KEYS).filter(|n| is_black(*n));
+        for note in blacks.clone() {
+            let fill = if held(note) { Color32::DARK_BLUE } else { Color32::BLACK };
+            painter.rect_filled(key_rect(note), 2.0, fill);
+        }
+
+        // Black keys are on top, so they win when the pointer is over both
+        let pressed = response.is_pointer_button_down_on()
+            .then(|| response.interact_pointer_pos())
+            .flatten()
+            .and_then(|pos| {
+                blacks.clone().find(|&n| key_rect(n).contains(pos))
+                    .or_else(|| whites.iter().copied().find(|&n| key_rect(n).contains(pos)))
+            });
+        if pressed != self.mouse_note {
+            if let Some(note) = self.mouse_note {
+                self.note_off(note);
+            }
+            if let Some(note) = pressed {
+                self.note_on(note);
+            }
+            self.mouse_note = pressed;
+        }
+    }
+
+    /// Play and release notes from the computer keyboard
+    fn computer_keys(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        for (key, offset) in KEY_MAP {
+            let note = KEYBOARD_LOW + offset;
+            let down = ctx.input(|i| i.key_down(key));
+            let held = self.keys_held.contains(&note);
+            if down && !held {
+                self.keys_held.push(note);
+                self.note_on(note);
+            } else if !down && held {
+                self.keys_held.retain(|&n| n != note);
+                self.note_off(note);
+            }
+        }
+    }
+
+    fn scope(&self, ui: &mut egui::Ui, samples: &[f32]) {
+        let size = Vec2::new(ui.available_width() / 2.0 - 4.0, 140.0);
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 2.0, Color32::from_gray(20));
+
+        // Start at a rising zero crossing so a steady note stands still
+        let recent = &samples[samples.len() - 2 * SCOPE_WINDOW..];
+        let start = (1..SCOPE_WINDOW)
+            .find(|&i| recent[i - 1] < 0.0 && recent[i] >= 0.0)
+            .unwrap_or(SCOPE_WINDOW);
+        let points = recent[start..start + SCOPE_WINDOW]
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                let x = rect.left() + rect.width() * i as f32 / SCOPE_WINDOW as f32;
+                Pos2::new(x, rect.center().y - s.clamp(-1.0, 1.0) * rect.height() * 0.5)
+            })
+            .collect();
+        painter.line(points, Stroke::new(1.5, Color32::LIGHT_GREEN));
+    }
+
+    /// Log-frequency spectrum from 20 Hz to 20 kHz, -100 to 0 dB
+    fn spectrum(&self, ui: &mut egui::Ui, samples: &[f32], sample_rate: f32) {
+        let size = Vec2::new(ui.available_width(), 140.0);
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 2.0, Color32::from_gray(20));
+
+        let power = power_spectrum(samples);
+        // A full-scale sine through the Hann window peaks at (N / 4)^2
+        let full_scale = (power.len() as f64 / 2.0).powi(2);
+        let bin_hz = sample_rate / (2 * power.len()) as f32;
+        let columns = rect.width() as usize;
+        let points = (0..columns)
+            .map(|x| {
+                let freq = |x: usize| 20.0 * 1000f32.powf(x as f32 / columns as f32);
+                let low = ((freq(x) / bin_hz) as usize).min(power.len() - 1);
+                let high = ((freq(x + 1) / bin_hz) as usize).clamp(low + 1, power.len());
+                let peak = power[low..high].iter().copied().fold(0.0, f64::max);
+                let db = (10.0 * (peak / full_scale).max(1e-10).log10()) as f32;
+                let y = rect.top() + rect.height() * (-db / 100.0).clamp(0.0, 1.0);
+                Pos2::new(rect.left() + x as f32, y)
+            })
+            .collect();
+        painter.line(points, Stroke::new(1.5, Color32::LIGHT_YELLOW));
+    }
+}
+
+fn wave_picker(ui: &mut egui::Ui, label: &str, wave: &mut Waveform) {
+    egui::ComboBox::from_label(label)
+        .selected_text(wave.name())
+        .show_ui(ui, |ui| {
+            for option in [Waveform::Sine, Waveform::Square, Waveform::Saw] {
+                ui.selectable_value(wave, option, option.name());
+            }
+        });
+}
+
+impl eframe::App for SynthApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.computer_keys(ctx);
+
+        egui::SidePanel::left("presets").show(ctx, |ui| self.preset_browser(ui));
+        egui::CentralPanel::default().show(ctx, |ui| {
+            self.controls(ui);
+            ui.separator();
+
+            let held: Vec<String> = self.keys_held.iter().chain(&self.mouse_note).map(|&n| note_name(n)).collect();
+            ui.label(format!("Keyboard (A W S E D F T G Y H U J K): {}", held.join(" ")));
+            self.keyboard(ui);
+            ui.separator();
+
+            let (samples, sample_rate) = {
+                let engine = self.engine.lock().unwrap();
+                (engine.snapshot(), engine.synth.sample_rate())
+            };
+            ui.horizontal(|ui| {
+                self.scope(ui, &samples);
+                self.spectrum(ui, &samples, sample_rate);
+            });
+        });
+
+        // Keep the scope moving
+        ctx.request_repaint();
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.engine.lock().unwrap().synth.all_notes_off();
+        self.output.fade_out();
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let config = Config::load().unwrap_or_else(|err| {
+        eprintln!("Warning: ignoring config file: {}", err);
+        Config::default()
+    });
+    if let Some(a4) = config.a4 {
+        synth_data::set_a4(a4);
+    }
+    let app = SynthApp::new(&config)?;
+
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([900.0, 640.0]),
+        ..Default::default()
+    };
+    eframe::run_native("FM Synthesizer", options, Box::new(|_cc| Ok(Box::new(app))))
+        .map_err(|err| anyhow::anyhow!("{}", err))
+}
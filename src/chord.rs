@@ -0,0 +1,126 @@
+// src/chord.rs - Chord symbol library
+
+use crate::synth_data::midi_to_freq;
+
+/// Chord qualities by suffix, as semitone intervals above the root
+const QUALITIES: [(&str, &[i32]); 27] = [
+    ("maj13", &[0, 4, 7, 11, 14, 21]),
+    ("maj9", &[0, 4, 7, 11, 14]),
+    ("maj7", &[0, 4, 7, 11]),
+    ("m7b5", &[0, 3, 6, 10]),
+    ("dim7", &[0, 3, 6, 9]),
+    ("min7", &[0, 3, 7, 10]),
+    ("aug7", &[0, 4, 8, 10]),
+    ("sus2", &[0, 2, 7]),
+    ("sus4", &[0, 5, 7]),
+    ("add9", &[0, 4, 7, 14]),
+    ("7sus4", &[0, 5, 7, 10]),
+    ("mMaj7", &[0, 3, 7, 11]),
+    ("maj", &[0, 4, 7]),
+    ("min", &[0, 3, 7]),
+    ("dim", &[0, 3, 6]),
+    ("aug", &[0, 4, 8]),
+    ("sus", &[0, 5, 7]),
+    ("M7", &[0, 4, 7, 11]),
+    ("m7", &[0, 3, 7, 10]),
+    ("m9", &[0, 3, 7, 10, 14]),
+    ("m6", &[0, 3, 7, 9]),
+    ("13", &[0, 4, 7, 10, 14, 21]),
+    ("11", &[0, 4, 7, 10, 14, 17]),
+    ("9", &[0, 4, 7, 10, 14]),
+    ("7", &[0, 4, 7, 10]),
+    ("6", &[0, 4, 7, 9]),
+    ("5", &[0, 7]),
+];
+
+/// Short spellings mapped onto the names above
+const ALIASES: [(&str, &str); 8] = [
+    ("", "maj"),
+    ("M", "maj"),
+    ("m", "min"),
+    ("-", "min"),
+    ("+", "aug"),
+    ("o", "dim"),
+    ("o7", "dim7"),
+    ("ø", "m7b5"),
+];
+
+/// Semitone of a note letter above C
+fn letter_semitone(letter: char) -> Option<i32> {
+    match letter.to_ascii_uppercase() {
+        'C' => Some(0),
+        'D' => Some(2),
+        'E' => Some(4),
+        'F' => Some(5),
+        'G' => Some(7),
+        'A' => Some(9),
+        'B' => Some(11),
+        _ => None,
+    }
+}
+
+/// Split a leading note name (`C`, `F#`, `Bb`) off `s`, returning its
+/// semitone above C and the rest of the string
+fn parse_root(s: &str) -> Option<(i32, &str)> {
+    let mut chars = s.chars();
+    let mut semitone = letter_semitone(chars.next()?)?;
+    let rest = chars.as_str();
+    let rest = if let Some(rest) = rest.strip_prefix('#') {
+        semitone += 1;
+        rest
+    } else if let Some(rest) = rest.strip_prefix('b') {
+        semitone -= 1;
+        rest
+    } else {
+        rest
+    };
+    Some((semitone, rest))
+}
+
+/// Intervals for a quality suffix such as `maj7`, `m`, or `sus4`
+pub fn quality_intervals(suffix: &str) -> Option<&'static [i32]> {
+    let name = ALIASES.iter()
+        .find(|(alias, _)| *alias == suffix)
+        .map_or(suffix, |(_, name)| *name);
+    QUALITIES.iter().find(|(q, _)| *q == name).map(|(_, intervals)| *intervals)
+}
+
+/// MIDI notes of a chord symbol such as `Cmaj7`, `F#m`, `Bb7`, or `C/E`,
+/// voiced upwards from the root in `octave` (4 = middle C). A slash bass is
+/// placed below the chord.
+pub fn chord_notes(symbol: &str, octave: i32) -> Option<Vec<i32>> {
+    let (chord, bass) = match symbol.split_once('/') {
+        Some((chord, bass)) => (chord, Some(bass)),
+        None => (symbol, None),
+    };
+
+    let (root, suffix) = parse_root(chord.trim())?;
+    let intervals = quality_intervals(suffix)?;
+    let root_note = 12 * (octave + 1) + root;
+    let mut notes: Vec<i32> = intervals.iter().map(|i| root_note + i).collect();
+
+    if let Some(bass) = bass {
+        let (bass, rest) = parse_root(bass.trim())?;
+        if !rest.is_empty() {
+            return None;
+        }
+        // Highest matching pitch class below the root
+        let mut bass_note = 12 * (octave + 1) + bass;
+        while bass_note >= root_note {
+            bass_note -= 12;
+        }
+        notes.retain(|n| (n - bass_note) % 12 != 0);
+        notes.insert(0, bass_note);
+    }
+    Some(notes)
+}
+
+/// Frequencies of a chord symbol, as for `chord_notes`
+pub fn chord_freqs(symbol: &str, octave: i32) -> Option<Vec<f32>> {
+    chord_notes(symbol, octave).map(|notes| notes.into_iter().map(midi_to_freq).collect())
+}
+
+/// Quality suffixes `chord_notes` understands, for help text
+pub fn quality_names() -> Vec<&'static str> {
+    QUALITIES.iter().map(|(q, _)| *q).collect()
+}
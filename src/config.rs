@@ -0,0 +1,299 @@
+// src/config.rs - User configuration and preset/melody files
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{ChannelMap, OutputConfig};
+use crate::macros::MAX_MACROS;
+use crate::sequencer::{TempoMap, TempoPoint};
+use crate::synth_core::FMParams;
+use crate::synth_data::PresetMeta;
+
+/// Defaults loaded from `config.toml`; command-line options take precedence
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Audio host, as for `--host`
+    pub host: Option<String>,
+    /// Output device, as for `--device`
+    pub device: Option<String>,
+    /// Stream sample rate in Hz, as for `--sample-rate`
+    pub sample_rate: Option<u32>,
+    /// Fixed rate the synth runs at, as for `--engine-rate`
+    pub engine_rate: Option<u32>,
+    /// Buffer size in frames, as for `--buffer-size`
+    pub buffer_size: Option<u32>,
+    /// Realtime priority for the audio thread, as for `--realtime`
+    pub realtime: Option<bool>,
+    /// Master volume (0.0 - 1.0), as for `--volume`
+    pub volume: Option<f32>,
+    /// Device channels for each output, as for `--channel-map`
+    pub channel_map: Option<String>,
+    /// Preset loaded as the active patch at startup
+    pub preset: Option<String>,
+    /// Directory of extra `*.toml` preset files
+    pub preset_dir: Option<PathBuf>,
+    /// Directory of extra `*.toml` melody files
+    pub melody_dir: Option<PathBuf>,
+    /// MIDI input port, by name
+    pub midi_port: Option<String>,
+    /// Audio input device `input on` opens, by name (default: the host's
+    /// default input)
+    pub input_device: Option<String>,
+    /// Apply presets' reverb and stereo effects (default on)
+    pub effects: Option<bool>,
+    /// Concert pitch in Hz (default 440), as for `--a4`
+    pub a4: Option<f32>,
+    /// Play presets loudness-matched, as for `loudness` (default off)
+    pub loudness_match: Option<bool>,
+    /// Gate (0.01 - 1.0) unmarked melody notes are held for, as for `gate`,
+    /// in place of the presets' own
+    pub gate: Option<f32>,
+    /// Voices of each output's polyphonic engine, as for `--voices`
+    pub voices: Option<usize>,
+    /// Sine lookup table in place of `f32::sin`, as for `--sine-table`
+    /// (default off)
+    pub sine_table: Option<bool>,
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/fm_synth/config.toml`, normally `~/.config/fm_synth/config.toml`
+    pub fn path() -> Option<PathBuf> {
+        let base = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => home_dir()?.join(".config"),
+        };
+        Some(base.join("fm_synth").join("config.toml"))
+    }
+
+    /// Load the user's config file; a missing file gives the defaults
+    pub fn load() -> anyhow::Result<Self> {
+        match Self::path() {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))
+    }
+
+    /// Where preset files are loaded from and saved to: `preset_dir`, else
+    /// `presets` next to the config file
+    pub fn preset_dir(&self) -> Option<PathBuf> {
+        match &self.preset_dir {
+            Some(dir) => Some(expand_home(dir)),
+            None => Some(Self::path()?.parent()?.join("presets")),
+        }
+    }
+
+    /// Output settings from the config, before command-line overrides
+    pub fn output(&self) -> OutputConfig {
+        OutputConfig {
+            host: self.host.clone(),
+            device: self.device.clone(),
+            sample_rate: self.sample_rate,
+            engine_rate: self.engine_rate,
+            buffer_size: self.buffer_size,
+            volume: self.volume,
+            channel_map: self.channel_map.as_deref().and_then(|map| {
+                let parsed = ChannelMap::parse(map);
+                if parsed.is_none() {
+                    eprintln!("Warning: ignoring invalid channel_map '{}'", map);
+                }
+                parsed
+            }),
+            realtime: self.realtime.unwrap_or(false),
+        }
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+/// Expand a leading `~` to the home directory
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// A preset file: an optional display name, `PresetMeta` fields, and
+/// `FMParams` fields
+#[derive(Serialize, Deserialize)]
+struct PresetFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(flatten)]
+    meta: PresetMeta,
+    #[serde(flatten)]
+    params: FMParams,
+}
+
+/// A melody file: `notes = [["C4", 400], ["REST", 200], ...]` with durations
+/// in ms, and optionally a tempo map such as
+/// `tempo = [{ bar = 7, bpm = 120 }, { bar = 9, bpm = 80, ramp = true }]`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MelodyFile {
+    name: Option<String>,
+    notes: MelodyNotes,
+    #[serde(default)]
+    tempo: Vec<TempoPoint>,
+}
+
+/// Melody notes with durations in ms, with owned note names
+pub type MelodyNotes = Vec<(String, u64)>;
+
+/// `*.toml` files in `dir`, sorted by name
+fn toml_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(expand_home(dir))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Name from the file's `name` field, else its file stem
+fn file_name(name: Option<String>, path: &Path) -> String {
+    name.unwrap_or_else(|| path.file_stem().unwrap_or_default().to_string_lossy().into_owned())
+}
+
+/// Load every preset file in `dir`. Files that fail to parse are reported
+/// and skipped so one bad file doesn't hide the rest.
+pub fn load_presets(dir: &Path) -> anyhow::Result<Vec<(String, FMParams, PresetMeta)>> {
+    let mut presets = Vec::new();
+    for path in toml_files(dir)? {
+        let parsed = std::fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| Ok(toml::from_str::<PresetFile>(&text)?));
+        match parsed {
+            Ok(mut file) => {
+                if file.meta.macros.len() > MAX_MACROS {
+                    eprintln!("Warning: preset {} has more than {} macros; using the first {}", path.display(), MAX_MACROS, MAX_MACROS);
+                    file.meta.macros.truncate(MAX_MACROS);
+                }
+                presets.push((file_name(file.name, &path), file.params, file.meta))
+            }
+            Err(err) => eprintln!("Warning: skipping preset {}: {}", path.display(), err),
+        }
+    }
+    Ok(presets)
+}
+
+/// Load every melody file in `dir`, with its tempo map if it has one,
+/// skipping (and reporting) bad files
+pub fn load_melodies(dir: &Path) -> anyhow::Result<Vec<(String, MelodyNotes, Option<TempoMap>)>> {
+    let mut melodies = Vec::new();
+    for path in toml_files(dir)? {
+        match load_melody(&path) {
+            Ok(melody) => melodies.push(melody),
+            Err(err) => eprintln!("Warning: skipping melody {}: {}", path.display(), err),
+        }
+    }
+    Ok(melodies)
+}
+
+/// Load one melody file, whatever its extension, with its tempo map if it
+/// has one
+pub fn load_melody(path: &Path) -> anyhow::Result<(String, MelodyNotes, Option<TempoMap>)> {
+    let file: MelodyFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+    Ok((file_name(file.name, path), file.notes, TempoMap::new(file.tempo)))
+}
+
+/// Write `params` and `meta` as a preset file named after `name` in `dir`
+/// (created if needed), returning the file's path
+pub fn save_preset(dir: &Path, name: &str, params: &FMParams, meta: &PresetMeta) -> anyhow::Result<PathBuf> {
+    let dir = expand_home(dir);
+    std::fs::create_dir_all(&dir)?;
+    let stem: String = name.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    let path = dir.join(format!("{}.toml", stem));
+    let file = PresetFile { name: Some(name.trim().to_string()), meta: meta.clone(), params: params.clone() };
+    let mut value = toml::Value::try_from(&file)?;
+    tidy_floats(&mut value);
+    std::fs::write(&path, toml::to_string(&value)?)?;
+    Ok(path)
+}
+
+/// Start of an exported preset string, versioning the encoding
+const EXPORT_PREFIX: &str = "fm1.";
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encode a preset as a short URL-safe string that can be pasted into chat
+/// or an issue: its preset file, minus fields left at their defaults, in
+/// unpadded base64url
+pub fn export_preset(name: &str, params: &FMParams, meta: &PresetMeta) -> anyhow::Result<String> {
+    let file = PresetFile { name: Some(name.trim().to_string()), meta: meta.clone(), params: params.clone() };
+    let defaults = toml::Value::try_from(PresetFile { name: None, meta: PresetMeta::default(), params: FMParams::default() })?;
+    let mut value = toml::Value::try_from(&file)?;
+    if let (toml::Value::Table(table), toml::Value::Table(defaults)) = (&mut value, &defaults) {
+        table.retain(|key, v| defaults.get(key) != Some(v));
+    }
+    tidy_floats(&mut value);
+    Ok(format!("{}{}", EXPORT_PREFIX, base64_encode(toml::to_string(&value)?.as_bytes())))
+}
+
+/// Decode a string from `export_preset` into its name, parameters, and
+/// metadata. Whitespace is ignored, so line-wrapped pastes still work.
+pub fn import_preset(text: &str) -> anyhow::Result<(String, FMParams, PresetMeta)> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let Some(encoded) = text.strip_prefix(EXPORT_PREFIX) else {
+        anyhow::bail!("not an exported preset (expected it to start with '{}')", EXPORT_PREFIX);
+    };
+    let bytes = base64_decode(encoded).ok_or_else(|| anyhow::anyhow!("preset string is damaged"))?;
+    let file: PresetFile = toml::from_str(std::str::from_utf8(&bytes)?)?;
+    Ok((file.name.unwrap_or_else(|| "Imported".to_string()), file.params, file.meta))
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        // n bytes carry n + 1 six-bit digits
+        for i in 0..=chunk.len() {
+            out.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+    }
+    out
+}
+
+/// `None` if `text` has characters outside the alphabet or a stray digit
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u32> = text.bytes()
+        .map(|c| BASE64_ALPHABET.iter().position(|&a| a == c).map(|d| d as u32))
+        .collect::<Option<_>>()?;
+    let mut out = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        if chunk.len() < 2 {
+            return None;
+        }
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &d)| bits | d << (18 - 6 * i));
+        for i in 0..chunk.len() - 1 {
+            out.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Parameters are `f32`, so write the shortest decimal that reads back the
+/// same `f32` (0.3 rather than 0.30000001192092896)
+fn tidy_floats(value: &mut toml::Value) {
+    match value {
+        toml::Value::Float(f) => *f = (*f as f32).to_string().parse().unwrap_or(*f),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, v)| tidy_floats(v)),
+        toml::Value::Array(items) => items.iter_mut().for_each(tidy_floats),
+        _ => {}
+    }
+}
@@ -0,0 +1,379 @@
+// src/drums.rs - FM drum machine: kick, snare, hat, and tom voices and step patterns
+//
+// Each drum is a two-operator FM voice whose pitch falls from a click to
+// its body (the pitch envelope), mixed with high-passed noise, both
+// decaying exponentially: the recipe behind most FM drum machines. A
+// `Pattern` is a grid of steps the sequencer plays from its own score
+// position (`Sequence::set_drums`), so drums follow the transport: pause,
+// seek, tempo changes, and loops.
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use crate::rng::Rng;
+use crate::synth_core::SineTable;
+use crate::synth_data::MELODY_BPM;
+
+/// Drums in a kit, in the order of a pattern's rows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrumKind {
+    Kick,
+    Snare,
+    Hat,
+    Tom,
+}
+
+impl DrumKind {
+    pub const ALL: [DrumKind; 4] = [DrumKind::Kick, DrumKind::Snare, DrumKind::Hat, DrumKind::Tom];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DrumKind::Kick => "kick",
+            DrumKind::Snare => "snare",
+            DrumKind::Hat => "hat",
+            DrumKind::Tom => "tom",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == s.to_lowercase())
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// How a drum sounds. Times are decay time constants in seconds: the
+/// level falls to about a third in that time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrumSound {
+    /// Pitch at the strike, falling to `freq`
+    pub start_freq: f32,
+    pub freq: f32,
+    pub pitch_decay: f32,
+    /// Modulator frequency over the carrier's
+    pub ratio: f32,
+    pub index: f32,
+    pub index_decay: f32,
+    pub tone_decay: f32,
+    /// Share of noise in the mix (0.0 - 1.0)
+    pub noise: f32,
+    pub noise_decay: f32,
+    /// High-pass cutoff of the noise (Hz)
+    pub noise_cutoff: f32,
+    pub level: f32,
+}
+
+impl DrumSound {
+    /// The built-in sound of a drum
+    pub fn preset(kind: DrumKind) -> Self {
+        match kind {
+            DrumKind::Kick => Self {
+                start_freq: 180.0,
+                freq: 48.0,
+                pitch_decay: 0.03,
+                ratio: 1.0,
+                index: 2.0,
+                index_decay: 0.01,
+                tone_decay: 0.25,
+                noise: 0.05,
+                noise_decay: 0.005,
+                noise_cutoff: 2000.0,
+                level: 0.7,
+            },
+            DrumKind::Snare => Self {
+                start_freq: 320.0,
+                freq: 190.0,
+                pitch_decay: 0.015,
+                ratio: 1.47,
+                index: 1.5,
+                index_decay: 0.03,
+                tone_decay: 0.08,
+                noise: 0.65,
+                noise_decay: 0.12,
+                noise_cutoff: 1200.0,
+                level: 0.5,
+            },
+            DrumKind::Hat => Self {
+                start_freq: 820.0,
+                freq: 820.0,
+                pitch_decay: 0.01,
+                // Inharmonic, for a metallic ring
+                ratio: 2.83,
+                index: 6.0,
+                index_decay: 0.05,
+                tone_decay: 0.03,
+                noise: 0.7,
+                noise_decay: 0.035,
+                noise_cutoff: 7000.0,
+                level: 0.3,
+            },
+            DrumKind::Tom => Self {
+                start_freq: 260.0,
+                freq: 130.0,
+                pitch_decay: 0.06,
+                ratio: 1.0,
+                index: 1.2,
+                index_decay: 0.04,
+                tone_decay: 0.3,
+                noise: 0.08,
+                noise_decay: 0.01,
+                noise_cutoff: 2000.0,
+                level: 0.45,
+            },
+        }
+    }
+
+    /// Time after which the drum is inaudible (about -60 dB)
+    fn length(&self) -> f32 {
+        7.0 * self.tone_decay.max(self.noise_decay)
+    }
+}
+
+/// How long the longest built-in drum rings, for render tails
+pub fn tail() -> Duration {
+    let longest = DrumKind::ALL.map(|kind| DrumSound::preset(kind).length()).into_iter().fold(0.0, f32::max);
+    Duration::from_secs_f32(longest)
+}
+
+/// A sounding drum hit
+struct Hit {
+    kind: DrumKind,
+    sound: DrumSound,
+    velocity: f32,
+    /// Seconds since the strike
+    time: f32,
+    carrier_phase: f32,
+    modulator_phase: f32,
+    /// Last noise sample and output of the high-pass filter
+    noise_in: f32,
+    noise_out: f32,
+}
+
+/// Most hits sounding at once; the oldest is cut off past this
+const MAX_HITS: usize = 16;
+
+/// The drum engine: plays hits of a kit's sounds, each ringing out on its
+/// own. A new hat chokes the one before, as a closed hi-hat does.
+pub struct Drums {
+    sample_rate: f32,
+    kit: [DrumSound; 4],
+    hits: Vec<Hit>,
+    sine: SineTable,
+    rng: Rng,
+}
+
+/// Seed of the noise, so renders with drums stay reproducible
+const NOISE_SEED: u64 = 0xD2_0B5;
+
+impl Drums {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            sample_rate,
+            kit: DrumKind::ALL.map(DrumSound::preset),
+            hits: Vec::with_capacity(MAX_HITS),
+            sine: SineTable::default(),
+            rng: Rng::new(NOISE_SEED),
+        }
+    }
+
+    /// Change the sound of one drum, for hits from now on
+    pub fn set_sound(&mut self, kind: DrumKind, sound: DrumSound) {
+        self.kit[kind.index()] = sound;
+    }
+
+    pub fn sound(&self, kind: DrumKind) -> DrumSound {
+        self.kit[kind.index()]
+    }
+
+    /// Strike a drum at `velocity` (0.0 - 1.0)
+    pub fn hit(&mut self, kind: DrumKind, velocity: f32) {
+        if kind == DrumKind::Hat {
+            self.hits.retain(|hit| hit.kind != DrumKind::Hat);
+        }
+        if self.hits.len() >= MAX_HITS {
+            self.hits.remove(0);
+        }
+        self.hits.push(Hit {
+            kind,
+            sound: self.kit[kind.index()],
+            velocity: velocity.clamp(0.0, 1.0),
+            time: 0.0,
+            carrier_phase: 0.0,
+            modulator_phase: 0.0,
+            noise_in: 0.0,
+            noise_out: 0.0,
+        });
+    }
+
+    /// Cut every hit off
+    pub fn silence(&mut self) {
+        self.hits.clear();
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.hits.is_empty()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let dt = 1.0 / self.sample_rate;
+        let mut out = 0.0;
+        for hit in &mut self.hits {
+            let s = &hit.sound;
+            let t = hit.time;
+            let freq = s.freq + (s.start_freq - s.freq) * (-t / s.pitch_decay).exp();
+            let index = s.index * (-t / s.index_decay).exp();
+            let modulator = index * self.sine.lookup(hit.modulator_phase);
+            let tone = self.sine.lookup(hit.carrier_phase + modulator / (2.0 * PI)) * (-t / s.tone_decay).exp();
+            hit.carrier_phase = (hit.carrier_phase + freq * dt).fract();
+            hit.modulator_phase = (hit.modulator_phase + freq * s.ratio * dt).fract();
+
+            // One-pole high-pass over white noise
+            let white = self.rng.next_bipolar();
+            let rc = 1.0 / (2.0 * PI * s.noise_cutoff);
+            let alpha = rc / (rc + dt);
+            hit.noise_out = alpha * (hit.noise_out + white - hit.noise_in);
+            hit.noise_in = white;
+            let noise = hit.noise_out * (-t / s.noise_decay).exp();
+
+            out += s.level * hit.velocity * (tone * (1.0 - s.noise) + noise * s.noise);
+            hit.time += dt;
+        }
+        self.hits.retain(|hit| hit.time < hit.sound.length());
+        out
+    }
+}
+
+/// Steps per beat of a pattern: sixteenth notes
+pub const STEPS_PER_BEAT: usize = 4;
+
+/// Velocity of an accented step (`X`) and a normal one (`x`); a ghost
+/// note (`o`) is quieter still
+const ACCENT: f32 = 1.0;
+const NORMAL: f32 = 0.7;
+const GHOST: f32 = 0.35;
+
+/// A drum pattern: a row of steps (sixteenth notes at the written tempo)
+/// for each drum, with the velocity of each hit, repeating every `steps`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    rows: [Vec<f32>; 4],
+    steps: usize,
+}
+
+impl Pattern {
+    /// A pattern from one grid per drum: `x` a hit, `X` an accent, `o` a
+    /// ghost note, and `.` or `-` a rest, with `|` and spaces ignored, e.g.
+    /// `x...x...|x...x...` for the kick. Rows may differ in length; each
+    /// repeats over the longest, which sets the pattern's length. `None`
+    /// if a grid has anything else in it or every grid is empty.
+    pub fn parse(rows: &[(DrumKind, &str)]) -> Option<Self> {
+        let mut grids: [Vec<f32>; 4] = Default::default();
+        for &(kind, grid) in rows {
+            grids[kind.index()] = grid.chars()
+                .filter(|c| !matches!(c, '|' | ' '))
+                .map(|c| match c {
+                    'X' => Some(ACCENT),
+                    'x' => Some(NORMAL),
+                    'o' => Some(GHOST),
+                    '.' | '-' => Some(0.0),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?;
+        }
+        let steps = grids.iter().map(Vec::len).max().unwrap_or(0);
+        (steps > 0).then_some(Self { rows: grids, steps })
+    }
+
+    /// Length of the pattern in steps
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Length of the pattern at the written tempo
+    pub fn length(&self) -> Duration {
+        step_length() * self.steps as u32
+    }
+
+    /// Drums struck on step `step` (counting from 0, wrapping round) and
+    /// their velocities
+    pub fn hits(&self, step: usize) -> impl Iterator<Item = (DrumKind, f32)> + '_ {
+        DrumKind::ALL.into_iter().filter_map(move |kind| {
+            let row = &self.rows[kind.index()];
+            let velocity = *row.get(step % row.len().max(1))?;
+            (velocity > 0.0).then_some((kind, velocity))
+        })
+    }
+
+    /// The pattern as grids, one line per drum that plays
+    pub fn grid(&self) -> Vec<(DrumKind, String)> {
+        DrumKind::ALL.into_iter()
+            .filter(|kind| self.rows[kind.index()].iter().any(|&v| v > 0.0))
+            .map(|kind| {
+                let row = &self.rows[kind.index()];
+                let grid = (0..self.steps)
+                    .map(|step| {
+                        let bar = if step > 0 && step % (4 * STEPS_PER_BEAT) == 0 { "|" } else { "" };
+                        let cell = match row[step % row.len()] {
+                            v if v >= ACCENT => 'X',
+                            v if v >= NORMAL => 'x',
+                            v if v > 0.0 => 'o',
+                            _ => '.',
+                        };
+                        format!("{}{}", bar, cell)
+                    })
+                    .collect();
+                (kind, grid)
+            })
+            .collect()
+    }
+}
+
+/// A step (sixteenth note) at the written tempo
+pub fn step_length() -> Duration {
+    Duration::from_secs_f32(60.0 / MELODY_BPM / STEPS_PER_BEAT as f32)
+}
+
+/// Built-in patterns, one bar each unless noted
+pub fn get_patterns() -> Vec<(&'static str, Pattern)> {
+    use DrumKind::*;
+    let pattern = |rows: &[(DrumKind, &str)]| Pattern::parse(rows).expect("built-in pattern");
+    vec![
+        ("Rock", pattern(&[
+            (Kick, "x.......x.x....."),
+            (Snare, "....X.......X..."),
+            (Hat, "x.x.x.x.x.x.x.x."),
+        ])),
+        ("Four on the Floor", pattern(&[
+            (Kick, "X...x...X...x..."),
+            (Snare, "....x.......x..."),
+            (Hat, "..x...x...x...x."),
+        ])),
+        ("Breakbeat", pattern(&[
+            (Kick, "x.........x.....|..x.......x....."),
+            (Snare, "....X..o.o..X...|....X..o....X..o"),
+            (Hat, "x.x.x.x.x.x.x.x.|x.x.x.x.x.x.x.xx"),
+        ])),
+        ("Half Time", pattern(&[
+            (Kick, "x......x..x....."),
+            (Snare, "........X......."),
+            (Hat, "x.xox.xox.xox.xo"),
+        ])),
+        ("Shuffle", pattern(&[
+            (Kick, "x.....x.x......."),
+            (Snare, "....X.......X..o"),
+            (Hat, "x..xx..xx..xx..x"),
+        ])),
+        ("Tom Fill", pattern(&[
+            (Kick, "x...............|x..............."),
+            (Snare, "................|X.x.x.x........."),
+            (Hat, "x.x.x.x.x.x.x.x.|................"),
+            (Tom, "................|........XxX.x.xX"),
+        ])),
+    ]
+}
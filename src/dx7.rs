@@ -0,0 +1,243 @@
+// src/dx7.rs - DX7 single-voice SysEx dumps for real DX7 and Volca FM hardware
+
+use std::f32::consts::PI;
+use std::io;
+
+use crate::operator::{Operator, RateLevel, MAX_RATE_LEVEL};
+use crate::synth_core::{FMParams, REFERENCE_FREQ};
+
+/// Length of a single-voice dump: 6-byte header, 155 voice bytes, checksum,
+/// and end of exclusive
+pub const VOICE_DUMP_LEN: usize = 163;
+
+/// Longest voice name the DX7 shows
+pub const NAME_LEN: usize = 10;
+
+/// Operators in a DX7 voice
+const OPERATORS: usize = 6;
+
+/// Bytes per operator in a voice dump
+const OPERATOR_LEN: usize = 21;
+
+/// Phase modulation index of a modulator at output level 99
+const MAX_PM_INDEX: f32 = 4.0 * PI;
+
+/// The DX7 operator the bottom of a stack is written to: algorithm 1 has a
+/// four-operator serial stack from operator 6 down to carrier 3
+const STACK_CARRIER: usize = 3;
+
+/// Longest stack a voice dump holds
+pub const MAX_STACK: usize = 4;
+
+/// Algorithms with the serial stack from operator 6 down to carrier 3 (2
+/// differs from 1 only in its feedback, on operator 2)
+const STACK_ALGORITHMS: [u8; 2] = [0, 1];
+
+/// A voice read from a SysEx dump by `parse_voice`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Voice {
+    pub name: String,
+    /// The serial stack, carrier first, each operator with its rate/level
+    /// envelope
+    pub operators: Vec<Operator>,
+    /// Whether oscillators restart at each key press
+    pub key_sync: bool,
+}
+
+impl Voice {
+    /// The stack for an `FMVoice<MAX_STACK>`, silent operators filling the
+    /// top
+    pub fn stack(&self) -> [Operator; MAX_STACK] {
+        let mut stack = [Operator::default(); MAX_STACK];
+        stack.iter_mut().zip(&self.operators).for_each(|(slot, op)| *slot = *op);
+        stack
+    }
+
+    /// The voice as a preset: the carrier and the operator above it, their
+    /// rate/level envelopes becoming `carrier_env` and `modulator_env`.
+    /// Operators higher up the stack don't fit a two-operator preset and
+    /// are left out.
+    pub fn params(&self) -> FMParams {
+        let carrier = self.operators.first().copied().unwrap_or_default();
+        let modulator = self.operators.get(1).copied().unwrap_or(Operator { ratio: carrier.ratio, ..Operator::default() });
+        let mut params = FMParams {
+            carrier_freq: REFERENCE_FREQ * carrier.ratio,
+            modulator_freq: REFERENCE_FREQ * modulator.ratio,
+            modulation_index: modulator.level,
+            phase_reset: self.key_sync,
+            carrier_env: carrier.envelope,
+            modulator_env: modulator.envelope,
+            ..FMParams::default()
+        };
+        params.set_gain(carrier.level);
+        params.clamped()
+    }
+}
+
+/// A voice dump of a patch's carrier and modulator. The patch's
+/// `carrier_env` and `modulator_env` are written as they are; without them
+/// its ADSR becomes the carrier's rate/level envelope and, when `index_env`
+/// is on, the index envelope the modulator's. Its frequency ratio, modulation
+/// index, level, key sync (`phase_reset`), and velocity sensitivity are
+/// kept. Waveforms, the LFO, the sub-oscillator, key scaling, and the
+/// effects have no DX7 equivalent and are dropped.
+pub fn voice_sysex(name: &str, params: &FMParams) -> Vec<u8> {
+    let carrier_env = params
+        .carrier_env
+        .unwrap_or_else(|| RateLevel::from_adsr(params.attack, params.decay, params.sustain, params.release));
+    let modulator_env = match params.modulator_env {
+        Some(envelope) => envelope,
+        None if params.index_env => {
+            RateLevel::from_adsr(params.index_attack, params.index_decay, params.index_sustain, params.index_release)
+        }
+        None => RateLevel { rates: [MAX_RATE_LEVEL; 4], levels: [MAX_RATE_LEVEL; 4] },
+    };
+    let operators = [
+        Operator { ratio: 1.0, level: params.gain(), envelope: Some(carrier_env) },
+        Operator {
+            ratio: params.modulator_freq / params.carrier_freq,
+            level: params.modulation_index,
+            envelope: Some(modulator_env),
+        },
+    ];
+    let velocity = (params.velocity_index.clamp(0.0, 1.0) * 7.0).round() as u8;
+    dump(name, &operators, params.phase_reset, velocity)
+}
+
+/// A voice dump of an `FMVoice` stack (`FMVoice::operators`). Stacks longer
+/// than the DX7's four-operator stack keep the carrier and the three
+/// operators above it. Operators without an envelope hold their level, as
+/// the DX7's initial voice does.
+pub fn stack_sysex(name: &str, operators: &[Operator]) -> Vec<u8> {
+    dump(name, &operators[..operators.len().min(MAX_STACK)], true, 0)
+}
+
+/// Read a DX7 single-voice dump, the inverse of `voice_sysex` and
+/// `stack_sysex`: the stack from operator 6 down to carrier 3 of algorithm
+/// 1 or 2, with each operator's ratio, output level, and rate/level
+/// envelope. Silent operators at the top of the stack are dropped, as are
+/// operators 1 and 2, feedback, the pitch envelope, the LFO, and keyboard
+/// scaling. Other algorithms and fixed-frequency operators are refused.
+pub fn parse_voice(sysex: &[u8]) -> io::Result<Voice> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    if sysex.len() != VOICE_DUMP_LEN
+        || sysex[..2] != [0xF0, 0x43]
+        || sysex[2] & 0xF0 != 0
+        || sysex[3..6] != [0x00, 0x01, 0x1B]
+        || sysex[VOICE_DUMP_LEN - 1] != 0xF7
+    {
+        return Err(invalid("not a DX7 single-voice dump".to_string()));
+    }
+    let voice = &sysex[6..VOICE_DUMP_LEN - 2];
+    let checksum = voice.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)).wrapping_neg() & 0x7F;
+    if checksum != sysex[VOICE_DUMP_LEN - 2] {
+        return Err(invalid("voice dump checksum doesn't match".to_string()));
+    }
+    let global = &voice[OPERATORS * OPERATOR_LEN..];
+    if !STACK_ALGORITHMS.contains(&global[8]) {
+        return Err(invalid(format!("algorithm {} isn't supported (only 1 and 2)", global[8] + 1)));
+    }
+
+    let mut stack: Vec<(Operator, u8)> = Vec::new();
+    for dx7_op in STACK_CARRIER..=OPERATORS {
+        let bytes = &voice[(OPERATORS - dx7_op) * OPERATOR_LEN..][..OPERATOR_LEN];
+        if bytes[17] != 0 {
+            return Err(invalid(format!("operator {} has a fixed frequency", dx7_op)));
+        }
+        let base = if bytes[18] == 0 { 0.5 } else { bytes[18] as f32 };
+        let envelope = RateLevel {
+            rates: [bytes[0], bytes[1], bytes[2], bytes[3]].map(|r| r.min(MAX_RATE_LEVEL)),
+            levels: [bytes[4], bytes[5], bytes[6], bytes[7]].map(|l| l.min(MAX_RATE_LEVEL)),
+        };
+        let op = Operator { ratio: base * (1.0 + bytes[19].min(MAX_RATE_LEVEL) as f32 / 100.0), level: 0.0, envelope: Some(envelope) };
+        stack.push((op, bytes[16].min(MAX_RATE_LEVEL)));
+    }
+    // A modulator at output level 0 adds nothing, nor does anything above it
+    let used = stack.iter().skip(1).position(|&(_, output)| output == 0).map_or(stack.len(), |i| i + 1);
+    let mut operators: Vec<Operator> = Vec::with_capacity(used);
+    for (i, &(mut op, output)) in stack[..used].iter().enumerate() {
+        let gain = RateLevel::gain(output as f32);
+        // Undo the conversion from frequency to phase deviation in `dump`
+        op.level = match i {
+            0 => gain,
+            _ => gain * MAX_PM_INDEX * op.ratio / operators[i - 1].ratio,
+        };
+        operators.push(op);
+    }
+    let name = String::from_utf8_lossy(&global[19..19 + NAME_LEN]).trim_end().to_string();
+    Ok(Voice { name, operators, key_sync: global[10] != 0 })
+}
+
+/// Coarse (0 meaning 0.5) and fine settings giving the ratio closest to
+/// `ratio`, which the DX7 limits to 0.5–61.69
+fn coarse_fine(ratio: f32) -> (u8, u8) {
+    let mut best = (1, 0, f32::INFINITY);
+    for coarse in 0..=31u8 {
+        let base = if coarse == 0 { 0.5 } else { coarse as f32 };
+        for fine in 0..=MAX_RATE_LEVEL {
+            let error = (base * (1.0 + fine as f32 / 100.0) / ratio).ln().abs();
+            if error < best.2 {
+                best = (coarse, fine, error);
+            }
+        }
+    }
+    (best.0, best.1)
+}
+
+/// Write a serial stack (carrier first) into algorithm 1 and wrap it in
+/// SysEx
+fn dump(name: &str, stack: &[Operator], key_sync: bool, velocity: u8) -> Vec<u8> {
+    let mut voice = [0u8; OPERATORS * OPERATOR_LEN + 29];
+    // Operators 1 and 2 and any unused stack operators stay silent
+    for dx7_op in 1..=OPERATORS {
+        let index = dx7_op.checked_sub(STACK_CARRIER).filter(|&i| i < stack.len());
+        let op = index.map(|i| stack[i]).unwrap_or_default();
+        let envelope = op.envelope.unwrap_or_default();
+        let output = match index {
+            None => 0,
+            Some(0) => RateLevel::level_for_gain(op.level),
+            // The crate's index deviates the frequency of the operator below
+            // by `level` times its own; the DX7 deviates its phase instead
+            Some(i) => {
+                let pm_index = op.level * stack[i - 1].ratio / op.ratio.max(f32::EPSILON);
+                RateLevel::level_for_gain(pm_index / MAX_PM_INDEX)
+            }
+        };
+        let (coarse, fine) = coarse_fine(op.ratio.max(f32::EPSILON));
+        // Dumps list operator 6 first
+        let bytes = &mut voice[(OPERATORS - dx7_op) * OPERATOR_LEN..][..OPERATOR_LEN];
+        bytes[..4].copy_from_slice(&envelope.rates.map(|r| r.min(MAX_RATE_LEVEL)));
+        bytes[4..8].copy_from_slice(&envelope.levels.map(|l| l.min(MAX_RATE_LEVEL)));
+        bytes[8] = 39; // Keyboard level scaling break point (C3), with no scaling
+        bytes[15] = if index.is_some_and(|i| i > 0) { velocity } else { 0 };
+        bytes[16] = output;
+        bytes[17] = 0; // Ratio, not fixed frequency
+        bytes[18] = coarse;
+        bytes[19] = fine;
+        bytes[20] = 7; // No detune
+    }
+    let global = &mut voice[OPERATORS * OPERATOR_LEN..];
+    // Pitch envelope flat at the centre
+    global[..4].fill(MAX_RATE_LEVEL);
+    global[4..8].fill(50);
+    global[8] = 0; // Algorithm 1
+    global[9] = 0; // No feedback
+    global[10] = key_sync as u8;
+    // LFO as on the initial voice, but with no depth
+    global[11] = 35;
+    global[15] = 1;
+    global[17] = 3;
+    global[18] = 24; // No transpose (C3)
+    let name = name.chars().map(|c| if c.is_ascii_graphic() || c == ' ' { c as u8 } else { b'?' });
+    let mut padded = [b' '; NAME_LEN];
+    padded.iter_mut().zip(name).for_each(|(byte, c)| *byte = c);
+    global[19..].copy_from_slice(&padded);
+
+    let checksum = voice.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)).wrapping_neg() & 0x7F;
+    let mut sysex = Vec::with_capacity(VOICE_DUMP_LEN);
+    // Yamaha, channel 1, single voice format, 155 bytes
+    sysex.extend_from_slice(&[0xF0, 0x43, 0x00, 0x00, 0x01, 0x1B]);
+    sysex.extend_from_slice(&voice);
+    sysex.extend_from_slice(&[checksum, 0xF7]);
+    sysex
+}
@@ -0,0 +1,210 @@
+// src/effects.rs - Master bus effects
+
+use std::time::Duration;
+
+/// Longest Haas delay accepted by `FMParams::validate` (milliseconds)
+pub const MAX_STEREO_DELAY: f32 = 30.0;
+
+/// Widest stereo width accepted by `FMParams::validate`
+pub const MAX_STEREO_WIDTH: f32 = 2.0;
+
+/// Comb and allpass lengths of the reverb at 44.1 kHz (Freeverb's tuning)
+const COMB_TUNING: [usize; 4] = [1116, 1188, 1277, 1356];
+const ALLPASS_TUNING: [usize; 2] = [556, 441];
+
+/// A preset's master bus effects
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EffectSettings {
+    /// Reverb wet level (0 = dry)
+    pub reverb_mix: f32,
+    /// Reverb room size (0.0 - 1.0); larger rooms ring longer
+    pub reverb_size: f32,
+    /// Haas delay of the right channel in milliseconds (0 = off)
+    pub stereo_delay: f32,
+    /// Stereo width (0 = mono, 1 = as delayed)
+    pub stereo_width: f32,
+}
+
+impl EffectSettings {
+    /// Whether these settings change the signal at all
+    pub fn is_dry(&self) -> bool {
+        self.reverb_mix <= 0.0 && self.stereo_delay <= 0.0 && self.stereo_width == 1.0
+    }
+}
+
+/// The master bus: reverb on the mono signal, then the stereo widener
+pub struct EffectChain {
+    reverb: Option<Reverb>,
+    widener: Widener,
+}
+
+impl EffectChain {
+    pub fn new(sample_rate: f32, settings: EffectSettings) -> Self {
+        Self {
+            reverb: (settings.reverb_mix > 0.0)
+                .then(|| Reverb::new(sample_rate, settings.reverb_size, settings.reverb_mix)),
+            widener: Widener::new(sample_rate, settings.stereo_delay, settings.stereo_width),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        if let Some(reverb) = &mut self.reverb {
+            reverb.set_sample_rate(sample_rate);
+        }
+        self.widener.set_sample_rate(sample_rate);
+    }
+
+    /// How long the chain rings on after its input falls silent
+    pub fn tail(&self) -> Duration {
+        self.reverb.as_ref().map_or(Duration::ZERO, Reverb::tail)
+    }
+
+    /// Left and right output for one input sample
+    pub fn process(&mut self, input: f32) -> [f32; 2] {
+        let sample = match &mut self.reverb {
+            Some(reverb) => reverb.process(input),
+            None => input,
+        };
+        self.widener.process(sample)
+    }
+}
+
+/// Feedback comb filter with a one-pole lowpass in the loop
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    store: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.pos];
+        self.store = output * (1.0 - damping) + self.store * damping;
+        self.buffer[self.pos] = input + self.store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        output
+    }
+}
+
+/// Schroeder allpass diffuser
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.pos];
+        self.buffer[self.pos] = input + delayed * 0.5;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+/// Small mono Schroeder reverb: parallel combs into series allpasses
+pub struct Reverb {
+    size: f32,
+    mix: f32,
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl Reverb {
+    pub fn new(sample_rate: f32, size: f32, mix: f32) -> Self {
+        let mut reverb = Self {
+            size: size.clamp(0.0, 1.0),
+            mix: mix.clamp(0.0, 1.0),
+            combs: Vec::new(),
+            allpasses: Vec::new(),
+        };
+        reverb.set_sample_rate(sample_rate);
+        reverb
+    }
+
+    /// Rebuild the delay lines for `sample_rate`, clearing the tail
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let scaled = |length: usize| ((length as f32 * sample_rate / 44100.0) as usize).max(1);
+        self.combs = COMB_TUNING.iter()
+            .map(|&length| Comb { buffer: vec![0.0; scaled(length)], pos: 0, store: 0.0 })
+            .collect();
+        self.allpasses = ALLPASS_TUNING.iter()
+            .map(|&length| Allpass { buffer: vec![0.0; scaled(length)], pos: 0 })
+            .collect();
+    }
+
+    fn feedback(&self) -> f32 {
+        0.7 + 0.28 * self.size
+    }
+
+    /// Time for the longest comb to decay by 60 dB
+    pub fn tail(&self) -> Duration {
+        let longest = *COMB_TUNING.iter().max().unwrap_or(&0) as f32 / 44100.0;
+        Duration::from_secs_f32(-3.0 * longest / self.feedback().log10())
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let feedback = self.feedback();
+        let damping = 0.2;
+        // Scaled down so the summed combs stay near the input level
+        let mut wet: f32 = self.combs.iter_mut()
+            .map(|comb| comb.process(input * 0.05, feedback, damping))
+            .sum();
+        for allpass in &mut self.allpasses {
+            wet = allpass.process(wet);
+        }
+        input + wet * self.mix
+    }
+}
+
+/// Spreads a mono signal across two channels: the right channel is delayed
+/// by a few milliseconds (the Haas effect), then a mid/side stage scales the
+/// difference between the channels by `width` (0 = mono, 1 = as delayed,
+/// 2 = twice as wide)
+pub struct Widener {
+    sample_rate: f32,
+    delay_ms: f32,
+    width: f32,
+    /// Ring buffer of recent input, one delay long
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Widener {
+    pub fn new(sample_rate: f32, delay_ms: f32, width: f32) -> Self {
+        let mut widener = Self {
+            sample_rate,
+            delay_ms: delay_ms.clamp(0.0, MAX_STEREO_DELAY),
+            width: width.clamp(0.0, MAX_STEREO_WIDTH),
+            buffer: Vec::new(),
+            pos: 0,
+        };
+        widener.resize();
+        widener
+    }
+
+    fn resize(&mut self) {
+        let samples = (self.delay_ms / 1000.0 * self.sample_rate).round() as usize;
+        self.buffer = vec![0.0; samples];
+        self.pos = 0;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.resize();
+    }
+
+    /// Left and right output for one input sample
+    pub fn process(&mut self, input: f32) -> [f32; 2] {
+        let delayed = match self.buffer.get_mut(self.pos) {
+            Some(slot) => std::mem::replace(slot, input),
+            None => input,
+        };
+        if !self.buffer.is_empty() {
+            self.pos = (self.pos + 1) % self.buffer.len();
+        }
+
+        let mid = (input + delayed) * 0.5;
+        let side = (input - delayed) * 0.5 * self.width;
+        [mid + side, mid - side]
+    }
+}
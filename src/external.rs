@@ -0,0 +1,154 @@
+// src/external.rs - Signals from outside the synth used as an audio-rate modulator
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Input samples kept for the outputs to read (about a third of a second at
+/// 48 kHz); an output that falls further behind skips ahead
+const RING_LEN: usize = 16384;
+
+/// Output buffers an output reads behind the input, so small differences in
+/// callback timing don't run it dry
+const INPUT_LAG_BUFFERS: f64 = 2.0;
+
+/// Where the external modulator comes from
+enum Source {
+    /// The open audio input, copied here by its stream
+    Input { ring: Vec<f32>, written: u64, sample_rate: f32 },
+    /// A WAV file, looped
+    Wav { samples: Arc<[f32]>, sample_rate: f32 },
+}
+
+static SOURCE: Mutex<Option<Source>> = Mutex::new(None);
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Bumped on every change of source, so readers start it afresh
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn set_source(source: Option<Source>) {
+    ACTIVE.store(source.is_some(), Ordering::SeqCst);
+    *SOURCE.lock().unwrap() = source;
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Modulate with the audio input (`input::AudioInput`), from now on
+pub fn use_input() {
+    set_source(Some(Source::Input { ring: vec![0.0; RING_LEN], written: 0, sample_rate: 0.0 }));
+}
+
+/// Modulate with mono `samples`, looped; an empty file turns the external
+/// modulator off
+pub fn use_wav(sample_rate: u32, samples: Vec<f32>) {
+    match samples.is_empty() {
+        true => clear(),
+        false => set_source(Some(Source::Wav { samples: samples.into(), sample_rate: sample_rate as f32 })),
+    }
+}
+
+/// Stop modulating with an external signal
+pub fn clear() {
+    set_source(None);
+}
+
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Copy a buffer of the audio input, as mono samples, for the outputs to
+/// read when it's the external modulator. Never waits: a buffer lost to a
+/// busy lock is a short gap in the modulation.
+pub fn push_input(samples: impl Iterator<Item = f32>, sample_rate: f32) {
+    if !is_active() {
+        return;
+    }
+    let Ok(mut source) = SOURCE.try_lock() else {
+        return;
+    };
+    if let Some(Source::Input { ring, written, sample_rate: rate }) = source.as_mut() {
+        *rate = sample_rate;
+        for sample in samples {
+            ring[(*written % RING_LEN as u64) as usize] = sample;
+            *written += 1;
+        }
+    }
+}
+
+/// One output stream's place in the external signal. Each stream reads it
+/// at its own sample rate, so several outputs can share one source.
+pub struct ExternalReader {
+    generation: u64,
+    /// Position in the source, in source samples
+    position: f64,
+}
+
+impl Default for ExternalReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExternalReader {
+    pub fn new() -> Self {
+        Self { generation: u64::MAX, position: 0.0 }
+    }
+
+    /// Fill `block` with the next `frames` samples for an output at
+    /// `sample_rate`, returning `false` when there's no external signal
+    pub fn fill(&mut self, block: &mut Vec<f32>, frames: usize, sample_rate: f32) -> bool {
+        if !is_active() {
+            return false;
+        }
+        block.clear();
+        // Never wait on the input stream; a busy lock is a buffer of silence
+        let Ok(source) = SOURCE.try_lock() else {
+            block.resize(frames, 0.0);
+            return true;
+        };
+        let Some(source) = source.as_ref() else {
+            return false;
+        };
+        let generation = GENERATION.load(Ordering::Relaxed);
+        let restart = generation != self.generation;
+        self.generation = generation;
+
+        match source {
+            Source::Wav { samples, sample_rate: rate } => {
+                if restart {
+                    self.position = 0.0;
+                }
+                let step = (rate / sample_rate) as f64;
+                let len = samples.len();
+                for _ in 0..frames {
+                    let i = self.position as usize % len;
+                    let frac = self.position.fract() as f32;
+                    let (a, b) = (samples[i], samples[(i + 1) % len]);
+                    block.push(a + (b - a) * frac);
+                    self.position = (self.position + step) % len as f64;
+                }
+            }
+            Source::Input { ring, written, sample_rate: rate } => {
+                let written = *written as f64;
+                let step = if *rate > 0.0 { (rate / sample_rate) as f64 } else { 1.0 };
+                let lag = frames as f64 * step * INPUT_LAG_BUFFERS;
+                let overrun = written - self.position > RING_LEN as f64 - lag;
+                if restart || overrun || self.position > written {
+                    self.position = (written - lag).max(0.0);
+                }
+                for _ in 0..frames {
+                    // Run dry, hold the last sample until the input catches up
+                    if self.position + 1.0 < written {
+                        let i = self.position as u64;
+                        let frac = self.position.fract() as f32;
+                        let a = ring[(i % RING_LEN as u64) as usize];
+                        let b = ring[((i + 1) % RING_LEN as u64) as usize];
+                        block.push(a + (b - a) * frac);
+                        self.position += step;
+                    } else {
+                        block.push(block.last().copied().unwrap_or(0.0));
+                    }
+                }
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,213 @@
+// src/ffi.rs - C ABI for embedding the engine in C/C++ applications and game engines
+//
+// `include/fm_synth.h` is generated from this file by cbindgen (see
+// `cbindgen.toml`); regenerate it after changing a signature.
+
+use std::ffi::{c_char, CString};
+
+use crate::synth_core::{FMParams, PolySynth, MAX_VOICES};
+use crate::synth_data::{get_presets, midi_to_freq};
+
+/// Voices used when `fm_synth_new` is given 0
+const DEFAULT_VOICES: u32 = 8;
+
+/// An engine instance: a polyphonic synth playing one of the built-in
+/// presets. Opaque to C.
+pub struct FmSynth {
+    synth: PolySynth,
+    presets: Vec<(CString, FMParams)>,
+    /// Preset played by `fm_synth_note_on`
+    preset: usize,
+}
+
+/// Create an engine rendering at `sample_rate` with up to `max_voices`
+/// notes at once (0 for the default of 8, at most 256), playing the first
+/// preset.
+/// Returns null if `sample_rate` isn't positive. Free it with
+/// `fm_synth_free`.
+#[no_mangle]
+pub extern "C" fn fm_synth_new(sample_rate: f32, max_voices: u32) -> *mut FmSynth {
+    if sample_rate.is_nan() || sample_rate <= 0.0 {
+        return std::ptr::null_mut();
+    }
+    let synth = FmSynth { synth: PolySynth::new(sample_rate, voice_count(max_voices)), presets: c_presets(), preset: 0 };
+    Box::into_raw(Box::new(synth))
+}
+
+/// `max_voices` as passed from C, where 0 means the default
+pub(crate) fn voice_count(max_voices: u32) -> usize {
+    if max_voices == 0 { DEFAULT_VOICES as usize } else { (max_voices as usize).min(MAX_VOICES) }
+}
+
+/// The built-in presets with their names as C strings
+pub(crate) fn c_presets() -> Vec<(CString, FMParams)> {
+    get_presets()
+        .into_iter()
+        .map(|(name, params)| (CString::new(name).unwrap_or_default(), params.clamped()))
+        .collect()
+}
+
+/// Parameters for MIDI note `note` at `velocity` (1 - 127) with a preset,
+/// and the note's frequency
+pub(crate) fn note_params(preset: &FMParams, note: u8, velocity: u8) -> (f32, FMParams) {
+    let freq = midi_to_freq(note as i32);
+    (freq, preset.for_note(freq).for_velocity(velocity.min(127) as f32 / 127.0))
+}
+
+/// Free an engine created by `fm_synth_new`. Null is ignored.
+///
+/// # Safety
+///
+/// `synth` must be null or a pointer from `fm_synth_new` that hasn't been
+/// freed yet; it can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_free(synth: *mut FmSynth) {
+    if !synth.is_null() {
+        drop(Box::from_raw(synth));
+    }
+}
+
+/// Number of built-in presets
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_preset_count(synth: *const FmSynth) -> u32 {
+    let synth = &*synth;
+    synth.presets.len() as u32
+}
+
+/// Name of preset `index` (0-based) as a NUL-terminated string, or null if
+/// there is no such preset. The string lives as long as the engine.
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_preset_name(synth: *const FmSynth, index: u32) -> *const c_char {
+    let synth = &*synth;
+    match synth.presets.get(index as usize) {
+        Some((name, _)) => name.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
+
+/// Choose the preset (0-based) for notes started afterwards; sounding
+/// notes keep theirs. Returns false, changing nothing, if there is no such
+/// preset.
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_set_preset(synth: *mut FmSynth, index: u32) -> bool {
+    let synth = &mut *synth;
+    if index as usize >= synth.presets.len() {
+        return false;
+    }
+    synth.preset = index as usize;
+    true
+}
+
+/// Start MIDI note `note` (60 = C4) at `velocity` (1 - 127; 0 releases the
+/// note, as in MIDI). Velocity scales the amplitude and, by the preset's
+/// `velocity_index`, the modulation index.
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_note_on(synth: *mut FmSynth, note: u8, velocity: u8) {
+    let synth = &mut *synth;
+    if velocity == 0 {
+        synth.synth.note_off(midi_to_freq(note as i32));
+        return;
+    }
+    let (freq, params) = note_params(&synth.presets[synth.preset].1, note, velocity);
+    synth.synth.note_on_with(freq, params);
+}
+
+/// Release MIDI note `note`; it fades out over the preset's release
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_note_off(synth: *mut FmSynth, note: u8) {
+    (*synth).synth.note_off(midi_to_freq(note as i32));
+}
+
+/// Release every held note
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_all_notes_off(synth: *mut FmSynth) {
+    (*synth).synth.all_notes_off();
+}
+
+/// Number of voices still sounding, releases included
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_active_voices(synth: *const FmSynth) -> u32 {
+    let synth = &*synth;
+    synth.synth.active_voices() as u32
+}
+
+/// Most notes that can sound at once
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_max_voices(synth: *const FmSynth) -> u32 {
+    let synth = &*synth;
+    synth.synth.max_voices() as u32
+}
+
+/// Change the most notes that can sound at once, as for `fm_synth_new`.
+/// Lowering it cuts off the oldest notes beyond the new limit. Allocates
+/// when raising it, so call it outside the audio callback.
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_set_max_voices(synth: *mut FmSynth, max_voices: u32) {
+    (*synth).synth.set_max_voices(voice_count(max_voices));
+}
+
+/// Follow a change of output rate; sounding notes keep their pitch
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_set_sample_rate(synth: *mut FmSynth, sample_rate: f32) {
+    if sample_rate > 0.0 {
+        (*synth).synth.set_sample_rate(sample_rate);
+    }
+}
+
+/// Render the next `frames` mono samples into `out`, overwriting it. Doesn't
+/// allocate or lock, so it can be called from an audio callback.
+///
+/// # Safety
+///
+/// `synth` must be a live pointer from `fm_synth_new`, and `out` must point
+/// to at least `frames` writable floats (it may be null if `frames` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn fm_synth_process(synth: *mut FmSynth, out: *mut f32, frames: usize) {
+    if frames == 0 {
+        return;
+    }
+    let synth = &mut *synth;
+    for sample in std::slice::from_raw_parts_mut(out, frames) {
+        *sample = synth.synth.next_sample();
+    }
+}
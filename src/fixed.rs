@@ -0,0 +1,438 @@
+// src/fixed.rs - Fixed-point oscillator and envelope for targets without an FPU
+//
+// Samples and levels are Q15 (`i16`, 1.0 = 32767), envelope state is Q31,
+// and phases are 32-bit accumulators where 2^32 is one cycle, so they wrap
+// for free. Floats are only used when parameters are set, never per sample.
+
+use crate::synth_core::{FMParams, Waveform};
+
+/// Largest Q15 value (just under 1.0)
+pub const Q15_ONE: i16 = i16::MAX;
+
+/// Largest Q31 value (just under 1.0)
+pub const Q31_ONE: i32 = i32::MAX;
+
+/// log2 of the sine table length
+const SINE_BITS: u32 = 10;
+
+/// Convert a float in [-1, 1] to Q15, saturating
+pub fn to_q15(x: f32) -> i16 {
+    (x.clamp(-1.0, 1.0) * Q15_ONE as f32) as i16
+}
+
+/// Convert a Q15 value back to a float
+pub fn from_q15(x: i16) -> f32 {
+    x as f32 / Q15_ONE as f32
+}
+
+/// Phase increment per sample for `freq` Hz, in 2^-32 cycles
+fn phase_step(freq: f32, sample_rate: f32) -> i64 {
+    (freq as f64 / sample_rate as f64 * 4_294_967_296.0) as i64
+}
+
+/// Fixed-point FM oscillator, matching `FMOscillator`
+pub struct FixedOscillator {
+    sample_rate: f32,
+    params: FMParams,
+    /// One period of sine in Q15, with a guard point for interpolation
+    sine: Vec<i16>,
+    carrier_phase: u32,
+    modulator_phase: u32,
+    carrier_step: i64,
+    modulator_step: i64,
+    /// Carrier step deviation at full modulator output
+    depth: i64,
+    amplitude: i16,
+}
+
+impl FixedOscillator {
+    pub fn new(sample_rate: f32, params: FMParams) -> Self {
+        let size = 1 << SINE_BITS;
+        let sine = (0..=size)
+            .map(|i| to_q15((2.0 * std::f32::consts::PI * i as f32 / size as f32).sin()))
+            .collect();
+        let mut oscillator = Self {
+            sample_rate,
+            params,
+            sine,
+            carrier_phase: 0,
+            modulator_phase: 0,
+            carrier_step: 0,
+            modulator_step: 0,
+            depth: 0,
+            amplitude: 0,
+        };
+        oscillator.update();
+        oscillator
+    }
+
+    /// Recompute the per-sample constants from the parameters
+    fn update(&mut self) {
+        let p = &self.params;
+        self.carrier_step = phase_step(p.carrier_freq, self.sample_rate);
+        self.modulator_step = phase_step(p.modulator_freq, self.sample_rate);
+        self.depth = (self.carrier_step as f64 * p.modulation_index as f64) as i64;
+        self.amplitude = to_q15(p.gain());
+    }
+
+    /// Interpolated sine of a phase accumulator
+    #[inline]
+    fn sine(&self, phase: u32) -> i16 {
+        let index = (phase >> (32 - SINE_BITS)) as usize;
+        let frac = ((phase >> (16 - SINE_BITS)) & 0xFFFF) as i32;
+        let a = self.sine[index] as i32;
+        let b = self.sine[index + 1] as i32;
+        (a + (((b - a) * frac) >> 16)) as i16
+    }
+
+    /// One sample of `waveform`; square and saw are not band-limited here
+    #[inline]
+    fn wave(&self, waveform: Waveform, phase: u32) -> i16 {
+        match waveform {
+            Waveform::Sine => self.sine(phase),
+            // Rising from -1 at phase 0
+            Waveform::Saw => ((phase >> 16) as i32 - 32768).max(-Q15_ONE as i32) as i16,
+            Waveform::Square => if phase < 1 << 31 { Q15_ONE } else { -Q15_ONE },
+        }
+    }
+
+    /// Next sample in Q15
+    pub fn next_sample(&mut self) -> i16 {
+        let modulator = self.wave(self.params.modulator_wave, self.modulator_phase);
+        let carrier = self.wave(self.params.carrier_wave, self.carrier_phase);
+
+        let step = self.carrier_step + ((self.depth * modulator as i64) >> 15);
+        self.carrier_phase = self.carrier_phase.wrapping_add(step as u32);
+        self.modulator_phase = self.modulator_phase.wrapping_add(self.modulator_step as u32);
+
+        ((carrier as i32 * self.amplitude as i32) >> 15) as i16
+    }
+
+    pub fn set_params(&mut self, params: FMParams) {
+        self.params = params;
+        self.update();
+    }
+
+    /// Restart carrier and modulator at phase zero
+    pub fn reset_phase(&mut self) {
+        self.carrier_phase = 0;
+        self.modulator_phase = 0;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.update();
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Fixed-point linear ADSR, matching `Envelope`
+pub struct FixedEnvelope {
+    sample_rate: f32,
+    attack: f32,
+    decay: f32,
+    sustain: f32,
+    release: f32,
+    stage: Stage,
+    /// Current level in Q31
+    level: i32,
+    sustain_level: i32,
+    /// Level in Q31 the release started from
+    release_from: i32,
+    attack_step: i32,
+    decay_step: i32,
+    release_step: i32,
+}
+
+impl FixedEnvelope {
+    pub fn new(sample_rate: f32) -> Self {
+        let mut envelope = Self {
+            sample_rate,
+            attack: 0.01,
+            decay: 0.1,
+            sustain: 0.7,
+            release: 0.5,
+            stage: Stage::Idle,
+            level: 0,
+            sustain_level: 0,
+            release_from: 0,
+            attack_step: 0,
+            decay_step: 0,
+            release_step: 0,
+        };
+        envelope.update();
+        envelope
+    }
+
+    /// Take the attack, decay, sustain, and release settings of a patch
+    pub fn set_adsr(&mut self, params: &FMParams) {
+        self.attack = params.attack;
+        self.decay = params.decay;
+        self.sustain = params.sustain;
+        self.release = params.release;
+        self.update();
+    }
+
+    /// Per-sample steps that cross each stage in its time; zero-length
+    /// stages take one sample
+    fn update(&mut self) {
+        let step = |span: f32, time: f32| {
+            let samples = (time * self.sample_rate).max(1.0);
+            ((span as f64 * Q31_ONE as f64 / samples as f64) as i32).max(1)
+        };
+        let sustain = self.sustain.clamp(0.0, 1.0);
+        self.sustain_level = (sustain as f64 * Q31_ONE as f64) as i32;
+        self.attack_step = step(1.0, self.attack);
+        self.decay_step = step(1.0 - sustain, self.decay);
+        self.release_step = step((self.release_from as f64 / Q31_ONE as f64) as f32, self.release);
+    }
+
+    /// Start the attack from the current level, as `Envelope` does, so a
+    /// retriggered note doesn't click
+    pub fn trigger(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    /// Fade out from the current level over the release time, as
+    /// `Envelope` does
+    pub fn release(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+            self.release_from = self.level;
+            self.update();
+        }
+    }
+
+    /// Next level in Q15
+    pub fn process(&mut self) -> i16 {
+        match self.stage {
+            Stage::Idle => self.level = 0,
+            Stage::Attack => {
+                self.level = self.level.saturating_add(self.attack_step);
+                if self.level == Q31_ONE {
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level = (self.level - self.decay_step).max(self.sustain_level);
+                if self.level == self.sustain_level {
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = self.sustain_level,
+            Stage::Release => {
+                self.level = (self.level - self.release_step).max(0);
+                if self.level == 0 {
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.level()
+    }
+
+    /// Current level in Q15
+    pub fn level(&self) -> i16 {
+        (self.level >> 16) as i16
+    }
+
+    /// True once the release has finished (or before the first trigger)
+    pub fn is_idle(&self) -> bool {
+        self.stage == Stage::Idle
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.update();
+    }
+}
+
+/// Fixed-point FM synth with envelope, matching `FMSynth`
+pub struct FixedSynth {
+    oscillator: FixedOscillator,
+    envelope: FixedEnvelope,
+    phase_reset: bool,
+}
+
+impl FixedSynth {
+    pub fn new(sample_rate: f32, params: FMParams) -> Self {
+        let mut envelope = FixedEnvelope::new(sample_rate);
+        envelope.set_adsr(&params);
+        Self {
+            phase_reset: params.phase_reset,
+            oscillator: FixedOscillator::new(sample_rate, params),
+            envelope,
+        }
+    }
+
+    /// Next sample in Q15
+    pub fn next_sample(&mut self) -> i16 {
+        let osc_out = self.oscillator.next_sample() as i32;
+        let env_out = self.envelope.process() as i32;
+        ((osc_out * env_out) >> 15) as i16
+    }
+
+    pub fn note_on(&mut self) {
+        if self.phase_reset {
+            self.oscillator.reset_phase();
+        }
+        self.envelope.trigger();
+    }
+
+    pub fn note_off(&mut self) {
+        self.envelope.release();
+    }
+
+    pub fn set_params(&mut self, params: FMParams) {
+        self.envelope.set_adsr(&params);
+        self.phase_reset = params.phase_reset;
+        self.oscillator.set_params(params);
+    }
+
+    /// Follow a change of output rate (e.g. after switching devices)
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.oscillator.set_sample_rate(sample_rate);
+        self.envelope.set_sample_rate(sample_rate);
+    }
+
+    /// Current amplitude envelope level in Q15
+    pub fn envelope_level(&self) -> i16 {
+        self.envelope.level()
+    }
+
+    /// True while the note is sounding, including its release
+    pub fn is_active(&self) -> bool {
+        !self.envelope.is_idle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth_core::{Envelope, FMSynth};
+    use crate::synth_data::get_presets;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    /// Largest and RMS difference from the float engine allowed over a
+    /// quarter second; the phases drift apart slowly, most at high indices
+    const MAX_ERROR: f32 = 0.05;
+    const RMS_ERROR: f32 = 0.02;
+
+    /// The parts of a preset both engines share: the operators and the
+    /// envelope, without the float engine's LFOs, drift, and effects
+    fn core(preset: &FMParams) -> FMParams {
+        FMParams {
+            carrier_freq: preset.carrier_freq,
+            modulator_freq: preset.modulator_freq,
+            modulation_index: preset.modulation_index,
+            level_db: preset.level_db,
+            carrier_wave: preset.carrier_wave,
+            modulator_wave: preset.modulator_wave,
+            attack: preset.attack,
+            decay: preset.decay,
+            sustain: preset.sustain,
+            release: preset.release,
+            ..FMParams::default()
+        }
+    }
+
+    /// Largest and RMS difference between the engines over `frames`, with
+    /// the note released halfway
+    fn compare(params: &FMParams, frames: usize) -> (f32, f32) {
+        let mut float = FMSynth::new(SAMPLE_RATE, params.clone());
+        let mut fixed = FixedSynth::new(SAMPLE_RATE, params.clone());
+        float.note_on();
+        fixed.note_on();
+        let (mut max, mut sum) = (0.0f32, 0.0f64);
+        for i in 0..frames {
+            if i == frames / 2 {
+                float.note_off();
+                fixed.note_off();
+            }
+            let error = (float.next_sample() - from_q15(fixed.next_sample())).abs();
+            max = max.max(error);
+            sum += (error * error) as f64;
+        }
+        (max, (sum / frames as f64).sqrt() as f32)
+    }
+
+    #[test]
+    fn sine_presets_track_the_float_engine() {
+        let mut compared = 0;
+        for (name, preset) in get_presets() {
+            if preset.carrier_wave != Waveform::Sine || preset.modulator_wave != Waveform::Sine {
+                continue;
+            }
+            let (max, rms) = compare(&core(&preset.for_note(440.0)), SAMPLE_RATE as usize / 4);
+            assert!(max < MAX_ERROR, "{}: max error {}", name, max);
+            assert!(rms < RMS_ERROR, "{}: rms error {}", name, rms);
+            compared += 1;
+        }
+        assert!(compared > 0);
+    }
+
+    #[test]
+    fn retrigger_attacks_from_the_current_level() {
+        let params = FMParams { attack: 0.05, release: 0.2, ..FMParams::default() };
+        let mut float = Envelope::new(SAMPLE_RATE);
+        let mut fixed = FixedEnvelope::new(SAMPLE_RATE);
+        float.set_adsr(&params);
+        fixed.set_adsr(&params);
+        float.trigger();
+        fixed.trigger();
+        // Hold, release partway, then retrigger mid-release
+        for (frames, event) in [(9600, 1), (2400, 2), (4800, 0)] {
+            for _ in 0..frames {
+                let error = (float.process() - from_q15(fixed.process())).abs();
+                assert!(error < 1e-3, "envelope error {}", error);
+            }
+            match event {
+                1 => {
+                    float.release();
+                    fixed.release();
+                }
+                2 => {
+                    float.trigger();
+                    fixed.trigger();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn release_mid_attack_fades_from_the_current_level() {
+        let params = FMParams { attack: 0.1, sustain: 0.3, release: 0.2, ..FMParams::default() };
+        let mut float = Envelope::new(SAMPLE_RATE);
+        let mut fixed = FixedEnvelope::new(SAMPLE_RATE);
+        float.set_adsr(&params);
+        fixed.set_adsr(&params);
+        float.trigger();
+        fixed.trigger();
+        // A quarter of the way up the attack, well short of the sustain level
+        for _ in 0..1200 {
+            float.process();
+            fixed.process();
+        }
+        float.release();
+        fixed.release();
+        // No sample moves further than an attack step (the steepest stage)
+        let max_step = 1.5 / (params.attack * SAMPLE_RATE);
+        let (mut last_float, mut last_fixed) = (float.level(), from_q15(fixed.level()));
+        while !float.is_idle() || !fixed.is_idle() {
+            let (level_float, level_fixed) = (float.process(), from_q15(fixed.process()));
+            assert!((level_float - last_float).abs() < max_step, "float step {} to {}", last_float, level_float);
+            assert!((level_fixed - last_fixed).abs() < max_step, "fixed step {} to {}", last_fixed, level_fixed);
+            assert!((level_float - level_fixed).abs() < 1e-3, "envelope error {}", level_float - level_fixed);
+            (last_float, last_fixed) = (level_float, level_fixed);
+        }
+    }
+}
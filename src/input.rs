@@ -0,0 +1,232 @@
+// src/input.rs - Native audio input (cpal), followed for its level
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, Sample, SampleFormat, SizedSample};
+
+use crate::audio::{self, OutputConfig};
+use crate::external;
+use crate::synth_core::db_to_gain;
+
+/// How long the follower takes to rise to a louder input, and to fall back
+/// once it's quieter (time constants)
+const FOLLOW_ATTACK: Duration = Duration::from_millis(5);
+const FOLLOW_RELEASE: Duration = Duration::from_millis(150);
+
+/// How often the input thread checks whether to close the stream
+const INPUT_POLL: Duration = Duration::from_millis(100);
+
+/// Follower level of the open input as `f32` bits, read by every output
+/// stream before each buffer; 0 while no input is open
+static LEVEL: AtomicU32 = AtomicU32::new(0);
+
+/// Where the open input sends what it hears while it's being captured, and
+/// whether anything is listening
+static CAPTURE: Mutex<Option<mpsc::Sender<CaptureBlock>>> = Mutex::new(None);
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+/// One callback's worth of the open input, mixed to mono and scaled by its
+/// gain
+pub struct CaptureBlock {
+    pub sample_rate: u32,
+    /// When the callback ran, about when the last sample arrived
+    pub received: Instant,
+    pub samples: Vec<f32>,
+}
+
+/// Start copying the open input to the returned receiver, replacing any
+/// earlier capture
+pub fn start_capture() -> mpsc::Receiver<CaptureBlock> {
+    let (sender, receiver) = mpsc::channel();
+    *CAPTURE.lock().unwrap() = Some(sender);
+    CAPTURING.store(true, Ordering::SeqCst);
+    receiver
+}
+
+/// Stop the capture; its receiver sees the end once queued blocks are read
+pub fn stop_capture() {
+    CAPTURING.store(false, Ordering::SeqCst);
+    *CAPTURE.lock().unwrap() = None;
+}
+
+/// Envelope (0.0 - 1.0) of the open audio input; 0 without one
+pub fn level() -> f32 {
+    f32::from_bits(LEVEL.load(Ordering::Relaxed))
+}
+
+/// A peak envelope follower: rises quickly to the input's peaks and falls
+/// back slowly, so a drum hit gives a smooth swell rather than a buzz
+#[derive(Clone, Copy, Debug)]
+pub struct EnvelopeFollower {
+    attack: f32,
+    release: f32,
+    level: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(sample_rate: f32) -> Self {
+        let coefficient = |time: Duration| (-1.0 / (time.as_secs_f32() * sample_rate)).exp();
+        Self { attack: coefficient(FOLLOW_ATTACK), release: coefficient(FOLLOW_RELEASE), level: 0.0 }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let peak = sample.abs().min(1.0);
+        let coefficient = if peak > self.level { self.attack } else { self.release };
+        self.level = peak + (self.level - peak) * coefficient;
+        self.level
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+}
+
+/// Keeps an audio input open, publishing its level for `level()`, for as
+/// long as the value lives. The stream lives on its own thread, as cpal
+/// streams can't move between threads on every platform.
+pub struct AudioInput {
+    device_name: String,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AudioInput {
+    /// Open `device` (by name, or the host's default input) on the host
+    /// chosen in `output`, scaling the input by `gain_db` before following
+    /// it
+    pub fn start(output: &OutputConfig, device: Option<&str>, gain_db: f32) -> anyhow::Result<Self> {
+        let host = audio::select_host(output)?;
+        let device = match device {
+            Some(wanted) => find_input(&host, wanted)?,
+            None => host.default_input_device().ok_or_else(|| anyhow::anyhow!("No input device available"))?,
+        };
+        let device_name = device.name().unwrap_or_default();
+        let gain = db_to_gain(gain_db);
+        let stop = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let thread = {
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || run(device, gain, stop, ready_tx))
+        };
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self { device_name, stop, thread: Some(thread) }),
+            Ok(Err(err)) => {
+                let _ = thread.join();
+                Err(err)
+            }
+            Err(_) => anyhow::bail!("Audio input thread exited unexpectedly"),
+        }
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+}
+
+impl Drop for AudioInput {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        LEVEL.store(0f32.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// An input device by case-insensitive name, or part of one
+fn find_input(host: &cpal::Host, wanted: &str) -> anyhow::Result<cpal::Device> {
+    let devices: Vec<cpal::Device> = host.input_devices()?.collect();
+    let names: Vec<String> = devices.iter().map(|d| d.name().unwrap_or_default()).collect();
+    let wanted_lower = wanted.to_lowercase();
+    let found = names.iter()
+        .position(|n| n.to_lowercase() == wanted_lower)
+        .or_else(|| names.iter().position(|n| n.to_lowercase().contains(&wanted_lower)));
+    match found {
+        Some(i) => Ok(devices[i].clone()),
+        None => anyhow::bail!("Input device '{}' not found (inputs: {})", wanted, names.join(", ")),
+    }
+}
+
+/// Input thread body: open the stream, report whether that worked, and
+/// hold it open until `stop`
+fn run(device: cpal::Device, gain: f32, stop: Arc<AtomicBool>, ready: mpsc::Sender<anyhow::Result<()>>) {
+    let opened = device.default_input_config()
+        .map_err(anyhow::Error::from)
+        .and_then(|config| build_format(&device, &config, gain))
+        .and_then(|stream| {
+            stream.play()?;
+            Ok(stream)
+        });
+    let _stream = match opened {
+        Ok(stream) => {
+            let _ = ready.send(Ok(()));
+            stream
+        }
+        Err(err) => {
+            let _ = ready.send(Err(err));
+            return;
+        }
+    };
+    while !stop.load(Ordering::SeqCst) {
+        std::thread::sleep(INPUT_POLL);
+    }
+}
+
+fn build_format(device: &cpal::Device, config: &cpal::SupportedStreamConfig, gain: f32) -> anyhow::Result<cpal::Stream> {
+    let stream_config = config.config();
+    match config.sample_format() {
+        SampleFormat::F32 => build::<f32>(device, &stream_config, gain),
+        SampleFormat::F64 => build::<f64>(device, &stream_config, gain),
+        SampleFormat::I8 => build::<i8>(device, &stream_config, gain),
+        SampleFormat::I16 => build::<i16>(device, &stream_config, gain),
+        SampleFormat::I32 => build::<i32>(device, &stream_config, gain),
+        SampleFormat::U8 => build::<u8>(device, &stream_config, gain),
+        SampleFormat::U16 => build::<u16>(device, &stream_config, gain),
+        SampleFormat::U32 => build::<u32>(device, &stream_config, gain),
+        format => anyhow::bail!("Unsupported input sample format {}", format),
+    }
+}
+
+/// Follow the loudest channel of each frame, publishing the level once per
+/// buffer, and pass the input on to `external` in case it's the external
+/// modulator, and to the capture if one is running
+fn build<T>(device: &cpal::Device, config: &cpal::StreamConfig, gain: f32) -> anyhow::Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = (config.channels as usize).max(1);
+    let sample_rate = config.sample_rate.0 as f32;
+    let mut follower = EnvelopeFollower::new(sample_rate);
+    let stream = device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let received = Instant::now();
+            for frame in data.chunks(channels) {
+                let peak = frame.iter().fold(0f32, |peak, &s| peak.max(f32::from_sample(s).abs()));
+                follower.process(peak * gain);
+            }
+            LEVEL.store(follower.level().to_bits(), Ordering::Relaxed);
+            let mono = data.chunks(channels).map(|frame| {
+                frame.iter().map(|&s| f32::from_sample(s)).sum::<f32>() / channels as f32 * gain
+            });
+            if CAPTURING.load(Ordering::Relaxed) {
+                let samples: Vec<f32> = mono.clone().collect();
+                // Never wait on the capture, as for the output tap
+                if let Ok(capture) = CAPTURE.try_lock() {
+                    if let Some(sender) = capture.as_ref() {
+                        let _ = sender.send(CaptureBlock { sample_rate: sample_rate as u32, received, samples });
+                    }
+                }
+            }
+            external::push_input(mono, sample_rate);
+        },
+        |err| eprintln!("Error in audio input stream: {}", err),
+        None,
+    )?;
+    Ok(stream)
+}
@@ -0,0 +1,348 @@
+// src/jam.rs - Endless generative music: chord progressions, melodies, and bass lines
+//
+// A jam is a stream of four-bar phrases, each a pass through a diatonic
+// chord progression with a scale-bound melody on top and a bass line
+// under it. Lead, chords, and bass play through outputs 0, 1, and 2, so
+// each has its own engine (and, with a channel map, its own speakers).
+// Everything comes from one seed, so a jam can be heard again.
+
+use std::time::Duration;
+
+use crate::render;
+use crate::rng::Rng;
+use crate::sequencer::{Sequence, BEATS_PER_BAR};
+use crate::synth_core::FMParams;
+use crate::synth_data::{self, midi_to_freq, note_name};
+
+/// Bars in each phrase: one pass through a progression
+pub const PHRASE_BARS: usize = 4;
+
+/// Eighth notes in a 4/4 bar, the grid the rhythms are written on
+const STEPS: u32 = 8;
+
+/// Phrases each preset plays before the next takes over, by default
+pub const DEFAULT_ROTATE: usize = 2;
+
+/// Outputs of the three parts
+const LEAD: usize = 0;
+const CHORDS: usize = 1;
+const BASS: usize = 2;
+
+/// Output for drums played along with a jam
+pub const DRUMS: usize = 3;
+
+/// Level of each part's presets, so three triads, a bass, and a lead
+/// together stay about as loud as one melody
+const PART_LEVELS: [f32; 3] = [0.6, 0.25, 0.5];
+
+/// Progressions as scale degrees counting from 0 (I = 0, vi = 5)
+const MAJOR_PROGRESSIONS: [[i32; PHRASE_BARS]; 6] = [
+    [0, 4, 5, 3], // I V vi IV
+    [5, 3, 0, 4], // vi IV I V
+    [0, 5, 3, 4], // I vi IV V
+    [1, 4, 0, 0], // ii V I I
+    [0, 3, 4, 3], // I IV V IV
+    [3, 4, 2, 5], // IV V iii vi
+];
+
+const MINOR_PROGRESSIONS: [[i32; PHRASE_BARS]; 5] = [
+    [0, 3, 4, 0], // i iv v i
+    [0, 5, 2, 6], // i VI III VII
+    [0, 6, 5, 6], // i VII VI VII
+    [0, 3, 6, 2], // i iv VII III
+    [5, 6, 0, 0], // VI VII i i
+];
+
+/// One bar of melody rhythm, as note lengths in eighths
+const RHYTHMS: [&[u32]; 8] = [
+    &[2, 2, 2, 2],
+    &[2, 1, 1, 2, 2],
+    &[1, 1, 1, 1, 2, 2],
+    &[3, 1, 2, 2],
+    &[4, 2, 2],
+    &[2, 2, 4],
+    &[1, 1, 2, 1, 1, 2],
+    &[3, 3, 2],
+];
+
+/// Rhythms for the last bar of a phrase, ending on a held note
+const CADENCES: [&[u32]; 3] = [&[2, 2, 4], &[4, 4], &[2, 6]];
+
+/// Bass patterns: lengths in eighths and the chord tone played (0 root,
+/// 1 third, 2 fifth, 3 the root an octave up)
+const BASS_PATTERNS: [&[(u32, usize)]; 4] = [
+    &[(8, 0)],
+    &[(4, 0), (4, 2)],
+    &[(2, 0), (2, 0), (2, 2), (2, 3)],
+    &[(3, 0), (3, 0), (2, 2)],
+];
+
+/// Melody range in scale steps above the key note
+const MELODY_STEPS: std::ops::RangeInclusive<i32> = 0..=11;
+
+/// Scales a jam can be in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scale {
+    Major,
+    Minor,
+}
+
+impl Scale {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "major" | "maj" => Some(Scale::Major),
+            "minor" | "min" | "m" => Some(Scale::Minor),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scale::Major => "major",
+            Scale::Minor => "minor",
+        }
+    }
+
+    /// Semitones of each degree above the key note
+    fn intervals(self) -> [i32; 7] {
+        match self {
+            Scale::Major => [0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => [0, 2, 3, 5, 7, 8, 10],
+        }
+    }
+
+    fn progressions(self) -> &'static [[i32; PHRASE_BARS]] {
+        match self {
+            Scale::Major => &MAJOR_PROGRESSIONS,
+            Scale::Minor => &MINOR_PROGRESSIONS,
+        }
+    }
+}
+
+/// The presets a part takes turns with, by name
+pub type PartPresets = Vec<(String, FMParams)>;
+
+/// A generated phrase: its notes, chord symbols, and the presets playing
+/// the lead, chords, and bass
+pub struct Phrase {
+    pub sequence: Sequence,
+    pub chords: Vec<String>,
+    pub presets: [String; 3],
+}
+
+/// Generates an endless run of phrases. Progressions and the melody's
+/// rhythm carry over between phrases some of the time, so the music
+/// develops rather than starting afresh every four bars.
+pub struct Jam {
+    rng: Rng,
+    /// MIDI note of the key note, in octave 4
+    key: i32,
+    scale: Scale,
+    /// Presets of the lead, chords, and bass
+    parts: [PartPresets; 3],
+    /// Where each part starts in its presets
+    offsets: [usize; 3],
+    rotate: usize,
+    phrase: usize,
+    progression: usize,
+    rhythm: usize,
+    /// Scale step of the last melody note, where the next one moves from
+    step: i32,
+}
+
+impl Jam {
+    /// A jam in the key of MIDI note `key` (any octave), with each part
+    /// changing to its next preset every `rotate` phrases. Parts without
+    /// presets are left out.
+    pub fn new(seed: u64, key: i32, scale: Scale, mut parts: [PartPresets; 3], rotate: usize) -> Self {
+        for (presets, level) in parts.iter_mut().zip(PART_LEVELS) {
+            presets.iter_mut().for_each(|(_, params)| params.scale_gain(level));
+        }
+        let mut rng = Rng::new(seed);
+        let offsets = std::array::from_fn(|part| Self::pick(&mut rng, parts[part].len()));
+        let progression = Self::pick(&mut rng, scale.progressions().len());
+        let rhythm = Self::pick(&mut rng, RHYTHMS.len());
+        Self {
+            rng,
+            key: 60 + key.rem_euclid(12),
+            scale,
+            parts,
+            offsets,
+            rotate: rotate.max(1),
+            phrase: 0,
+            progression,
+            rhythm,
+            step: 4,
+        }
+    }
+
+    /// Length of a phrase at the written tempo
+    pub fn phrase_length() -> Duration {
+        Duration::from_millis(eighth_ms() * STEPS as u64 * PHRASE_BARS as u64)
+    }
+
+    /// Key and scale, e.g. `A minor`
+    pub fn key_name(&self) -> String {
+        format!("{} {}", pitch_class(self.key), self.scale.name())
+    }
+
+    /// Generate the next phrase
+    pub fn next_phrase(&mut self) -> Phrase {
+        // Keep the progression and rhythm for a while, but not for ever
+        if self.phrase > 0 && self.rng.next_f32() < 0.5 {
+            self.progression = Self::pick(&mut self.rng, self.scale.progressions().len());
+        }
+        if self.phrase > 0 && self.rng.next_f32() < 0.4 {
+            self.rhythm = Self::pick(&mut self.rng, RHYTHMS.len());
+        }
+        let degrees = self.scale.progressions()[self.progression];
+        let presets: [Option<&(String, FMParams)>; 3] = std::array::from_fn(|part| {
+            let presets = &self.parts[part];
+            (!presets.is_empty()).then(|| &presets[(self.offsets[part] + self.phrase / self.rotate) % presets.len()])
+        });
+        let presets = presets.map(|preset| preset.cloned());
+
+        let mut sequence = Sequence::new();
+        // Chords and their releases overlap from bar to bar
+        sequence.set_voices(render::MELODY_VOICES * 2);
+        let eighth = eighth_ms();
+        let bar = Duration::from_millis(eighth * STEPS as u64);
+        let bass_pattern = BASS_PATTERNS[Self::pick(&mut self.rng, BASS_PATTERNS.len())];
+
+        let mut melody = Vec::new();
+        for (i, &degree) in degrees.iter().enumerate() {
+            let chord = self.chord(degree);
+            melody.extend(self.melody_bar(degree, i == PHRASE_BARS - 1));
+
+            let at = bar * i as u32;
+            if let Some((_, preset)) = &presets[CHORDS] {
+                sequence.set_output(CHORDS);
+                let gate = bar.mul_f32(preset.gate);
+                for &note in &chord {
+                    sequence.note(at, gate, midi_to_freq(note - 12), preset);
+                }
+            }
+            if let Some((_, preset)) = &presets[BASS] {
+                let root = chord[0] - 24;
+                let tones = [root, chord[1] - 24, chord[2] - 24, root + 12];
+                let line: Vec<(String, u64)> = bass_pattern.iter()
+                    .map(|&(eighths, tone)| (note_name(tones[tone]), eighths as u64 * eighth))
+                    .collect();
+                sequence.set_output(BASS);
+                sequence.add_melody(at, preset, &note_refs(&line));
+            }
+        }
+        if let Some((_, preset)) = &presets[LEAD] {
+            sequence.set_output(LEAD);
+            sequence.add_melody(Duration::ZERO, preset, &note_refs(&melody));
+        }
+        // A phrase is always four bars, whatever rings on past them
+        sequence.rest(Self::phrase_length().saturating_sub(sequence.length()));
+
+        self.phrase += 1;
+        Phrase {
+            sequence,
+            chords: degrees.iter().map(|&degree| self.chord_name(degree)).collect(),
+            presets: presets.map(|preset| preset.map(|(name, _)| name).unwrap_or_default()),
+        }
+    }
+
+    /// One bar of melody over the chord on `degree`: chord tones on the
+    /// strong beats, steps and small leaps through the scale between them
+    fn melody_bar(&mut self, degree: i32, last: bool) -> Vec<(String, u64)> {
+        let rhythm: Vec<u32> = match last {
+            true => CADENCES[Self::pick(&mut self.rng, CADENCES.len())].to_vec(),
+            false => self.vary(RHYTHMS[self.rhythm]),
+        };
+        let eighth = eighth_ms();
+        let mut position = 0;
+        let mut notes = Vec::new();
+        for (i, &length) in rhythm.iter().enumerate() {
+            let strong = position % 4 == 0;
+            let held = last && i == rhythm.len() - 1;
+            if !strong && self.rng.next_f32() < 0.1 {
+                notes.push(("REST".to_string(), length as u64 * eighth));
+                position += length;
+                continue;
+            }
+            self.step = if strong || held {
+                self.nearest_chord_step(degree, held)
+            } else {
+                const MOVES: [i32; 7] = [-2, -1, -1, 0, 1, 1, 2];
+                let step = self.step + MOVES[Self::pick(&mut self.rng, MOVES.len())];
+                // Turn back at the edges of the range
+                match step {
+                    s if s < *MELODY_STEPS.start() => self.step + 1,
+                    s if s > *MELODY_STEPS.end() => self.step - 1,
+                    s => s,
+                }
+            };
+            notes.push((note_name(self.pitch(self.step)), length as u64 * eighth));
+            position += length;
+        }
+        notes
+    }
+
+    /// The bar's rhythm, now and then with a note split in two
+    fn vary(&mut self, rhythm: &[u32]) -> Vec<u32> {
+        let mut rhythm = rhythm.to_vec();
+        if self.rng.next_f32() < 0.3 {
+            let i = Self::pick(&mut self.rng, rhythm.len());
+            if rhythm[i] >= 2 {
+                let half = rhythm[i] / 2;
+                rhythm[i] -= half;
+                rhythm.insert(i + 1, half);
+            }
+        }
+        rhythm
+    }
+
+    /// Scale step of the chord tone nearest the last melody note, the root
+    /// if `root` (to end a phrase on)
+    fn nearest_chord_step(&mut self, degree: i32, root: bool) -> i32 {
+        let tones: &[i32] = if root { &[0] } else { &[0, 2, 4] };
+        MELODY_STEPS
+            .filter(|step| tones.iter().any(|tone| (step - degree - tone).rem_euclid(7) == 0))
+            .min_by_key(|step| ((step - self.step).abs(), self.rng.next_u64()))
+            .unwrap_or(self.step)
+    }
+
+    /// MIDI note of a scale step above the key note
+    fn pitch(&self, step: i32) -> i32 {
+        self.key + 12 * step.div_euclid(7) + self.scale.intervals()[step.rem_euclid(7) as usize]
+    }
+
+    /// The triad on a scale degree, root first
+    fn chord(&self, degree: i32) -> [i32; 3] {
+        [self.pitch(degree), self.pitch(degree + 2), self.pitch(degree + 4)]
+    }
+
+    /// Chord symbol of the triad on a degree, e.g. `Am` or `Bdim`
+    fn chord_name(&self, degree: i32) -> String {
+        let [root, third, fifth] = self.chord(degree);
+        let quality = match (third - root, fifth - root) {
+            (4, _) => "",
+            (3, 6) => "dim",
+            _ => "m",
+        };
+        format!("{}{}", pitch_class(root), quality)
+    }
+
+    fn pick(rng: &mut Rng, len: usize) -> usize {
+        ((rng.next_f32() * len as f32) as usize).min(len.saturating_sub(1))
+    }
+}
+
+/// An eighth note at the written tempo, in ms
+fn eighth_ms() -> u64 {
+    (BEATS_PER_BAR * 60_000.0 / synth_data::MELODY_BPM / STEPS as f32).round() as u64
+}
+
+/// Note name without its octave, e.g. `F#`
+fn pitch_class(note: i32) -> String {
+    note_name(note).trim_end_matches(|c: char| c.is_ascii_digit() || c == '-').to_string()
+}
+
+fn note_refs(notes: &[(String, u64)]) -> Vec<(&str, u64)> {
+    notes.iter().map(|(note, ms)| (note.as_str(), *ms)).collect()
+}
@@ -0,0 +1,154 @@
+// src/latency.rs - Round-trip latency measured through a loopback
+
+use std::f32::consts::PI;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::audio::{AudioOutput, OutputConfig};
+use crate::input::{self, AudioInput, CaptureBlock};
+use crate::render::DEFAULT_RENDER_RATE;
+use crate::synth_core::SampleSource;
+
+/// The beep played for each measurement: short, so its onset is sharp, and
+/// at 1 kHz, where speakers and microphones are at their best
+const BEEP: Duration = Duration::from_millis(5);
+const BEEP_FREQ: f32 = 1000.0;
+const BEEP_LEVEL: f32 = 0.5;
+
+/// The input is listened to this long before the first beep, to learn its
+/// noise floor...
+const SETTLE: Duration = Duration::from_millis(300);
+
+/// ...and a beep must come back this far above it (and above
+/// `MIN_THRESHOLD`) to count
+const THRESHOLD_RATIO: f32 = 4.0;
+const MIN_THRESHOLD: f32 = 0.01;
+
+/// Longest a beep is waited for before it counts as missed
+const LISTEN: Duration = Duration::from_secs(1);
+
+/// Time left for a beep's echoes to die away before the next one
+const GAP: Duration = Duration::from_millis(250);
+
+/// Plays a beep when fired, noting when its first sample was rendered
+struct Beep {
+    sample_rate: f32,
+    /// Samples of the beep still to play
+    remaining: usize,
+    elapsed: usize,
+    sent: Option<Instant>,
+}
+
+impl Beep {
+    fn new() -> Self {
+        Self { sample_rate: DEFAULT_RENDER_RATE, remaining: 0, elapsed: 0, sent: None }
+    }
+
+    fn fire(&mut self) {
+        self.remaining = (BEEP.as_secs_f32() * self.sample_rate) as usize;
+        self.elapsed = 0;
+        self.sent = None;
+    }
+}
+
+impl SampleSource for Beep {
+    fn next_sample(&mut self) -> f32 {
+        if self.remaining == 0 {
+            return 0.0;
+        }
+        if self.elapsed == 0 {
+            self.sent = Some(Instant::now());
+        }
+        let t = self.elapsed as f32 / self.sample_rate;
+        self.elapsed += 1;
+        self.remaining -= 1;
+        (2.0 * PI * BEEP_FREQ * t).sin() * BEEP_LEVEL
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+}
+
+/// Round trips measured by `measure_loopback`
+#[derive(Clone, Debug, Default)]
+pub struct Loopback {
+    /// Name of the input listened to
+    pub input: String,
+    /// Time from rendering each beep to hearing it back, for the beeps
+    /// that were heard
+    pub round_trips: Vec<Duration>,
+    /// Beeps never heard back
+    pub missed: usize,
+}
+
+impl Loopback {
+    /// The middle round trip, which a stray noise can't move
+    pub fn median(&self) -> Option<Duration> {
+        let mut sorted = self.round_trips.clone();
+        sorted.sort();
+        sorted.get(sorted.len() / 2).copied()
+    }
+}
+
+/// Measure the round-trip latency of the output and an input, by playing
+/// `tries` beeps through the output and timing how long each takes to
+/// arrive at the input (`input_device`, or the host's default). The output
+/// has to reach the input: a cable from output to input, or speakers near a
+/// microphone. Includes the output's and input's buffering and converters,
+/// to within a millisecond or so.
+pub fn measure_loopback(output: &OutputConfig, input_device: Option<&str>, tries: usize) -> anyhow::Result<Loopback> {
+    let beep = Arc::new(Mutex::new(Beep::new()));
+    let _output = AudioOutput::start(output.clone(), Arc::clone(&beep))?;
+    let input = AudioInput::start(output, input_device, 0.0)?;
+    let capture = input::start_capture();
+    let result = listen(&beep, &capture, tries.max(1));
+    input::stop_capture();
+    result.map(|(round_trips, missed)| Loopback { input: input.device_name().to_string(), round_trips, missed })
+}
+
+/// Fire each beep and wait for it on the capture
+fn listen(beep: &Mutex<Beep>, capture: &mpsc::Receiver<CaptureBlock>, tries: usize) -> anyhow::Result<(Vec<Duration>, usize)> {
+    std::thread::sleep(SETTLE);
+    let noise = capture.try_iter()
+        .flat_map(|block| block.samples)
+        .fold(0f32, |peak, sample| peak.max(sample.abs()));
+    let threshold = (noise * THRESHOLD_RATIO).max(MIN_THRESHOLD);
+    if threshold >= BEEP_LEVEL {
+        anyhow::bail!("The input is too noisy to hear the beeps (peak {:.2})", noise);
+    }
+
+    let mut round_trips = Vec::new();
+    let mut missed = 0;
+    for _ in 0..tries {
+        capture.try_iter().for_each(drop);
+        // The callback holds the lock for a whole buffer, so the beep starts
+        // at the top of the next one
+        beep.lock().unwrap().fire();
+        let start = Instant::now();
+        let mut heard = None;
+        while heard.is_none() && start.elapsed() < LISTEN {
+            let Ok(block) = capture.recv_timeout(LISTEN) else {
+                break;
+            };
+            let Some(sent) = beep.lock().unwrap().sent else {
+                continue;
+            };
+            heard = onset(&block, threshold).and_then(|at| at.checked_duration_since(sent));
+        }
+        match heard {
+            Some(round_trip) => round_trips.push(round_trip),
+            None => missed += 1,
+        }
+        std::thread::sleep(GAP);
+    }
+    Ok((round_trips, missed))
+}
+
+/// When the first sample of `block` above `threshold` arrived, counting
+/// back from the callback at a sample period per sample
+fn onset(block: &CaptureBlock, threshold: f32) -> Option<Instant> {
+    let index = block.samples.iter().position(|sample| sample.abs() > threshold)?;
+    let after = (block.samples.len() - index) as f64 / block.sample_rate.max(1) as f64;
+    block.received.checked_sub(Duration::from_secs_f64(after))
+}
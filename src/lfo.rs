@@ -0,0 +1,140 @@
+// src/lfo.rs - Low-frequency oscillator for vibrato and timbre movement
+
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::rng::Rng;
+
+/// Fastest LFO rate accepted by `FMParams::validate` (Hz)
+pub const MAX_LFO_RATE: f32 = 50.0;
+
+/// LFO waveform
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LfoShape {
+    #[default]
+    Sine,
+    Triangle,
+    Square,
+    /// Rising ramp
+    Saw,
+    /// A new random level each cycle, held until the next
+    SampleHold,
+    /// Random levels each cycle, glided between
+    SmoothRandom,
+}
+
+impl LfoShape {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "sine" | "sin" => Some(LfoShape::Sine),
+            "triangle" | "tri" => Some(LfoShape::Triangle),
+            "square" | "sqr" => Some(LfoShape::Square),
+            "saw" | "sawtooth" => Some(LfoShape::Saw),
+            "sample-hold" | "s&h" | "sh" => Some(LfoShape::SampleHold),
+            "smooth-random" | "random" => Some(LfoShape::SmoothRandom),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LfoShape::Sine => "sine",
+            LfoShape::Triangle => "triangle",
+            LfoShape::Square => "square",
+            LfoShape::Saw => "saw",
+            LfoShape::SampleHold => "sample-hold",
+            LfoShape::SmoothRandom => "smooth-random",
+        }
+    }
+}
+
+/// A free-running LFO with output in [-1, 1]. The random shapes draw from
+/// a seeded `Rng`, so a given seed always produces the same movement.
+#[derive(Clone, Debug)]
+pub struct Lfo {
+    shape: LfoShape,
+    rate: f32,
+    sample_rate: f32,
+    /// Position in the current cycle, in [0, 1)
+    phase: f32,
+    rng: Rng,
+    seed: u64,
+    /// Random level for this cycle, and the one before it
+    level: f32,
+    previous: f32,
+}
+
+impl Lfo {
+    pub fn new(sample_rate: f32, shape: LfoShape, rate: f32, seed: u64) -> Self {
+        let mut lfo = Self {
+            shape,
+            rate,
+            sample_rate,
+            phase: 0.0,
+            rng: Rng::new(seed),
+            seed,
+            level: 0.0,
+            previous: 0.0,
+        };
+        lfo.reseed(seed);
+        lfo
+    }
+
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape;
+    }
+
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    /// Restart the random sequence from `seed` (only if it changed)
+    pub fn set_seed(&mut self, seed: u64) {
+        if seed != self.seed {
+            self.reseed(seed);
+        }
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+        self.previous = self.rng.next_bipolar();
+        self.level = self.rng.next_bipolar();
+    }
+
+    /// Restart the cycle (the random sequence carries on)
+    pub fn reset_phase(&mut self) {
+        self.phase = 0.0;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Current output, then advance one sample
+    pub fn next_value(&mut self) -> f32 {
+        let t = self.phase;
+        let value = match self.shape {
+            LfoShape::Sine => (2.0 * PI * t).sin(),
+            LfoShape::Triangle => 1.0 - 4.0 * (t - 0.5).abs(),
+            LfoShape::Square => if t < 0.5 { 1.0 } else { -1.0 },
+            LfoShape::Saw => 2.0 * t - 1.0,
+            LfoShape::SampleHold => self.level,
+            LfoShape::SmoothRandom => {
+                // Cosine interpolation keeps the glide free of corners
+                let blend = 0.5 - 0.5 * (PI * t).cos();
+                self.previous + (self.level - self.previous) * blend
+            }
+        };
+
+        self.phase += self.rate / self.sample_rate;
+        if self.phase >= 1.0 {
+            self.phase -= self.phase.floor();
+            self.previous = self.level;
+            self.level = self.rng.next_bipolar();
+        }
+        value
+    }
+}
@@ -2,21 +2,181 @@
 
 pub mod synth_core;
 pub mod synth_data;
+pub mod analysis;
+pub mod chord;
+pub mod drums;
+pub mod dx7;
+pub mod effects;
+pub mod jam;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+#[cfg(feature = "fixed-point")]
+pub mod fixed;
+pub mod lfo;
+pub mod macros;
+pub mod midi;
+#[cfg(all(feature = "mobile", not(target_arch = "wasm32")))]
+pub mod mobile;
+pub mod mutate;
+#[cfg(all(feature = "node", not(target_arch = "wasm32")))]
+pub mod node;
+pub mod operator;
+pub mod render;
+pub mod resample;
+pub mod resynth;
+pub mod rng;
+pub mod sequencer;
+pub mod testsignal;
+pub mod train;
+pub mod wav;
+pub mod waveform;
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+pub mod audio;
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+pub mod external;
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+pub mod input;
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+pub mod latency;
+#[cfg(all(feature = "playback", not(target_arch = "wasm32")))]
+pub mod config;
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+pub mod player;
+#[cfg(all(feature = "audio", not(target_arch = "wasm32")))]
+pub mod recorder;
 
+#[cfg(target_arch = "wasm32")]
+use std::cell::{Cell, RefCell};
+#[cfg(target_arch = "wasm32")]
+use std::collections::HashMap;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 #[cfg(target_arch = "wasm32")]
-use web_sys::AudioContext;
+use web_sys::{AudioContext, AudioContextState, AudioNode, GainNode, OscillatorNode, OscillatorType};
+#[cfg(target_arch = "wasm32")]
+use crate::macros::{Macro, MAX_MACROS};
+#[cfg(target_arch = "wasm32")]
+use crate::midi::{MidiMessage, ProgramSelect};
+#[cfg(target_arch = "wasm32")]
+use crate::synth_core::{FMParams, Waveform};
+
+/// How far ahead of the AudioContext clock melody notes are scheduled (seconds)
+#[cfg(target_arch = "wasm32")]
+const LOOKAHEAD: f64 = 0.1;
+
+/// How often the melody scheduler wakes to schedule more notes
+#[cfg(target_arch = "wasm32")]
+const SCHEDULER_INTERVAL_MS: i32 = 25;
+
+/// Delay before the first note, so it isn't scheduled in the past (seconds)
+#[cfg(target_arch = "wasm32")]
+const START_DELAY: f64 = 0.05;
+
+/// Most harmonics allowed in a `PeriodicWave` operator
+#[cfg(target_arch = "wasm32")]
+const MAX_WAVE_HARMONICS: usize = 1024;
+
+/// A preset as `list_presets` returns it to JavaScript
+#[cfg(target_arch = "wasm32")]
+#[derive(serde::Serialize)]
+struct PresetInfo<'a> {
+    index: usize,
+    name: &'a str,
+    params: &'a FMParams,
+}
+
+/// A melody as `list_melodies` returns it to JavaScript
+#[cfg(target_arch = "wasm32")]
+#[derive(serde::Serialize)]
+struct MelodyInfo<'a> {
+    index: usize,
+    name: &'a str,
+    notes: Vec<NoteInfo<'a>>,
+}
+
+/// A macro knob of the live preset as `list_macros` returns it to
+/// JavaScript
+#[cfg(target_arch = "wasm32")]
+#[derive(serde::Serialize)]
+struct MacroInfo<'a> {
+    index: usize,
+    name: &'a str,
+    value: f32,
+}
+
+#[cfg(target_arch = "wasm32")]
+#[derive(serde::Serialize)]
+struct NoteInfo<'a> {
+    note: &'a str,
+    duration_ms: u64,
+}
+
+/// Pitch bend range of the MIDI input, in cents either way
+#[cfg(target_arch = "wasm32")]
+const BEND_RANGE_CENTS: f32 = 200.0;
+
+/// The audio nodes of one note
 #[cfg(target_arch = "wasm32")]
-use crate::synth_core::FMParams;
+struct Voice {
+    carrier: OscillatorNode,
+    modulator: OscillatorNode,
+    /// Modulation depth in Hz: the modulation index times the note frequency
+    modulation: GainNode,
+    gain: GainNode,
+}
+
+/// A note held from `note_on` until `note_off`
 #[cfg(target_arch = "wasm32")]
-use crate::synth_data::{get_melodies, get_presets, note_freq};
+struct HeldNote {
+    voice: Voice,
+    freq: f32,
+    /// Poly aftertouch (0.0 - 1.0)
+    pressure: f32,
+    /// Parameters resolved for the note, with velocity applied
+    params: FMParams,
+    start: f64,
+    /// Released while the sustain pedal was down
+    sustained: bool,
+}
+
+/// Wait on a browser timer
+#[cfg(target_arch = "wasm32")]
+async fn sleep_ms(ms: i32) -> Result<(), JsValue> {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let window = web_sys::window().unwrap();
+        window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms).unwrap();
+    });
+    wasm_bindgen_futures::JsFuture::from(promise).await?;
+    Ok(())
+}
 
 // WebAssembly exports
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 pub struct WebFMSynth {
     context: AudioContext,
+    /// Master volume; every note is routed through it to the destination
+    master: GainNode,
+    /// Harmonics in the `PeriodicWave` built for square and saw operators;
+    /// 0 uses the browser's built-in oscillator shapes
+    wave_harmonics: usize,
+    /// Called as each melody note is scheduled
+    on_note: Option<js_sys::Function>,
+    /// Preset played by `note_on` and MIDI input
+    live_preset: Cell<usize>,
+    /// The live preset as its macros have left it
+    live_patch: RefCell<FMParams>,
+    /// Sounding `note_on` notes by MIDI note number
+    held: RefCell<HashMap<u8, HeldNote>>,
+    sustain_pedal: Cell<bool>,
+    /// Current pitch bend, in cents
+    bend: Cell<f32>,
+    /// Channel aftertouch (0.0 - 1.0)
+    pressure: Cell<f32>,
+    /// How far full aftertouch raises the modulation index (1.0 doubles it)
+    pressure_depth: Cell<f32>,
+    /// Bank select state, for program changes
+    programs: RefCell<ProgramSelect>,
     presets: Vec<(&'static str, synth_core::FMParams)>,
     melodies: Vec<(&'static str, Vec<(&'static str, u64)>)>,
 }
@@ -30,98 +190,497 @@ impl WebFMSynth {
         console_error_panic_hook::set_once();
         
         let context = AudioContext::new()?;
+        let master = context.create_gain()?;
+        master.connect_with_audio_node(&context.destination())?;
+        let presets = synth_data::get_presets();
         Ok(WebFMSynth {
             context,
-            presets: synth_data::get_presets(),
+            master,
+            wave_harmonics: 0,
+            on_note: None,
+            live_preset: Cell::new(0),
+            live_patch: RefCell::new(presets[0].1.clamped()),
+            held: RefCell::new(HashMap::new()),
+            sustain_pedal: Cell::new(false),
+            bend: Cell::new(0.0),
+            pressure: Cell::new(0.0),
+            pressure_depth: Cell::new(1.0),
+            programs: RefCell::new(ProgramSelect::new()),
+            presets,
             melodies: synth_data::get_melodies(),
         })
     }
 
-    pub fn list_presets(&self) -> String {
-        self.presets.iter()
+    /// Start (or restart) audio processing. Browsers create contexts
+    /// suspended until a user gesture, so call this from a click handler;
+    /// the `play_*` methods also call it.
+    pub async fn resume(&self) -> Result<(), JsValue> {
+        if self.context.state() != AudioContextState::Running {
+            wasm_bindgen_futures::JsFuture::from(self.context.resume()?).await?;
+        }
+        Ok(())
+    }
+
+    /// Pause audio processing, freezing the AudioContext clock
+    pub async fn suspend(&self) -> Result<(), JsValue> {
+        wasm_bindgen_futures::JsFuture::from(self.context.suspend()?).await?;
+        Ok(())
+    }
+
+    /// `"suspended"`, `"running"`, or `"closed"`
+    pub fn state(&self) -> String {
+        match self.context.state() {
+            AudioContextState::Suspended => "suspended",
+            AudioContextState::Running => "running",
+            AudioContextState::Closed => "closed",
+            _ => "unknown",
+        }
+        .to_string()
+    }
+
+    /// Route the synth's output into `node` (an effect, analyser, or
+    /// MediaStreamAudioDestinationNode) instead of the speakers. The node
+    /// must belong to this synth's AudioContext, `output_node().context`.
+    pub fn connect_to(&self, node: &AudioNode) -> Result<(), JsValue> {
+        self.master.disconnect()?;
+        self.master.connect_with_audio_node(node)?;
+        Ok(())
+    }
+
+    /// The master gain every note passes through, for routing by hand; it
+    /// starts connected to the context's destination
+    pub fn output_node(&self) -> GainNode {
+        self.master.clone()
+    }
+
+    /// Set the master volume (0.0 - 1.0), ramping briefly to avoid clicks
+    pub fn set_volume(&self, gain: f32) -> Result<(), JsValue> {
+        let gain = if gain.is_finite() { gain.clamp(0.0, 1.0) } else { 1.0 };
+        let param = self.master.gain();
+        let now = self.context.current_time();
+        param.cancel_scheduled_values(now)?;
+        param.set_value_at_time(param.value(), now)?;
+        param.linear_ramp_to_value_at_time(gain, now + 0.02)?;
+        Ok(())
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.master.gain().value()
+    }
+
+    /// Set the concert pitch (A4 in Hz, default 440) for notes started
+    /// from now on
+    pub fn set_a4(&self, freq: f32) {
+        synth_data::set_a4(freq);
+    }
+
+    pub fn a4(&self) -> f32 {
+        synth_data::a4()
+    }
+
+    /// Build square and saw operators from a `PeriodicWave` with this many
+    /// harmonics (the same Fourier series as the native oscillators), or
+    /// pass 0 for the browser's built-in shapes
+    pub fn set_wave_harmonics(&mut self, harmonics: usize) {
+        self.wave_harmonics = harmonics.min(MAX_WAVE_HARMONICS);
+    }
+
+    /// Call `callback(note, index, time)` for each note of a melody as it
+    /// is scheduled, where `index` counts the melody's notes (rests
+    /// included) from 0 and `time` is when the note sounds on the
+    /// `current_time()` clock. Pass `undefined` to remove it.
+    pub fn on_note(&mut self, callback: Option<js_sys::Function>) {
+        self.on_note = callback;
+    }
+
+    /// The AudioContext clock, in seconds
+    pub fn current_time(&self) -> f64 {
+        self.context.current_time()
+    }
+
+    /// Presets as an array of `{ index, name, params }`, where `index`
+    /// is what `play_melody` takes and `params` holds every preset field
+    pub fn list_presets(&self) -> Result<JsValue, JsValue> {
+        let presets: Vec<_> = self.presets.iter()
             .enumerate()
-            .map(|(i, (name, _))| format!("{}. {}", i + 1, name))
-            .collect::<Vec<_>>()
-            .join("\n")
+            .map(|(index, (name, params))| PresetInfo { index, name, params })
+            .collect();
+        Ok(serde_wasm_bindgen::to_value(&presets)?)
     }
 
-    pub fn list_melodies(&self) -> String {
-        self.melodies.iter()
+    /// Melodies as an array of `{ index, name, notes }`, where each note is
+    /// `{ note, duration_ms }` and `note` is `"REST"` for a rest
+    pub fn list_melodies(&self) -> Result<JsValue, JsValue> {
+        let melodies: Vec<_> = self.melodies.iter()
             .enumerate()
-            .map(|(i, (name, _))| format!("{}. {}", i + 1, name))
-            .collect::<Vec<_>>()
-            .join("\n")
+            .map(|(index, (name, notes))| MelodyInfo {
+                index,
+                name,
+                notes: notes.iter().map(|&(note, duration_ms)| NoteInfo { note, duration_ms }).collect(),
+            })
+            .collect();
+        Ok(serde_wasm_bindgen::to_value(&melodies)?)
     }
 
+    /// Play a melody, scheduling notes on the AudioContext clock a little
+    /// ahead of time so timer throttling in background tabs can't push them
+    /// out of time. Resolves once the last note has finished.
     pub async fn play_melody(&self, preset_idx: usize, melody_idx: usize) -> Result<(), JsValue> {
         if preset_idx >= self.presets.len() || melody_idx >= self.melodies.len() {
             return Err(JsValue::from_str("Invalid preset or melody index"));
         }
 
-        let preset = &self.presets[preset_idx].1;
+        let preset = &self.presets[preset_idx].1.clamped();
         let melody = &self.melodies[melody_idx].1;
+        self.resume().await?;
+
+        let mut at = self.context.current_time() + START_DELAY;
+        for (index, (note, duration)) in melody.iter().enumerate() {
+            // Wait until the note is inside the lookahead window
+            while at > self.context.current_time() + LOOKAHEAD {
+                sleep_ms(SCHEDULER_INTERVAL_MS).await?;
+            }
 
-        for (note, duration) in melody {
+            let length = *duration as f64 / 1000.0;
             let freq = synth_data::note_freq(note);
+            let (_, articulation) = synth_data::parse_articulation(note);
+            // A sliding note (`C4~`) is held for its whole length, gliding to the next pitch
+            let slide_to = melody.get(index + 1)
+                .map(|(next, _)| synth_data::note_freq(next))
+                .filter(|&to| articulation.slide && to > 0.0);
             if freq > 0.0 {
-                self.play_note(freq, preset, *duration as f32 / 1000.0)?;
+                // Same gates as native playback; the release overlaps the next note
+                let gate = if slide_to.is_some() { length } else { length * articulation.gate(preset.gate) as f64 };
+                let accented = articulation.accent.then(|| preset.accented());
+                self.play_note(freq, accented.as_ref().unwrap_or(preset), at, gate, slide_to)?;
+            }
+            if let Some(callback) = &self.on_note {
+                callback.call3(&JsValue::NULL, &JsValue::from_str(note), &JsValue::from(index as u32), &JsValue::from(at))?;
             }
-            
-            // Wait for note duration
-            let promise = js_sys::Promise::new(&mut |resolve, _| {
-                let window = web_sys::window().unwrap();
-                window.set_timeout_with_callback_and_timeout_and_arguments_0(
-                    &resolve,
-                    *duration as i32,
-                ).unwrap();
-            });
-            wasm_bindgen_futures::JsFuture::from(promise).await?;
+            at += length;
         }
 
+        let end = at + preset.release as f64;
+        while self.context.current_time() < end {
+            sleep_ms(SCHEDULER_INTERVAL_MS).await?;
+        }
         Ok(())
     }
 
-    fn play_note(&self, freq: f32, preset: &synth_core::FMParams, duration: f32) -> Result<(), JsValue> {
-        let current_time = self.context.current_time();
-        
+    /// Choose the preset for `note_on` and MIDI input (0-based)
+    pub fn set_preset(&self, preset_idx: usize) -> Result<(), JsValue> {
+        if preset_idx >= self.presets.len() {
+            return Err(JsValue::from_str("Invalid preset index"));
+        }
+        self.select_preset(preset_idx);
+        Ok(())
+    }
+
+    /// Macro knobs of the live preset as an array of `{ index, name, value }`,
+    /// `value` being where the knob stands (0.0 - 1.0)
+    pub fn list_macros(&self) -> Result<JsValue, JsValue> {
+        let macros = self.live_macros();
+        let patch = self.live_patch.borrow();
+        let info: Vec<_> = macros.iter()
+            .enumerate()
+            .map(|(index, knob)| MacroInfo { index, name: &knob.name, value: knob.position(&patch) })
+            .collect();
+        Ok(serde_wasm_bindgen::to_value(&info)?)
+    }
+
+    /// Turn a macro knob of the live preset (0-based) to `value` (0.0 -
+    /// 1.0). Notes started afterwards use the new settings; choosing a
+    /// preset resets its macros.
+    pub fn set_macro(&self, index: usize, value: f32) -> Result<(), JsValue> {
+        let macros = self.live_macros();
+        let Some(knob) = macros.get(index) else {
+            return Err(JsValue::from_str("Invalid macro index"));
+        };
+        let value = if value.is_finite() { value } else { 0.5 };
+        knob.apply(&mut self.live_patch.borrow_mut(), value);
+        Ok(())
+    }
+
+    /// Start a MIDI note (60 = C4) now, holding it until `note_off`.
+    /// `velocity` (1 - 127) scales the preset's amplitude and, by its
+    /// `velocity_index`, its brightness.
+    pub fn note_on(&self, note: u8, velocity: u8) -> Result<(), JsValue> {
+        if self.context.state() != AudioContextState::Running {
+            // Can't wait here; notes start as soon as the context runs
+            let _ = self.context.resume();
+        }
+        self.release_note(note)?;
+
+        let freq = synth_data::midi_to_freq(note as i32);
+        let params = self.live_patch.borrow()
+            .for_note(freq)
+            .for_velocity(midi::unit(velocity));
+        let start = self.context.current_time();
+        let voice = self.create_voice(freq, &params, start)?;
+        let gain = voice.gain.gain();
+        gain.set_value_at_time(0.0, start)?;
+        gain.linear_ramp_to_value_at_time(params.gain(), start + params.attack as f64)?;
+        gain.linear_ramp_to_value_at_time(
+            params.gain() * params.sustain,
+            start + (params.attack + params.decay) as f64,
+        )?;
+        let held = HeldNote { voice, freq, pressure: 0.0, params, start, sustained: false };
+        self.apply_pressure(&held)?;
+        self.held.borrow_mut().insert(note, held);
+        Ok(())
+    }
+
+    /// How far full aftertouch raises the modulation index of held notes,
+    /// so pressing harder brightens them: 1.0 (the default) doubles it, 0
+    /// turns aftertouch off
+    pub fn set_pressure_depth(&self, depth: f32) -> Result<(), JsValue> {
+        let depth = if depth.is_finite() { depth.clamp(0.0, 10.0) } else { 1.0 };
+        self.pressure_depth.set(depth);
+        for held in self.held.borrow().values() {
+            self.apply_pressure(held)?;
+        }
+        Ok(())
+    }
+
+    /// Release a note started by `note_on`, unless the sustain pedal is
+    /// down, in which case it's released with the pedal
+    pub fn note_off(&self, note: u8) -> Result<(), JsValue> {
+        if self.sustain_pedal.get() {
+            if let Some(held) = self.held.borrow_mut().get_mut(&note) {
+                held.sustained = true;
+            }
+            return Ok(());
+        }
+        self.release_note(note)
+    }
+
+    /// Release every note started by `note_on`
+    pub fn all_notes_off(&self) -> Result<(), JsValue> {
+        let notes: Vec<u8> = self.held.borrow().keys().copied().collect();
+        for note in notes {
+            self.release_note(note)?;
+        }
+        Ok(())
+    }
+
+    /// Handle a raw MIDI message, such as the `data` of a WebMIDI
+    /// `midimessage` event: notes, program change and bank select (preset
+    /// slot, ignored past the last preset), pitch bend (±2 semitones),
+    /// channel and poly aftertouch (modulation index), and the volume,
+    /// sustain, macro (20 - 23), and all-notes-off controllers. Messages on
+    /// every channel are played.
+    pub fn midi_message(&self, data: &[u8]) -> Result<(), JsValue> {
+        match MidiMessage::parse(data) {
+            Some(MidiMessage::NoteOn { note, velocity, .. }) => self.note_on(note, velocity),
+            Some(MidiMessage::NoteOff { note, .. }) => self.note_off(note),
+            Some(MidiMessage::ProgramChange { channel, program }) => {
+                let slot = self.programs.borrow().slot(channel, program);
+                if slot < self.presets.len() {
+                    self.select_preset(slot);
+                }
+                Ok(())
+            }
+            Some(MidiMessage::PitchBend { value, .. }) => {
+                self.bend.set(value as f32 / 8192.0 * BEND_RANGE_CENTS);
+                for held in self.held.borrow().values() {
+                    self.apply_bend(&held.voice);
+                }
+                Ok(())
+            }
+            Some(MidiMessage::ChannelPressure { pressure, .. }) => {
+                self.pressure.set(midi::unit(pressure));
+                for held in self.held.borrow().values() {
+                    self.apply_pressure(held)?;
+                }
+                Ok(())
+            }
+            Some(MidiMessage::PolyPressure { note, pressure, .. }) => {
+                if let Some(held) = self.held.borrow_mut().get_mut(&note) {
+                    held.pressure = midi::unit(pressure);
+                    self.apply_pressure(held)?;
+                }
+                Ok(())
+            }
+            Some(MidiMessage::ControlChange { channel, controller, value }) => match controller {
+                midi::CC_BANK_SELECT | midi::CC_BANK_SELECT_LSB => {
+                    self.programs.borrow_mut().control_change(channel, controller, value);
+                    Ok(())
+                }
+                midi::CC_VOLUME => self.set_volume(midi::unit(value)),
+                midi::CC_SUSTAIN => {
+                    let down = value >= 64;
+                    self.sustain_pedal.set(down);
+                    if !down {
+                        let sustained: Vec<u8> = self.held.borrow().iter()
+                            .filter(|(_, held)| held.sustained)
+                            .map(|(note, _)| *note)
+                            .collect();
+                        for note in sustained {
+                            self.release_note(note)?;
+                        }
+                    }
+                    Ok(())
+                }
+                midi::CC_ALL_SOUND_OFF | midi::CC_ALL_NOTES_OFF => self.all_notes_off(),
+                // Controllers past the preset's last macro do nothing
+                c if (midi::CC_MACRO_FIRST..midi::CC_MACRO_FIRST + MAX_MACROS as u8).contains(&c) => {
+                    let index = (c - midi::CC_MACRO_FIRST) as usize;
+                    if index < self.live_macros().len() {
+                        self.set_macro(index, midi::unit(value))?;
+                    }
+                    Ok(())
+                }
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Render a melody offline with the native synth engine and return it
+    /// as a 16-bit WAV file, without playing it
+    pub fn render_melody_to_wav(&self, preset_idx: usize, melody_idx: usize) -> Result<Vec<u8>, JsValue> {
+        if preset_idx >= self.presets.len() || melody_idx >= self.melodies.len() {
+            return Err(JsValue::from_str("Invalid preset or melody index"));
+        }
+        let preset = self.presets[preset_idx].1.clamped();
+        let sample_rate = self.context.sample_rate();
+        let samples = render::render_melody(sample_rate, &preset, &self.melodies[melody_idx].1);
+        Ok(wav::encode_wav(sample_rate as u32, &samples, wav::BitDepth::Int16))
+    }
+
+    /// Schedule one note to start at `current_time` on the AudioContext
+    /// clock, held for `gate` seconds and then released, with the preset's
+    /// ADSR envelope. With `slide_to`, the pitch moves continuously to that
+    /// frequency over the gate.
+    fn play_note(&self, freq: f32, preset: &synth_core::FMParams, current_time: f64, gate: f64, slide_to: Option<f32>) -> Result<(), JsValue> {
+        let note = preset.for_note(freq);
+        let voice = self.create_voice(freq, &note, current_time)?;
+        let gain_param = voice.gain.gain();
+
+        if let Some(to) = slide_to {
+            // Linear in cents is exponential in Hz, like the native slide.
+            // Detune scales the carrier's modulated frequency as a whole, so
+            // the deviation follows the pitch and the modulation gain stays
+            let (bend, cents) = (self.bend.get(), 1200.0 * (to / freq).log2());
+            for detune in [voice.carrier.detune(), voice.modulator.detune()] {
+                detune.set_value_at_time(bend, current_time)?;
+                detune.linear_ramp_to_value_at_time(bend + cents, current_time + gate)?;
+            }
+        }
+
+        // ADSR envelope with the same linear stages as the native one; a
+        // note released early releases from wherever its ramp has reached
+        let (attack, decay) = (preset.attack as f64, preset.decay as f64);
+        let release_at = current_time + gate;
+        gain_param.set_value_at_time(0.0, current_time)?;
+        if gate > attack {
+            gain_param.linear_ramp_to_value_at_time(preset.gain(), current_time + attack)?;
+            if gate > attack + decay {
+                gain_param.linear_ramp_to_value_at_time(preset.gain() * preset.sustain, current_time + attack + decay)?;
+            }
+        }
+        let held_level = preset.gain() * preset.held_level(gate as f32);
+        gain_param.linear_ramp_to_value_at_time(held_level, release_at)?;
+        gain_param.linear_ramp_to_value_at_time(0.0, release_at + preset.release as f64)?;
+
+        // Stop oscillators once the release has finished
+        let stop_time = release_at + preset.release as f64 + 0.1;
+        voice.modulator.stop_with_when(stop_time)?;
+        voice.carrier.stop_with_when(stop_time)?;
+
+        Ok(())
+    }
+
+    /// Make a preset the live one, with its macros where the preset has them
+    fn select_preset(&self, preset_idx: usize) {
+        self.live_preset.set(preset_idx);
+        *self.live_patch.borrow_mut() = self.presets[preset_idx].1.clamped();
+    }
+
+    /// Macros of the live preset, centred on the preset as it was chosen
+    fn live_macros(&self) -> Vec<Macro> {
+        let (name, params) = &self.presets[self.live_preset.get()];
+        crate::macros::preset_macros(&synth_data::preset_meta(name).macros, params)
+    }
+
+    /// Release a held note from wherever its envelope has reached
+    fn release_note(&self, note: u8) -> Result<(), JsValue> {
+        let Some(held) = self.held.borrow_mut().remove(&note) else {
+            return Ok(());
+        };
+        let now = self.context.current_time();
+        let params = &held.params;
+        let level = params.gain() * params.held_level((now - held.start) as f32);
+        let gain = held.voice.gain.gain();
+        gain.cancel_scheduled_values(now)?;
+        gain.set_value_at_time(level, now)?;
+        gain.linear_ramp_to_value_at_time(0.0, now + params.release as f64)?;
+
+        let stop_time = now + params.release as f64 + 0.1;
+        held.voice.modulator.stop_with_when(stop_time)?;
+        held.voice.carrier.stop_with_when(stop_time)?;
+        Ok(())
+    }
+
+    /// Raise a held note's modulation index by its aftertouch (the greater
+    /// of channel and poly pressure), smoothing the change
+    fn apply_pressure(&self, held: &HeldNote) -> Result<(), JsValue> {
+        let pressure = self.pressure.get().max(held.pressure);
+        let depth = held.params.modulation_index * held.freq * (1.0 + self.pressure_depth.get() * pressure);
+        held.voice.modulation.gain().set_target_at_time(depth, self.context.current_time(), 0.02)?;
+        Ok(())
+    }
+
+    fn apply_bend(&self, voice: &Voice) {
+        voice.carrier.detune().set_value(self.bend.get());
+        voice.modulator.detune().set_value(self.bend.get());
+    }
+
+    /// Build the FM chain for a note with parameters already resolved for
+    /// it, starting at `start`, with its output gain (the envelope) at 1
+    fn create_voice(&self, freq: f32, note: &FMParams, start: f64) -> Result<Voice, JsValue> {
         // Create carrier oscillator
         let carrier = self.context.create_oscillator()?;
         carrier.frequency().set_value(freq);
+        self.set_waveform(&carrier, note.carrier_wave)?;
         
         // Create modulator oscillator
         let modulator = self.context.create_oscillator()?;
-        let freq_ratio = freq / 440.0;
-        modulator.frequency().set_value(preset.modulator_freq * freq_ratio);
+        modulator.frequency().set_value(note.modulator_freq);
+        self.set_waveform(&modulator, note.modulator_wave)?;
         
         // Create modulation gain
         let mod_gain = self.context.create_gain()?;
-        mod_gain.gain().set_value(preset.modulation_index * freq);
+        mod_gain.gain().set_value(note.modulation_index * freq);
         
-        // Create output gain with envelope
+        // Create output gain for the envelope
         let output_gain = self.context.create_gain()?;
-        let gain_param = output_gain.gain();
-        
-        // ADSR envelope
-        gain_param.set_value_at_time(0.0, current_time)?;
-        gain_param.linear_ramp_to_value_at_time(preset.amplitude, current_time + 0.01)?;
-        gain_param.exponential_ramp_to_value_at_time(preset.amplitude * 0.7, current_time + 0.1)?;
-        gain_param.linear_ramp_to_value_at_time(0.001, current_time + duration as f64)?;
         
         // Connect FM synthesis chain
         modulator.connect_with_audio_node(&mod_gain)?;
         mod_gain.connect_with_audio_param(&carrier.frequency())?;
         carrier.connect_with_audio_node(&output_gain)?;
-        output_gain.connect_with_audio_node(&self.context.destination())?;
+        output_gain.connect_with_audio_node(&self.master)?;
         
         // Start oscillators
-        modulator.start()?;
-        carrier.start()?;
-        
-        // Stop oscillators after duration
-        let stop_time = current_time + duration as f64 + 0.1;
-        modulator.stop_with_when(stop_time)?;
-        carrier.stop_with_when(stop_time)?;
-        
+        let voice = Voice { carrier, modulator, modulation: mod_gain, gain: output_gain };
+        self.apply_bend(&voice);
+        voice.modulator.start_with_when(start)?;
+        voice.carrier.start_with_when(start)?;
+        Ok(voice)
+    }
+
+    fn set_waveform(&self, oscillator: &OscillatorNode, waveform: Waveform) -> Result<(), JsValue> {
+        match waveform {
+            Waveform::Sine => oscillator.set_type(OscillatorType::Sine),
+            _ if self.wave_harmonics > 0 => {
+                let (mut real, mut imag) = waveform.fourier_series(self.wave_harmonics);
+                let wave = self.context.create_periodic_wave(&mut real, &mut imag)?;
+                oscillator.set_periodic_wave(&wave);
+            }
+            Waveform::Square => oscillator.set_type(OscillatorType::Square),
+            Waveform::Saw => oscillator.set_type(OscillatorType::Sawtooth),
+        }
         Ok(())
     }
 }
@@ -0,0 +1,252 @@
+// src/macros.rs - Macro knobs, each turning several patch parameters at once
+
+use serde::{Deserialize, Serialize};
+
+use crate::synth_core::FMParams;
+
+/// Most macros a preset can have
+pub const MAX_MACROS: usize = 4;
+
+/// Lowest modulation index the generic Brightness macro reaches up from, so
+/// presets with little or no index still get a useful knob
+const MIN_BRIGHTNESS_INDEX: f32 = 0.5;
+
+/// A continuous patch parameter a macro can drive, named as for `set`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MacroParam {
+    Index,
+    /// Modulator frequency as a multiple of the carrier's
+    Ratio,
+    Amplitude,
+    KeyScaling,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Gate,
+    LfoRate,
+    LfoPitch,
+    LfoIndex,
+    IndexAttack,
+    IndexDecay,
+    IndexSustain,
+    IndexRelease,
+    VelocityIndex,
+    SubLevel,
+    StereoDelay,
+    StereoWidth,
+    ReverbMix,
+    ReverbSize,
+    Drift,
+    Spread,
+    VibratoRate,
+    VibratoDepth,
+    VibratoDelay,
+    VibratoFade,
+    TremoloRate,
+    TremoloDepth,
+    InputDuck,
+    InputIndex,
+    ExtIndex,
+}
+
+impl MacroParam {
+    /// The parameter's name in preset files and for `set`
+    pub fn name(self) -> &'static str {
+        match self {
+            MacroParam::Index => "index",
+            MacroParam::Ratio => "ratio",
+            MacroParam::Amplitude => "amplitude",
+            MacroParam::KeyScaling => "key-scaling",
+            MacroParam::Attack => "attack",
+            MacroParam::Decay => "decay",
+            MacroParam::Sustain => "sustain",
+            MacroParam::Release => "release",
+            MacroParam::Gate => "gate",
+            MacroParam::LfoRate => "lfo-rate",
+            MacroParam::LfoPitch => "lfo-pitch",
+            MacroParam::LfoIndex => "lfo-index",
+            MacroParam::IndexAttack => "index-attack",
+            MacroParam::IndexDecay => "index-decay",
+            MacroParam::IndexSustain => "index-sustain",
+            MacroParam::IndexRelease => "index-release",
+            MacroParam::VelocityIndex => "velocity-index",
+            MacroParam::SubLevel => "sub-level",
+            MacroParam::StereoDelay => "stereo-delay",
+            MacroParam::StereoWidth => "stereo-width",
+            MacroParam::ReverbMix => "reverb-mix",
+            MacroParam::ReverbSize => "reverb-size",
+            MacroParam::Drift => "drift",
+            MacroParam::Spread => "spread",
+            MacroParam::VibratoRate => "vibrato-rate",
+            MacroParam::VibratoDepth => "vibrato-depth",
+            MacroParam::VibratoDelay => "vibrato-delay",
+            MacroParam::VibratoFade => "vibrato-fade",
+            MacroParam::TremoloRate => "tremolo-rate",
+            MacroParam::TremoloDepth => "tremolo-depth",
+            MacroParam::InputDuck => "input-duck",
+            MacroParam::InputIndex => "input-index",
+            MacroParam::ExtIndex => "ext-index",
+        }
+    }
+
+    pub fn get(self, params: &FMParams) -> f32 {
+        match self {
+            MacroParam::Index => params.modulation_index,
+            MacroParam::Ratio => params.modulator_freq / params.carrier_freq.max(f32::EPSILON),
+            MacroParam::Amplitude => params.gain(),
+            MacroParam::KeyScaling => params.key_scaling,
+            MacroParam::Attack => params.attack,
+            MacroParam::Decay => params.decay,
+            MacroParam::Sustain => params.sustain,
+            MacroParam::Release => params.release,
+            MacroParam::Gate => params.gate,
+            MacroParam::LfoRate => params.lfo_rate,
+            MacroParam::LfoPitch => params.lfo_pitch,
+            MacroParam::LfoIndex => params.lfo_index,
+            MacroParam::IndexAttack => params.index_attack,
+            MacroParam::IndexDecay => params.index_decay,
+            MacroParam::IndexSustain => params.index_sustain,
+            MacroParam::IndexRelease => params.index_release,
+            MacroParam::VelocityIndex => params.velocity_index,
+            MacroParam::SubLevel => params.sub_level,
+            MacroParam::StereoDelay => params.stereo_delay,
+            MacroParam::StereoWidth => params.stereo_width,
+            MacroParam::ReverbMix => params.reverb_mix,
+            MacroParam::ReverbSize => params.reverb_size,
+            MacroParam::Drift => params.drift,
+            MacroParam::Spread => params.spread,
+            MacroParam::VibratoRate => params.vibrato_rate,
+            MacroParam::VibratoDepth => params.vibrato_depth,
+            MacroParam::VibratoDelay => params.vibrato_delay,
+            MacroParam::VibratoFade => params.vibrato_fade,
+            MacroParam::TremoloRate => params.tremolo_rate,
+            MacroParam::TremoloDepth => params.tremolo_depth,
+            MacroParam::InputDuck => params.input_duck,
+            MacroParam::InputIndex => params.input_index,
+            MacroParam::ExtIndex => params.ext_index,
+        }
+    }
+
+    pub fn set(self, params: &mut FMParams, value: f32) {
+        match self {
+            MacroParam::Index => params.modulation_index = value,
+            MacroParam::Ratio => params.modulator_freq = params.carrier_freq * value,
+            MacroParam::Amplitude => params.set_gain(value),
+            MacroParam::KeyScaling => params.key_scaling = value,
+            MacroParam::Attack => params.attack = value,
+            MacroParam::Decay => params.decay = value,
+            MacroParam::Sustain => params.sustain = value,
+            MacroParam::Release => params.release = value,
+            MacroParam::Gate => params.gate = value,
+            MacroParam::LfoRate => params.lfo_rate = value,
+            MacroParam::LfoPitch => params.lfo_pitch = value,
+            MacroParam::LfoIndex => params.lfo_index = value,
+            MacroParam::IndexAttack => params.index_attack = value,
+            MacroParam::IndexDecay => params.index_decay = value,
+            MacroParam::IndexSustain => params.index_sustain = value,
+            MacroParam::IndexRelease => params.index_release = value,
+            MacroParam::VelocityIndex => params.velocity_index = value,
+            MacroParam::SubLevel => params.sub_level = value,
+            MacroParam::StereoDelay => params.stereo_delay = value,
+            MacroParam::StereoWidth => params.stereo_width = value,
+            MacroParam::ReverbMix => params.reverb_mix = value,
+            MacroParam::ReverbSize => params.reverb_size = value,
+            MacroParam::Drift => params.drift = value,
+            MacroParam::Spread => params.spread = value,
+            MacroParam::VibratoRate => params.vibrato_rate = value,
+            MacroParam::VibratoDepth => params.vibrato_depth = value,
+            MacroParam::VibratoDelay => params.vibrato_delay = value,
+            MacroParam::VibratoFade => params.vibrato_fade = value,
+            MacroParam::TremoloRate => params.tremolo_rate = value,
+            MacroParam::TremoloDepth => params.tremolo_depth = value,
+            MacroParam::InputDuck => params.input_duck = value,
+            MacroParam::InputIndex => params.input_index = value,
+            MacroParam::ExtIndex => params.ext_index = value,
+        }
+    }
+}
+
+/// One parameter a macro drives: it moves linearly from `min` with the knob
+/// at 0 to `max` with the knob at 1. `min` may be above `max`, so turning
+/// the knob up turns the parameter down.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MacroTarget {
+    pub param: MacroParam,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl MacroTarget {
+    pub fn value_at(&self, position: f32) -> f32 {
+        self.min + (self.max - self.min) * position
+    }
+}
+
+/// A knob (0.0 - 1.0) turning several parameters together, such as a
+/// Brightness macro raising the modulation index while lowering key scaling
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: String,
+    pub targets: Vec<MacroTarget>,
+}
+
+impl Macro {
+    /// Set every target for the knob at `position`, keeping the patch in
+    /// its supported ranges
+    pub fn apply(&self, params: &mut FMParams, position: f32) {
+        let position = position.clamp(0.0, 1.0);
+        for target in &self.targets {
+            target.param.set(params, target.value_at(position));
+        }
+        *params = params.clamped();
+    }
+
+    /// Where the knob stands for `params`, read from its first target; 0.5
+    /// if that target doesn't move
+    pub fn position(&self, params: &FMParams) -> f32 {
+        match self.targets.first() {
+            Some(target) if target.max != target.min => {
+                ((target.param.get(params) - target.min) / (target.max - target.min)).clamp(0.0, 1.0)
+            }
+            _ => 0.5,
+        }
+    }
+}
+
+/// The macros of a preset: its own (`PresetMeta::macros`), or when it has
+/// none, generic ones centred on its parameters so each knob starts where
+/// the preset already is
+pub fn preset_macros(own: &[Macro], params: &FMParams) -> Vec<Macro> {
+    if !own.is_empty() {
+        return own.iter().take(MAX_MACROS).cloned().collect();
+    }
+    // From nothing up to twice the preset's value
+    let around = |param: MacroParam| MacroTarget { param, min: 0.0, max: 2.0 * param.get(params) };
+    let index = params.modulation_index.max(MIN_BRIGHTNESS_INDEX);
+    let mut macros = vec![
+        Macro {
+            name: "Brightness".to_string(),
+            targets: vec![
+                MacroTarget { param: MacroParam::Index, min: 0.0, max: 2.0 * index },
+                // Less key scaling keeps high notes bright too
+                MacroTarget { param: MacroParam::KeyScaling, min: 2.0 * params.key_scaling, max: 0.0 },
+            ],
+        },
+        Macro { name: "Length".to_string(), targets: vec![around(MacroParam::Decay), around(MacroParam::Release)] },
+    ];
+    if params.reverb_mix > 0.0 {
+        macros.push(Macro {
+            name: "Space".to_string(),
+            targets: vec![around(MacroParam::ReverbMix), around(MacroParam::StereoDelay)],
+        });
+    }
+    if params.vibrato_depth > 0.0 || params.tremolo_depth > 0.0 {
+        macros.push(Macro {
+            name: "Motion".to_string(),
+            targets: vec![around(MacroParam::VibratoDepth), around(MacroParam::TremoloDepth)],
+        });
+    }
+    macros
+}
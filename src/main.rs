@@ -1,177 +1,3446 @@
-use std::io::{self, Write};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rayon::prelude::*;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
 
 // Import from our library crate
-use fm_synth::synth_core::{FMSynth, FMParams};
-use fm_synth::synth_data::{get_presets, get_melodies, note_freq};
+use fm_synth::chord;
+use fm_synth::drums::{self, DrumKind, Pattern};
+use fm_synth::dx7;
+use fm_synth::operator::RateLevel;
+use fm_synth::synth_core::{gain_to_db, FMSynth, FMParams, NotePriority, PolySynth, SineTable, Waveform, MAX_OUTPUTS, MAX_VOICES};
+use fm_synth::synth_data::{self, get_presets, get_melodies, note_freq, PresetMeta};
+use fm_synth::analysis;
+use fm_synth::audio::{self, ChannelMap, OutputConfig};
+use fm_synth::external;
+use fm_synth::input::{self, AudioInput};
+use fm_synth::jam::{self, Jam, Scale};
+use fm_synth::latency;
+use fm_synth::lfo::LfoShape;
+use fm_synth::macros::{self, Macro};
+use fm_synth::mutate;
+use fm_synth::resynth;
+use fm_synth::config::{self, Config, MelodyNotes};
+use fm_synth::player::Player;
+use fm_synth::recorder::Recorder;
+use fm_synth::sequencer::{Mono, RoundPart, Sequence, TempoMap, BEATS_PER_BAR};
+use fm_synth::testsignal::{self, Channels, Signal, TestSignal};
+use fm_synth::train::{self, Quiz};
+use fm_synth::render::{self, Normalize, DEFAULT_RENDER_RATE};
+use fm_synth::resample;
+use fm_synth::wav::{self, BitDepth};
+use fm_synth::waveform::{ImageFormat, WaveformImage};
 
 
+/// Positional arguments and `--flag value` options of a command
+struct CommandArgs<'a> {
+    positional: Vec<&'a str>,
+    flags: Vec<(&'a str, Option<&'a str>)>,
+}
+
+impl<'a> CommandArgs<'a> {
+    fn parse(parts: &[&'a str]) -> Self {
+        let mut positional = Vec::new();
+        let mut flags = Vec::new();
+        let mut iter = parts.iter().peekable();
+
+        while let Some(&part) = iter.next() {
+            if let Some(name) = part.strip_prefix("--") {
+                let value = iter.next_if(|next| !next.starts_with("--")).copied();
+                flags.push((name, value));
+            } else {
+                positional.push(part);
+            }
+        }
+
+        Self { positional, flags }
+    }
+
+    fn flag(&self, name: &str) -> Option<&'a str> {
+        self.flags.iter()
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, v)| *v)
+    }
+
+    fn has_flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|(n, _)| *n == name)
+    }
+}
+
+/// Parse a concert pitch such as `442` or `432Hz` within the accepted range
+fn parse_a4(s: &str) -> Option<f32> {
+    s.trim_end_matches("Hz").parse::<f32>().ok().filter(|hz| (synth_data::MIN_A4..=synth_data::MAX_A4).contains(hz))
+}
+
+/// Parse a note gate such as `60%` or `0.6` within the accepted range
+fn parse_gate(s: &str) -> Option<f32> {
+    let gate = match s.strip_suffix('%') {
+        Some(percent) => percent.parse::<f32>().ok()? / 100.0,
+        None => s.parse::<f32>().ok()?,
+    };
+    Some(gate).filter(|gate| (synth_data::MIN_GATE..=synth_data::LEGATO_GATE).contains(gate))
+}
+
+/// Parse a duration such as `2s`, `1.5s`, `500ms`, or a bare number of milliseconds
+fn parse_duration_ms(s: &str) -> Option<u64> {
+    let (value, scale) = if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 1.0)
+    } else if let Some(secs) = s.strip_suffix('s') {
+        (secs, 1000.0)
+    } else {
+        (s, 1.0)
+    };
+    let ms = value.parse::<f64>().ok()? * scale;
+    if ms >= 0.0 { Some(ms.round() as u64) } else { None }
+}
+
+/// Name as typed on the command line: lowercase with spaces as '-'
+fn slug(name: &str) -> String {
+    name.trim().to_lowercase().replace(' ', "-")
+}
+
+/// Command names offered by tab completion
+const COMMANDS: [&str; 48] = [
+    "list", "play", "round", "watch", "jam", "drums", "chord", "audition", "train", "mutate", "breed", "resynth", "render", "render-all", "waveform", "analyze", "describe", "verify", "set", "macro", "show", "save", "export", "import", "drone", "input", "external", "testsignal", "stop", "pause", "resume", "seek", "tempo", "voices", "status",
+    "effects", "gate", "loudness", "tune", "record", "loop", "devices", "latency", "bench", "demo", "help", "quit", "exit",
+];
+
+/// Tab completion of commands, preset names, and melody names for the REPL
+struct CliHelper {
+    presets: Vec<String>,
+    melodies: Vec<String>,
+    patterns: Vec<String>,
+}
+
+impl CliHelper {
+    fn new(cli: &Cli) -> Self {
+        Self {
+            presets: cli.presets.iter().map(|(n, _)| slug(n)).collect(),
+            melodies: cli.melodies.iter().map(|(n, _)| slug(n)).collect(),
+            patterns: drums::get_patterns().iter().map(|(n, _)| slug(n)).collect(),
+        }
+    }
+
+    /// Candidates for the word following `words`
+    fn options(&self, words: &[&str]) -> Vec<&str> {
+        let presets = self.presets.iter().map(String::as_str);
+        let melodies = self.melodies.iter().map(String::as_str);
+        let patterns = self.patterns.iter().map(String::as_str);
+        match words {
+            [] => COMMANDS.to_vec(),
+            [.., "--preset"] => presets.collect(),
+            ["list"] => vec!["presets", "melodies"],
+            ["list", "presets"] => vec!["--verbose"],
+            ["set"] => ["preset"].into_iter().chain(PARAMS).collect(),
+            ["set", "preset"] => presets.collect(),
+            ["set", "carrier-wave" | "modulator-wave"] => vec!["sine", "square", "saw"],
+            ["set", "key-scaling-low" | "phase-reset"] => vec!["on", "off"],
+            ["show"] => vec!["params"],
+            ["macro"] => vec!["brightness", "length", "space", "motion"],
+            ["effects" | "loudness"] => vec!["on", "off"],
+            ["gate"] => vec!["preset", "50%", "80%", "100%"],
+            ["tune"] => vec!["440", "442", "432", "415"],
+            ["record"] => vec!["stop"],
+            ["input"] => vec!["on", "off"],
+            ["external"] => vec!["input", "off"],
+            ["train"] => vec!["intervals", "chords"],
+            [.., "--mode"] => train::Mode::ALL.iter().map(|mode| mode.name()).collect(),
+            ["latency"] => vec!["--loopback"],
+            ["voices"] => vec!["default", "4", "8", "16", "32"],
+            ["testsignal"] => vec!["sine", "sweep", "steps", "white", "pink"],
+            [.., "--channel"] => vec!["both", "left", "right"],
+            ["loop"] => vec!["rec", "clear", "stop"],
+            ["save"] => vec!["preset"],
+            ["import"] => vec!["preset", "sysex"],
+            ["export"] => vec!["preset", "sysex"],
+            ["export", "preset" | "sysex"] => presets.collect(),
+            ["play"] => ["note", "freq"].into_iter().chain(presets).collect(),
+            ["round" | "render" | "verify" | "waveform" | "analyze" | "describe" | "audition"] => presets.collect(),
+            [.., "--scale"] => vec!["major", "minor"],
+            ["drums"] | [.., "--drums"] => patterns.collect(),
+            ["play" | "round" | "render" | "verify", preset] if !preset.starts_with("--") && *preset != "note" && *preset != "freq" => {
+                melodies.collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Completer for CliHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = before[start..].to_lowercase();
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+
+        let candidates = self.options(&words)
+            .into_iter()
+            .filter(|option| option.starts_with(&word))
+            .map(|option| Pair {
+                display: option.to_string(),
+                replacement: format!("{} ", option),
+            })
+            .collect();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for CliHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CliHelper {}
+
+impl Validator for CliHelper {}
+
+impl Helper for CliHelper {}
+
+/// Where the REPL keeps its command history
+fn history_path() -> Option<std::path::PathBuf> {
+    std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".fm_synth_history"))
+}
+
+/// Names accepted by `set`, in `show params` order
+const PARAMS: [&str; 49] = [
+    "carrier", "modulator", "ratio", "index", "level", "key-scaling", "key-scaling-low", "phase-reset",
+    "legato-retrigger", "carrier-wave", "modulator-wave", "attack", "decay", "sustain", "release", "carrier-env",
+    "gate", "lfo-shape", "lfo-rate", "lfo-pitch", "lfo-index", "lfo-seed",
+    "index-env", "index-attack", "index-decay", "index-sustain", "index-release", "modulator-env", "velocity-index",
+    "sub-level", "sub-octave", "sub-wave", "stereo-delay", "stereo-width",
+    "reverb-mix", "reverb-size", "drift", "spread", "spread-seed",
+    "vibrato-rate", "vibrato-depth", "vibrato-delay", "vibrato-fade",
+    "tremolo-rate", "tremolo-depth", "tremolo-beats", "input-duck", "input-index",
+    "ext-index",
+];
+
+/// Set one patch parameter from its command-line name and value text
+fn set_param(patch: &mut FMParams, name: &str, value: &str) -> Result<(), String> {
+    let number = || value.parse::<f32>().map_err(|_| format!("'{}' is not a number", value));
+    let switch = || match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("'{}' is not on or off", value)),
+    };
+    let wave = || Waveform::parse(value).ok_or_else(|| format!("Unknown waveform '{}'. Use sine, square, or saw.", value));
+    let rate_level = || match value.to_lowercase().as_str() {
+        "off" | "none" => Ok(None),
+        _ => RateLevel::parse(value)
+            .map(Some)
+            .ok_or_else(|| format!("'{}' is not a rate/level envelope like 99,70,60,50/99,90,80,0 (or off)", value)),
+    };
+    let db = |text: &str| text.trim().parse::<f32>().map_err(|_| format!("'{}' is not a level in dB", value));
+
+    match name {
+        "carrier" => {
+            // Keep the ratio when moving the carrier
+            let ratio = if patch.carrier_freq > 0.0 { patch.modulator_freq / patch.carrier_freq } else { 1.0 };
+            patch.carrier_freq = number()?;
+            patch.modulator_freq = patch.carrier_freq * ratio;
+        }
+        "modulator" => patch.modulator_freq = number()?,
+        "ratio" => patch.modulator_freq = patch.carrier_freq * number()?,
+        "index" => patch.modulation_index = number()?,
+        "level" => patch.level_db = db(value.to_lowercase().trim_end_matches("db"))?,
+        // A linear gain, or a level if given in dB
+        "amplitude" | "amp" => match value.to_lowercase().strip_suffix("db") {
+            Some(level) => patch.level_db = db(level)?,
+            None => patch.set_gain(number()?),
+        },
+        "key-scaling" => patch.key_scaling = number()?,
+        "key-scaling-low" => patch.key_scaling_low = switch()?,
+        "phase-reset" => patch.phase_reset = switch()?,
+        "legato-retrigger" => patch.legato_retrigger = switch()?,
+        "carrier-wave" => patch.carrier_wave = wave()?,
+        "modulator-wave" => patch.modulator_wave = wave()?,
+        "attack" => patch.attack = number()?,
+        "decay" => patch.decay = number()?,
+        "sustain" => patch.sustain = number()?,
+        "release" => patch.release = number()?,
+        "carrier-env" => patch.carrier_env = rate_level()?,
+        "gate" => patch.gate = parse_gate(value).ok_or_else(|| format!("'{}' is not a gate from 1% to 100%", value))?,
+        "lfo-shape" => {
+            patch.lfo_shape = LfoShape::parse(value).ok_or_else(|| {
+                format!("Unknown LFO shape '{}'. Use sine, triangle, square, saw, sample-hold, or smooth-random.", value)
+            })?
+        }
+        "lfo-rate" => patch.lfo_rate = number()?,
+        "lfo-pitch" => patch.lfo_pitch = number()?,
+        "lfo-index" => patch.lfo_index = number()?,
+        "index-env" => patch.index_env = switch()?,
+        "index-attack" => patch.index_attack = number()?,
+        "index-decay" => patch.index_decay = number()?,
+        "index-sustain" => patch.index_sustain = number()?,
+        "index-release" => patch.index_release = number()?,
+        "modulator-env" => patch.modulator_env = rate_level()?,
+        "velocity-index" => patch.velocity_index = number()?,
+        "sub-level" => patch.sub_level = number()?,
+        "sub-octave" => patch.sub_octave = value.parse().map_err(|_| format!("'{}' is not 1 or 2", value))?,
+        "sub-wave" => patch.sub_wave = wave()?,
+        "stereo-delay" => patch.stereo_delay = number()?,
+        "stereo-width" => patch.stereo_width = number()?,
+        "reverb-mix" => patch.reverb_mix = number()?,
+        "reverb-size" => patch.reverb_size = number()?,
+        "drift" => patch.drift = number()?,
+        "spread" => patch.spread = number()?,
+        "vibrato-rate" => patch.vibrato_rate = number()?,
+        "vibrato-depth" => patch.vibrato_depth = number()?,
+        "vibrato-delay" => patch.vibrato_delay = number()?,
+        "vibrato-fade" => patch.vibrato_fade = number()?,
+        "tremolo-rate" => patch.tremolo_rate = number()?,
+        "tremolo-depth" => patch.tremolo_depth = number()?,
+        "tremolo-beats" => patch.tremolo_beats = number()?,
+        "input-duck" => patch.input_duck = number()?,
+        "input-index" => patch.input_index = number()?,
+        "ext-index" => patch.ext_index = number()?,
+        "lfo-seed" => patch.lfo_seed = value.parse().map_err(|_| format!("'{}' is not a whole number", value))?,
+        "spread-seed" => patch.spread_seed = value.parse().map_err(|_| format!("'{}' is not a whole number", value))?,
+        _ => return Err(format!("Unknown parameter '{}'. Parameters: {}", name, PARAMS.join(", "))),
+    }
+    Ok(())
+}
+
+/// Audio output the CLI keeps open for live playing (drones)
+struct Engine {
+    synth: Arc<Mutex<FMSynth>>,
+    output: audio::AudioOutput,
+}
+
+/// A test signal playing on its own output until it ends or `stop`
+struct TestTone {
+    signal: Arc<Mutex<TestSignal>>,
+    output: audio::AudioOutput,
+}
+
 /// CLI interface
-struct CLI {
+struct Cli {
     presets: Vec<(&'static str, FMParams)>,
+    /// Author, description, tags, and range of each preset, by slug
+    preset_meta: HashMap<String, PresetMeta>,
     melodies: Vec<(&'static str, Vec<(&'static str, u64)>)>,
+    /// Tempo maps of the melodies that have one, by slug
+    tempo_maps: HashMap<String, TempoMap>,
+    output: OutputConfig,
+    /// Patch edited by `set` and played when a command names no preset
+    patch: FMParams,
+    patch_name: String,
+    preset_dir: Option<PathBuf>,
+    engine: Option<Engine>,
+    /// Frequency of the note held by `drone`, if any
+    drone: Option<f32>,
+    /// Signal started by `testsignal`
+    test_tone: Option<TestTone>,
+    /// Input device `input on` opens when none is named
+    input_device: Option<String>,
+    /// Audio input opened by `input on`, followed for patches' `input-duck`
+    /// and `input-index`
+    input: Option<AudioInput>,
+    /// What `external` set as the external modulator: "input" or a WAV path
+    external: Option<String>,
+    /// Whether presets' master bus effects are applied; off plays everything dry
+    effects: bool,
+    /// Play presets loudness-matched (`loudness`), for even A/B comparisons
+    loudness: bool,
+    /// Gate unmarked melody notes are held for in place of their presets'
+    /// (`gate`); `None` keeps the presets'
+    gate: Option<f32>,
+    /// Voices of each output's polyphonic engine (`voices`); `None` keeps
+    /// each sequence's own
+    voices: Option<usize>,
+    /// Play with the sine lookup table rather than `f32::sin`
+    /// (`--sine-table`)
+    sine_table: bool,
+    /// Background playback started by `play`, `chord`, and friends
+    job: Option<Job>,
+    /// Capture of the master output started by `record`
+    recording: Option<Recorder>,
+    /// Set while the job is a loop started by `loop <bars>`
+    looper: Option<Looper>,
+}
+
+/// A loop playing as the background job: whatever is played while it runs
+/// is layered onto it, in time with its transport
+struct Looper {
+    bars: u32,
+    /// Keep what is played, so it repeats on every pass (overdubbing);
+    /// otherwise it's heard over the loop once
+    recording: bool,
 }
 
-impl CLI {
-    fn new() -> Self {
-        Self {
-            presets: get_presets(),
-            melodies: get_melodies(),
+/// Something playing in the background, controlled by the transport commands
+struct Job {
+    title: String,
+    player: Player,
+    /// Tempo the sequence was written at, for `tempo`
+    bpm: f32,
+}
+
+impl Cli {
+    fn new(config: &Config, output: OutputConfig) -> Self {
+        let presets = get_presets();
+        let mut cli = Self {
+            patch: presets[0].1.clone(),
+            patch_name: presets[0].0.to_string(),
+            preset_meta: presets.iter().map(|(name, _)| (slug(name), synth_data::preset_meta(name))).collect(),
+            presets,
+            melodies: get_melodies(),
+            tempo_maps: HashMap::new(),
+            output,
+            preset_dir: config.preset_dir(),
+            engine: None,
+            drone: None,
+            test_tone: None,
+            input_device: config.input_device.clone(),
+            input: None,
+            external: None,
+            effects: config.effects.unwrap_or(true),
+            loudness: config.loudness_match.unwrap_or(false),
+            gate: config.gate.map(|gate| gate.clamp(synth_data::MIN_GATE, synth_data::LEGATO_GATE)),
+            voices: config.voices.map(|voices| voices.clamp(1, MAX_VOICES)),
+            sine_table: config.sine_table.unwrap_or(false),
+            job: None,
+            recording: None,
+            looper: None,
+        };
+        // The default directory is only read once something has been saved there
+        if let Some(dir) = cli.preset_dir.clone().filter(|dir| config.preset_dir.is_some() || dir.exists()) {
+            match config::load_presets(&dir) {
+                Ok(presets) => presets.into_iter().for_each(|(name, params, meta)| cli.add_preset(name, params, meta)),
+                Err(err) => println!("Warning: couldn't read preset directory {}: {}", dir.display(), err),
+            }
+        }
+        if let Some(dir) = &config.melody_dir {
+            match config::load_melodies(dir) {
+                Ok(melodies) => melodies.into_iter().for_each(|(name, notes, tempo)| cli.add_melody(name, notes, tempo)),
+                Err(err) => println!("Warning: couldn't read melody directory {}: {}", dir.display(), err),
+            }
+        }
+        if let Some(name) = &config.preset {
+            if !cli.load_patch(name) {
+                println!("Warning: default preset '{}' not found", name);
+            }
+        }
+        cli
+    }
+
+    /// Make a preset the active patch, returning `false` if it doesn't exist
+    fn load_patch(&mut self, name: &str) -> bool {
+        let Some(params) = self.find_preset(name) else {
+            return false;
+        };
+        self.patch = params;
+        self.patch_name = self.preset_name(name).unwrap_or(name).to_string();
+        true
+    }
+
+    /// Display name of the preset `name` refers to
+    fn preset_name(&self, name: &str) -> Option<&'static str> {
+        match name.parse::<usize>() {
+            Ok(num) if num > 0 && num <= self.presets.len() => Some(self.presets[num - 1].0),
+            _ => self.presets.iter().find(|(n, _)| slug(n) == slug(name)).map(|(n, _)| *n),
+        }
+    }
+
+    /// The named preset, or the active patch when `name` is `None`
+    fn preset_or_patch(&self, name: Option<&str>) -> Option<FMParams> {
+        match name {
+            Some(name) => {
+                let preset = self.find_preset(name);
+                if preset.is_none() {
+                    println!("Preset '{}' not found. Use 'list presets' to see available options.", name);
+                }
+                preset
+            }
+            None => Some(self.patch.clone()),
+        }
+        .map(|preset| self.matched(preset))
+    }
+
+    /// `preset` at an even loudness with the others while `loudness` is on
+    fn matched(&self, preset: FMParams) -> FMParams {
+        match self.loudness {
+            true => render::loudness_matched(&preset),
+            false => preset,
+        }
+    }
+
+    /// Add a user preset, replacing a built-in one of the same name. Names
+    /// live as long as the program, like the built-in tables.
+    fn add_preset(&mut self, name: String, params: FMParams, meta: PresetMeta) {
+        self.preset_meta.insert(slug(&name), meta);
+        match self.presets.iter_mut().find(|(n, _)| slug(n) == slug(&name)) {
+            Some(existing) => existing.1 = params,
+            None => self.presets.push((Box::leak(name.into_boxed_str()), params)),
+        }
+    }
+
+    /// Add a user melody, replacing a built-in one of the same name
+    fn add_melody(&mut self, name: String, notes: MelodyNotes, tempo: Option<TempoMap>) {
+        warn_unknown_notes(&name, &notes);
+        let notes: Vec<(&'static str, u64)> = notes
+            .into_iter()
+            .map(|(note, ms)| (&*Box::leak(note.into_boxed_str()), ms))
+            .collect();
+        match tempo {
+            Some(tempo) => self.tempo_maps.insert(slug(&name), tempo),
+            None => self.tempo_maps.remove(&slug(&name)),
+        };
+        match self.melodies.iter_mut().find(|(n, _)| slug(n) == slug(&name)) {
+            Some(existing) => existing.1 = notes,
+            None => self.melodies.push((Box::leak(name.into_boxed_str()), notes)),
+        }
+    }
+
+    fn print_menu(&self) {
+        println!("\n=== FM Synthesizer CLI ===");
+        println!("Commands:");
+        println!("  list presets [--verbose] - Show all available presets (with descriptions and tags)");
+        println!("  list melodies - Show all available melodies");
+        println!("  play <preset> <melody> [--bpm <n> | --speed <x>] [--transpose <semitones>] [--loop] [--gate <percent>]");
+        println!("       [--mono | --legato] [--glide <ms>] [--priority last|high|low] [--output <n>] [--drums <pattern>]");
+        println!("       - Play a melody with a preset");
+        println!("  round <preset> <melody> [--parts 3] [--entry <bars>] [--repeat 2] [--presets bell,organ]");
+        println!("       [--transpose 0,12,-12] [--outputs 1,2] [--bpm <n> | --speed <x>] [--gate <percent>] [--loop] [--out <file.wav>]");
+        println!("       - Play a melody as a round, each part entering after the last");
+        println!("  watch <file.fmsong> [--preset bell] [--top] [--loop] - Play a melody file and replay it from the");
+        println!("         first edited bar (or with --top, from the start) each time it's saved, until Ctrl-C");
+        println!("  jam [--seed N] [--key A] [--scale major|minor] [--bpm <n> | --speed <x>] [--rotate 2]");
+        println!("      [--lead a,b] [--chords a,b] [--bass a,b] [--drums <pattern>] - Play endless generated music until Ctrl-C");
+        println!("  drums [<pattern>] [--kick x...x...] [--snare ....x...] [--hat x.x.x.x.] [--tom ...]");
+        println!("        [--bars N] [--bpm <n> | --speed <x>] [--out <file.wav>] - Play (or render) a drum pattern");
+        println!("  play note <note> [--preset bell] [--dur 1s] - Play a single note");
+        println!("  play freq <hz> [--index 6] [--ratio 1.41] [--preset bell] [--dur 1s]");
+        println!("         - Play a frequency in Hz, optionally overriding index and modulator ratio");
+        println!("  render <preset> <melody> [--out <file.wav>] [--normalize peak|-1dBFS] [--bit-depth 16|24|32f]");
+        println!("         [--sample-rate 48000] [--bpm <n> | --speed <x>] [--transpose <semitones>] [--gate <percent>] [--drums <pattern>]");
+        println!("         - Render a melody to a WAV file");
+        println!("  render-all --out <dir> [--presets a,b] [--melodies x,y] [--jobs N]");
+        println!("         - Render every (or each matching) preset/melody pair in parallel");
+        println!("  waveform <preset> [--note A4] [--dur 1s] [--out <file.svg|png>] [--envelope]");
+        println!("         - Render a note and write a waveform image");
+        println!("  analyze <preset> [--sample-rate 44100] - Measure aliasing and THD across the keyboard");
+        println!("  describe <preset> [--note A4] [--sample-rate 44100] - Show the Bessel sidebands of a preset beside its measured spectrum");
+        println!("  verify <preset> <melody> [--sample-rate 44100] [--seed N] [--expect <hash>]");
+        println!("         - Print a deterministic render hash, optionally checking it");
+        println!("  set <param> <value> - Edit the active patch (e.g. set index 5.5, set ratio 2.0)");
+        println!("  set preset <preset> - Load a preset as the active patch");
+        println!("  macro [<name|number> <0-1>] - List the active patch's macro knobs, or turn one");
+        println!("  show params - Show the active patch");
+        println!("  input on [<device>] [--gain <dB>] | input off - Follow an audio input's level for the");
+        println!("         patch's input-duck (sidechain ducking) and input-index (modulation index)");
+        println!("  external input | external <file.wav> | external off - Cross-modulate the carrier at audio");
+        println!("         rate with the open input or a looped WAV file, by the patch's ext-index");
+        println!("  save preset <name> - Save the active patch as a preset file");
+        println!("  testsignal sine <hz> | sweep | steps | white | pink [--level -18] [--channel both|left|right]");
+        println!("         [--from 20] [--to 20000] [--dur 10s] [--step 1s] [--out <file.wav>]");
+        println!("         - Play a calibrated test signal (level is the sine's peak; noise matches its RMS)");
+        println!("  export preset [<preset>] - Print a preset (default: the active patch) as a shareable string");
+        println!("  export sysex [<preset>] [--out <file.syx>] - Write a preset as a DX7 voice dump for DX7/Volca FM hardware");
+        println!("  import preset <string> - Load an exported preset as the active patch");
+        println!("  import sysex <file.syx> [--note C4] [--dur 1s] - Import a DX7 voice dump as the active patch and play it");
+        println!("  chord <symbol> [--preset organ] [--dur 2s] [--octave 4] - Play a chord, e.g. chord Cmaj7");
+        println!("  pause | resume | seek <bar> | tempo <bpm> | status - Control what is playing in the background");
+        println!("  voices [<n> | default] - Show the voices sounding, or set each output's polyphony (1 - 256)");
+        println!("  audition <preset> | --all - Play a note, an octave, and a chord (or step through all presets)");
+        println!("  train intervals|chords [--preset <preset>] [--set m3,M3,P5 | maj,min] [--mode up|down|harmonic|mixed] [--rounds 10]");
+        println!("         - Ear training: name random intervals or chords, keeping score");
+        println!("  mutate <preset> [--amount 0.2] [--seed N] [--save <name>] - Play a random variant as the active patch");
+        println!("  breed <presetA> <presetB> [--amount 0.1] [--seed N] - Cross two presets, keeping offspring you like");
+        println!("  resynth <file.wav> [--save <name>] - Fit a preset to a recorded sound and play it");
+        println!("  drone <note> - Hold a note with the active patch while you 'set' parameters");
+        println!("  stop - Release the drone");
+        println!("  effects [on|off] - Apply or bypass the presets' reverb and stereo effects");
+        println!("  loudness [on|off] - Match the presets' loudness for even A/B comparisons");
+        println!("  gate [<percent>|preset] - Hold unmarked melody notes for a fraction of their length, or as the presets say");
+        println!("  tune [<hz>] - Show or set the concert pitch (A4, default 440 Hz)");
+        println!("  loop <bars> [--bpm <n>] | loop rec | loop clear | loop stop");
+        println!("         - Loop a number of bars and overdub what you play onto it");
+        println!("  record <file.wav> [--bit-depth 16|24|32f] - Record what plays until 'record' again");
+        println!("  devices - List audio hosts, output devices, and supported configs");
+        println!("  latency [--loopback] [--input <device>] [--tries 5]");
+        println!("         - Show the output's buffer and latency, or measure the round trip through a cable or microphone");
+        println!("  bench [--voices 1,8,16,32,64] [--seconds 5] [--preset <preset>] [--sample-rate 48000] [--buffer 512]");
+        println!("         - Measure rendering speed and realtime headroom at each polyphony, with no audio device");
+        println!("  demo - Play all presets with a scale");
+        println!("  help - Show this menu");
+        println!("  quit - Exit the program");
+        println!();
+    }
+
+    fn list_presets(&self, verbose: bool) {
+        println!("\nAvailable Presets:");
+        for (i, (name, _)) in self.presets.iter().enumerate() {
+            let meta = self.preset_meta.get(&slug(name)).filter(|meta| verbose && !meta.is_empty());
+            let Some(meta) = meta else {
+                println!("  {}. {}", i + 1, name);
+                continue;
+            };
+            let mut details = Vec::new();
+            if !meta.tags.is_empty() {
+                details.push(meta.tags.join(", "));
+            }
+            if let Some((low, high)) = &meta.range {
+                details.push(format!("{}-{}", low, high));
+            }
+            if details.is_empty() {
+                println!("  {}. {}", i + 1, name);
+            } else {
+                println!("  {}. {} [{}]", i + 1, name, details.join("; "));
+            }
+            if !meta.description.is_empty() {
+                println!("       {}", meta.description);
+            }
+            if !meta.macros.is_empty() {
+                let names: Vec<&str> = meta.macros.iter().map(|knob| knob.name.as_str()).collect();
+                println!("       macros: {}", names.join(", "));
+            }
+            if !meta.author.is_empty() {
+                println!("       by {}", meta.author);
+            }
+        }
+    }
+
+    fn list_melodies(&self) {
+        println!("\nAvailable Melodies:");
+        for (i, (name, _)) in self.melodies.iter().enumerate() {
+            println!("  {}. {}", i + 1, name);
+        }
+    }
+
+    fn find_preset(&self, name: &str) -> Option<FMParams> {
+        // Try by number first
+        let found = match name.parse::<usize>() {
+            Ok(num) if num > 0 && num <= self.presets.len() => Some(&self.presets[num - 1]),
+            // Try by name (case insensitive, '-' for spaces)
+            _ => self.presets.iter().find(|(n, _)| slug(n) == slug(name)),
+        };
+
+        found.map(|(n, p)| match p.validate() {
+            Ok(()) => p.clone(),
+            Err(err) => {
+                println!("Warning: preset '{}' has invalid parameters ({}); clamping.", n, err);
+                p.clamped()
+            }
+        })
+    }
+
+    fn find_melody(&self, name: &str) -> Option<Vec<(&'static str, u64)>> {
+        self.melody_entry(name).map(|(_, m)| m.clone())
+    }
+
+    /// Tempo map of a melody, found as by `find_melody`, if it has one
+    fn find_tempo_map(&self, name: &str) -> Option<TempoMap> {
+        self.melody_entry(name).and_then(|(n, _)| self.tempo_maps.get(&slug(n)).cloned())
+    }
+
+    fn melody_entry(&self, name: &str) -> Option<&(&'static str, Vec<(&'static str, u64)>)> {
+        // Try by number first
+        if let Ok(num) = name.parse::<usize>() {
+            if num > 0 && num <= self.melodies.len() {
+                return Some(&self.melodies[num - 1]);
+            }
+        }
+
+        // Try by name (case insensitive, '-' for spaces)
+        self.melodies.iter().find(|(n, _)| slug(n).contains(&slug(name)))
+    }
+
+    /// Run a single command, returning `false` when the user asked to quit
+    fn execute(&mut self, parts: &[&str]) -> anyhow::Result<bool> {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        match parts[0] {
+            "list" => {
+                if parts.len() > 1 {
+                    match parts[1] {
+                        "presets" => self.list_presets(matches!(parts.get(2), Some(&"--verbose" | &"-v"))),
+                        "melodies" => self.list_melodies(),
+                        _ => println!("Unknown list command. Use 'list presets' or 'list melodies'"),
+                    }
+                } else {
+                    println!("Usage: list <presets|melodies>");
+                }
+            }
+            "play" if parts.get(1) == Some(&"note") => self.play_note(&parts[2..])?,
+            "play" if parts.get(1) == Some(&"freq") => self.play_freq(&parts[2..])?,
+            "play" => self.play(&parts[1..])?,
+            "round" => self.round(&parts[1..])?,
+            "watch" => self.watch(&parts[1..])?,
+            "jam" => self.jam(&parts[1..])?,
+            "drums" => self.drums(&parts[1..])?,
+            "render" => self.render(&parts[1..])?,
+            "render-all" => self.render_all(&parts[1..])?,
+            "waveform" => self.waveform(&parts[1..])?,
+            "analyze" => self.analyze(&parts[1..]),
+            "describe" => self.describe(&parts[1..]),
+            "verify" => self.verify(&parts[1..])?,
+            "devices" => list_devices(),
+            "latency" => self.latency(&parts[1..])?,
+            "bench" => self.bench(&parts[1..]),
+            "set" => self.set(&parts[1..]),
+            "macro" => self.set_macro(&parts[1..]),
+            "drone" => self.drone(&parts[1..])?,
+            "input" => self.input(&parts[1..])?,
+            "external" => self.external(&parts[1..])?,
+            "testsignal" => self.test_signal(&parts[1..])?,
+            "chord" => self.chord(&parts[1..])?,
+            "audition" => self.audition(&parts[1..])?,
+            "train" => self.train(&parts[1..])?,
+            "mutate" => self.mutate(&parts[1..])?,
+            "breed" => self.breed(&parts[1..])?,
+            "resynth" => self.resynth(&parts[1..])?,
+            "stop" => self.stop(),
+            "pause" => self.pause(),
+            "resume" => self.resume(),
+            "seek" => self.seek(&parts[1..]),
+            "status" => self.status(),
+            "voices" => self.voices(&parts[1..]),
+            "tempo" => self.tempo(&parts[1..]),
+            "effects" => self.effects(&parts[1..]),
+            "gate" => self.gate(&parts[1..]),
+            "loudness" => self.loudness(&parts[1..]),
+            "tune" => self.tune(&parts[1..]),
+            "record" => self.record(&parts[1..])?,
+            "loop" => self.looper(&parts[1..])?,
+            "show" if parts.get(1) == Some(&"params") => self.show_params(),
+            "show" => println!("Usage: show params"),
+            "save" if parts.get(1) == Some(&"preset") && parts.len() > 2 => self.save_preset(&parts[2..].join(" "))?,
+            "save" => println!("Usage: save preset <name>"),
+            "export" if parts.get(1) == Some(&"preset") => self.export_preset(parts.get(2..).map(|p| p.join(" ")))?,
+            "export" if parts.get(1) == Some(&"sysex") => self.export_sysex(&parts[2..])?,
+            "export" => println!("Usage: export preset [<preset>] | export sysex [<preset>] [--out <file.syx>]"),
+            "import" if parts.get(1) == Some(&"preset") && parts.len() > 2 => self.import_preset(&parts[2..].concat()),
+            "import" if parts.get(1) == Some(&"sysex") => self.import_sysex(&parts[2..])?,
+            "import" => println!("Usage: import preset <string> | import sysex <file.syx> [--note C4] [--dur 1s]"),
+            "demo" => {
+                let scale = [
+                    ("C4", 300), ("D4", 300), ("E4", 300), ("F4", 300),
+                    ("G4", 300), ("A4", 300), ("B4", 300), ("C5", 600),
+                ];
+                let mut sequence = Sequence::new();
+                sequence.set_gate(self.gate);
+                for (_, preset) in &self.presets {
+                    sequence.append_melody(&self.matched(preset.clone()), &scale);
+                    sequence.rest(DEMO_GAP);
+                }
+                let names: Vec<&str> = self.presets.iter().map(|(name, _)| *name).collect();
+                println!("Playing demo with all presets: {}", names.join(", "));
+                self.start_job("demo".to_string(), sequence, false, 1.0)?;
+            }
+            "help" => self.print_menu(),
+            "quit" | "exit" => {
+                self.stop();
+                self.close_engine();
+                self.stop_recording()?;
+                println!("Goodbye!");
+                return Ok(false);
+            }
+            _ => println!("Unknown command. Type 'help' for available commands."),
+        }
+
+        Ok(true)
+    }
+
+    fn set(&mut self, parts: &[&str]) {
+        let [name, value @ ..] = parts else {
+            println!("Usage: set <param> <value>   (see 'show params' for the parameters)");
+            println!("       set preset <preset>  - load a preset as the active patch");
+            return;
+        };
+        if value.is_empty() {
+            println!("Usage: set {} <value>", name);
+            return;
+        }
+
+        if *name == "preset" {
+            let preset = value.join(" ");
+            if self.load_patch(&preset) {
+                println!("Active patch: {}", self.patch_name);
+                self.update_drone();
+            } else {
+                println!("Preset '{}' not found. Use 'list presets' to see available options.", preset);
+            }
+            return;
+        }
+
+        let mut patch = self.patch.clone();
+        if let Err(err) = set_param(&mut patch, name, value[0]) {
+            println!("{}", err);
+            return;
+        }
+        match patch.validate() {
+            Ok(()) => {
+                self.patch = patch;
+                println!("{} = {}", name, value[0]);
+                self.update_drone();
+            }
+            Err(err) => println!("Invalid value: {}", err),
+        }
+    }
+
+    /// Macro knobs of the active patch. Generic macros are centred on the
+    /// preset the patch was loaded from, so turning one doesn't move the
+    /// others' ranges.
+    fn macros(&self) -> Vec<Macro> {
+        let base = self.find_preset(&self.patch_name).unwrap_or_else(|| self.patch.clone());
+        let own = self.preset_meta.get(&slug(&self.patch_name)).map_or(&[][..], |meta| &meta.macros[..]);
+        macros::preset_macros(own, &base)
+    }
+
+    fn set_macro(&mut self, parts: &[&str]) {
+        let macros = self.macros();
+        let [name, value] = parts else {
+            println!("Macros of {}:", self.patch_name);
+            for (i, knob) in macros.iter().enumerate() {
+                let params: Vec<&str> = knob.targets.iter().map(|t| t.param.name()).collect();
+                println!("  {}. {:<12} {:.2}  ({})", i + 1, knob.name, knob.position(&self.patch), params.join(", "));
+            }
+            println!("Usage: macro <name|number> <0-1>");
+            return;
+        };
+        let knob = match name.parse::<usize>() {
+            Ok(num) if num > 0 && num <= macros.len() => Some(&macros[num - 1]),
+            _ => macros.iter().find(|knob| slug(&knob.name) == slug(name)),
+        };
+        let Some(knob) = knob else {
+            let names: Vec<&str> = macros.iter().map(|knob| knob.name.as_str()).collect();
+            println!("Unknown macro '{}'. Macros of {}: {}", name, self.patch_name, names.join(", "));
+            return;
+        };
+        let position = match value.parse::<f32>() {
+            Ok(position) if (0.0..=1.0).contains(&position) => position,
+            _ => {
+                println!("'{}' is not a number from 0 to 1", value);
+                return;
+            }
+        };
+        knob.apply(&mut self.patch, position);
+        let targets: Vec<String> = knob.targets.iter()
+            .map(|t| format!("{} = {:.3}", t.param.name(), t.param.get(&self.patch)))
+            .collect();
+        println!("{} = {:.2} ({})", knob.name, position, targets.join(", "));
+        self.update_drone();
+    }
+
+    /// Close the live engine's output once a released drone has rung out
+    fn close_engine(&mut self) {
+        if let Some(Engine { synth, output }) = self.engine.take() {
+            output.close_when_idle(|| !synth.lock().unwrap().is_active());
+        }
+    }
+
+    /// The live engine, opening the audio output on first use
+    fn engine(&mut self) -> anyhow::Result<&Engine> {
+        if self.engine.is_none() {
+            let synth = Arc::new(Mutex::new(FMSynth::new(DEFAULT_RENDER_RATE, self.patch.clone())));
+            let output = audio::AudioOutput::start(self.output.clone(), Arc::clone(&synth))?;
+            self.engine = Some(Engine { synth, output });
+        }
+        Ok(self.engine.as_ref().unwrap())
+    }
+
+    fn input(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let usage = "Usage: input on [<device>] [--gain <dB>] | input off";
+        match args.positional.first().copied() {
+            Some("on") => {
+                let Some(gain) = args.flag("gain").map_or(Some(0.0), |db| db.trim_end_matches("dB").parse::<f32>().ok()) else {
+                    println!("Gain is in dB, e.g. --gain 6");
+                    return Ok(());
+                };
+                let named = args.positional[1..].join(" ");
+                let device = Some(named).filter(|name| !name.is_empty()).or_else(|| self.input_device.clone());
+                // Close the old input first; some devices can only be opened once
+                self.input = None;
+                let input = AudioInput::start(&self.output, device.as_deref(), gain)?;
+                println!("Listening to {}. Route it with 'set input-duck' or 'set input-index'.", input.device_name());
+                self.input = Some(input);
+            }
+            Some("off") => match self.input.take() {
+                Some(_) => {
+                    println!("Input closed.");
+                    if self.external.as_deref() == Some("input") {
+                        external::clear();
+                        self.external = None;
+                        println!("The external modulator is off.");
+                    }
+                }
+                None => println!("No input is open."),
+            },
+            None => match &self.input {
+                Some(open) => println!("Input: {} at level {:.2}", open.device_name(), input::level()),
+                None => println!("No input is open. {}", usage),
+            },
+            Some(_) => println!("{}", usage),
+        }
+        Ok(())
+    }
+
+    fn external(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let usage = "Usage: external input | external <file.wav> | external off";
+        match parts {
+            [] => match &self.external {
+                Some(source) => println!("External modulator: {} (ext-index {:.2})", source, self.patch.ext_index),
+                None => println!("No external modulator. {}", usage),
+            },
+            ["off"] => {
+                external::clear();
+                match self.external.take() {
+                    Some(_) => println!("The external modulator is off."),
+                    None => println!("No external modulator is set."),
+                }
+            }
+            ["input"] => {
+                if self.input.is_none() {
+                    println!("No input is open; open one with 'input on' first.");
+                    return Ok(());
+                }
+                external::use_input();
+                self.external = Some("input".to_string());
+                println!("Modulating with the audio input. Set its depth with 'set ext-index'.");
+            }
+            _ => {
+                let path = parts.join(" ");
+                let (sample_rate, samples) = wav::read_wav(Path::new(&path))
+                    .map_err(|e| anyhow::anyhow!("Could not read '{}': {}", path, e))?;
+                if samples.is_empty() {
+                    println!("'{}' has no audio.", path);
+                    return Ok(());
+                }
+                let seconds = samples.len() as f32 / sample_rate as f32;
+                external::use_wav(sample_rate, samples);
+                println!("Modulating with {} ({:.2} s, looped). Set its depth with 'set ext-index'.", path, seconds);
+                self.external = Some(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn drone(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let Some(&note) = parts.first() else {
+            println!("Usage: drone <note|hz>   (edit it with 'set', end it with 'stop')");
+            return Ok(());
+        };
+        let freq = match note_freq(note) {
+            f if f > 0.0 => f,
+            _ => match note.parse::<f32>() {
+                Ok(hz) if hz > 0.0 => hz,
+                _ => {
+                    println!("Unknown note '{}'", note);
+                    return Ok(());
+                }
+            },
+        };
+
+        let params = self.patch.for_note(freq);
+        let retrigger = self.drone.is_none();
+        let engine = self.engine()?;
+        let mut synth = engine.synth.lock().unwrap();
+        synth.set_params(params);
+        if retrigger {
+            synth.note_on();
+        }
+        drop(synth);
+
+        self.drone = Some(freq);
+        println!("Droning {} ({:.2} Hz) with '{}'.", note, freq, self.patch_name);
+        println!("Use 'set' to edit it live and 'stop' to end it.");
+        Ok(())
+    }
+
+    /// Send the edited patch to a running drone so the change is heard at once
+    fn update_drone(&self) {
+        if let (Some(freq), Some(engine)) = (self.drone, &self.engine) {
+            engine.synth.lock().unwrap().set_params(self.patch.for_note(freq));
+        }
+    }
+
+    /// Release the drone, if one is playing
+    fn stop(&mut self) {
+        self.looper = None;
+        if let Some(mut job) = self.job.take() {
+            if !job.player.is_finished() {
+                job.player.stop();
+                println!("Stopped {}.", job.title);
+            }
+        }
+        if self.drone.take().is_some() {
+            if let Some(engine) = &self.engine {
+                engine.synth.lock().unwrap().note_off();
+            }
+            println!("Drone stopped.");
+        }
+        if let Some(tone) = self.test_tone.take() {
+            if !tone.signal.lock().unwrap().is_finished() {
+                tone.output.fade_out();
+                println!("Test signal stopped.");
+            }
+        }
+    }
+
+    fn show_params(&self) {
+        let p = &self.patch;
+        let on_off = |b: bool| if b { "on" } else { "off" };
+        println!("\nActive patch: {}", self.patch_name);
+        println!("  carrier          {:.2} Hz", p.carrier_freq);
+        println!("  modulator        {:.2} Hz", p.modulator_freq);
+        if p.carrier_freq > 0.0 {
+            println!("  ratio            {:.3}", p.modulator_freq / p.carrier_freq);
+        }
+        println!("  index            {:.2}", p.modulation_index);
+        println!("  level            {:.1} dB (gain {:.2})", p.level_db, p.gain());
+        println!("  key-scaling      {:.2}", p.key_scaling);
+        println!("  key-scaling-low  {}", on_off(p.key_scaling_low));
+        println!("  phase-reset      {}", on_off(p.phase_reset));
+        println!("  legato-retrigger {}", on_off(p.legato_retrigger));
+        println!("  carrier-wave     {}", p.carrier_wave.name());
+        println!("  modulator-wave   {}", p.modulator_wave.name());
+        println!("  attack           {:.3} s", p.attack);
+        println!("  decay            {:.3} s", p.decay);
+        println!("  sustain          {:.2}", p.sustain);
+        println!("  release          {:.3} s", p.release);
+        println!("  carrier-env      {}", p.carrier_env.map_or("off".to_string(), |envelope| envelope.to_string()));
+        println!("  gate             {:.0}%", p.gate * 100.0);
+        println!("  lfo-shape        {}", p.lfo_shape.name());
+        println!("  lfo-rate         {:.2} Hz", p.lfo_rate);
+        println!("  lfo-pitch        {:.1} cents", p.lfo_pitch);
+        println!("  lfo-index        {:.2}", p.lfo_index);
+        println!("  lfo-seed         {}", p.lfo_seed);
+        println!("  index-env        {}", on_off(p.index_env));
+        println!("  index-attack     {:.3} s", p.index_attack);
+        println!("  index-decay      {:.3} s", p.index_decay);
+        println!("  index-sustain    {:.2}", p.index_sustain);
+        println!("  index-release    {:.3} s", p.index_release);
+        println!("  modulator-env    {}", p.modulator_env.map_or("off".to_string(), |envelope| envelope.to_string()));
+        println!("  velocity-index   {:.2}", p.velocity_index);
+        println!("  sub-level        {:.2}", p.sub_level);
+        println!("  sub-octave       {}", p.sub_octave);
+        println!("  sub-wave         {}", p.sub_wave.name());
+        println!("  stereo-delay     {:.1} ms", p.stereo_delay);
+        println!("  stereo-width     {:.2}", p.stereo_width);
+        println!("  reverb-mix       {:.2}", p.reverb_mix);
+        println!("  reverb-size      {:.2}", p.reverb_size);
+        println!("  drift            {:.2}", p.drift);
+        println!("  spread           {:.1} cents", p.spread);
+        println!("  spread-seed      {}", p.spread_seed);
+        println!("  vibrato-rate     {:.2} Hz", p.vibrato_rate);
+        println!("  vibrato-depth    {:.1} cents", p.vibrato_depth);
+        println!("  vibrato-delay    {:.3} s", p.vibrato_delay);
+        println!("  vibrato-fade     {:.3} s", p.vibrato_fade);
+        println!("  tremolo-rate     {:.2} Hz", p.tremolo_rate);
+        println!("  tremolo-depth    {:.2}", p.tremolo_depth);
+        println!("  tremolo-beats    {:.2}", p.tremolo_beats);
+        println!("  input-duck       {:.2}", p.input_duck);
+        println!("  input-index      {:.2}", p.input_index);
+        println!("  ext-index        {:.2}", p.ext_index);
+    }
+
+    fn save_preset(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(dir) = self.preset_dir.clone() else {
+            println!("No preset directory; set preset_dir in the config file.");
+            return Ok(());
+        };
+        // Saving over the active preset keeps its description and tags
+        let meta = match slug(name) == slug(&self.patch_name) {
+            true => self.preset_meta.get(&slug(name)).cloned().unwrap_or_default(),
+            false => PresetMeta::default(),
+        };
+        self.save_patch(&dir, name, meta)
+    }
+
+    /// Save the active patch as preset `name` with `meta`, and make it the
+    /// active preset
+    fn save_patch(&mut self, dir: &Path, name: &str, meta: PresetMeta) -> anyhow::Result<()> {
+        let path = config::save_preset(dir, name, &self.patch, &meta)?;
+        self.add_preset(name.trim().to_string(), self.patch.clone(), meta);
+        self.patch_name = name.trim().to_string();
+        println!("Saved preset '{}' to {}", self.patch_name, path.display());
+        Ok(())
+    }
+
+    fn export_preset(&self, name: Option<String>) -> anyhow::Result<()> {
+        let (name, params) = match name.filter(|name| !name.is_empty()) {
+            Some(name) => match (self.preset_name(&name), self.find_preset(&name)) {
+                (Some(display), Some(params)) => (display.to_string(), params),
+                _ => {
+                    println!("Preset '{}' not found. Use 'list presets' to see available options.", name);
+                    return Ok(());
+                }
+            },
+            None => (self.patch_name.clone(), self.patch.clone()),
+        };
+        let meta = self.preset_meta.get(&slug(&name)).cloned().unwrap_or_default();
+        println!("{}", config::export_preset(&name, &params, &meta)?);
+        Ok(())
+    }
+
+    /// Write a preset as a DX7 single-voice SysEx file
+    fn export_sysex(&self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let (name, params) = match args.positional.join(" ") {
+            name if name.is_empty() => (self.patch_name.clone(), self.patch.clone()),
+            name => match (self.preset_name(&name), self.find_preset(&name)) {
+                (Some(display), Some(params)) => (display.to_string(), params),
+                _ => {
+                    println!("Preset '{}' not found. Use 'list presets' to see available options.", name);
+                    return Ok(());
+                }
+            },
+        };
+        let default_out = format!("{}.syx", slug(&name));
+        let out = args.flag("out").unwrap_or(&default_out);
+        std::fs::write(out, dx7::voice_sysex(&name, &params))?;
+        if name.chars().count() > dx7::NAME_LEN {
+            println!("Note: the DX7 shows only the first {} characters of the name", dx7::NAME_LEN);
+        }
+        println!("Wrote '{}' to {} (DX7 algorithm 1, operators 3 and 4)", name, out);
+        Ok(())
+    }
+
+    /// Add an exported preset for this session and make it the active patch;
+    /// `save preset` keeps it
+    fn import_preset(&mut self, text: &str) {
+        let (name, params, meta) = match config::import_preset(text) {
+            Ok(preset) => preset,
+            Err(err) => {
+                println!("Couldn't import preset: {}", err);
+                return;
+            }
+        };
+        let params = match params.validate() {
+            Ok(()) => params,
+            Err(err) => {
+                println!("Warning: imported preset has invalid parameters ({}); clamping.", err);
+                params.clamped()
+            }
+        };
+        self.add_preset(name.clone(), params, meta);
+        self.load_patch(&name);
+        println!("Imported preset '{}' as the active patch", self.patch_name);
+    }
+
+    /// Read a DX7 single-voice SysEx file into a preset for this session,
+    /// keeping the rate/level envelopes, make it the active patch, and play
+    /// a note with it
+    fn import_sysex(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let Some(&path) = args.positional.first() else {
+            println!("Usage: import sysex <file.syx> [--note C4] [--dur 1s]");
+            return Ok(());
+        };
+        let note = args.flag("note").unwrap_or("C4");
+        let freq = note_freq(note);
+        if freq <= 0.0 {
+            println!("Unknown note '{}'", note);
+            return Ok(());
+        }
+        let Some(dur_ms) = parse_duration_ms(args.flag("dur").unwrap_or("1s")) else {
+            println!("Invalid duration. Use e.g. '2s' or '500ms'.");
+            return Ok(());
+        };
+        let voice = match std::fs::read(path).and_then(|bytes| dx7::parse_voice(&bytes)) {
+            Ok(voice) => voice,
+            Err(err) => {
+                println!("Couldn't import {}: {}", path, err);
+                return Ok(());
+            }
+        };
+        println!("DX7 voice '{}', {} operators (carrier first):", voice.name, voice.operators.len());
+        for (i, op) in voice.operators.iter().enumerate() {
+            let envelope = op.envelope.unwrap_or_default();
+            println!(
+                "  {}  ratio {:.2}  {} {:.2}  R {:?}  L {:?}",
+                i + 1,
+                op.ratio,
+                if i == 0 { "amplitude" } else { "index" },
+                op.level,
+                envelope.rates,
+                envelope.levels
+            );
+        }
+        if voice.operators.len() > 2 {
+            println!("Note: operators 3 - {} don't fit a two-operator patch and are left out", voice.operators.len());
+        }
+
+        let name = match voice.name.trim() {
+            "" => Path::new(path).file_stem().map_or(path.to_string(), |stem| stem.to_string_lossy().into_owned()),
+            name => name.to_string(),
+        };
+        let meta = PresetMeta { description: format!("Imported from {}", path), ..PresetMeta::default() };
+        self.add_preset(name.clone(), voice.params(), meta);
+        self.load_patch(&name);
+        println!("Imported preset '{}' as the active patch; 'save preset <name>' keeps it", self.patch_name);
+
+        let mut sequence = Sequence::new();
+        sequence.note(Duration::ZERO, Duration::from_millis(dur_ms), freq, &self.matched(self.patch.clone()));
+        println!("Playing {} for {} ms...", note, dur_ms);
+        self.start_job(format!("'{}'", self.patch_name), sequence, false, 1.0)
+    }
+
+    fn play_note(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let Some(&note) = args.positional.first() else {
+            println!("Usage: play note <note> [--preset bell] [--dur 1s]");
+            println!("Example: play note A4 --preset bell --dur 2s   (cent offsets too: play note E4-14)");
+            return Ok(());
+        };
+
+        let freq = note_freq(note);
+        if freq <= 0.0 {
+            println!("Unknown note '{}'", note);
+            return Ok(());
+        }
+        let Some(preset) = self.preset_or_patch(args.flag("preset")) else {
+            return Ok(());
+        };
+        let Some(dur_ms) = parse_duration_ms(args.flag("dur").unwrap_or("1s")) else {
+            println!("Invalid duration. Use e.g. '2s' or '500ms'.");
+            return Ok(());
+        };
+
+        let mut sequence = Sequence::new();
+        sequence.note(Duration::ZERO, Duration::from_millis(dur_ms), freq, &preset);
+        println!("Playing {} ({:.2} Hz) for {} ms...", note, freq, dur_ms);
+        self.start_job(format!("note {}", note), sequence, false, 1.0)
+    }
+
+    fn chord(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let Some(&symbol) = args.positional.first() else {
+            println!("Usage: chord <symbol> [--preset organ] [--dur 2s] [--octave 4]");
+            println!("Example: chord Cmaj7 --preset organ --dur 2s");
+            println!("Qualities: {}", chord::quality_names().join(" "));
+            return Ok(());
+        };
+        let Some(octave) = args.flag("octave").unwrap_or("4").parse::<i32>().ok().filter(|o| (0..=8).contains(o)) else {
+            println!("Invalid octave. Use 0 - 8.");
+            return Ok(());
+        };
+        let Some(freqs) = chord::chord_freqs(symbol, octave) else {
+            println!("Unknown chord '{}'. Try e.g. C, Am, F#m7, Bbmaj7, Gsus4, C/E.", symbol);
+            return Ok(());
+        };
+        let Some(preset) = self.preset_or_patch(args.flag("preset")) else {
+            return Ok(());
+        };
+        let Some(dur_ms) = parse_duration_ms(args.flag("dur").unwrap_or("2s")) else {
+            println!("Invalid duration. Use e.g. '2s' or '500ms'.");
+            return Ok(());
+        };
+
+        // Keep the summed voices at roughly the level of a single note
+        let mut voice = preset.clone();
+        voice.scale_gain((freqs.len() as f32).sqrt().recip());
+        let mut sequence = Sequence::new();
+        for &freq in &freqs {
+            sequence.note(Duration::ZERO, Duration::from_millis(dur_ms), freq, &voice);
+        }
+
+        let hz: Vec<String> = freqs.iter().map(|f| format!("{:.1}", f)).collect();
+        println!("Playing {} ({} Hz) for {} ms...", symbol, hz.join(", "), dur_ms);
+        self.start_job(format!("chord {}", symbol), sequence, false, 1.0)
+    }
+
+    fn audition(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        if args.has_flag("all") {
+            return self.audition_all();
+        }
+        let Some(&preset_name) = args.positional.first() else {
+            println!("Usage: audition <preset>   - Play a note, an octave, and a chord");
+            println!("       audition --all      - Step through every preset");
+            return Ok(());
+        };
+        let Some(preset) = self.find_preset(preset_name).map(|preset| self.matched(preset)) else {
+            println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name);
+            return Ok(());
+        };
+        let name = self.preset_name(preset_name).unwrap_or(preset_name).to_string();
+        println!("Auditioning '{}'...", name);
+        self.start_job(format!("audition of '{}'", name), audition_phrase(&preset), false, 1.0)
+    }
+
+    /// Make a randomized variant of a preset the active patch and audition
+    /// it, saving it as a preset with `--save`
+    fn mutate(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        if args.positional.is_empty() {
+            println!("Usage: mutate <preset> [--amount 0.2] [--seed N] [--save <name>]");
+            println!("Example: mutate bell --amount 0.3 --seed 7");
+            return Ok(());
+        }
+        let preset_name = args.positional.join(" ");
+        let amount = args.flag("amount").map_or(Some(mutate::DEFAULT_AMOUNT), |a| a.parse::<f32>().ok());
+        let Some(amount) = amount.filter(|a| (0.0..=mutate::MAX_AMOUNT).contains(a)) else {
+            println!("Mutate by an amount from 0 to {}, such as --amount 0.2.", mutate::MAX_AMOUNT);
+            return Ok(());
+        };
+        let Some(seed) = args.flag("seed").map_or(Some(clock_seed()), |s| s.parse::<u64>().ok()) else {
+            println!("The seed must be a whole number, such as --seed 7.");
+            return Ok(());
+        };
+        let Some(preset) = self.find_preset(&preset_name) else {
+            println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name);
+            return Ok(());
+        };
+
+        let name = self.preset_name(&preset_name).unwrap_or(&preset_name).to_string();
+        self.patch = mutate::mutate(&preset, amount, seed);
+        self.patch_name = format!("{} mutant {}", name, seed);
+        self.update_drone();
+        println!("Mutated '{}' by {} (seed {}):", name, amount, seed);
+        print_changes(&preset, &self.patch);
+        match args.flag("save") {
+            Some(save_as) => self.save_preset(save_as)?,
+            None => println!("It's now the active patch: 'save preset <name>' keeps it, 'mutate' again for another."),
+        }
+        let phrase = audition_phrase(&self.matched(self.patch.clone()));
+        self.start_job(format!("'{}'", self.patch_name), phrase, false, 1.0)
+    }
+
+    /// Audition offspring of two presets one after another, saving the
+    /// keepers with their parents noted in the metadata
+    fn breed(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let [first, second] = args.positional[..] else {
+            println!("Usage: breed <presetA> <presetB> [--amount 0.1] [--seed N]");
+            println!("Example: breed bell marimba --amount 0.2");
+            return Ok(());
+        };
+        let amount = args.flag("amount").map_or(Some(mutate::BREED_AMOUNT), |a| a.parse::<f32>().ok());
+        let Some(amount) = amount.filter(|a| (0.0..=mutate::MAX_AMOUNT).contains(a)) else {
+            println!("Mutate offspring by an amount from 0 to {}, such as --amount 0.1.", mutate::MAX_AMOUNT);
+            return Ok(());
+        };
+        let Some(seed) = args.flag("seed").map_or(Some(clock_seed()), |s| s.parse::<u64>().ok()) else {
+            println!("The seed must be a whole number, such as --seed 7.");
+            return Ok(());
+        };
+        let find = |name: &str| {
+            let found = self.preset_name(name).zip(self.find_preset(name));
+            if found.is_none() {
+                println!("Preset '{}' not found. Use 'list presets' to see available options.", name);
+            }
+            found
+        };
+        let (Some((first, a)), Some((second, b))) = (find(first), find(second)) else {
+            return Ok(());
+        };
+
+        println!("Breeding '{}' and '{}' (seed {}).", first, second, seed);
+        let mut generation = 0;
+        let mut offspring = mutate::breed(&a, &b, amount, seed);
+        loop {
+            let inherited = |from_second: bool| {
+                let genes: Vec<&str> = offspring.genes.iter()
+                    .filter(|(_, from)| *from == from_second)
+                    .map(|(gene, _)| *gene)
+                    .collect();
+                match genes.is_empty() {
+                    true => "nothing".to_string(),
+                    false => genes.join(", "),
+                }
+            };
+            println!("[{}] {}: {}", generation + 1, first, inherited(false));
+            println!("    {}: {}", second, inherited(true));
+            let mut phrase = audition_phrase(&self.matched(offspring.params.clone()));
+            self.live_settings(&mut phrase);
+            let mut player = Player::start(&self.output, phrase, false, bar_length(1.0))?;
+            wait_for(&mut player);
+            if INTERRUPTED.swap(false, Ordering::SeqCst) {
+                break;
+            }
+
+            print!("  Enter: next, k: keep, r: repeat, q: quit > ");
+            std::io::stdout().flush()?;
+            let mut input = String::new();
+            if std::io::stdin().read_line(&mut input)? == 0 {
+                break;
+            }
+            match input.trim() {
+                "q" => break,
+                "r" => continue,
+                "k" => self.keep_offspring(&offspring.params, first, second, seed.wrapping_add(generation))?,
+                _ => {}
+            }
+            generation += 1;
+            offspring = mutate::breed(&a, &b, amount, seed.wrapping_add(generation));
+        }
+        Ok(())
+    }
+
+    /// Make an offspring of `breed` the active patch and save it under a
+    /// name asked for, crediting its parents
+    fn keep_offspring(&mut self, params: &FMParams, first: &str, second: &str, seed: u64) -> anyhow::Result<()> {
+        let suggested = format!("{} x {} {}", first, second, seed);
+        print!("  Name (Enter for '{}') > ", suggested);
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        let name = match input.trim() {
+            "" => suggested,
+            name => name.to_string(),
+        };
+        self.patch = params.clone();
+        self.patch_name = name.clone();
+        self.update_drone();
+        let Some(dir) = self.preset_dir.clone() else {
+            println!("  No preset directory to save it in; it's the active patch for now.");
+            return Ok(());
+        };
+        let meta_of = |name: &str| self.preset_meta.get(&slug(name)).cloned().unwrap_or_default();
+        let mut tags = meta_of(first).tags;
+        tags.extend(meta_of(second).tags.into_iter().filter(|tag| !tags.contains(tag)).collect::<Vec<_>>());
+        let meta = PresetMeta {
+            description: format!("Offspring of {} and {} (seed {})", first, second, seed),
+            tags,
+            ..PresetMeta::default()
+        };
+        self.save_patch(&dir, &name, meta)
+    }
+
+    /// Fit a preset to a recording, make it the active patch, and play it
+    /// at the recording's pitch and length
+    fn resynth(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        if args.positional.is_empty() {
+            println!("Usage: resynth <file.wav> [--save <name>]");
+            println!("Example: resynth bell-hit.wav --save Church Bell");
+            return Ok(());
+        }
+        let path = args.positional.join(" ");
+        let (sample_rate, samples) = wav::read_wav(Path::new(&path))
+            .map_err(|e| anyhow::anyhow!("Could not read '{}': {}", path, e))?;
+        let Some(fit) = resynth::resynthesize(&samples, sample_rate) else {
+            println!("'{}' is silent.", path);
+            return Ok(());
+        };
+
+        let p = &fit.params;
+        let note = note_with_cents(fit.pitch);
+        println!("Resynthesized '{}': pitch {:.2} Hz ({}), spectral match {:.0}%", path, fit.pitch, note, fit.similarity * 100.0);
+        println!("  ratio {:.3}, index {:.2}, level {:.1} dB", p.modulator_freq / p.carrier_freq, p.modulation_index, p.level_db);
+        println!("  attack {:.3} s, decay {:.3} s, sustain {:.2}, release {:.3} s", p.attack, p.decay, p.sustain, p.release);
+        if p.index_env {
+            println!("  index envelope: decay {:.3} s to {:.2}", p.index_decay, p.index_sustain);
+        }
+        self.patch = fit.params.clone();
+        self.patch_name = Path::new(&path).file_stem().map_or(path.clone(), |stem| stem.to_string_lossy().into_owned());
+        self.update_drone();
+        match (args.flag("save"), self.preset_dir.clone()) {
+            (Some(name), Some(dir)) => {
+                let meta = PresetMeta { description: format!("Resynthesized from {}", path), ..PresetMeta::default() };
+                self.save_patch(&dir, name, meta)?;
+            }
+            (Some(_), None) => println!("No preset directory; set preset_dir in the config file."),
+            (None, _) => println!("It's now the active patch; 'save preset <name>' keeps it. Play it at its own pitch with 'play note {}'.", note),
+        }
+
+        let mut sequence = Sequence::new();
+        let length = Duration::from_secs_f32(fit.duration.max(0.1));
+        sequence.note(Duration::ZERO, length, fit.pitch, &self.matched(self.patch.clone()));
+        self.start_job(format!("'{}'", self.patch_name), sequence, false, 1.0)
+    }
+
+    /// Audition each preset in turn, navigating with Enter / b / r / q
+    fn audition_all(&self) -> anyhow::Result<()> {
+        let mut index = 0;
+        while index < self.presets.len() {
+            let (name, _) = self.presets[index];
+            println!("[{}/{}] {}", index + 1, self.presets.len(), name);
+            if let Some(preset) = self.find_preset(name).map(|preset| self.matched(preset)) {
+                let mut phrase = audition_phrase(&preset);
+                self.live_settings(&mut phrase);
+                let mut player = Player::start(&self.output, phrase, false, bar_length(1.0))?;
+                wait_for(&mut player);
+            }
+            if INTERRUPTED.swap(false, Ordering::SeqCst) {
+                break;
+            }
+
+            print!("  Enter: next, b: back, r: repeat, q: quit > ");
+            std::io::stdout().flush()?;
+            let mut input = String::new();
+            if std::io::stdin().read_line(&mut input)? == 0 {
+                break;
+            }
+            match input.trim() {
+                "q" => break,
+                "r" => {}
+                "b" => index = index.saturating_sub(1),
+                _ => index += 1,
+            }
+        }
+        Ok(())
+    }
+
+    /// Ear training: play `--rounds` random intervals (or chords) and ask
+    /// for each one's name, replaying on request, then show the score and
+    /// which answers were hardest
+    fn train(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let usage = "Usage: train intervals|chords [--preset <preset>] [--set <names>] [--mode up|down|harmonic|mixed] [--rounds 10] [--seed <n>]";
+        let Some(seed) = args.flag("seed").map_or(Some(clock_seed()), |s| s.parse::<u64>().ok()) else {
+            println!("Invalid seed. Use a whole number.");
+            return Ok(());
+        };
+        let Some(rounds) = args.flag("rounds").map_or(Some(TRAIN_ROUNDS), |r| r.parse::<u32>().ok()).filter(|r| (1..=1000).contains(r)) else {
+            println!("Rounds are 1 to 1000, such as --rounds 10.");
+            return Ok(());
+        };
+        let set: Vec<&str> = args.flag("set").map_or(Vec::new(), |set| set.split(',').map(str::trim).collect());
+        let mut quiz = match args.positional.first().copied() {
+            Some("intervals") => {
+                let Some(mode) = train::Mode::parse(args.flag("mode").unwrap_or("up")) else {
+                    println!("Modes: up, down, harmonic, mixed.");
+                    return Ok(());
+                };
+                let mut intervals = Vec::new();
+                for name in set {
+                    match train::INTERVALS.iter().position(|&(short, long, semitones)| {
+                        name == short || name.eq_ignore_ascii_case(long) || name.parse::<i32>().ok() == Some(semitones)
+                    }) {
+                        Some(index) => intervals.push(index),
+                        None => {
+                            let names: Vec<&str> = train::INTERVALS.iter().map(|interval| interval.0).collect();
+                            println!("Unknown interval '{}'. Intervals: {}", name, names.join(" "));
+                            return Ok(());
+                        }
+                    }
+                }
+                Quiz::intervals(seed, intervals, mode)
+            }
+            Some("chords") => {
+                let mut qualities = Vec::new();
+                for name in set {
+                    let known = chord::quality_intervals(name).and_then(|intervals| {
+                        chord::quality_names().into_iter().find(|&quality| quality == name || chord::quality_intervals(quality) == Some(intervals))
+                    });
+                    match known {
+                        Some(quality) => qualities.push(quality),
+                        None => {
+                            println!("Unknown chord quality '{}'. Qualities: {}", name, chord::quality_names().join(" "));
+                            return Ok(());
+                        }
+                    }
+                }
+                Quiz::chords(seed, qualities)
+            }
+            _ => {
+                println!("{}", usage);
+                println!("Example: train intervals --set m3,M3,P4,P5 --mode mixed --preset organ");
+                return Ok(());
+            }
+        };
+        let Some(preset) = self.preset_or_patch(args.flag("preset")) else {
+            return Ok(());
+        };
+
+        // Training holds the prompt, so nothing else should be playing
+        if let Some(mut job) = self.job.take() {
+            job.player.stop_now();
+        }
+        let choices = quiz.choices();
+        println!("Name each one: {}   (Enter: reveal, r: repeat, q: quit)", choices.join(" "));
+        let mut round = 1;
+        while round <= rounds {
+            let question = quiz.next_question();
+            let mut sequence = question.sequence(&self.matched(preset.clone()));
+            self.live_settings(&mut sequence);
+            loop {
+                let mut player = Player::start(&self.output, sequence.clone(), false, bar_length(1.0))?;
+                wait_for(&mut player);
+                if INTERRUPTED.swap(false, Ordering::SeqCst) {
+                    round = rounds;
+                    break;
+                }
+                print!("[{}/{}] > ", round, rounds);
+                std::io::stdout().flush()?;
+                let mut input = String::new();
+                if std::io::stdin().read_line(&mut input)? == 0 {
+                    round = rounds;
+                    break;
+                }
+                let answer = match input.trim() {
+                    "q" => {
+                        round = rounds;
+                        break;
+                    }
+                    "r" => continue,
+                    "" => None,
+                    answer => match quiz.parse_answer(answer) {
+                        Some(answer) => Some(answer),
+                        None => {
+                            println!("  Answer one of: {}", choices.join(" "));
+                            continue;
+                        }
+                    },
+                };
+                // Revealing counts as a miss
+                let right = quiz.answer(&question, answer.unwrap_or(usize::MAX));
+                match (right, answer) {
+                    (true, _) => println!("  Right: {}.", quiz.describe(question.answer)),
+                    (false, Some(_)) => println!("  No, that was a {}.", quiz.describe(question.answer)),
+                    (false, None) => println!("  That was a {}.", quiz.describe(question.answer)),
+                }
+                break;
+            }
+            round += 1;
+        }
+
+        let score = quiz.score();
+        if score.asked == 0 {
+            return Ok(());
+        }
+        println!("Score: {} of {} ({:.0}%)", score.correct, score.asked, score.ratio() * 100.0);
+        let mut missed: Vec<(usize, u32, u32)> = score.by_choice.iter().enumerate()
+            .filter(|(_, &(asked, correct))| correct < asked)
+            .map(|(i, &(asked, correct))| (i, asked, correct))
+            .collect();
+        missed.sort_by(|a, b| (a.2 as f32 / a.1 as f32).total_cmp(&(b.2 as f32 / b.1 as f32)));
+        if !missed.is_empty() {
+            let hardest: Vec<String> = missed.iter().take(3).map(|&(i, asked, correct)| format!("{} ({} of {})", choices[i], correct, asked)).collect();
+            println!("Hardest: {}", hardest.join(", "));
+        }
+        Ok(())
+    }
+
+    fn play_freq(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let Some(&freq_arg) = args.positional.first() else {
+            println!("Usage: play freq <hz> [--index 6] [--ratio 1.41] [--preset bell] [--dur 1s]");
+            println!("Example: play freq 432.5 --index 6 --ratio 1.41");
+            return Ok(());
+        };
+        let freq = match freq_arg.parse::<f32>() {
+            Ok(freq) if freq > 0.0 => freq,
+            _ => {
+                println!("Invalid frequency '{}'", freq_arg);
+                return Ok(());
+            }
+        };
+        let Some(preset) = self.preset_or_patch(args.flag("preset")) else {
+            return Ok(());
+        };
+        let Some(dur_ms) = parse_duration_ms(args.flag("dur").unwrap_or("1s")) else {
+            println!("Invalid duration. Use e.g. '2s' or '500ms'.");
+            return Ok(());
+        };
+
+        // Explicit index and ratio replace the preset's (and its key scaling)
+        let mut params = preset.for_note(freq);
+        if let Some(index) = args.flag("index") {
+            let Ok(index) = index.parse::<f32>() else {
+                println!("Invalid modulation index '{}'", index);
+                return Ok(());
+            };
+            params.modulation_index = index;
+        }
+        if let Some(ratio) = args.flag("ratio") {
+            let Ok(ratio) = ratio.parse::<f32>() else {
+                println!("Invalid modulator ratio '{}'", ratio);
+                return Ok(());
+            };
+            params.modulator_freq = params.carrier_freq * ratio;
+        }
+        if let Err(err) = params.validate() {
+            println!("Invalid parameters: {}", err);
+            return Ok(());
+        }
+
+        println!(
+            "Playing {:.2} Hz (modulator {:.2} Hz, index {:.2}) for {} ms...",
+            params.carrier_freq, params.modulator_freq, params.modulation_index, dur_ms
+        );
+        let mut sequence = Sequence::new();
+        sequence.tone(Duration::ZERO, Duration::from_millis(dur_ms), freq, params);
+        self.start_job(format!("{} Hz", freq), sequence, false, 1.0)
+    }
+
+    fn play(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        if args.positional.len() < 2 {
+            println!("Usage: play <preset> <melody> [--bpm <n> | --speed <x>] [--transpose <semitones>] [--loop]");
+            println!("       [--mono | --legato] [--glide <ms>] [--priority last|high|low] [--output <n>] [--drums <pattern>]");
+            println!("Example: play bell twinkle");
+            println!("Example: play 1 3 --speed 1.5 --transpose -5");
+            println!("Example: play brass scale --legato --glide 60ms");
+            return Ok(());
+        }
+
+        let preset_name = args.positional[0];
+        let melody_name = args.positional[1..].join(" ");
+        let Some((speed, semitones)) = arrangement_options(&args) else {
+            return Ok(());
+        };
+        let Some(mono) = mono_option(&args) else {
+            return Ok(());
+        };
+        let Some(output) = args.flag("output").map_or(Some(0), parse_output) else {
+            println!("Outputs are numbered 1 to {}; route them with --channel-map.", MAX_OUTPUTS);
+            return Ok(());
+        };
+        let Some(gate) = self.gate_option(&args) else {
+            return Ok(());
+        };
+        let Some(drums) = drums_option(&args) else {
+            return Ok(());
+        };
+
+        match (self.find_preset(preset_name).map(|preset| self.matched(preset)), self.find_melody(&melody_name)) {
+            (Some(preset), Some(melody)) => {
+                let melody = arrange(&melody, speed, semitones);
+                let mut sequence = Sequence::new();
+                sequence.set_mono(mono);
+                sequence.set_output(output);
+                sequence.set_gate(gate);
+                sequence.set_tempo_map(self.find_tempo_map(&melody_name));
+                sequence.append_melody(&preset, &note_refs(&melody));
+                sequence.set_drums(drums, speed);
+                let title = format!("'{}' with '{}'", melody_name, preset_name);
+                self.start_job(title, sequence, args.has_flag("loop"), speed)?;
+                println!("Playing '{}' melody with '{}' preset...", melody_name, preset_name);
+                println!("  pause, resume, seek <bar>, tempo <bpm>, status, and stop control playback");
+            }
+            (None, _) => println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name),
+            (_, None) => println!("Melody '{}' not found. Use 'list melodies' to see available options.", melody_name),
+        }
+        Ok(())
+    }
+
+    /// Play (or with `--out`, render) a melody as a round. Per-part presets
+    /// and transpositions repeat if there are more parts than entries.
+    fn round(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        if args.positional.len() < 2 {
+            println!("Usage: round <preset> <melody> [--parts 3] [--entry <bars>] [--repeat 2] [--presets bell,organ]");
+            println!("       [--transpose 0,12,-12] [--outputs 1,2] [--bpm <n> | --speed <x>] [--gate <percent>] [--loop] [--out <file.wav>]");
+            println!("Example: round flute frere-jacques --parts 4 --entry 2");
+            println!("Example: round bell frere-jacques --presets bell,organ,strings --transpose 0,-12,12");
+            return Ok(());
+        }
+
+        let preset_name = args.positional[0];
+        let melody_name = args.positional[1..].join(" ");
+        let Some(melody) = self.find_melody(&melody_name) else {
+            println!("Melody '{}' not found. Use 'list melodies' to see available options.", melody_name);
+            return Ok(());
+        };
+        let Some(speed) = tempo_option(&args) else {
+            return Ok(());
+        };
+        let Some(gate) = self.gate_option(&args) else {
+            return Ok(());
+        };
+        let (Some((normalize, depth)), Some(sample_rate)) = (export_options(&args), export_rate(&args)) else {
+            return Ok(());
+        };
+        let preset_names: Vec<&str> = args.flag("presets").map_or(vec![preset_name], |list| list.split(',').collect());
+        let mut presets = Vec::new();
+        for name in &preset_names {
+            match self.find_preset(name.trim()) {
+                Some(preset) => presets.push(FMParams { gate: gate.unwrap_or(preset.gate), ..self.matched(preset) }),
+                None => {
+                    println!("Preset '{}' not found. Use 'list presets' to see available options.", name.trim());
+                    return Ok(());
+                }
+            }
+        }
+        let transpositions = match args.flag("transpose") {
+            Some(list) => match list.split(',').map(|t| t.trim().trim_start_matches('+').parse::<i32>()).collect::<Result<Vec<_>, _>>() {
+                Ok(semitones) if semitones.iter().all(|s| s.abs() <= 48) => semitones,
+                _ => {
+                    println!("Transpose each part by a whole number of semitones between -48 and 48, e.g. 0,12,-12.");
+                    return Ok(());
+                }
+            },
+            None => vec![0],
+        };
+        let outputs = match args.flag("outputs") {
+            Some(list) => match list.split(',').map(|o| parse_output(o.trim())).collect::<Option<Vec<_>>>() {
+                Some(outputs) => outputs,
+                None => {
+                    println!("Send each part to an output from 1 to {}, e.g. 1,2.", MAX_OUTPUTS);
+                    return Ok(());
+                }
+            },
+            None => vec![0],
+        };
+        // One part per listed preset, transposition, or output, or three
+        let default_parts = match presets.len().max(transpositions.len()).max(outputs.len()) {
+            1 => 3,
+            n => n,
+        };
+        let Some(count) = args.flag("parts").map_or(Some(default_parts), |n| n.parse::<usize>().ok()).filter(|n| (1..=8).contains(n)) else {
+            println!("A round has 1 to 8 parts.");
+            return Ok(());
+        };
+        let Some(entry) = args.flag("entry").map_or(Some(2.0), |bars| bars.parse::<f32>().ok()).filter(|b| b.is_finite() && *b >= 0.0 && *b <= 64.0) else {
+            println!("Entry must be a number of bars from 0 to 64, such as 2 or 0.5.");
+            return Ok(());
+        };
+        let Some(repeats) = args.flag("repeat").map_or(Some(2), |n| n.parse::<u32>().ok()).filter(|n| (1..=16).contains(n)) else {
+            println!("Repeat the melody 1 to 16 times.");
+            return Ok(());
+        };
+
+        let parts: Vec<RoundPart> = (0..count)
+            .map(|i| RoundPart {
+                preset: presets[i % presets.len()].clone(),
+                semitones: transpositions[i % transpositions.len()],
+                output: outputs[i % outputs.len()],
+            })
+            .collect();
+        let melody = arrange(&melody, speed, 0);
+        let mut sequence = Sequence::round(&parts, &note_refs(&melody), bar_length(speed).mul_f32(entry), repeats);
+        sequence.set_tempo_map(self.find_tempo_map(&melody_name));
+
+        if let Some(out) = args.flag("out") {
+            let rendered = render::render_tracks(DEFAULT_RENDER_RATE, sequence);
+            let mut buffer = resample::resample(&rendered, DEFAULT_RENDER_RATE as u32, sample_rate);
+            if let Some(gain) = normalize.map(|mode| render::normalize(&mut buffer, mode)) {
+                println!("Normalized with gain {:.2} dB", gain_to_db(gain));
+            }
+            wav::write_wav(out, sample_rate, &buffer, depth)?;
+            println!("Wrote {} ({:.1}s, {}-bit, {} Hz)", out, buffer.len() as f32 / sample_rate as f32, depth.bits(), sample_rate);
+            return Ok(());
+        }
+        let title = format!("'{}' round in {} parts", melody_name, count);
+        self.start_job(title, sequence, args.has_flag("loop"), speed)?;
+        println!("Playing '{}' as a round in {} parts, entering {} bar(s) apart...", melody_name, count, entry);
+        println!("  pause, resume, seek <bar>, tempo <bpm>, status, and stop control playback");
+        Ok(())
+    }
+
+    /// Apply the session's playback settings (effects, polyphony, sine
+    /// table) to a sequence about to play
+    fn live_settings(&self, sequence: &mut Sequence) {
+        sequence.set_dry(!self.effects);
+        sequence.set_sine_table(self.sine_table);
+        if let Some(voices) = self.voices {
+            sequence.set_voices(voices);
+        }
+    }
+
+    /// Replace whatever is playing in the background with `sequence`, or
+    /// with a loop running, play it over the loop (keeping it if recording)
+    fn start_job(&mut self, title: String, mut sequence: Sequence, looping: bool, speed: f32) -> anyhow::Result<()> {
+        if let Some(looper) = &self.looper {
+            if let Some(job) = self.job.as_ref().filter(|job| !job.player.is_finished()) {
+                job.player.overdub(sequence, looper.recording);
+                return Ok(());
+            }
+            self.looper = None;
+        }
+        // The new job takes over at once rather than waiting for releases
+        if let Some(mut job) = self.job.take() {
+            job.player.stop_now();
+        }
+        self.live_settings(&mut sequence);
+        let player = Player::start(&self.output, sequence, looping, bar_length(speed))?;
+        self.job = Some(Job { title, player, bpm: synth_data::MELODY_BPM * speed });
+        Ok(())
+    }
+
+    /// The player, if something is still playing
+    fn active_player(&self) -> Option<&Player> {
+        let player = self.job.as_ref().map(|job| &job.player).filter(|p| !p.is_finished());
+        if player.is_none() {
+            println!("Nothing is playing. Start a melody with 'play <preset> <melody>'.");
+        }
+        player
+    }
+
+    /// `voices` reports the voices sounding; `voices <n>` sets each
+    /// output's polyphony for what plays from now on, the running job
+    /// included, and `voices default` goes back to each sequence's own
+    fn voices(&mut self, parts: &[&str]) {
+        match parts.first().copied() {
+            None => {
+                match self.voices {
+                    Some(voices) => println!("Polyphony: {} voices per output.", voices),
+                    None => println!("Polyphony: each sequence's own ({} voices per output for melodies).", render::MELODY_VOICES),
+                }
+                if let Some(job) = self.job.as_ref().filter(|job| !job.player.is_finished()) {
+                    let (active, max) = job.player.voices();
+                    println!("{}: {} of {} voices sounding.", job.title, active, max);
+                }
+            }
+            Some("default") => {
+                self.voices = None;
+                println!("Polyphony back to each sequence's own; it applies from the next thing played.");
+            }
+            Some(value) => {
+                let Some(voices) = value.parse::<usize>().ok().filter(|v| (1..=MAX_VOICES).contains(v)) else {
+                    println!("Usage: voices [<1 - {}> | default]", MAX_VOICES);
+                    return;
+                };
+                self.voices = Some(voices);
+                if let Some(job) = self.job.as_ref().filter(|job| !job.player.is_finished()) {
+                    job.player.set_max_voices(voices);
+                }
+                println!("Polyphony {} voices per output.", voices);
+            }
+        }
+    }
+
+    fn tempo(&self, parts: &[&str]) {
+        let Some(bpm) = parts.first().and_then(|b| b.parse::<f32>().ok()).filter(|b| (12.0..=1200.0).contains(b)) else {
+            println!("Usage: tempo <bpm>   (12 - 1200)");
+            return;
+        };
+        let Some(job) = self.job.as_ref().filter(|job| !job.player.is_finished()) else {
+            println!("Nothing is playing. Start a melody with 'play <preset> <melody>'.");
+            return;
+        };
+        job.player.set_speed(bpm / job.bpm);
+        println!("Tempo {} BPM.", bpm);
+    }
+
+    fn status(&self) {
+        match self.job.as_ref().filter(|job| !job.player.is_finished()) {
+            Some(Job { title, player, bpm }) => {
+                let state = if player.is_paused() { "Paused" } else { "Playing" };
+                println!(
+                    "{} {}: bar {}, {} / {} at {:.0} BPM{}",
+                    state,
+                    title,
+                    bar_number(player),
+                    format_time(player.position()),
+                    format_time(player.length()),
+                    bpm * player.speed() * player.tempo(),
+                    if player.is_looping() { " (looping)" } else { "" }
+                );
+                let (active, max) = player.voices();
+                println!("  Voices: {} of {} sounding", active, max);
+            }
+            None => println!("Nothing is playing."),
+        }
+        if let Some(freq) = self.drone {
+            println!("Drone holding {:.2} Hz with '{}'.", freq, self.patch_name);
+        }
+        if let Some(tone) = &self.test_tone {
+            let signal = tone.signal.lock().unwrap();
+            if !signal.is_finished() {
+                println!("Test signal: {} at {}.", signal.signal().name(), format_time(signal.position()));
+            }
+        }
+        if let Some(recorder) = &self.recording {
+            println!("Recording to {} ({}).", recorder.path().display(), format_time(recorder.elapsed()));
+        }
+    }
+
+    /// Play a melody file, replaying it whenever it changes on disk, until
+    /// Ctrl-C. A replay starts at the bar of the first note that changed,
+    /// or from the top with `--top` or when only the tempo map changed. A
+    /// file that doesn't parse is reported and the last good version kept.
+    fn watch(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        if args.positional.is_empty() {
+            println!("Usage: watch <file.fmsong> [--preset <preset>] [--top] [--loop]");
+            println!("  The file is a melody file: notes = [[\"C4\", 400], [\"REST\", 200], ...]");
+            println!("Example: watch song.fmsong --preset bell");
+            return Ok(());
+        }
+        let path = PathBuf::from(args.positional.join(" "));
+        let Some(preset) = self.preset_or_patch(args.flag("preset")) else {
+            return Ok(());
+        };
+        let Some(gate) = self.gate_option(&args) else {
+            return Ok(());
+        };
+        let (top, looping) = (args.has_flag("top"), args.has_flag("loop"));
+
+        // Watching holds the prompt, so nothing else should be playing
+        if let Some(mut job) = self.job.take() {
+            job.player.stop_now();
+        }
+        let _playing = Playing::start();
+        println!("Watching {}; save it to replay, Ctrl-C to stop.", path.display());
+
+        let mut modified = None;
+        let mut song: Option<(MelodyNotes, Option<TempoMap>)> = None;
+        let mut player: Option<Player> = None;
+        let mut finished = false;
+        loop {
+            // Editors that save by renaming replace the file, so look it up each time
+            let stamp = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+            if stamp.is_some() && stamp != modified {
+                modified = stamp;
+                match config::load_melody(&path) {
+                    Ok((name, notes, tempo)) => {
+                        warn_unknown_notes(&name, &notes);
+                        let bar = match &song {
+                            Some((old, old_tempo)) if !top && *old_tempo == tempo => first_edited_bar(old, &notes),
+                            _ => 1,
+                        };
+                        let mut sequence = Sequence::new();
+                        sequence.set_gate(gate);
+                        sequence.set_tempo_map(tempo.clone());
+                        self.live_settings(&mut sequence);
+                        sequence.append_melody(&preset, &note_refs(&notes));
+                        if let Some(mut old) = player.take() {
+                            old.stop_now();
+                        }
+                        let start = bar_length(1.0) * (bar - 1);
+                        player = Some(Player::start_at(&self.output, sequence, looping, bar_length(1.0), start)?);
+                        finished = false;
+                        match bar {
+                            1 => println!("Playing '{}' from the top.", name),
+                            bar => println!("Playing '{}' from bar {}.", name, bar),
+                        }
+                        song = Some((notes, tempo));
+                    }
+                    Err(err) => println!("Couldn't load {}: {} (keeping the last version)", path.display(), err),
+                }
+            } else if stamp.is_none() && song.is_none() {
+                println!("Couldn't read {}.", path.display());
+                return Ok(());
+            }
+            if !finished && player.as_ref().is_some_and(Player::is_finished) {
+                println!("Finished; waiting for changes.");
+                finished = true;
+            }
+            if !wait_ms(WATCH_POLL_MS) {
+                break;
+            }
+        }
+        if let Some(mut player) = player {
+            player.stop_now();
+        }
+        Ok(())
+    }
+
+    /// Play generated phrases (see `jam::Jam`) one after another until
+    /// Ctrl-C. Each part takes turns with the presets given for it, or
+    /// else those tagged for the part.
+    fn jam(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let Some(seed) = args.flag("seed").map_or(Some(clock_seed()), |s| s.parse::<u64>().ok()) else {
+            println!("The seed must be a whole number, such as --seed 7.");
+            return Ok(());
+        };
+        let Some(key) = args.flag("key").map_or(Some(0), |key| synth_data::note_number(&format!("{}4", key))) else {
+            println!("The key is a note name without an octave, such as --key A or --key Eb.");
+            return Ok(());
+        };
+        let Some(scale) = args.flag("scale").map_or(Some(Scale::Major), Scale::parse) else {
+            println!("The scale is major or minor.");
+            return Ok(());
+        };
+        let Some(speed) = tempo_option(&args) else {
+            return Ok(());
+        };
+        let Some(drums) = drums_option(&args) else {
+            return Ok(());
+        };
+        let Some(rotate) = args.flag("rotate").map_or(Some(jam::DEFAULT_ROTATE), |r| r.parse::<usize>().ok()).filter(|&r| r > 0) else {
+            println!("Rotate presets every so many phrases, such as --rotate 2.");
+            return Ok(());
+        };
+        let mut presets: [jam::PartPresets; 3] = Default::default();
+        for (part, (flag, tags)) in presets.iter_mut().zip(JAM_PARTS) {
+            *part = match args.flag(flag) {
+                Some(list) => {
+                    let found: Option<Vec<_>> = list.split(',')
+                        .map(|name| {
+                            let preset = self.find_preset(name.trim());
+                            if preset.is_none() {
+                                println!("Preset '{}' not found. Use 'list presets' to see available options.", name.trim());
+                            }
+                            Some((self.preset_name(name.trim()).unwrap_or(name.trim()).to_string(), self.matched(preset?)))
+                        })
+                        .collect();
+                    let Some(found) = found else {
+                        return Ok(());
+                    };
+                    found
+                }
+                None => self.presets.iter()
+                    .filter(|(name, _)| self.preset_meta.get(&slug(name)).is_some_and(|meta| meta.tags.iter().any(|tag| tags.contains(&tag.as_str()))))
+                    .map(|(name, params)| (name.to_string(), self.matched(params.clone())))
+                    .collect(),
+            };
+            if part.is_empty() {
+                // Nothing tagged for it, so it plays the active patch
+                part.push((self.patch_name.clone(), self.matched(self.patch.clone())));
+            }
+        }
+
+        // Jamming holds the prompt, so nothing else should be playing
+        if let Some(mut job) = self.job.take() {
+            job.player.stop_now();
+        }
+        let _playing = Playing::start();
+        let mut jam = Jam::new(seed, key, scale, presets, rotate);
+        println!("Jamming in {} at {:.0} BPM (seed {}); Ctrl-C to stop.", jam.key_name(), synth_data::MELODY_BPM * speed, seed);
+
+        // Phrases are queued a phrase ahead and announced as they start
+        let mut announce = VecDeque::new();
+        let first = jam.next_phrase();
+        announce.push_back((Duration::ZERO, describe_phrase(1, &first)));
+        let mut sequence = first.sequence;
+        self.live_settings(&mut sequence);
+        // On an output of their own, with written-tempo steps as the
+        // phrases have; the player sets the speed
+        sequence.set_output(jam::DRUMS);
+        sequence.set_drums(drums, 1.0);
+        let mut player = Player::start(&self.output, sequence, false, bar_length(1.0))?;
+        player.set_speed(speed);
+        let mut phrases = 1;
+        loop {
+            if player.length().saturating_sub(player.position()) < Jam::phrase_length() {
+                let phrase = jam.next_phrase();
+                phrases += 1;
+                announce.push_back((player.length(), describe_phrase(phrases, &phrase)));
+                player.extend(phrase.sequence);
+            }
+            while announce.front().is_some_and(|(start, _)| player.position() >= *start) {
+                if let Some((_, line)) = announce.pop_front() {
+                    println!("{}", line);
+                }
+            }
+            if !wait_ms(INTERRUPT_POLL_MS) {
+                break;
+            }
+        }
+        player.stop_now();
+        Ok(())
+    }
+
+    /// Play a drum pattern (built-in, or written with `--kick` and the
+    /// like) in the background, looping until stopped or for `--bars`, or
+    /// render it with `--out`
+    fn drums(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let rows: Vec<(DrumKind, &str)> = DrumKind::ALL.into_iter()
+            .filter_map(|kind| Some((kind, args.flag(kind.name())?)))
+            .collect();
+        let pattern = if !rows.is_empty() {
+            let Some(pattern) = Pattern::parse(&rows) else {
+                println!("Write each drum's steps with x (hit), X (accent), o (ghost), and . (rest), such as --kick x...x...");
+                return Ok(());
+            };
+            pattern
+        } else if !args.positional.is_empty() {
+            let name = args.positional.join(" ");
+            let Some(pattern) = find_pattern(&name) else {
+                println!("Pattern '{}' not found. Use 'drums' to see available options.", name);
+                return Ok(());
+            };
+            pattern
+        } else {
+            println!("Usage: drums [<pattern>] [--kick <steps>] [--snare <steps>] [--hat <steps>] [--tom <steps>]");
+            println!("       [--bars N] [--bpm <n> | --speed <x>] [--out <file.wav>] [--normalize peak|-1dBFS] [--bit-depth 16|24|32f]");
+            println!("Patterns:");
+            for (i, (name, _)) in drums::get_patterns().iter().enumerate() {
+                println!("  {}. {}", i + 1, name);
+            }
+            println!("Example: drums rock --bpm 100");
+            println!("Example: drums --kick x..x..x. --snare ....x... --hat xxxxxxxx");
+            return Ok(());
+        };
+        let Some(speed) = tempo_option(&args) else {
+            return Ok(());
+        };
+        let bars = match args.flag("bars").map(str::parse::<u32>) {
+            Some(Ok(bars)) if (1..=999).contains(&bars) => Some(bars),
+            Some(_) => {
+                println!("Play a number of bars from 1 to 999, such as --bars 8.");
+                return Ok(());
+            }
+            None => None,
+        };
+        for (kind, grid) in pattern.grid() {
+            println!("  {:<6}{}", kind.name(), grid);
+        }
+
+        let mut sequence = Sequence::new();
+        // Without a number of bars the pattern loops, so one pass will do
+        sequence.rest(bars.map_or(pattern.length(), |bars| bar_length(1.0) * bars).div_f32(speed));
+        sequence.set_drums(Some(pattern), speed);
+        if let Some(out) = args.flag("out") {
+            let (Some((normalize, depth)), Some(sample_rate)) = (export_options(&args), export_rate(&args)) else {
+                return Ok(());
+            };
+            let (secs, gain) = render_file(Path::new(out), sequence, normalize, depth, sample_rate)?;
+            if let Some(gain) = gain {
+                println!("Normalized with gain {:.2} dB", gain_to_db(gain));
+            }
+            println!("Wrote {} ({:.1}s, {}-bit, {} Hz)", out, secs, depth.bits(), sample_rate);
+            return Ok(());
+        }
+        self.start_job("drums".to_string(), sequence, bars.is_none(), speed)?;
+        println!("Playing drums at {:.0} BPM...", synth_data::MELODY_BPM * speed);
+        println!("  pause, resume, seek <bar>, tempo <bpm>, status, and stop control playback");
+        Ok(())
+    }
+
+    /// Report the output stream's buffer and latency: the one playing, or
+    /// one opened briefly for the purpose. `--loopback` also measures the
+    /// round trip from the output back into an input.
+    fn latency(&self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let Some(tries) = args.flag("tries").map_or(Some(LOOPBACK_TRIES), |t| t.parse::<usize>().ok()).filter(|t| (1..=50).contains(t)) else {
+            println!("Tries are 1 to 50, such as --tries 5.");
+            return Ok(());
+        };
+        let playing = self.job.as_ref().filter(|job| !job.player.is_finished()).and_then(|job| job.player.output_stats());
+        let stats = match playing
+            .or_else(|| self.engine.as_ref().map(|engine| engine.output.stats()))
+            .or_else(|| self.test_tone.as_ref().map(|tone| tone.output.stats()))
+        {
+            Some(stats) => stats,
+            None => {
+                // Silent, but enough for the stream to report its callbacks
+                let synth = Arc::new(Mutex::new(PolySynth::new(DEFAULT_RENDER_RATE, 1)));
+                let output = audio::AudioOutput::start(self.output.clone(), synth)?;
+                let start = std::time::Instant::now();
+                while output.stats().callbacks < 2 && start.elapsed() < Duration::from_secs(1) {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                output.stats()
+            }
+        };
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        println!("Output '{}' at {} Hz, {} channels", stats.device, stats.sample_rate, stats.channels);
+        let requested = match stats.buffer_size {
+            Some(frames) => format!("{} frames requested", frames),
+            None => "backend default".to_string(),
+        };
+        if stats.callbacks == 0 {
+            println!("  Buffer: {} (no callback yet)", requested);
+        } else {
+            println!("  Buffer: {}, {} frames per callback ({:.1} ms)", requested, stats.callback_frames, ms(stats.buffer_latency()));
+        }
+        match stats.stream_latency {
+            Some(latency) => println!("  Stream latency: {:.1} ms, as the backend reports it", ms(latency)),
+            None => println!("  Stream latency: not reported by this backend; estimating one buffer"),
+        }
+        println!("  Output latency: about {:.1} ms", ms(stats.latency()));
+        match &stats.realtime {
+            None if self.output.realtime => println!("  Realtime priority: requested, not yet applied"),
+            None => println!("  Realtime priority: off (--realtime on to ask for it)"),
+            Some(Ok(())) => println!("  Realtime priority: granted"),
+            Some(Err(reason)) => println!("  Realtime priority: refused ({}){}", reason, REALTIME_HINT),
+        }
+
+        if !args.has_flag("loopback") {
+            return Ok(());
+        }
+        if self.job.as_ref().is_some_and(|job| !job.player.is_finished()) || self.drone.is_some() || self.test_tone.is_some() {
+            println!("Stop what is playing first: the measurement listens for its own beeps.");
+            return Ok(());
+        }
+        let device = args.flag("input").map(str::to_string).or_else(|| self.input_device.clone());
+        println!("Playing {} beeps; the output has to reach the input (a cable, or speakers near a microphone)...", tries);
+        let loopback = match latency::measure_loopback(&self.output, device.as_deref(), tries) {
+            Ok(loopback) => loopback,
+            Err(err) => {
+                println!("Couldn't measure the loopback: {}", err);
+                return Ok(());
+            }
+        };
+        let Some(median) = loopback.median() else {
+            println!("No beep came back from '{}'. Check the connection and the input's level.", loopback.input);
+            return Ok(());
+        };
+        let trips: Vec<String> = loopback.round_trips.iter().map(|&trip| format!("{:.1}", ms(trip))).collect();
+        println!("Round trip through '{}': {:.1} ms (median of {} ms)", loopback.input, ms(median), trips.join(", "));
+        if loopback.missed > 0 {
+            println!("  {} of {} beeps weren't heard.", loopback.missed, tries);
+        }
+        Ok(())
+    }
+
+    /// Render `--seconds` of held notes at each polyphony in `--voices`
+    /// through a null output (no device), reporting the rendering speed and
+    /// how far inside realtime it is
+    fn bench(&self, parts: &[&str]) {
+        let args = CommandArgs::parse(parts);
+        let voices = match args.flag("voices") {
+            Some(list) => list.split(',').map(|v| v.trim().parse::<usize>().ok().filter(|v| (1..=MAX_BENCH_VOICES).contains(v))).collect(),
+            None => Some(BENCH_VOICES.to_vec()),
+        };
+        let Some(voices) = voices else {
+            println!("List polyphonies from 1 to {}, such as --voices 1,16,64.", MAX_BENCH_VOICES);
+            return;
+        };
+        let Some(seconds) = args.flag("seconds").map_or(Some(BENCH_SECONDS), |s| s.trim_end_matches('s').parse::<f32>().ok()).filter(|s| *s > 0.0 && *s <= 600.0) else {
+            println!("Render for 0 to 600 seconds per run, such as --seconds 5.");
+            return;
+        };
+        let Some(buffer) = args.flag("buffer").map_or(Some(BENCH_BUFFER), |b| b.parse::<usize>().ok()).filter(|b| (16..=8192).contains(b)) else {
+            println!("Buffers are 16 to 8192 frames, such as --buffer 512.");
+            return;
+        };
+        let Some(sample_rate) = export_rate(&args) else {
+            return;
+        };
+        let Some(preset) = self.preset_or_patch(args.flag("preset")) else {
+            return;
+        };
+        let name = args.flag("preset").map_or(self.patch_name.as_str(), |name| self.preset_name(name).unwrap_or(name));
+
+        println!(
+            "Rendering '{}' at {} Hz in {}-frame buffers, {} s per run ({} {}, {} build)",
+            name,
+            sample_rate,
+            buffer,
+            seconds,
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            if cfg!(debug_assertions) { "debug" } else { "release" }
+        );
+        if cfg!(debug_assertions) {
+            println!("  (Debug builds are many times slower; build with --release for real figures.)");
+        }
+        if self.sine_table {
+            println!("  Sines from the lookup table (--sine-table on).");
+        }
+        let sine_table = self.sine_table.then(|| Arc::new(SineTable::default()));
+        println!("  {:>6}  {:>12}  {:>16}  {:>9}  {:>6}", "Voices", "Samples/s", "Voice-samples/s", "Realtime", "Load");
+        let frames = (seconds * sample_rate as f32) as u64;
+        let mut output = audio::NullOutput::new(sample_rate, 2, buffer);
+        let _playing = Playing::start();
+        for count in voices {
+            let synth = Mutex::new(PolySynth::new(sample_rate as f32, count));
+            {
+                let mut synth = synth.lock().unwrap();
+                synth.set_sine_table(sine_table.clone());
+                // Every voice held on its own note, so none is stolen or idle
+                for i in 0..count {
+                    let freq = synth_data::midi_to_freq(36 + (i % 60) as i32);
+                    synth.note_on_with(freq, preset.for_note(freq));
+                }
+            }
+            let elapsed = output.run(&synth, frames).as_secs_f64().max(1e-9);
+            let rate = frames as f64 / elapsed;
+            let realtime = rate / sample_rate as f64;
+            println!(
+                "  {:>6}  {:>12}  {:>16}  {:>8.1}x  {:>5.1}%",
+                count,
+                format_rate(rate),
+                format_rate(rate * count as f64),
+                realtime,
+                100.0 / realtime
+            );
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+    }
+
+    /// `loop <bars>` starts an empty loop; `rec` toggles overdubbing, `clear`
+    /// erases what was recorded, and `stop` ends it
+    fn looper(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let active = self.looper.is_some() && self.job.as_ref().is_some_and(|job| !job.player.is_finished());
+        match args.positional.first().copied() {
+            Some("rec") if active => {
+                let looper = self.looper.as_mut().expect("active loop");
+                looper.recording = !looper.recording;
+                if looper.recording {
+                    println!("Recording: notes you play now repeat on every pass. 'loop rec' again to stop.");
+                } else {
+                    println!("Recording off: notes play over the loop once.");
+                }
+            }
+            Some("clear") if active => {
+                if let Some(job) = &self.job {
+                    job.player.clear();
+                }
+                println!("Loop cleared.");
+            }
+            Some("stop") if active => self.stop(),
+            Some("rec" | "clear" | "stop") => println!("No loop is playing. Start one with 'loop <bars>'."),
+            Some(bars) => {
+                let Some(bars) = bars.parse::<u32>().ok().filter(|b| (1..=64).contains(b)) else {
+                    println!("Loop length must be 1 to 64 bars.");
+                    return Ok(());
+                };
+                let Some(speed) = tempo_option(&args) else {
+                    return Ok(());
+                };
+                let mut sequence = Sequence::new();
+                sequence.rest(bar_length(speed) * bars);
+                self.stop();
+                self.start_job(format!("{}-bar loop", bars), sequence, true, speed)?;
+                self.looper = Some(Looper { bars, recording: false });
+                println!(
+                    "Looping {} bars at {:.0} BPM. 'loop rec' to start overdubbing; notes, chords, and melodies you play land on the loop.",
+                    bars,
+                    synth_data::MELODY_BPM * speed
+                );
+            }
+            None => match &self.looper {
+                Some(looper) if active => println!(
+                    "{}-bar loop{}.",
+                    looper.bars,
+                    if looper.recording { ", recording" } else { "" }
+                ),
+                _ => println!("Usage: loop <bars> [--bpm <n>] | loop rec | loop clear | loop stop"),
+            },
+        }
+        Ok(())
+    }
+
+    /// Start recording the master output to a WAV file, or stop and
+    /// finalize the recording in progress
+    fn record(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        if self.recording.is_some() {
+            return self.stop_recording();
+        }
+        let args = CommandArgs::parse(parts);
+        let Some(&path) = args.positional.first().filter(|&&p| p != "stop") else {
+            println!("Usage: record <file.wav> [--bit-depth 16|24|32f]   (run 'record' again to stop)");
+            return Ok(());
+        };
+        let Some((_, depth)) = export_options(&args) else {
+            return Ok(());
+        };
+        let recorder = Recorder::start(Path::new(path), depth)?;
+        println!("Recording to {} ({}-bit stereo). Run 'record' again to stop.", path, depth.bits());
+        self.recording = Some(recorder);
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) -> anyhow::Result<()> {
+        if let Some(recorder) = self.recording.take() {
+            let path = recorder.path().display().to_string();
+            let length = recorder.stop()?;
+            println!("Saved recording {} ({}).", path, format_time(length));
+        }
+        Ok(())
+    }
+
+    /// `testsignal <kind>` plays a calibrated sine, sweep, stepped tones,
+    /// or noise on the output (or writes it to a WAV file with `--out`)
+    fn test_signal(&mut self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let usage = || {
+            println!("Usage: testsignal sine <hz> | sweep | steps | white | pink");
+            println!("       [--from 20] [--to 20000] [--dur 10s] [--step 1s] [--level -18] [--channel both|left|right]");
+            println!("       [--out <file.wav>] [--bit-depth 16|24|32f]");
+        };
+        let number = |name: &str, default: f32| match args.flag(name) {
+            Some(value) => value.trim_end_matches("dBFS").trim_end_matches("Hz").parse::<f32>().ok(),
+            None => Some(default),
+        };
+        let duration = |name: &str| args.flag(name).map(|d| parse_duration_ms(d).map(Duration::from_millis));
+        let kind = args.positional.first().map(|s| s.to_lowercase());
+        // Octave steps default to the centres of a ten-band EQ's upper nine bands
+        let (low, high) = if kind.as_deref() == Some("steps") { (62.5, 16000.0) } else { (20.0, 20000.0) };
+
+        let (Some(level), Some(from), Some(to), Some(dur), Some(step)) = (
+            number("level", testsignal::DEFAULT_LEVEL_DB).filter(|l| *l <= 0.0),
+            number("from", low).filter(|f| *f > 0.0),
+            number("to", high).filter(|f| *f > 0.0),
+            duration("dur").unwrap_or(Some(Duration::ZERO)),
+            duration("step").unwrap_or(Some(Duration::from_secs(1))).filter(|s| !s.is_zero()),
+        ) else {
+            println!("Levels are in dBFS (at most 0), frequencies in Hz, and durations like 2s or 500ms.");
+            return Ok(());
+        };
+        let length = Some(dur).filter(|d| !d.is_zero());
+        let signal = match kind.as_deref() {
+            Some("sine") => args.positional.get(1)
+                .and_then(|hz| hz.parse::<f32>().ok())
+                .filter(|hz| *hz > 0.0)
+                .map(Signal::Sine),
+            Some("sweep") => Some(Signal::Sweep { from, to, length: length.unwrap_or(Duration::from_secs(10)) }),
+            Some("steps") => Some(Signal::Steps { from, to, step }),
+            Some("white") => Some(Signal::WhiteNoise),
+            Some("pink") => Some(Signal::PinkNoise),
+            _ => None,
+        };
+        let channels = match args.flag("channel") {
+            Some(channel) => Channels::parse(channel),
+            None => Some(Channels::Both),
+        };
+        let (Some(signal), Some(channels)) = (signal, channels) else {
+            usage();
+            return Ok(());
+        };
+        let name = signal.name();
+
+        if let Some(path) = args.flag("out") {
+            let Some((_, depth)) = export_options(&args) else {
+                return Ok(());
+            };
+            let mut source = TestSignal::new(DEFAULT_RENDER_RATE, signal, level, length);
+            // Signals that play until stopped get five seconds in a file
+            let samples = source.render(5.0);
+            wav::write_wav(path, DEFAULT_RENDER_RATE as u32, &samples, depth)?;
+            println!("Wrote {} at {} dBFS to {} ({}).", name, level, path, format_time(source.position()));
+            return Ok(());
+        }
+
+        self.stop();
+        let mut source = TestSignal::new(DEFAULT_RENDER_RATE, signal, level, length);
+        source.set_channels(channels);
+        let length = source.length();
+        let signal = Arc::new(Mutex::new(source));
+        let output = audio::AudioOutput::start(self.output.clone(), Arc::clone(&signal))?;
+        self.test_tone = Some(TestTone { signal, output });
+        match length {
+            Some(length) => println!("Playing {} at {} dBFS for {}.", name, level, format_time(length)),
+            None => println!("Playing {} at {} dBFS. Use 'stop' to end it.", name, level),
+        }
+        Ok(())
+    }
+
+    /// Show or change the concert pitch everything is tuned to
+    fn tune(&self, parts: &[&str]) {
+        let Some(&value) = parts.first() else {
+            println!("Tuned to A4 = {} Hz.", synth_data::a4());
+            return;
+        };
+        let Some(a4) = parse_a4(value) else {
+            println!("Usage: tune [<hz>]   (A4 between {} and {} Hz, e.g. tune 442)", synth_data::MIN_A4, synth_data::MAX_A4);
+            return;
+        };
+        synth_data::set_a4(a4);
+        println!("Tuned to A4 = {} Hz; notes already playing keep their pitch.", a4);
+    }
+
+    /// Turn the presets' master bus effects on or off for what plays next
+    fn effects(&mut self, parts: &[&str]) {
+        match parts.first().map(|s| s.to_lowercase()).as_deref() {
+            None => {}
+            Some("on") => self.effects = true,
+            Some("off") => self.effects = false,
+            Some(_) => {
+                println!("Usage: effects [on|off]");
+                return;
+            }
+        }
+        println!("Preset effects are {}.", if self.effects { "on" } else { "off (playing dry)" });
+    }
+
+    /// Turn loudness matching of the presets on or off for what plays next
+    fn loudness(&mut self, parts: &[&str]) {
+        match parts.first().map(|s| s.to_lowercase()).as_deref() {
+            None => {}
+            Some("on") => self.loudness = true,
+            Some("off") => self.loudness = false,
+            Some(_) => {
+                println!("Usage: loudness [on|off]");
+                return;
+            }
+        }
+        match self.loudness {
+            true => println!("Loudness matching is on: presets play at {} dBFS RMS.", render::LOUDNESS_TARGET_DB),
+            false => println!("Loudness matching is off: presets play at their own level."),
+        }
+    }
+
+    /// Show or set the gate unmarked melody notes are held for, in place of
+    /// the presets' own, for what plays next
+    fn gate(&mut self, parts: &[&str]) {
+        match parts.first().map(|s| s.to_lowercase()).as_deref() {
+            None => {}
+            Some("preset") => self.gate = None,
+            Some(value) => match parse_gate(value) {
+                Some(gate) => self.gate = Some(gate),
+                None => {
+                    println!("Usage: gate [<percent>|preset]   (1% to 100%, e.g. gate 60%)");
+                    return;
+                }
+            },
+        }
+        match self.gate {
+            Some(gate) => println!("Unmarked notes are held for {:.0}% of their length.", gate * 100.0),
+            None => println!("Unmarked notes are held for their presets' gates."),
+        }
+    }
+
+    /// The `--gate` option, else the `gate` setting; `None` (after saying
+    /// why) if the option is invalid
+    fn gate_option(&self, args: &CommandArgs) -> Option<Option<f32>> {
+        match args.flag("gate") {
+            Some(gate) => match parse_gate(gate) {
+                Some(gate) => Some(Some(gate)),
+                None => {
+                    println!("Gate must be 1% to 100% of a note's length, such as --gate 60%.");
+                    None
+                }
+            },
+            None => Some(self.gate),
+        }
+    }
+
+    fn pause(&self) {
+        if let Some(player) = self.active_player() {
+            player.pause();
+            println!("Paused at bar {}.", bar_number(player));
+        }
+    }
+
+    fn resume(&self) {
+        if let Some(player) = self.active_player() {
+            player.resume();
+            println!("Resumed at bar {}.", bar_number(player));
         }
     }
 
-    fn print_menu(&self) {
-        println!("\n=== FM Synthesizer CLI ===");
-        println!("Commands:");
-        println!("  list presets  - Show all available presets");
-        println!("  list melodies - Show all available melodies");
-        println!("  play <preset> <melody> - Play a melody with a preset");
-        println!("  demo - Play all presets with a scale");
-        println!("  help - Show this menu");
-        println!("  quit - Exit the program");
+    fn seek(&self, parts: &[&str]) {
+        let Some(bar) = parts.first().and_then(|b| b.parse::<u32>().ok()).filter(|&b| b > 0) else {
+            println!("Usage: seek <bar>   (bars count from 1)");
+            return;
+        };
+        if let Some(player) = self.active_player() {
+            player.seek_bar(bar);
+            println!("Jumped to bar {}.", bar);
+        }
+    }
+
+    fn render(&self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        if args.positional.len() < 2 {
+            println!("Usage: render <preset> <melody> [--out <file.wav>] [--normalize peak|-1dBFS]");
+            println!("       [--bit-depth 16|24|32f] [--sample-rate 48000] [--bpm <n> | --speed <x>] [--transpose <semitones>]");
+            println!("       [--gate <percent>] [--drums <pattern>]");
+            println!("Example: render bell twinkle --out bell.wav --normalize -1dBFS --bit-depth 24");
+            return Ok(());
+        }
+
+        let preset_name = args.positional[0];
+        let melody_name = args.positional[1..].join(" ");
+        let (Some((normalize, depth)), Some(sample_rate)) = (export_options(&args), export_rate(&args)) else {
+            return Ok(());
+        };
+        let Some((speed, semitones)) = arrangement_options(&args) else {
+            return Ok(());
+        };
+        let Some(gate) = self.gate_option(&args) else {
+            return Ok(());
+        };
+        let Some(drums) = drums_option(&args) else {
+            return Ok(());
+        };
+
+        let (mut preset, melody) = match (self.find_preset(preset_name), self.find_melody(&melody_name)) {
+            (Some(preset), Some(melody)) => (preset, melody),
+            (None, _) => {
+                println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name);
+                return Ok(());
+            }
+            (_, None) => {
+                println!("Melody '{}' not found. Use 'list melodies' to see available options.", melody_name);
+                return Ok(());
+            }
+        };
+
+        let default_out = format!("{}-{}.wav", preset_name, melody_name.replace(' ', "-")).to_lowercase();
+        let out = args.flag("out").unwrap_or(&default_out);
+
+        if let Some(gate) = gate {
+            preset.gate = gate;
+        }
+        let melody = arrange(&melody, speed, semitones);
+        let mut sequence = Sequence::melody(&preset, &note_refs(&melody));
+        sequence.set_tempo_map(self.find_tempo_map(&melody_name));
+        sequence.set_drums(drums, speed);
+        let (secs, gain) = render_file(Path::new(out), sequence, normalize, depth, sample_rate)?;
+        if let Some(gain) = gain {
+            println!("Normalized with gain {:.2} dB", gain_to_db(gain));
+        }
+        println!("Wrote {} ({:.1}s, {}-bit, {} Hz)", out, secs, depth.bits(), sample_rate);
+        Ok(())
+    }
+
+    fn render_all(&self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let Some(dir) = args.flag("out") else {
+            println!("Usage: render-all --out <dir> [--presets bell,organ] [--melodies twinkle,ode]");
+            println!("       [--normalize peak|-1dBFS] [--bit-depth 16|24|32f] [--sample-rate 48000] [--jobs N]");
+            return Ok(());
+        };
+        let (Some((normalize, depth)), Some(sample_rate)) = (export_options(&args), export_rate(&args)) else {
+            return Ok(());
+        };
+        let jobs = args.flag("jobs")
+            .and_then(|j| j.parse::<usize>().ok())
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()))
+            .max(1);
+
+        // Comma-separated filters match list numbers or parts of names
+        let matches = |filter: Option<&str>, index: usize, name: &str| {
+            filter.is_none_or(|f| {
+                f.split(',').any(|term| term.trim() == (index + 1).to_string() || slug(name).contains(&slug(term)))
+            })
+        };
+        let presets: Vec<(&str, FMParams)> = self.presets.iter()
+            .enumerate()
+            .filter(|(i, (name, _))| matches(args.flag("presets"), *i, name))
+            .filter_map(|(_, (name, _))| Some((*name, self.find_preset(name)?)))
+            .map(|(name, preset)| (name, FMParams { gate: self.gate.unwrap_or(preset.gate), ..preset }))
+            .collect();
+        let melodies: Vec<&(&str, Vec<(&str, u64)>)> = self.melodies.iter()
+            .enumerate()
+            .filter(|(i, (name, _))| matches(args.flag("melodies"), *i, name))
+            .map(|(_, melody)| melody)
+            .collect();
+
+        let work: Vec<_> = presets.iter()
+            .flat_map(|(preset_name, preset)| {
+                melodies.iter().map(move |(melody_name, melody)| {
+                    let file = format!("{}-{}.wav", slug(preset_name), slug(melody_name));
+                    let tempo = self.tempo_maps.get(&slug(melody_name)).cloned();
+                    (Path::new(dir).join(file), preset, melody.as_slice(), tempo)
+                })
+            })
+            .collect();
+        if work.is_empty() {
+            println!("Nothing to render: no preset/melody matches the filters.");
+            return Ok(());
+        }
+        std::fs::create_dir_all(dir)?;
+
+        println!("Rendering {} files to {} with {} jobs...", work.len(), dir, jobs);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+        let done = AtomicUsize::new(0);
+        // Failures come back in work order, however the jobs were scheduled
+        let failures: Vec<String> = std::thread::scope(|scope| {
+            let rendering = scope.spawn(|| {
+                pool.install(|| {
+                    work.par_iter()
+                        .filter_map(|(path, preset, melody, tempo)| {
+                            let mut sequence = Sequence::melody(preset, melody);
+                            sequence.set_tempo_map(tempo.clone());
+                            let result = render_file(path, sequence, normalize, depth, sample_rate);
+                            done.fetch_add(1, Ordering::Relaxed);
+                            result.err().map(|err| format!("{}: {}", path.display(), err))
+                        })
+                        .collect()
+                })
+            });
+
+            loop {
+                let finished = done.load(Ordering::Relaxed);
+                print_progress(finished, work.len());
+                if finished == work.len() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            rendering.join().unwrap()
+        });
         println!();
+
+        for failure in &failures {
+            println!("Failed: {}", failure);
+        }
+        println!("Wrote {} of {} files to {}", work.len() - failures.len(), work.len(), dir);
+        Ok(())
     }
 
-    fn list_presets(&self) {
-        println!("\nAvailable Presets:");
-        for (i, (name, _)) in self.presets.iter().enumerate() {
-            println!("  {}. {}", i + 1, name);
+    fn waveform(&self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        let Some(&preset_name) = args.positional.first() else {
+            println!("Usage: waveform <preset> [--note A4] [--dur 1s] [--out <file.svg|png>] [--envelope]");
+            println!("       [--width 1200] [--height 300]");
+            println!("Example: waveform bell --note C5 --out bell.png --envelope");
+            return Ok(());
+        };
+
+        let Some(preset) = self.find_preset(preset_name) else {
+            println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name);
+            return Ok(());
+        };
+        let note = args.flag("note").unwrap_or("A4");
+        let freq = note_freq(note);
+        if freq <= 0.0 {
+            println!("Unknown note '{}'", note);
+            return Ok(());
+        }
+        let Some(gate_ms) = parse_duration_ms(args.flag("dur").unwrap_or("1s")) else {
+            println!("Invalid duration. Use e.g. '2s' or '500ms'.");
+            return Ok(());
+        };
+        let width = args.flag("width").and_then(|w| w.parse().ok()).unwrap_or(1200);
+        let height = args.flag("height").and_then(|h| h.parse().ok()).unwrap_or(300);
+        let default_out = format!("{}-{}.svg", preset_name, note).to_lowercase();
+        let out = args.flag("out").unwrap_or(&default_out);
+
+        let (samples, envelope) = render::render_note(DEFAULT_RENDER_RATE, &preset, freq, gate_ms);
+        let mut image = WaveformImage::new(width.max(1), height.max(2), &samples);
+        if args.has_flag("envelope") {
+            image = image.with_envelope(&envelope);
         }
+
+        std::fs::write(out, image.encode(ImageFormat::from_path(out)))?;
+        println!("Wrote {} ({}x{})", out, image.width, image.height);
+        Ok(())
     }
 
-    fn list_melodies(&self) {
-        println!("\nAvailable Melodies:");
-        for (i, (name, _)) in self.melodies.iter().enumerate() {
-            println!("  {}. {}", i + 1, name);
+    fn analyze(&self, parts: &[&str]) {
+        let args = CommandArgs::parse(parts);
+        let Some(&preset_name) = args.positional.first() else {
+            println!("Usage: analyze <preset> [--sample-rate 44100]");
+            return;
+        };
+        let Some(preset) = self.find_preset(preset_name) else {
+            println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name);
+            return;
+        };
+        let sample_rate = args.flag("sample-rate")
+            .and_then(|r| r.parse::<f32>().ok())
+            .unwrap_or(DEFAULT_RENDER_RATE);
+
+        println!("\nAnalysis of '{}' at {} Hz", preset_name, sample_rate);
+        println!(
+            "  {:<5} {:>9} {:>6} {:>10} {:>14} {:>10}",
+            "Note", "Freq", "Beta", "Sidebands", "Aliasing (dB)", "THD (dB)"
+        );
+        for result in analysis::analyze_preset(&preset, sample_rate) {
+            println!(
+                "  {:<5} {:>9.2} {:>6.2} {:>10} {:>14.1} {:>10.1}",
+                result.note, result.freq, result.beta, result.sidebands, result.aliasing_db, result.thd_db
+            );
         }
+        println!("  Aliasing: energy away from predicted sidebands. THD: deviation from the Bessel spectrum.");
     }
 
-    fn find_preset(&self, name: &str) -> Option<FMParams> {
-        // Try by number first
-        if let Ok(num) = name.parse::<usize>() {
-            if num > 0 && num <= self.presets.len() {
-                return Some(self.presets[num - 1].1.clone());
+    /// Print the sidebands FM theory predicts for a preset at one note next
+    /// to those measured in a render
+    fn describe(&self, parts: &[&str]) {
+        let args = CommandArgs::parse(parts);
+        let Some(&preset_name) = args.positional.first() else {
+            println!("Usage: describe <preset> [--note A4] [--sample-rate 44100]");
+            return;
+        };
+        let Some(preset) = self.find_preset(preset_name) else {
+            println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name);
+            return;
+        };
+        let note = args.flag("note").unwrap_or("A4");
+        let freq = note_freq(note);
+        if freq <= 0.0 {
+            println!("Unknown note '{}'", note);
+            return;
+        }
+        let sample_rate = args.flag("sample-rate")
+            .and_then(|r| r.parse::<f32>().ok())
+            .unwrap_or(DEFAULT_RENDER_RATE);
+
+        let params = preset.for_note(freq);
+        let name = self.preset_name(preset_name).unwrap_or(preset_name);
+        println!("\n'{}' at {} ({:.2} Hz)", name, note, freq);
+        println!(
+            "  Carrier {:.2} Hz, modulator {:.2} Hz (ratio {:.3}), index {:.2}, beta {:.2}",
+            params.carrier_freq,
+            params.modulator_freq,
+            params.modulator_freq / params.carrier_freq,
+            params.modulation_index,
+            analysis::beta(&params)
+        );
+        println!("  Carson bandwidth {:.0} Hz: 2 x (beta + 1) x modulator", analysis::carson_bandwidth(&preset, freq));
+        println!("  {:>5} {:>10} {:>10} {:>8} {:>10} {:>8}", "Order", "Freq (Hz)", "Predicted", "dB", "Measured", "dB");
+        let db = |amplitude: f64| 20.0 * amplitude.max(1e-10).log10();
+        for level in analysis::measure_sidebands(&preset, freq, sample_rate) {
+            let sideband = &level.sideband;
+            let order = match sideband.order {
+                0 => "0".to_string(),
+                order => format!("{:+}", order),
+            };
+            println!(
+                "  {:>5} {:>10.2} {:>10.4} {:>8.1} {:>10.4} {:>8.1}",
+                order, sideband.freq, sideband.amplitude, db(sideband.amplitude), level.measured, db(level.measured)
+            );
+        }
+        println!("  Predicted: |J_k(beta)| at carrier + k x modulator, relative to the unmodulated carrier; sidebands");
+        println!("  below 0 Hz reflect, and ones that meet add by phase. Measured: a dry render, scaled to the same energy.");
+    }
+
+    fn verify(&self, parts: &[&str]) -> anyhow::Result<()> {
+        let args = CommandArgs::parse(parts);
+        if args.positional.len() < 2 {
+            println!("Usage: verify <preset> <melody> [--sample-rate 44100] [--seed N] [--expect <hash>]");
+            return Ok(());
+        }
+
+        let preset_name = args.positional[0];
+        let melody_name = args.positional[1..].join(" ");
+        let (preset, melody) = match (self.find_preset(preset_name), self.find_melody(&melody_name)) {
+            (Some(preset), Some(melody)) => (preset, melody),
+            (None, _) => {
+                println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name);
+                return Ok(());
+            }
+            (_, None) => {
+                println!("Melody '{}' not found. Use 'list melodies' to see available options.", melody_name);
+                return Ok(());
+            }
+        };
+        let sample_rate = args.flag("sample-rate")
+            .and_then(|r| r.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_RENDER_RATE as u32);
+        let seed = args.flag("seed")
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(wav::DITHER_SEED);
+
+        let hash = format!("{:016x}", render::render_hash(sample_rate, seed, &preset, &melody));
+        println!("{}  {} / {} @ {} Hz, seed {}", hash, preset_name, melody_name, sample_rate, seed);
+
+        if let Some(expected) = args.flag("expect") {
+            if !expected.eq_ignore_ascii_case(&hash) {
+                anyhow::bail!("render hash mismatch: expected {}, got {}", expected, hash);
             }
+            println!("OK");
         }
-        
-        // Try by name (case insensitive)
-        self.presets.iter()
-            .find(|(n, _)| n.to_lowercase() == name.to_lowercase())
-            .map(|(_, p)| p.clone())
+        Ok(())
     }
+}
 
-    fn find_melody(&self, name: &str) -> Option<Vec<(&'static str, u64)>> {
-        // Try by number first
-        if let Ok(num) = name.parse::<usize>() {
-            if num > 0 && num <= self.melodies.len() {
-                return Some(self.melodies[num - 1].1.clone());
+/// Why realtime priority is usually refused, where there's a usual reason
+const REALTIME_HINT: &str = if cfg!(target_os = "linux") {
+    "; allow it with an rtprio limit (/etc/security/limits.conf, or the audio group)"
+} else {
+    ""
+};
+
+/// Questions `train` asks, by default
+const TRAIN_ROUNDS: u32 = 10;
+
+/// Beeps `latency --loopback` times, by default
+const LOOPBACK_TRIES: usize = 5;
+
+/// Polyphonies `bench` measures by default, and the most it takes
+const BENCH_VOICES: [usize; 5] = [1, 8, 16, 32, 64];
+const MAX_BENCH_VOICES: usize = 1024;
+
+/// Seconds of audio `bench` renders per polyphony, by default
+const BENCH_SECONDS: f32 = 5.0;
+
+/// Frames per buffer `bench` renders, by default: a typical device buffer
+const BENCH_BUFFER: usize = 512;
+
+/// A rate per second with an SI prefix, e.g. `12.3 M`
+fn format_rate(rate: f64) -> String {
+    match rate {
+        r if r >= 1e9 => format!("{:.2} G", r / 1e9),
+        r if r >= 1e6 => format!("{:.2} M", r / 1e6),
+        r if r >= 1e3 => format!("{:.2} k", r / 1e3),
+        r => format!("{:.0}", r),
+    }
+}
+
+/// Print every host, output device, and supported stream configuration
+fn list_devices() {
+    for host in audio::list_output_devices() {
+        println!("\nHost: {}{}", host.name, if host.is_default { " (default)" } else { "" });
+        if host.devices.is_empty() {
+            println!("  No output devices");
+        }
+        for (i, device) in host.devices.iter().enumerate() {
+            println!("  {}. {}{}", i + 1, device.name, if device.is_default { " (default)" } else { "" });
+            for config in &device.configs {
+                let buffer = match config.buffer_size {
+                    cpal::SupportedBufferSize::Range { min, max } => format!("buffer {}-{}", min, max),
+                    cpal::SupportedBufferSize::Unknown => "buffer size unknown".to_string(),
+                };
+                println!(
+                    "       {} ch, {}-{} Hz, {}, {}",
+                    config.channels, config.min_sample_rate, config.max_sample_rate, config.sample_format, buffer
+                );
             }
         }
-        
-        // Try by name (case insensitive)
-        self.melodies.iter()
-            .find(|(n, _)| n.to_lowercase().contains(&name.to_lowercase()))
-            .map(|(_, m)| m.clone())
     }
+    println!("\nSelect a host with --host <name> and a device of that host with --device <number|name>");
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn play_melody(preset: FMParams, melody: Vec<(&'static str, u64)>) -> anyhow::Result<()> {
-    let host = cpal::default_host();
-    let device = host.default_output_device()
-        .expect("No output device available");
-    
-    let config = device.default_output_config()?;
-    let sample_rate = config.sample_rate().0 as f32;
-    
-    let synth = Arc::new(Mutex::new(FMSynth::new(sample_rate, preset.clone())));
-    let synth_clone = Arc::clone(&synth);
-    
-    let stream = match config.sample_format() {
-        cpal::SampleFormat::F32 => device.build_output_stream(
-            &config.into(),
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                let mut synth = synth_clone.lock().unwrap();
-                for sample in data.iter_mut() {
-                    *sample = synth.next_sample();
-                }
-            },
-            |err| eprintln!("Error in audio stream: {}", err),
-            None,
-        )?,
-        _ => panic!("Unsupported sample format"),
+/// Silence between presets in the demo
+const DEMO_GAP: Duration = Duration::from_millis(500);
+
+/// Length of a 4/4 bar of a built-in melody played at `speed`
+fn bar_length(speed: f32) -> Duration {
+    Duration::from_secs_f32(BEATS_PER_BAR * 60.0 / (synth_data::MELODY_BPM * speed))
+}
+
+/// Minutes and seconds, e.g. `1:05.3`
+fn format_time(time: Duration) -> String {
+    let secs = time.as_secs_f32();
+    format!("{}:{:04.1}", (secs / 60.0) as u32, secs % 60.0)
+}
+
+/// `jam` parts (lead, chords, bass): the flag choosing their presets,
+/// and the tags of the presets they take turns with by default
+const JAM_PARTS: [(&str, &[&str]); 3] = [
+    ("lead", &["lead", "mallet"]),
+    ("chords", &["pad", "keys"]),
+    ("bass", &["bass"]),
+];
+
+/// One line about a `jam` phrase as it starts
+fn describe_phrase(number: usize, phrase: &jam::Phrase) -> String {
+    let [lead, chords, bass] = &phrase.presets;
+    format!("  {:>3}: {:<20} lead '{}', chords '{}', bass '{}'", number, phrase.chords.join(" "), lead, chords, bass)
+}
+
+/// A built-in drum pattern by number or name
+fn find_pattern(name: &str) -> Option<Pattern> {
+    let patterns = drums::get_patterns();
+    let found = match name.parse::<usize>() {
+        Ok(num) if num > 0 => patterns.into_iter().nth(num - 1),
+        _ => patterns.into_iter().find(|(n, _)| slug(n) == slug(name)),
     };
-    
-    stream.play()?;
-    
-    for (note, duration) in melody {
-        let freq = note_freq(note);
-        if freq > 0.0 {
-            let mut params = preset.clone();
-            let freq_ratio = freq / 440.0;
-            params.carrier_freq *= freq_ratio;
-            params.modulator_freq *= freq_ratio;
-            
-            {
-                let mut synth = synth.lock().unwrap();
-                synth.set_params(params);
-                synth.note_on();
+    found.map(|(_, pattern)| pattern)
+}
+
+/// The `--drums <pattern>` option; `None` (after saying why) if there's no
+/// such pattern
+fn drums_option(args: &CommandArgs) -> Option<Option<Pattern>> {
+    match args.flag("drums") {
+        Some(name) => match find_pattern(name) {
+            Some(pattern) => Some(Some(pattern)),
+            None => {
+                println!("Pattern '{}' not found. Use 'drums' to see available options.", name);
+                None
             }
-            
-            std::thread::sleep(Duration::from_millis(duration * 80 / 100));
-            
-            {
-                let mut synth = synth.lock().unwrap();
-                synth.note_off();
+        },
+        None => Some(None),
+    }
+}
+
+/// How often `watch` checks its file for changes
+const WATCH_POLL_MS: u64 = 200;
+
+/// 1-based bar (at the written tempo) holding the first note of `new` that
+/// differs from `old`, or bar 1 if nothing did
+fn first_edited_bar(old: &MelodyNotes, new: &MelodyNotes) -> u32 {
+    let changed = old.iter().zip(new).position(|(a, b)| a != b);
+    // Notes added or removed at the end change things from where the shorter stops
+    let Some(first) = changed.or((old.len() != new.len()).then_some(old.len().min(new.len()))) else {
+        return 1;
+    };
+    let start: u64 = new[..first].iter().map(|&(_, ms)| ms).sum();
+    (start as f32 / 1000.0 / bar_length(1.0).as_secs_f32()) as u32 + 1
+}
+
+/// Warn about notes in a user melody that will play as rests
+fn warn_unknown_notes(name: &str, notes: &MelodyNotes) {
+    for (note, _) in notes {
+        if note_freq(note) <= 0.0 && !note.eq_ignore_ascii_case("rest") {
+            println!("Warning: melody '{}' has unknown note '{}' (played as a rest)", name, note);
+        }
+    }
+}
+
+/// 1-based bar the player is currently in
+fn bar_number(player: &Player) -> u32 {
+    (player.position().as_secs_f32() / player.bar_length().as_secs_f32()) as u32 + 1
+}
+
+/// Parse the tempo and `--transpose` options shared by play and render
+fn arrangement_options(args: &CommandArgs) -> Option<(f32, i32)> {
+    let speed = tempo_option(args)?;
+    let semitones = match args.flag("transpose").map(|t| t.trim_start_matches('+').parse::<i32>()) {
+        Some(Ok(semitones)) if semitones.abs() <= 48 => semitones,
+        Some(_) => {
+            println!("Transpose by a whole number of semitones between -48 and 48.");
+            return None;
+        }
+        None => 0,
+    };
+    Some((speed, semitones))
+}
+
+/// Parse `--mono`, `--legato`, `--glide <time>`, and `--priority` into the mono engine
+/// settings (`None` inside for the polyphonic engine), printing the problem
+/// and returning `None` if invalid
+fn mono_option(args: &CommandArgs) -> Option<Option<Mono>> {
+    let legato = args.has_flag("legato");
+    let glide = match args.flag("glide") {
+        Some(glide) => match parse_duration_ms(glide) {
+            Some(ms) if ms <= 5000 => Duration::from_millis(ms),
+            _ => {
+                println!("Glide must be a time up to 5s, such as 80ms.");
+                return None;
             }
-            
-            std::thread::sleep(Duration::from_millis(duration * 20 / 100));
-        } else {
-            std::thread::sleep(Duration::from_millis(duration));
+        },
+        None if args.has_flag("glide") => {
+            println!("Glide needs a time, such as --glide 80ms.");
+            return None;
         }
+        None => Duration::ZERO,
+    };
+    let priority = match args.flag("priority") {
+        Some(priority) => match NotePriority::parse(priority) {
+            Some(priority) => priority,
+            None => {
+                println!("Unknown note priority '{}'. Use last, high, or low.", priority);
+                return None;
+            }
+        },
+        None => NotePriority::Last,
+    };
+    if legato || args.has_flag("mono") || !glide.is_zero() || args.has_flag("priority") {
+        Some(Some(Mono { legato, glide, priority }))
+    } else {
+        Some(None)
     }
-    
-    std::thread::sleep(Duration::from_millis(500));
+}
+
+/// Apply a speed factor and transposition to a melody
+fn arrange(melody: &[(&str, u64)], speed: f32, semitones: i32) -> MelodyNotes {
+    synth_data::change_tempo(&synth_data::transpose(melody, semitones), speed)
+}
+
+/// Borrow note names so an owned melody can be played or rendered
+fn note_refs(melody: &MelodyNotes) -> Vec<(&str, u64)> {
+    melody.iter().map(|(note, duration)| (note.as_str(), *duration)).collect()
+}
+
+/// Parse `--bpm` (relative to the melodies' 120 BPM) or `--speed` into a
+/// speed factor, printing the problem and returning `None` if invalid
+fn tempo_option(args: &CommandArgs) -> Option<f32> {
+    let speed = match (args.flag("bpm"), args.flag("speed")) {
+        (Some(_), Some(_)) => {
+            println!("Use either --bpm or --speed, not both.");
+            return None;
+        }
+        (Some(bpm), None) => bpm.parse::<f32>().ok().map(|bpm| bpm / synth_data::MELODY_BPM),
+        (None, Some(speed)) => speed.parse::<f32>().ok(),
+        (None, None) => return Some(1.0),
+    };
+    match speed {
+        Some(speed) if speed.is_finite() && (0.1..=10.0).contains(&speed) => Some(speed),
+        _ => {
+            println!("Tempo must be between 12 and 1200 BPM (speed 0.1 to 10).");
+            None
+        }
+    }
+}
+
+/// Parse the `--normalize` and `--bit-depth` export options, printing the
+/// problem and returning `None` if either is invalid
+fn export_options(args: &CommandArgs) -> Option<(Option<Normalize>, BitDepth)> {
+    let normalize = match args.flag("normalize") {
+        Some(mode) => match Normalize::parse(mode) {
+            Some(mode) => Some(mode),
+            None => {
+                println!("Unknown normalization '{}'. Use 'peak' or a level such as '-1dBFS'.", mode);
+                return None;
+            }
+        },
+        None => None,
+    };
+    let depth = match args.flag("bit-depth") {
+        Some(bits) => match BitDepth::parse(bits) {
+            Some(depth) => depth,
+            None => {
+                println!("Unsupported bit depth '{}'. Use 16, 24, or 32f.", bits);
+                return None;
+            }
+        },
+        None => BitDepth::Int16,
+    };
+    Some((normalize, depth))
+}
+
+/// An output number as written (from 1) to its index, if it's one of the
+/// `MAX_OUTPUTS`
+fn parse_output(text: &str) -> Option<usize> {
+    text.parse::<usize>().ok().filter(|n| (1..=MAX_OUTPUTS).contains(n)).map(|n| n - 1)
+}
+
+/// Parse the `--sample-rate` of an exported file, printing the problem and
+/// returning `None` if it's invalid. Files are always rendered at
+/// `DEFAULT_RENDER_RATE` and resampled, so every rate sounds the same.
+fn export_rate(args: &CommandArgs) -> Option<u32> {
+    match args.flag("sample-rate") {
+        Some(rate) => match rate.parse::<u32>() {
+            Ok(rate) if (8000..=384_000).contains(&rate) => Some(rate),
+            _ => {
+                println!("Unsupported sample rate '{}'. Use 8000 to 384000 Hz, e.g. 48000.", rate);
+                None
+            }
+        },
+        None => Some(DEFAULT_RENDER_RATE as u32),
+    }
+}
+
+/// Render a sequence to a WAV file, returning its length in seconds and the
+/// normalization gain applied, if any
+fn render_file(
+    path: &Path,
+    sequence: Sequence,
+    normalize: Option<Normalize>,
+    depth: BitDepth,
+    sample_rate: u32,
+) -> anyhow::Result<(f32, Option<f32>)> {
+    if normalize.is_none() {
+        // Nothing needs the whole piece at once, so stream it to disk
+        let mut writer = wav::WavWriter::create(path, sample_rate, 1, depth)?;
+        let stream = render::RenderStream::new(DEFAULT_RENDER_RATE, sequence, render::STREAM_BLOCK);
+        let mut samples = resample::resample_iter(stream.flatten(), DEFAULT_RENDER_RATE as u32, sample_rate);
+        let mut block = Vec::with_capacity(render::STREAM_BLOCK);
+        loop {
+            block.clear();
+            block.extend(samples.by_ref().take(render::STREAM_BLOCK));
+            if block.is_empty() {
+                break;
+            }
+            writer.write(&block)?;
+        }
+        let frames = writer.finish()?;
+        return Ok((frames as f32 / sample_rate as f32, None));
+    }
+    let rendered = render::render_sequence(DEFAULT_RENDER_RATE, sequence);
+    let mut buffer = resample::resample(&rendered, DEFAULT_RENDER_RATE as u32, sample_rate);
+    let gain = normalize.map(|mode| render::normalize(&mut buffer, mode));
+    let path = path.to_str().ok_or_else(|| anyhow::anyhow!("Invalid path {}", path.display()))?;
+    wav::write_wav(path, sample_rate, &buffer, depth)?;
+    Ok((buffer.len() as f32 / sample_rate as f32, gain))
+}
+
+/// Redraw a one-line progress bar in place
+fn print_progress(done: usize, total: usize) {
+    const WIDTH: usize = 30;
+    let filled = WIDTH * done / total.max(1);
+    print!("\r  [{}{}] {}/{}", "#".repeat(filled), " ".repeat(WIDTH - filled), done, total);
+    let _ = std::io::stdout().flush();
+}
+
+/// Set by the Ctrl-C handler while something is playing; playback polls it
+/// and fades out. Cleared before each command.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether a command is currently playing audio that Ctrl-C should stop
+static PLAYING: AtomicBool = AtomicBool::new(false);
+
+/// Poll interval for interruptible waits
+const INTERRUPT_POLL_MS: u64 = 10;
+
+/// Marks playback as interruptible for as long as it lives
+struct Playing;
+
+impl Playing {
+    fn start() -> Self {
+        PLAYING.store(true, Ordering::SeqCst);
+        Playing
+    }
+}
+
+impl Drop for Playing {
+    fn drop(&mut self) {
+        PLAYING.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Sleep for `ms`, returning `false` early if Ctrl-C was pressed
+fn wait_ms(ms: u64) -> bool {
+    let mut left = ms;
+    while left > 0 {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return false;
+        }
+        let step = left.min(INTERRUPT_POLL_MS);
+        std::thread::sleep(Duration::from_millis(step));
+        left -= step;
+    }
+    !INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Block until `player` finishes, stopping it on Ctrl-C
+fn wait_for(player: &mut Player) {
+    let _playing = Playing::start();
+    while !player.is_finished() {
+        if !wait_ms(INTERRUPT_POLL_MS) {
+            player.stop_now();
+            break;
+        }
+    }
+}
+
+/// Ctrl-C stops playback with a short fade and returns to the prompt. With
+/// nothing playing (or on a second press) it restores the terminal and exits.
+fn install_interrupt_handler() -> anyhow::Result<()> {
+    ctrlc::set_handler(|| {
+        if PLAYING.load(Ordering::SeqCst) && !INTERRUPTED.swap(true, Ordering::SeqCst) {
+            println!();
+            return;
+        }
+        // Show the cursor again in case a progress display hid it
+        print!("\x1b[?25h");
+        println!();
+        let _ = std::io::stdout().flush();
+        // Exiting drops nothing, so fade whatever is still sounding first
+        audio::silence_all();
+        std::process::exit(130);
+    })?;
     Ok(())
 }
 
+/// The nearest note to `freq`, with its offset in cents if it's off pitch
+/// (`C5+3`), as `play note` takes it
+fn note_with_cents(freq: f32) -> String {
+    let midi = 69.0 + 12.0 * (freq / synth_data::a4()).log2();
+    let number = midi.round();
+    match ((midi - number) * 100.0).round() as i32 {
+        0 => synth_data::note_name(number as i32),
+        cents => format!("{}{:+}", synth_data::note_name(number as i32), cents),
+    }
+}
+
+/// A seed that differs from run to run, for commands where it's optional
+fn clock_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |time| time.as_nanos() as u64)
+}
+
+/// Print the sound-shaping parameters that differ between two patches
+fn print_changes(from: &FMParams, to: &FMParams) {
+    let ratio = |p: &FMParams| p.modulator_freq / p.carrier_freq.max(f32::EPSILON);
+    let mut changes = vec![
+        ("ratio", ratio(from), ratio(to)),
+        ("index", from.modulation_index, to.modulation_index),
+        ("attack", from.attack, to.attack),
+        ("decay", from.decay, to.decay),
+        ("sustain", from.sustain, to.sustain),
+        ("release", from.release, to.release),
+    ];
+    if from.index_env || to.index_env {
+        changes.extend([
+            ("index-attack", from.index_attack, to.index_attack),
+            ("index-decay", from.index_decay, to.index_decay),
+            ("index-sustain", from.index_sustain, to.index_sustain),
+            ("index-release", from.index_release, to.index_release),
+        ]);
+    }
+    for (name, before, after) in changes.into_iter().filter(|(_, before, after)| before != after) {
+        println!("  {:<16} {:.3} -> {:.3}", name, before, after);
+    }
+}
+
+/// Standard audition phrase: a single note, an octave leap, then a major chord
+fn audition_phrase(preset: &FMParams) -> Sequence {
+    let ms = Duration::from_millis;
+    let (c4, c5) = (note_freq("C4"), note_freq("C5"));
+    let mut chord_voice = preset.clone();
+    chord_voice.scale_gain(3f32.sqrt().recip());
+
+    let mut sequence = Sequence::new();
+    sequence.note(ms(0), ms(600), c4, preset);
+    sequence.note(ms(900), ms(300), c4, preset);
+    sequence.note(ms(1250), ms(300), c5, preset);
+    for note in ["C4", "E4", "G4"] {
+        sequence.note(ms(1850), ms(1000), note_freq(note), &chord_voice);
+    }
+    sequence
+}
+
+/// Split the global options (`--name value`) that precede the command off
+/// the command line
+fn parse_global_options(args: &mut Vec<String>, config: &mut Config) -> anyhow::Result<OutputConfig> {
+    let mut output = config.output();
+    while args.first().is_some_and(|a| a.starts_with("--")) {
+        let name = args.remove(0);
+        if args.is_empty() {
+            anyhow::bail!("{} needs a value", name);
+        }
+        let value = args.remove(0);
+        match name.as_str() {
+            "--host" => output.host = Some(value),
+            "--device" => output.device = Some(value),
+            "--sample-rate" => {
+                output.sample_rate = Some(value.parse().map_err(|_| anyhow::anyhow!("Invalid sample rate '{}'", value))?)
+            }
+            "--engine-rate" => {
+                output.engine_rate = Some(value.parse().map_err(|_| anyhow::anyhow!("Invalid engine rate '{}'", value))?)
+            }
+            "--buffer-size" => {
+                output.buffer_size = Some(value.parse().map_err(|_| anyhow::anyhow!("Invalid buffer size '{}'", value))?)
+            }
+            "--channel-map" => {
+                output.channel_map = Some(ChannelMap::parse(&value).ok_or_else(|| anyhow::anyhow!("Invalid channel map '{}'", value))?)
+            }
+            "--realtime" => {
+                output.realtime = match value.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => anyhow::bail!("Invalid realtime setting '{}' (on or off)", value),
+                }
+            }
+            "--volume" => output.volume = Some(value.parse().map_err(|_| anyhow::anyhow!("Invalid volume '{}'", value))?),
+            "--voices" => {
+                config.voices = Some(
+                    value.parse().ok().filter(|v| (1..=MAX_VOICES).contains(v))
+                        .ok_or_else(|| anyhow::anyhow!("Invalid voice count '{}' (1 - {})", value, MAX_VOICES))?,
+                )
+            }
+            "--sine-table" => {
+                config.sine_table = Some(match value.as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => anyhow::bail!("Invalid sine table setting '{}' (on or off)", value),
+                })
+            }
+            "--a4" => synth_data::set_a4(parse_a4(&value).ok_or_else(|| anyhow::anyhow!("Invalid A4 tuning '{}'", value))?),
+            _ => anyhow::bail!("Unknown option '{}'", name),
+        }
+    }
+    Ok(output)
+}
+
 #[cfg(not(target_arch = "wasm32"))]
 fn main() -> anyhow::Result<()> {
-    // Initialize audio
-    let host = cpal::default_host();
-    let device = host.default_output_device()
-        .expect("No output device available");
-    
-    let config = device.default_output_config()?;
-    let sample_rate = config.sample_rate().0 as f32;
-    
+    // Config file defaults, overridden by global options, apply to the
+    // command and the interactive session
+    let mut config = Config::load().unwrap_or_else(|err| {
+        println!("Warning: ignoring config file: {}", err);
+        Config::default()
+    });
+    if let Some(a4) = config.a4 {
+        synth_data::set_a4(a4);
+    }
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let output = parse_global_options(&mut args, &mut config)?;
+    install_interrupt_handler()?;
+
+    // Run a single command given on the command line, e.g. `fm_synth render bell twinkle`
+    if !args.is_empty() {
+        let parts: Vec<&str> = args.iter().map(String::as_str).collect();
+        let mut cli = Cli::new(&config, output);
+        cli.execute(&parts)?;
+        if let Some(job) = &mut cli.job {
+            // Nothing else can control a one-shot job, so wait for it to end
+            wait_for(&mut job.player);
+        }
+        if let Some(tone) = &cli.test_tone {
+            // Signals without an end play until Ctrl-C
+            let _playing = Playing::start();
+            while !tone.signal.lock().unwrap().is_finished() && wait_ms(INTERRUPT_POLL_MS) {}
+            cli.stop();
+        }
+        if cli.drone.is_some() {
+            // Nothing else can edit a one-shot drone, so hold it until Enter
+            println!("Press Enter to stop.");
+            std::io::stdin().read_line(&mut String::new())?;
+            cli.stop();
+            cli.close_engine();
+        }
+        return Ok(());
+    }
+
     // Create synth with default parameters
     let params = FMParams::default();
-    let synth = Arc::new(Mutex::new(FMSynth::new(sample_rate, params)));
-    
-    // Clone for audio callback
-    let synth_clone = Arc::clone(&synth);
+    let synth = Arc::new(Mutex::new(FMSynth::new(DEFAULT_RENDER_RATE, params)));
     
-    // Build output stream
-    let stream = match config.sample_format() {
-        cpal::SampleFormat::F32 => device.build_output_stream(
-            &config.into(),
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                let mut synth = synth_clone.lock().unwrap();
-                for sample in data.iter_mut() {
-                    *sample = synth.next_sample();
-                }
-            },
-            |err| eprintln!("Error in audio stream: {}", err),
-            None,
-        )?,
-        _ => panic!("Unsupported sample format"),
-    };
-    
-    stream.play()?;
+    // Start audio; the output keeps the stream alive across device changes
+    let _output = audio::AudioOutput::start(output.clone(), Arc::clone(&synth))?;
     
     println!("FM Synthesizer Demo");
     println!("==================");
@@ -239,7 +3508,8 @@ fn main() -> anyhow::Result<()> {
                         carrier_freq: carrier,
                         modulator_freq: modulator,
                         modulation_index: mod_index,
-                        amplitude: 0.3,
+                        level_db: -10.5,
+                        ..FMParams::default()
                     });
                     synth.note_on();
                 }
@@ -260,72 +3530,42 @@ fn main() -> anyhow::Result<()> {
     }
     
     println!("\nDone!");
-    let cli = CLI::new();
+    let mut cli = Cli::new(&config, output);
     cli.print_menu();
     
+    let mut editor: Editor<CliHelper, DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(CliHelper::new(&cli)));
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file just means a first run
+        let _ = editor.load_history(path);
+    }
+
     loop {
-        print!("> ");
-        io::stdout().flush()?;
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        let input = input.trim();
-        
+        let input = match editor.readline("> ") {
+            Ok(line) => line,
+            // Ctrl-C clears the line, Ctrl-D quits
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+
         let parts: Vec<&str> = input.split_whitespace().collect();
         if parts.is_empty() {
             continue;
         }
+        editor.add_history_entry(input.trim())?;
         
-        match parts[0] {
-            "list" => {
-                if parts.len() > 1 {
-                    match parts[1] {
-                        "presets" => cli.list_presets(),
-                        "melodies" => cli.list_melodies(),
-                        _ => println!("Unknown list command. Use 'list presets' or 'list melodies'"),
-                    }
-                } else {
-                    println!("Usage: list <presets|melodies>");
-                }
-            }
-            "play" => {
-                if parts.len() >= 3 {
-                    let preset_name = parts[1];
-                    let melody_name = parts[2..].join(" ");
-                    
-                    match (cli.find_preset(preset_name), cli.find_melody(&melody_name)) {
-                        (Some(preset), Some(melody)) => {
-                            println!("Playing '{}' melody with '{}' preset...", melody_name, preset_name);
-                            play_melody(preset, melody)?;
-                            println!("Done!");
-                        }
-                        (None, _) => println!("Preset '{}' not found. Use 'list presets' to see available options.", preset_name),
-                        (_, None) => println!("Melody '{}' not found. Use 'list melodies' to see available options.", melody_name),
-                    }
-                } else {
-                    println!("Usage: play <preset> <melody>");
-                    println!("Example: play bell twinkle");
-                    println!("Example: play 1 3");
-                }
-            }
-            "demo" => {
-                println!("Playing demo with all presets...");
-                let scale = vec![
-                    ("C4", 300), ("D4", 300), ("E4", 300), ("F4", 300),
-                    ("G4", 300), ("A4", 300), ("B4", 300), ("C5", 600),
-                ];
-                for (name, preset) in &cli.presets {
-                    println!("  Playing: {}", name);
-                    play_melody(preset.clone(), scale.clone())?;
-                }
-                println!("Demo complete!");
-            }
-            "help" => cli.print_menu(),
-            "quit" | "exit" => {
-                println!("Goodbye!");
-                break;
-            }
-            _ => println!("Unknown command. Type 'help' for available commands."),
+        match cli.execute(&parts) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => println!("Error: {}", err),
+        }
+    }
+
+    if let Some(path) = &history {
+        if let Err(err) = editor.save_history(path) {
+            println!("Warning: couldn't save history to {}: {}", path.display(), err);
         }
     }
     
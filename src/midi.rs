@@ -0,0 +1,101 @@
+// src/midi.rs - MIDI message parsing
+
+/// Controller numbers the synth responds to
+pub const CC_BANK_SELECT: u8 = 0;
+pub const CC_VOLUME: u8 = 7;
+pub const CC_SUSTAIN: u8 = 64;
+/// Macro knobs 1-4 (general purpose controllers 20-23)
+pub const CC_MACRO_FIRST: u8 = 20;
+/// Low 7 bits of the bank, after `CC_BANK_SELECT`'s high 7
+pub const CC_BANK_SELECT_LSB: u8 = 32;
+pub const CC_ALL_SOUND_OFF: u8 = 120;
+pub const CC_ALL_NOTES_OFF: u8 = 123;
+
+/// A channel voice message; anything else (system messages, running
+/// status) is ignored by `parse`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiMessage {
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    NoteOff { channel: u8, note: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    /// Aftertouch for one held note
+    PolyPressure { channel: u8, note: u8, pressure: u8 },
+    /// Aftertouch for the whole channel
+    ChannelPressure { channel: u8, pressure: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    /// Bend from -8192 to 8191, centred on 0
+    PitchBend { channel: u8, value: i16 },
+}
+
+impl MidiMessage {
+    /// Parse one complete message, such as the `data` of a WebMIDI event.
+    /// A note-on with velocity 0 is a note-off.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let (&status, data) = bytes.split_first()?;
+        let channel = status & 0x0F;
+        let byte = |i: usize| data.get(i).copied().filter(|b| *b < 0x80);
+        match status & 0xF0 {
+            0x80 => Some(MidiMessage::NoteOff { channel, note: byte(0)? }),
+            0x90 => {
+                let (note, velocity) = (byte(0)?, byte(1)?);
+                if velocity == 0 {
+                    Some(MidiMessage::NoteOff { channel, note })
+                } else {
+                    Some(MidiMessage::NoteOn { channel, note, velocity })
+                }
+            }
+            0xA0 => Some(MidiMessage::PolyPressure { channel, note: byte(0)?, pressure: byte(1)? }),
+            0xB0 => Some(MidiMessage::ControlChange { channel, controller: byte(0)?, value: byte(1)? }),
+            0xC0 => Some(MidiMessage::ProgramChange { channel, program: byte(0)? }),
+            0xD0 => Some(MidiMessage::ChannelPressure { channel, pressure: byte(0)? }),
+            0xE0 => {
+                let value = (byte(0)? as i16 | (byte(1)? as i16) << 7) - 8192;
+                Some(MidiMessage::PitchBend { channel, value })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Programs in a bank of presets
+pub const PROGRAMS_PER_BANK: usize = 128;
+
+/// The bank chosen on each channel by bank select, so a program change can
+/// pick a preset slot: program `p` of bank `b` is slot `b * 128 + p` in the
+/// loaded presets, making the first 128 presets bank 0. The bank is the
+/// 14-bit MSB/LSB pair, as the MIDI spec has it.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramSelect {
+    banks: [u16; 16],
+}
+
+impl ProgramSelect {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follow the bank select controllers; others are ignored
+    pub fn control_change(&mut self, channel: u8, controller: u8, value: u8) {
+        let bank = &mut self.banks[(channel & 0x0F) as usize];
+        let value = (value & 0x7F) as u16;
+        match controller {
+            CC_BANK_SELECT => *bank = value << 7 | (*bank & 0x7F),
+            CC_BANK_SELECT_LSB => *bank = (*bank & !0x7F) | value,
+            _ => {}
+        }
+    }
+
+    pub fn bank(&self, channel: u8) -> u16 {
+        self.banks[(channel & 0x0F) as usize]
+    }
+
+    /// Preset slot a program change on `channel` selects
+    pub fn slot(&self, channel: u8, program: u8) -> usize {
+        self.bank(channel) as usize * PROGRAMS_PER_BANK + (program & 0x7F) as usize
+    }
+}
+
+/// Scale a 7-bit MIDI value (velocity, controller) to 0.0 - 1.0
+pub fn unit(value: u8) -> f32 {
+    value.min(127) as f32 / 127.0
+}
@@ -0,0 +1,246 @@
+// src/mobile.rs - Realtime playback for Android (AAudio) and iOS (CoreAudio) apps
+//
+// Where the `ffi` engine leaves the audio callback to the host, a player
+// opens the device itself through cpal, so an app only forwards touches and
+// lifecycle events. The app has to tell the player about audio session
+// interruptions (an incoming call, Siri, another app taking audio focus,
+// going to the background) with `fm_player_suspend` and `fm_player_resume`:
+// neither system reliably reports them to the stream, which just stops
+// getting callbacks.
+
+use std::ffi::{c_char, CString};
+use std::sync::{Arc, Mutex};
+
+use crate::audio::{AudioOutput, OutputConfig};
+use crate::ffi::{c_presets, note_params, voice_count};
+use crate::synth_core::{FMParams, PolySynth};
+use crate::synth_data::midi_to_freq;
+
+/// A polyphonic synth playing the built-in presets through the device's
+/// default output. Opaque to C.
+pub struct FmPlayer {
+    synth: Arc<Mutex<PolySynth>>,
+    output: AudioOutput,
+    presets: Vec<(CString, FMParams)>,
+    /// Preset played by `fm_player_note_on`
+    preset: usize,
+}
+
+/// Open the default output and start a player with up to `max_voices`
+/// notes at once (0 for the default of 8). `buffer_frames` requests a
+/// callback size, which trades latency for robustness on slower phones; 0
+/// leaves it to the system (AAudio's burst size, or the iOS session's IO
+/// buffer duration), as does a size the device rejects. Returns null if no
+/// output could be opened. Free it with `fm_player_free`.
+///
+/// On Android, call `fm_player_android_init` first.
+#[no_mangle]
+pub extern "C" fn fm_player_new(max_voices: u32, buffer_frames: u32) -> *mut FmPlayer {
+    let mut output = OutputConfig::default();
+    if buffer_frames > 0 {
+        output = output.with_buffer_size(buffer_frames);
+    }
+    // The output switches the synth to the stream's rate once it's open
+    let synth = Arc::new(Mutex::new(PolySynth::new(48000.0, voice_count(max_voices))));
+    match AudioOutput::start(output, Arc::clone(&synth)) {
+        Ok(output) => Box::into_raw(Box::new(FmPlayer { synth, output, presets: c_presets(), preset: 0 })),
+        Err(err) => {
+            eprintln!("Couldn't open audio output: {}", err);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Hand cpal the JVM and the application `Context` (a global JNI
+/// reference) it needs to reach AAudio, e.g. from `JNI_OnLoad` and the
+/// app's `onCreate`. Call once, before `fm_player_new`.
+///
+/// # Safety
+///
+/// `vm` must be the process's `JavaVM*` and `context` a global reference
+/// to an `android.content.Context` that outlives every player.
+#[cfg(target_os = "android")]
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_android_init(vm: *mut std::ffi::c_void, context: *mut std::ffi::c_void) {
+    ndk_context::initialize_android_context(vm, context);
+}
+
+/// Fade out, close the output, and free a player. Null is ignored.
+///
+/// # Safety
+///
+/// `player` must be null or a pointer from `fm_player_new` that hasn't
+/// been freed yet; it can't be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_free(player: *mut FmPlayer) {
+    if !player.is_null() {
+        drop(Box::from_raw(player));
+    }
+}
+
+/// Number of built-in presets
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_preset_count(player: *const FmPlayer) -> u32 {
+    let player = &*player;
+    player.presets.len() as u32
+}
+
+/// Name of preset `index` (0-based) as a NUL-terminated string, or null if
+/// there is no such preset. The string lives as long as the player.
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_preset_name(player: *const FmPlayer, index: u32) -> *const c_char {
+    let player = &*player;
+    match player.presets.get(index as usize) {
+        Some((name, _)) => name.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
+
+/// Choose the preset (0-based) for notes started afterwards. Returns
+/// false, changing nothing, if there is no such preset.
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_set_preset(player: *mut FmPlayer, index: u32) -> bool {
+    let player = &mut *player;
+    if index as usize >= player.presets.len() {
+        return false;
+    }
+    player.preset = index as usize;
+    true
+}
+
+/// Start MIDI note `note` (60 = C4) at `velocity` (1 - 127; 0 releases the
+/// note). Notes played while suspended are ignored.
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_note_on(player: *mut FmPlayer, note: u8, velocity: u8) {
+    let player = &mut *player;
+    if velocity == 0 {
+        player.synth.lock().unwrap().note_off(midi_to_freq(note as i32));
+        return;
+    }
+    if player.output.is_suspended() {
+        return;
+    }
+    let (freq, params) = note_params(&player.presets[player.preset].1, note, velocity);
+    player.synth.lock().unwrap().note_on_with(freq, params);
+}
+
+/// Release MIDI note `note`
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_note_off(player: *mut FmPlayer, note: u8) {
+    let player = &*player;
+    player.synth.lock().unwrap().note_off(midi_to_freq(note as i32));
+}
+
+/// Release every held note
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_all_notes_off(player: *mut FmPlayer) {
+    let player = &*player;
+    player.synth.lock().unwrap().all_notes_off();
+}
+
+/// Number of voices still sounding, releases included
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_active_voices(player: *const FmPlayer) -> u32 {
+    let player = &*player;
+    player.synth.lock().unwrap().active_voices() as u32
+}
+
+/// Most notes that can sound at once
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_max_voices(player: *const FmPlayer) -> u32 {
+    let player = &*player;
+    player.synth.lock().unwrap().max_voices() as u32
+}
+
+/// Change the most notes that can sound at once, as for `fm_player_new`;
+/// lowering it cuts off the oldest notes beyond the new limit
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_set_max_voices(player: *mut FmPlayer, max_voices: u32) {
+    let player = &*player;
+    player.synth.lock().unwrap().set_max_voices(voice_count(max_voices));
+}
+
+/// Fade out, release held notes, and give up the audio device. Call it
+/// when an audio session interruption begins (iOS
+/// `AVAudioSessionInterruptionTypeBegan`, Android `AUDIOFOCUS_LOSS`) and
+/// when the app goes to the background without background audio. Blocks
+/// for the fade (about 100 ms).
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_suspend(player: *mut FmPlayer) {
+    let player = &*player;
+    if player.output.is_suspended() {
+        return;
+    }
+    // Held notes would otherwise hang: their note-offs may never come
+    player.synth.lock().unwrap().all_notes_off();
+    player.output.suspend();
+    // Whatever is left of the releases isn't heard, so resume starts silent
+    let mut synth = player.synth.lock().unwrap();
+    let sample_rate = synth.sample_rate();
+    *synth = PolySynth::new(sample_rate, synth.max_voices());
+}
+
+/// Reopen the audio device after `fm_player_suspend`, when the
+/// interruption ends or the app returns to the foreground. The device
+/// opens in the background within about half a second; on iOS, activate
+/// the audio session first.
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_resume(player: *mut FmPlayer) {
+    let player = &*player;
+    player.output.resume();
+}
+
+/// Whether the player is suspended
+///
+/// # Safety
+///
+/// `player` must be a live pointer from `fm_player_new`.
+#[no_mangle]
+pub unsafe extern "C" fn fm_player_is_suspended(player: *const FmPlayer) -> bool {
+    let player = &*player;
+    player.output.is_suspended()
+}
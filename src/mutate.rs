@@ -0,0 +1,135 @@
+// src/mutate.rs - Random variations and crossings of presets, for exploring sounds
+
+use crate::rng::Rng;
+use crate::synth_core::FMParams;
+
+/// Mutation amount when none is given: a variant still recognisably the
+/// original
+pub const DEFAULT_AMOUNT: f32 = 0.2;
+
+/// Mutation amount of `breed` offspring when none is given, lower as
+/// crossing already varies them
+pub const BREED_AMOUNT: f32 = 0.1;
+
+/// Largest mutation amount accepted by `mutate`
+pub const MAX_AMOUNT: f32 = 1.0;
+
+/// A randomized variant of `params`: the frequency ratio, modulation index,
+/// and both envelopes move by up to `amount` (0.0 - 1.0) of their values
+/// either way, sustain levels by up to `amount` of full scale. The same seed
+/// always gives the same variant, and the result stays in the supported
+/// ranges.
+pub fn mutate(params: &FMParams, amount: f32, seed: u64) -> FMParams {
+    let amount = amount.clamp(0.0, MAX_AMOUNT);
+    let mut rng = Rng::new(seed);
+    let mut vary = |value: f32| value * (1.0 + amount * rng.next_bipolar()).max(0.0);
+    let ratio = params.modulator_freq / params.carrier_freq.max(f32::EPSILON);
+    let mut variant = FMParams {
+        modulator_freq: params.carrier_freq * vary(ratio),
+        modulation_index: vary(params.modulation_index),
+        attack: vary(params.attack),
+        decay: vary(params.decay),
+        release: vary(params.release),
+        index_attack: vary(params.index_attack),
+        index_decay: vary(params.index_decay),
+        index_release: vary(params.index_release),
+        ..params.clone()
+    };
+    variant.sustain = (params.sustain + amount * rng.next_bipolar()).clamp(0.0, 1.0);
+    variant.index_sustain = (params.index_sustain + amount * rng.next_bipolar()).clamp(0.0, 1.0);
+    variant.clamped()
+}
+
+/// A gene: parameters that only make sense together, so an offspring takes
+/// all of them from one parent
+type Gene = (&'static str, fn(&mut FMParams, &FMParams));
+
+const GENES: [Gene; 9] = [
+    ("ratio", |child, parent| {
+        child.modulator_freq = child.carrier_freq * parent.modulator_freq / parent.carrier_freq.max(f32::EPSILON);
+    }),
+    ("index", |child, parent| {
+        child.modulation_index = parent.modulation_index;
+        child.key_scaling = parent.key_scaling;
+        child.key_scaling_low = parent.key_scaling_low;
+        child.velocity_index = parent.velocity_index;
+    }),
+    ("waveforms", |child, parent| {
+        child.carrier_wave = parent.carrier_wave;
+        child.modulator_wave = parent.modulator_wave;
+        child.sub_level = parent.sub_level;
+        child.sub_octave = parent.sub_octave;
+        child.sub_wave = parent.sub_wave;
+    }),
+    ("envelope", |child, parent| {
+        child.attack = parent.attack;
+        child.decay = parent.decay;
+        child.sustain = parent.sustain;
+        child.release = parent.release;
+        child.gate = parent.gate;
+    }),
+    ("index envelope", |child, parent| {
+        child.index_env = parent.index_env;
+        child.index_attack = parent.index_attack;
+        child.index_decay = parent.index_decay;
+        child.index_sustain = parent.index_sustain;
+        child.index_release = parent.index_release;
+    }),
+    ("LFO", |child, parent| {
+        child.lfo_shape = parent.lfo_shape;
+        child.lfo_rate = parent.lfo_rate;
+        child.lfo_pitch = parent.lfo_pitch;
+        child.lfo_index = parent.lfo_index;
+        child.lfo_seed = parent.lfo_seed;
+    }),
+    ("motion", |child, parent| {
+        child.vibrato_rate = parent.vibrato_rate;
+        child.vibrato_depth = parent.vibrato_depth;
+        child.vibrato_delay = parent.vibrato_delay;
+        child.vibrato_fade = parent.vibrato_fade;
+        child.tremolo_rate = parent.tremolo_rate;
+        child.tremolo_depth = parent.tremolo_depth;
+        child.tremolo_beats = parent.tremolo_beats;
+    }),
+    ("analog", |child, parent| {
+        child.drift = parent.drift;
+        child.spread = parent.spread;
+        child.spread_seed = parent.spread_seed;
+    }),
+    ("space", |child, parent| {
+        child.stereo_delay = parent.stereo_delay;
+        child.stereo_width = parent.stereo_width;
+        child.reverb_mix = parent.reverb_mix;
+        child.reverb_size = parent.reverb_size;
+    }),
+];
+
+/// A preset bred from two others, and where its genes came from
+#[derive(Clone, Debug)]
+pub struct Offspring {
+    pub params: FMParams,
+    /// Each gene by name, with `true` if it came from the second parent
+    pub genes: Vec<(&'static str, bool)>,
+}
+
+/// An offspring of `first` and `second`: each gene (the ratio, the index
+/// and its scaling, the envelope, ...) comes whole from one parent or the
+/// other, then the result is mutated by `amount` as by `mutate`. The rest,
+/// such as the level, comes from `first`. The same seed always gives the
+/// same offspring.
+pub fn breed(first: &FMParams, second: &FMParams, amount: f32, seed: u64) -> Offspring {
+    let mut rng = Rng::new(seed);
+    let mut params = first.clone();
+    let genes = GENES
+        .iter()
+        .map(|(name, inherit)| {
+            let from_second = rng.next_f32() < 0.5;
+            if from_second {
+                inherit(&mut params, second);
+            }
+            (*name, from_second)
+        })
+        .collect();
+    let params = mutate(&params, amount, rng.next_u64());
+    Offspring { params, genes }
+}
@@ -0,0 +1,235 @@
+// src/node.rs - Node.js native bindings (napi-rs), the native engine for Electron and Node audio tools
+//
+// Unlike `WebFMSynth`, which approximates the presets with WebAudio nodes,
+// this renders with the same DSP as the CLI and leaves the samples to the
+// caller (a Web Audio worklet, a WAV file, a native audio module, ...).
+
+use napi::bindgen_prelude::{Buffer, Float32Array};
+use napi_derive::napi;
+
+use crate::render;
+use crate::synth_core::{FMParams, PolySynth, MAX_VOICES};
+use crate::synth_data::{get_melodies, get_presets, midi_to_freq};
+use crate::wav;
+
+/// Voices used when the constructor isn't given a count
+const DEFAULT_VOICES: u32 = 8;
+
+/// A preset as `listPresets` returns it to JavaScript
+#[napi(object)]
+pub struct PresetInfo {
+    pub index: u32,
+    pub name: String,
+    /// Every preset field (`carrier_freq`, `modulation_index`, `attack`, ...)
+    pub params: serde_json::Value,
+}
+
+/// A melody as `listMelodies` returns it to JavaScript
+#[napi(object)]
+pub struct MelodyInfo {
+    pub index: u32,
+    pub name: String,
+    /// Note names (`"REST"` for rests), with `durations_ms` alongside
+    pub notes: Vec<String>,
+    pub durations_ms: Vec<u32>,
+}
+
+fn invalid(message: impl Into<String>) -> napi::Error {
+    napi::Error::new(napi::Status::InvalidArg, message.into())
+}
+
+/// The native engine: a polyphonic synth playing presets by MIDI note,
+/// rendered on demand into `Float32Array`s
+#[napi(js_name = "FMSynth")]
+pub struct NodeFMSynth {
+    synth: PolySynth,
+    presets: Vec<(String, FMParams)>,
+    melodies: Vec<(&'static str, Vec<(&'static str, u64)>)>,
+    /// Preset played by `noteOn`
+    preset: usize,
+}
+
+#[napi]
+impl NodeFMSynth {
+    /// An engine rendering at `sampleRate` with up to `maxVoices` notes at
+    /// once (8 by default, at most 256), playing the first built-in preset
+    #[napi(constructor)]
+    pub fn new(sample_rate: f64, max_voices: Option<u32>) -> napi::Result<Self> {
+        if !sample_rate.is_finite() || sample_rate <= 0.0 {
+            return Err(invalid(format!("Invalid sample rate {}", sample_rate)));
+        }
+        let voices = max_voices.filter(|&v| v > 0).unwrap_or(DEFAULT_VOICES).min(MAX_VOICES as u32);
+        let presets = get_presets()
+            .into_iter()
+            .map(|(name, params)| (name.to_string(), params.clamped()))
+            .collect();
+        Ok(Self {
+            synth: PolySynth::new(sample_rate as f32, voices as usize),
+            presets,
+            melodies: get_melodies(),
+            preset: 0,
+        })
+    }
+
+    #[napi(getter)]
+    pub fn sample_rate(&self) -> f64 {
+        self.synth.sample_rate() as f64
+    }
+
+    /// Follow a change of output rate; sounding notes keep their pitch
+    #[napi]
+    pub fn set_sample_rate(&mut self, sample_rate: f64) -> napi::Result<()> {
+        if !sample_rate.is_finite() || sample_rate <= 0.0 {
+            return Err(invalid(format!("Invalid sample rate {}", sample_rate)));
+        }
+        self.synth.set_sample_rate(sample_rate as f32);
+        Ok(())
+    }
+
+    /// Presets as an array of `{ index, name, params }`: the built-in ones
+    /// followed by any added with `addPreset`
+    #[napi]
+    pub fn list_presets(&self) -> napi::Result<Vec<PresetInfo>> {
+        self.presets.iter()
+            .enumerate()
+            .map(|(index, (name, params))| {
+                let params = serde_json::to_value(params).map_err(|e| napi::Error::from_reason(e.to_string()))?;
+                Ok(PresetInfo { index: index as u32, name: name.clone(), params })
+            })
+            .collect()
+    }
+
+    /// Add a preset from an object of preset fields (missing ones take
+    /// their defaults), returning its index. Out-of-range values are
+    /// rejected rather than clamped.
+    #[napi]
+    pub fn add_preset(&mut self, name: String, params: serde_json::Value) -> napi::Result<u32> {
+        let params: FMParams = serde_json::from_value(params).map_err(|e| invalid(format!("Invalid preset: {}", e)))?;
+        params.validate().map_err(|e| invalid(format!("Invalid preset: {}", e)))?;
+        self.presets.push((name, params));
+        Ok(self.presets.len() as u32 - 1)
+    }
+
+    /// Replace preset `index` with new fields, as for `addPreset`; notes
+    /// already sounding keep the old ones
+    #[napi]
+    pub fn update_preset(&mut self, index: u32, params: serde_json::Value) -> napi::Result<()> {
+        let index = self.preset_index(index)?;
+        let params: FMParams = serde_json::from_value(params).map_err(|e| invalid(format!("Invalid preset: {}", e)))?;
+        params.validate().map_err(|e| invalid(format!("Invalid preset: {}", e)))?;
+        self.presets[index].1 = params;
+        Ok(())
+    }
+
+    /// Preset for notes started by `noteOn` from now on
+    #[napi]
+    pub fn set_preset(&mut self, index: u32) -> napi::Result<()> {
+        self.preset = self.preset_index(index)?;
+        Ok(())
+    }
+
+    #[napi(getter)]
+    pub fn preset(&self) -> u32 {
+        self.preset as u32
+    }
+
+    /// Melodies as an array of `{ index, name, notes, durationsMs }`
+    #[napi]
+    pub fn list_melodies(&self) -> Vec<MelodyInfo> {
+        self.melodies.iter()
+            .enumerate()
+            .map(|(index, (name, notes))| MelodyInfo {
+                index: index as u32,
+                name: name.to_string(),
+                notes: notes.iter().map(|(note, _)| note.to_string()).collect(),
+                durations_ms: notes.iter().map(|&(_, ms)| ms as u32).collect(),
+            })
+            .collect()
+    }
+
+    /// Start MIDI note `note` (60 = C4); velocity 1 - 127 scales the
+    /// amplitude (and the modulation index, by the preset's
+    /// `velocity_index`), and 0 releases the note as in MIDI
+    #[napi]
+    pub fn note_on(&mut self, note: u8, velocity: u8) {
+        let freq = midi_to_freq(note as i32);
+        if velocity == 0 {
+            self.synth.note_off(freq);
+            return;
+        }
+        let params = self.presets[self.preset].1.for_note(freq).for_velocity(velocity.min(127) as f32 / 127.0);
+        self.synth.note_on_with(freq, params);
+    }
+
+    #[napi]
+    pub fn note_off(&mut self, note: u8) {
+        self.synth.note_off(midi_to_freq(note as i32));
+    }
+
+    #[napi]
+    pub fn all_notes_off(&mut self) {
+        self.synth.all_notes_off();
+    }
+
+    /// Number of voices still sounding, releases included
+    #[napi]
+    pub fn active_voices(&self) -> u32 {
+        self.synth.active_voices() as u32
+    }
+
+    /// Most notes that can sound at once
+    #[napi(getter)]
+    pub fn max_voices(&self) -> u32 {
+        self.synth.max_voices() as u32
+    }
+
+    /// Change the most notes that can sound at once (1 - 256); lowering it
+    /// cuts off the oldest notes beyond the new limit
+    #[napi]
+    pub fn set_max_voices(&mut self, max_voices: u32) -> napi::Result<()> {
+        if max_voices == 0 || max_voices as usize > MAX_VOICES {
+            return Err(invalid(format!("Invalid voice count {} (1 - {})", max_voices, MAX_VOICES)));
+        }
+        self.synth.set_max_voices(max_voices as usize);
+        Ok(())
+    }
+
+    /// Render the next `frames` mono samples of the live notes
+    #[napi]
+    pub fn render(&mut self, frames: u32) -> Float32Array {
+        let samples: Vec<f32> = (0..frames).map(|_| self.synth.next_sample()).collect();
+        Float32Array::new(samples)
+    }
+
+    /// Render a built-in melody offline, as the CLI's `render` command
+    /// does, and return the mono samples, release tail included
+    #[napi]
+    pub fn render_melody(&self, preset: u32, melody: u32) -> napi::Result<Float32Array> {
+        let (preset, melody) = (self.preset_index(preset)?, self.melody_index(melody)?);
+        let samples = render::render_melody(self.synth.sample_rate(), &self.presets[preset].1, &self.melodies[melody].1);
+        Ok(Float32Array::new(samples))
+    }
+
+    /// `renderMelody` encoded as a 16-bit WAV file
+    #[napi]
+    pub fn render_melody_to_wav(&self, preset: u32, melody: u32) -> napi::Result<Buffer> {
+        let (preset, melody) = (self.preset_index(preset)?, self.melody_index(melody)?);
+        let sample_rate = self.synth.sample_rate();
+        let samples = render::render_melody(sample_rate, &self.presets[preset].1, &self.melodies[melody].1);
+        Ok(wav::encode_wav(sample_rate as u32, &samples, wav::BitDepth::Int16).into())
+    }
+
+    fn preset_index(&self, index: u32) -> napi::Result<usize> {
+        match self.presets.get(index as usize) {
+            Some(_) => Ok(index as usize),
+            None => Err(invalid(format!("No preset {}", index))),
+        }
+    }
+
+    fn melody_index(&self, index: u32) -> napi::Result<usize> {
+        match self.melodies.get(index as usize) {
+            Some(_) => Ok(index as usize),
+            None => Err(invalid(format!("No melody {}", index))),
+        }
+    }
+}
@@ -0,0 +1,358 @@
+// src/operator.rs - Stacks of N sine operators with no per-voice allocation
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::synth_core::{Envelope, FMParams, SampleSource, SineTable};
+
+/// One sine operator of an `FMVoice`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Operator {
+    /// Frequency as a multiple of the note frequency
+    pub ratio: f32,
+    /// Modulation index applied to the operator below, or the output
+    /// amplitude for operator 0 (the carrier)
+    pub level: f32,
+    /// The operator's own rate/level envelope scaling `level`. Without one,
+    /// modulators hold their level and the carrier follows the voice's ADSR.
+    pub envelope: Option<RateLevel>,
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Self { ratio: 1.0, level: 0.0, envelope: None }
+    }
+}
+
+/// Highest DX7 rate or level
+pub const MAX_RATE_LEVEL: u8 = 99;
+
+/// Loudness of one level step, as on the DX7
+const DB_PER_LEVEL: f32 = 0.75;
+
+/// Time rate 0 takes to cover the whole level range; each `RATE_HALVING`
+/// rate steps halve it, so rate 99 takes about 2 ms
+const SLOWEST_SWEEP: f32 = 100.0;
+const RATE_HALVING: f32 = 6.3;
+
+/// Rising segments first jump to this level, as the DX7 skips the inaudible
+/// part of an attack
+const RISE_FLOOR: f32 = 26.0;
+
+/// A DX7-style four-stage envelope: on a key press the level moves to `L1`
+/// at rate `R1`, then to `L2` at `R2` and `L3` at `R3`, holding `L3` while
+/// the key is down; on release it moves to `L4` at `R4`. Rates and levels
+/// run 0–99, levels being 0.75 dB apart, and a level moves at a constant
+/// speed in dB set by the rate.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RateLevel {
+    pub rates: [u8; 4],
+    pub levels: [u8; 4],
+}
+
+impl Default for RateLevel {
+    /// The DX7's initial voice: instant attack to full level, held until
+    /// release
+    fn default() -> Self {
+        Self { rates: [99; 4], levels: [99, 99, 99, 0] }
+    }
+}
+
+/// Written as `R1,R2,R3,R4/L1,L2,L3,L4`, the form `parse` reads
+impl fmt::Display for RateLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r1, r2, r3, r4] = self.rates;
+        let [l1, l2, l3, l4] = self.levels;
+        write!(f, "{},{},{},{}/{},{},{},{}", r1, r2, r3, r4, l1, l2, l3, l4)
+    }
+}
+
+impl RateLevel {
+    /// Parse `R1,R2,R3,R4/L1,L2,L3,L4`, each 0 - 99
+    pub fn parse(s: &str) -> Option<Self> {
+        let (rates, levels) = s.split_once('/')?;
+        let stages = |text: &str| -> Option<[u8; 4]> {
+            let values: Vec<u8> = text.split(',').map(|v| v.trim().parse().ok()).collect::<Option<_>>()?;
+            let stages: [u8; 4] = values.try_into().ok()?;
+            stages.iter().all(|&v| v <= MAX_RATE_LEVEL).then_some(stages)
+        };
+        Some(Self { rates: stages(rates)?, levels: stages(levels)? })
+    }
+
+    /// Levels per second moved at `rate`
+    fn speed(rate: u8) -> f32 {
+        let rate = rate.min(MAX_RATE_LEVEL) as f32;
+        MAX_RATE_LEVEL as f32 / (SLOWEST_SWEEP * (-rate / RATE_HALVING).exp2())
+    }
+
+    /// Gain of a level, with level 0 silent
+    pub fn gain(level: f32) -> f32 {
+        if level <= 0.0 {
+            0.0
+        } else {
+            10f32.powf((level - MAX_RATE_LEVEL as f32) * DB_PER_LEVEL / 20.0)
+        }
+    }
+
+    /// Nearest level to a gain, the inverse of `gain`
+    pub fn level_for_gain(gain: f32) -> u8 {
+        if gain <= 0.0 {
+            return 0;
+        }
+        let level = MAX_RATE_LEVEL as f32 + 20.0 * gain.log10() / DB_PER_LEVEL;
+        level.round().clamp(0.0, MAX_RATE_LEVEL as f32) as u8
+    }
+
+    /// Rate that covers `levels` levels in about `seconds`
+    pub fn rate_for(levels: u8, seconds: f32) -> u8 {
+        if levels == 0 || seconds <= 0.0 {
+            return MAX_RATE_LEVEL;
+        }
+        let sweep = seconds * MAX_RATE_LEVEL as f32 / levels as f32;
+        let rate = -RATE_HALVING * (sweep / SLOWEST_SWEEP).log2();
+        rate.round().clamp(0.0, MAX_RATE_LEVEL as f32) as u8
+    }
+
+    /// Copy with every rate and level at most `MAX_RATE_LEVEL`
+    pub fn clamped(self) -> Self {
+        Self { rates: self.rates.map(|r| r.min(MAX_RATE_LEVEL)), levels: self.levels.map(|l| l.min(MAX_RATE_LEVEL)) }
+    }
+
+    /// Longest a release can take to reach `L4`: from the highest of `L1`
+    /// to `L3` at rate `R4` (seconds)
+    pub fn release_time(&self) -> f32 {
+        let from = self.levels[..3].iter().max().copied().unwrap_or(0).min(MAX_RATE_LEVEL);
+        from.abs_diff(self.levels[3].min(MAX_RATE_LEVEL)) as f32 / Self::speed(self.rates[3])
+    }
+
+    /// The closest rate/level shape to an ADSR envelope with linear
+    /// `sustain`
+    pub fn from_adsr(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
+        let max = MAX_RATE_LEVEL;
+        let sustain = Self::level_for_gain(sustain);
+        // Attacks start from RISE_FLOOR, not silence
+        let r1 = Self::rate_for(max - RISE_FLOOR as u8, attack);
+        let r2 = Self::rate_for(max - sustain, decay);
+        Self { rates: [r1, r2, r2, Self::rate_for(sustain, release)], levels: [max, sustain, sustain, 0] }
+    }
+}
+
+/// Where a `RateLevel` envelope has got to for one operator
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RateLevelState {
+    /// Stage heading for `levels[stage]`; 3 once released
+    stage: usize,
+    released: bool,
+    level: f32,
+}
+
+impl RateLevelState {
+    /// Idle at `L4`, where the DX7 starts a voice from
+    pub(crate) fn new(envelope: &RateLevel) -> Self {
+        Self { stage: 3, released: true, level: envelope.levels[3].min(MAX_RATE_LEVEL) as f32 }
+    }
+
+    pub(crate) fn trigger(&mut self) {
+        self.stage = 0;
+        self.released = false;
+    }
+
+    pub(crate) fn release(&mut self) {
+        self.stage = 3;
+        self.released = true;
+    }
+
+    /// True once released and settled on `L4` of 0
+    fn is_idle(&self, envelope: &RateLevel) -> bool {
+        self.released && self.level <= 0.0 && envelope.levels[3] == 0
+    }
+
+    /// True once released and settled on `L4`, whatever it is
+    pub(crate) fn is_settled(&self, envelope: &RateLevel) -> bool {
+        self.released && self.level == envelope.levels[3].min(MAX_RATE_LEVEL) as f32
+    }
+
+    /// True from the trigger until the release
+    pub(crate) fn is_held(&self) -> bool {
+        !self.released
+    }
+
+    /// Current gain
+    pub(crate) fn gain(&self) -> f32 {
+        RateLevel::gain(self.level)
+    }
+
+    pub(crate) fn process(&mut self, envelope: &RateLevel, sample_rate: f32) -> f32 {
+        // Hold L3 until the key is released
+        if self.stage == 3 && !self.released {
+            return RateLevel::gain(self.level);
+        }
+        let target = envelope.levels[self.stage].min(MAX_RATE_LEVEL) as f32;
+        let step = RateLevel::speed(envelope.rates[self.stage]) / sample_rate;
+        if target > self.level {
+            self.level = (self.level.max(RISE_FLOOR.min(target)) + step).min(target);
+        } else {
+            self.level = (self.level - step).max(target);
+        }
+        if self.level == target && self.stage < 3 && !self.released {
+            self.stage += 1;
+        }
+        RateLevel::gain(self.level)
+    }
+}
+
+/// A voice of `N` operators in a serial stack: operator `N - 1` modulates
+/// `N - 2`, and so on down to the carrier, operator 0. Operators and phases
+/// live in fixed arrays, so a 2-operator voice costs no more than `FMSynth`
+/// and bigger stacks need no heap allocation.
+pub struct FMVoice<const N: usize> {
+    sample_rate: f32,
+    freq: f32,
+    operators: [Operator; N],
+    phases: [f32; N],
+    envelope: Envelope,
+    /// Progress of each operator's `RateLevel` envelope, if it has one
+    rate_levels: [RateLevelState; N],
+    sine_table: Option<Arc<SineTable>>,
+}
+
+impl<const N: usize> FMVoice<N> {
+    pub fn new(sample_rate: f32, freq: f32, operators: [Operator; N]) -> Self {
+        Self {
+            sample_rate,
+            freq,
+            operators,
+            phases: [0.0; N],
+            envelope: Envelope::new(sample_rate),
+            rate_levels: operators.map(|op| RateLevelState::new(&op.envelope.unwrap_or_default())),
+            sine_table: None,
+        }
+    }
+
+    pub fn operators(&self) -> &[Operator; N] {
+        &self.operators
+    }
+
+    /// Replace the operators. Envelopes left as they were carry on from
+    /// their current level; a changed `RateLevel` envelope starts again
+    /// from its `L4`, attacking at once if the key is down.
+    pub fn set_operators(&mut self, operators: [Operator; N]) {
+        for (i, op) in operators.iter().enumerate() {
+            if op.envelope != self.operators[i].envelope {
+                let mut state = RateLevelState::new(&op.envelope.unwrap_or_default());
+                if !self.rate_levels[i].released {
+                    state.trigger();
+                }
+                self.rate_levels[i] = state;
+            }
+        }
+        self.operators = operators;
+    }
+
+    pub fn set_freq(&mut self, freq: f32) {
+        self.freq = freq;
+    }
+
+    /// Switch between the exact sine and a (possibly shared) lookup table,
+    /// as `FMSynth::set_sine_table`
+    pub fn set_sine_table(&mut self, table: Option<Arc<SineTable>>) {
+        self.sine_table = table;
+    }
+
+    #[inline]
+    fn sine(&self, phase: f32) -> f32 {
+        match &self.sine_table {
+            Some(table) => table.lookup(phase),
+            None => (2.0 * PI * phase).sin(),
+        }
+    }
+
+    /// Take the attack, decay, sustain, and release settings of a patch
+    pub fn set_adsr(&mut self, params: &FMParams) {
+        self.envelope.set_adsr(params);
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        // Work up from the top of the stack, each operator modulating the
+        // frequency of the one below as in `FMOscillator`
+        let mut modulation = 0.0;
+        let mut out = 0.0;
+        let mut carrier_gain = 1.0;
+        for i in (0..N).rev() {
+            let op = self.operators[i];
+            let gain = match &op.envelope {
+                Some(envelope) => self.rate_levels[i].process(envelope, self.sample_rate),
+                None => 1.0,
+            };
+            let freq = self.freq * op.ratio * (1.0 + modulation);
+            out = self.sine(self.phases[i]);
+            modulation = op.level * gain * out;
+            self.phases[i] += freq / self.sample_rate;
+            self.phases[i] -= self.phases[i].floor();
+            carrier_gain = gain;
+        }
+        let amplitude = if N > 0 { self.operators[0].level } else { 0.0 };
+        // The ADSR still runs so switching envelopes mid-note is seamless
+        let adsr = self.envelope.process();
+        match self.operators.first().and_then(|op| op.envelope.as_ref()) {
+            Some(_) => out * amplitude * carrier_gain,
+            None => out * amplitude * adsr,
+        }
+    }
+
+    pub fn note_on(&mut self) {
+        self.envelope.trigger();
+        self.rate_levels.iter_mut().for_each(RateLevelState::trigger);
+    }
+
+    pub fn note_off(&mut self) {
+        self.envelope.release();
+        self.rate_levels.iter_mut().for_each(RateLevelState::release);
+    }
+
+    /// Follow a change of output rate (e.g. after switching devices)
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.envelope.set_sample_rate(sample_rate);
+    }
+
+    /// True while the note is sounding, including its release. A carrier
+    /// with a `RateLevel` envelope whose `L4` isn't 0 sounds until the next
+    /// note, as on the DX7.
+    pub fn is_active(&self) -> bool {
+        match self.operators.first().and_then(|op| op.envelope.as_ref()) {
+            Some(envelope) => !self.rate_levels[0].is_idle(envelope),
+            None => !self.envelope.is_idle(),
+        }
+    }
+}
+
+impl<const N: usize> SampleSource for FMVoice<N> {
+    fn next_sample(&mut self) -> f32 {
+        FMVoice::next_sample(self)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        FMVoice::set_sample_rate(self, sample_rate)
+    }
+}
+
+impl FMVoice<2> {
+    /// The classic carrier and modulator pair of a (sine) preset, already
+    /// resolved for its note with `FMParams::for_note`. A carrier at 0 Hz
+    /// leaves no ratio to keep, so the modulator gets ratio 1.
+    pub fn from_params(sample_rate: f32, params: &FMParams) -> Self {
+        let freq = params.carrier_freq;
+        let ratio = if freq > 0.0 { params.modulator_freq / freq } else { 1.0 };
+        let operators = [
+            Operator { ratio: 1.0, level: params.gain(), envelope: None },
+            Operator { ratio, level: params.modulation_index, envelope: None },
+        ];
+        let mut voice = Self::new(sample_rate, freq, operators);
+        voice.set_adsr(params);
+        voice
+    }
+}
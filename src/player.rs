@@ -0,0 +1,149 @@
+// src/player.rs - Background playback with transport control
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::audio::{AudioOutput, OutputConfig, OutputStats};
+use crate::render::DEFAULT_RENDER_RATE;
+use crate::sequencer::{Sequence, Sequencer};
+
+/// Plays a sequence on the audio output, where the stream's own clock drives
+/// it, so the caller stays free to pause, resume, seek, or stop it
+pub struct Player {
+    sequencer: Arc<Mutex<Sequencer>>,
+    output: Option<AudioOutput>,
+    bar: Duration,
+}
+
+impl Player {
+    /// Open the output and start playing `sequence`, repeating it when
+    /// `looping`. `bar` is the length of one bar, used by `seek_bar`.
+    pub fn start(output: &OutputConfig, sequence: Sequence, looping: bool, bar: Duration) -> anyhow::Result<Self> {
+        Self::start_at(output, sequence, looping, bar, Duration::ZERO)
+    }
+
+    /// `start`, but from `position` within the sequence, so nothing before
+    /// it sounds even briefly
+    pub fn start_at(output: &OutputConfig, sequence: Sequence, looping: bool, bar: Duration, position: Duration) -> anyhow::Result<Self> {
+        // The output sets the real sample rate once the stream is open
+        let mut sequencer = Sequencer::new(DEFAULT_RENDER_RATE, sequence, looping);
+        sequencer.seek(position);
+        let sequencer = Arc::new(Mutex::new(sequencer));
+        let output = AudioOutput::start(output.clone(), Arc::clone(&sequencer))?;
+        Ok(Self { sequencer, output: Some(output), bar })
+    }
+
+    /// Hold the position and release the sounding notes
+    pub fn pause(&self) {
+        self.sequencer.lock().unwrap().pause();
+    }
+
+    pub fn resume(&self) {
+        self.sequencer.lock().unwrap().resume();
+    }
+
+    /// Jump to `position` within the sequence
+    pub fn seek(&self, position: Duration) {
+        self.sequencer.lock().unwrap().seek(position);
+    }
+
+    /// Jump to the start of a bar, counting from 1
+    pub fn seek_bar(&self, bar: u32) {
+        self.seek(self.bar * bar.saturating_sub(1));
+    }
+
+    /// Change the tempo from here on; 2.0 is twice as fast as written
+    pub fn set_speed(&self, speed: f32) {
+        self.sequencer.lock().unwrap().set_speed(speed);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.sequencer.lock().unwrap().speed()
+    }
+
+    /// Speed the sequence's tempo map sets right now, on top of `speed`
+    pub fn tempo(&self) -> f32 {
+        self.sequencer.lock().unwrap().tempo()
+    }
+
+    /// Play `take` over the sequence from the current position, keeping
+    /// it in the sequence if `keep` (see `Sequencer::overdub`)
+    pub fn overdub(&self, take: Sequence, keep: bool) {
+        self.sequencer.lock().unwrap().overdub(take, keep);
+    }
+
+    /// Add `take` at the end of the sequence (see `Sequencer::extend`)
+    pub fn extend(&self, take: Sequence) {
+        self.sequencer.lock().unwrap().extend(take);
+    }
+
+    /// Voices sounding and the most that can (see `Sequencer::voices`)
+    pub fn voices(&self) -> (usize, usize) {
+        self.sequencer.lock().unwrap().voices()
+    }
+
+    /// Resize the polyphonic engines while playing (see
+    /// `Sequencer::set_max_voices`)
+    pub fn set_max_voices(&self, voices: usize) {
+        self.sequencer.lock().unwrap().set_max_voices(voices);
+    }
+
+    /// Remove every note; the (now silent) sequence keeps playing
+    pub fn clear(&self) {
+        self.sequencer.lock().unwrap().clear();
+    }
+
+    /// Release the sounding notes and close the output once they have rung
+    /// out
+    pub fn stop(&mut self) {
+        if let Some(output) = self.output.take() {
+            self.sequencer.lock().unwrap().pause();
+            output.close_when_idle(|| self.sequencer.lock().unwrap().is_idle());
+        }
+    }
+
+    /// Fade out at once and close the output, cutting off any releases
+    pub fn stop_now(&mut self) {
+        if let Some(output) = self.output.take() {
+            output.fade_out();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.sequencer.lock().unwrap().is_paused()
+    }
+
+    /// Whether the sequence has finished, every voice having gone idle and
+    /// any reverb rung out, or was stopped
+    pub fn is_finished(&self) -> bool {
+        self.output.is_none() || self.sequencer.lock().unwrap().has_ended()
+    }
+
+    /// Current position within the sequence
+    pub fn position(&self) -> Duration {
+        self.sequencer.lock().unwrap().position()
+    }
+
+    pub fn length(&self) -> Duration {
+        self.sequencer.lock().unwrap().length()
+    }
+
+    pub fn bar_length(&self) -> Duration {
+        self.bar
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.sequencer.lock().unwrap().is_looping()
+    }
+
+    /// The output stream's configuration and latency, until stopped
+    pub fn output_stats(&self) -> Option<OutputStats> {
+        self.output.as_ref().map(AudioOutput::stats)
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
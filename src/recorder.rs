@@ -0,0 +1,149 @@
+// src/recorder.rs - Capture the master output to a WAV file while playing
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::audio::{self, TapBlock};
+use crate::render::DEFAULT_RENDER_RATE;
+use crate::wav::{BitDepth, WavWriter};
+
+/// A stream that has sent nothing for this long has been closed
+const STREAM_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// How often the WAV header is brought up to date while recording
+const HEADER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Records everything the output streams play (after volume) to a stereo
+/// WAV file until `stop` or drop. Several streams playing at once, such as
+/// a drone under a melody, are mixed together as they're heard.
+pub struct Recorder {
+    path: PathBuf,
+    started: Instant,
+    thread: Option<JoinHandle<anyhow::Result<Duration>>>,
+}
+
+impl Recorder {
+    pub fn start(path: &Path, depth: BitDepth) -> anyhow::Result<Self> {
+        // Opened here so a bad path fails the command, not the recording
+        let writer = WavWriter::create(path, DEFAULT_RENDER_RATE as u32, 2, depth)
+            .map_err(|err| anyhow::anyhow!("{}: {}", path.display(), err))?;
+        let receiver = audio::start_tap();
+        let thread = std::thread::spawn(move || record(receiver, writer));
+        Ok(Self { path: path.to_path_buf(), started: Instant::now(), thread: Some(thread) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Stop recording and finalize the file, returning its length
+    pub fn stop(mut self) -> anyhow::Result<Duration> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Duration> {
+        audio::stop_tap();
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => anyhow::bail!("Recording thread panicked"),
+            None => Ok(Duration::ZERO),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            eprintln!("Error finishing recording {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+/// Recording thread body: mix the tapped blocks and stream them to disk
+/// until the tap closes, returning the length recorded
+fn record(receiver: mpsc::Receiver<TapBlock>, mut writer: WavWriter) -> anyhow::Result<Duration> {
+    let mut mixer = Mixer::default();
+    let mut rate_known = false;
+    let mut header_due = Instant::now() + HEADER_INTERVAL;
+    loop {
+        match receiver.recv_timeout(STREAM_TIMEOUT / 2) {
+            Ok(block) => {
+                if !rate_known {
+                    writer.set_sample_rate(block.sample_rate);
+                    rate_known = true;
+                }
+                mixer.add(block.stream, &block.frames);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        mixer.expire(Instant::now());
+        writer.write(mixer.take_ready().as_flattened())?;
+        if Instant::now() >= header_due {
+            writer.update_header()?;
+            header_due += HEADER_INTERVAL;
+        }
+    }
+    writer.write(mixer.take_all().as_flattened())?;
+    let rate = writer.sample_rate();
+    let frames = writer.finish()?;
+    Ok(Duration::from_secs_f64(frames as f64 / rate.max(1) as f64))
+}
+
+/// Sums blocks from several streams into one timeline. Each stream writes
+/// at its own cursor, starting from where the busiest stream has got to
+/// when it first appears; frames behind every live stream's cursor are final.
+#[derive(Default)]
+struct Mixer {
+    frames: VecDeque<[f32; 2]>,
+    /// Timeline position of `frames[0]`
+    start: u64,
+    /// Each live stream's next position, and when it last sent a block
+    streams: HashMap<u64, (u64, Instant)>,
+}
+
+impl Mixer {
+    fn add(&mut self, stream: u64, block: &[[f32; 2]]) {
+        let now = self.streams.values().map(|&(cursor, _)| cursor).max()
+            .unwrap_or(self.start + self.frames.len() as u64);
+        let (cursor, seen) = self.streams.entry(stream).or_insert((now, Instant::now()));
+        *seen = Instant::now();
+
+        let offset = (*cursor - self.start) as usize;
+        if self.frames.len() < offset + block.len() {
+            self.frames.resize(offset + block.len(), [0.0; 2]);
+        }
+        for (i, [left, right]) in block.iter().enumerate() {
+            let frame = &mut self.frames[offset + i];
+            frame[0] += left;
+            frame[1] += right;
+        }
+        *cursor += block.len() as u64;
+    }
+
+    /// Forget streams that have stopped sending
+    fn expire(&mut self, now: Instant) {
+        self.streams.retain(|_, (_, seen)| now.duration_since(*seen) < STREAM_TIMEOUT);
+    }
+
+    /// Frames no live stream can still add to
+    fn take_ready(&mut self) -> Vec<[f32; 2]> {
+        let end = self.streams.values().map(|&(cursor, _)| cursor).min()
+            .unwrap_or(self.start + self.frames.len() as u64);
+        let count = (end - self.start) as usize;
+        self.start = end;
+        self.frames.drain(..count).collect()
+    }
+
+    fn take_all(&mut self) -> Vec<[f32; 2]> {
+        self.start += self.frames.len() as u64;
+        self.frames.drain(..).collect()
+    }
+}
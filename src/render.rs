@@ -0,0 +1,269 @@
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use crate::sequencer::{Sequence, Sequencer};
+use crate::synth_core::{db_to_gain, gain_to_db, FMParams, FMSynth, MIN_LEVEL_DB, SampleSource, STEAL_FADE};
+use crate::wav::{self, BitDepth};
+
+/// Default sample rate for offline renders
+pub const DEFAULT_RENDER_RATE: f32 = 44100.0;
+
+/// Silence appended after the last note so the release can ring out
+const TAIL_MS: u64 = 500;
+
+/// Voices used for melodies, so each release rings under the following notes
+pub const MELODY_VOICES: usize = 8;
+
+/// Level (dBFS RMS) `loudness_matched` brings every preset to
+pub const LOUDNESS_TARGET_DB: f32 = -16.0;
+
+/// A preset's loudness is measured on a C4 held this long...
+const LOUDNESS_FREQ: f32 = 261.63;
+const LOUDNESS_GATE_MS: u64 = 1000;
+
+/// ...as the RMS level of its loudest stretch this long (a momentary
+/// loudness window), so a percussive hit and a held pad compare fairly
+const LOUDNESS_WINDOW_MS: u64 = 400;
+
+/// Time to keep rendering after the last note: the preset's release (or its
+/// `carrier_env`'s, and the gate closing after it), but never less than
+/// `TAIL_MS`
+pub fn tail_ms(preset: &FMParams) -> u64 {
+    let release = match preset.carrier_env {
+        Some(envelope) => envelope.release_time() + STEAL_FADE,
+        None => preset.release,
+    };
+    ((release * 1000.0) as u64).max(TAIL_MS)
+}
+
+/// Render a melody with a preset into a mono sample buffer
+pub fn render_melody(sample_rate: f32, preset: &FMParams, melody: &[(&str, u64)]) -> Vec<f32> {
+    render_sequence(sample_rate, Sequence::melody(preset, melody))
+}
+
+/// Render a sequence, including its release tail, into a mono sample buffer.
+/// Renders are dry: master bus effects only apply to live playback.
+pub fn render_sequence(sample_rate: f32, sequence: Sequence) -> Vec<f32> {
+    let mut buffer = Vec::new();
+    for block in RenderStream::new(sample_rate, sequence, STREAM_BLOCK) {
+        buffer.extend_from_slice(&block);
+    }
+    buffer
+}
+
+/// Samples per block a `RenderStream` yields when there's no reason to
+/// pick another (about 0.1 s at 44.1 kHz)
+pub const STREAM_BLOCK: usize = 4096;
+
+/// A render of a sequence yielded a block at a time, so a long piece can
+/// go to disk or over the network without ever being held in memory. The
+/// blocks join up to exactly what `render_sequence` returns; the last one
+/// may be short. Stop iterating (or drop it) to cancel.
+pub struct RenderStream {
+    sequencer: Sequencer,
+    block: usize,
+}
+
+impl RenderStream {
+    /// Render `sequence` dry, in blocks of `block` samples
+    pub fn new(sample_rate: f32, mut sequence: Sequence, block: usize) -> Self {
+        sequence.set_dry(true);
+        Self { sequencer: Sequencer::new(sample_rate, sequence, false), block: block.max(1) }
+    }
+
+    /// How much of the render has been yielded (0.0 - 1.0)
+    pub fn progress(&self) -> f32 {
+        self.sequencer.progress()
+    }
+
+    /// Length of the whole render, release tail included, in score time
+    /// (a tempo map changes how long it takes to play)
+    pub fn length(&self) -> Duration {
+        self.sequencer.end()
+    }
+}
+
+impl Iterator for RenderStream {
+    type Item = Box<[f32]>;
+
+    fn next(&mut self) -> Option<Box<[f32]>> {
+        if self.sequencer.is_finished() {
+            return None;
+        }
+        let mut block = Vec::with_capacity(self.block);
+        while block.len() < self.block && !self.sequencer.is_finished() {
+            block.push(self.sequencer.next_sample());
+        }
+        Some(block.into_boxed_slice())
+    }
+}
+
+/// Render a sequence a block at a time, handing each block to `on_block`
+/// with the progress so far (0.0 - 1.0). Returning `ControlFlow::Break`
+/// cancels the render, which this then returns; a render that runs to the
+/// end returns `ControlFlow::Continue`.
+pub fn render_streaming(
+    sample_rate: f32,
+    sequence: Sequence,
+    block: usize,
+    mut on_block: impl FnMut(&[f32], f32) -> ControlFlow<()>,
+) -> ControlFlow<()> {
+    let mut stream = RenderStream::new(sample_rate, sequence, block);
+    while let Some(samples) = stream.next() {
+        on_block(&samples, stream.progress())?;
+    }
+    ControlFlow::Continue(())
+}
+
+/// Render a sequence like `render_sequence`, but each of its outputs
+/// (tracks, such as the parts of a round) on its own thread. The tracks are
+/// mixed in output order, so the result is the same sample for sample
+/// whatever the number of threads.
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+pub fn render_tracks(sample_rate: f32, sequence: Sequence) -> Vec<f32> {
+    use rayon::prelude::*;
+
+    let tracks: Vec<Vec<f32>> = sequence.tracks()
+        .into_par_iter()
+        .map(|track| render_sequence(sample_rate, track))
+        .collect();
+    mix_tracks(&tracks)
+}
+
+/// Render a sequence track by track as `render_tracks` does, one after the
+/// other, for builds without the `parallel` feature
+#[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+pub fn render_tracks(sample_rate: f32, sequence: Sequence) -> Vec<f32> {
+    let tracks: Vec<Vec<f32>> = sequence.tracks()
+        .into_iter()
+        .map(|track| render_sequence(sample_rate, track))
+        .collect();
+    mix_tracks(&tracks)
+}
+
+/// Sum tracks in order, as long as the longest
+fn mix_tracks(tracks: &[Vec<f32>]) -> Vec<f32> {
+    let mut mix = vec![0.0; tracks.iter().map(Vec::len).max().unwrap_or(0)];
+    for track in tracks {
+        for (out, sample) in mix.iter_mut().zip(track) {
+            *out += sample;
+        }
+    }
+    mix
+}
+
+/// Render a single note held for `gate_ms`, returning the samples and the
+/// amplitude envelope (scaled by the preset level) for each sample
+pub fn render_note(sample_rate: f32, preset: &FMParams, freq: f32, gate_ms: u64) -> (Vec<f32>, Vec<f32>) {
+    let mut synth = FMSynth::new(sample_rate, preset.for_note(freq));
+    let mut samples = Vec::new();
+    let mut envelope = Vec::new();
+    let mut run = |synth: &mut FMSynth, ms: u64| {
+        let frames = (sample_rate as u64 * ms / 1000) as usize;
+        for _ in 0..frames {
+            samples.push(synth.next_sample());
+            envelope.push(synth.envelope_level() * preset.gain());
+        }
+    };
+
+    synth.note_on();
+    run(&mut synth, gate_ms);
+    synth.note_off();
+    run(&mut synth, TAIL_MS);
+    (samples, envelope)
+}
+
+/// Loudness of a preset in dBFS: the RMS level of the loudest
+/// `LOUDNESS_WINDOW_MS` of a held C4, dry
+pub fn loudness_db(preset: &FMParams) -> f32 {
+    let (samples, _) = render_note(DEFAULT_RENDER_RATE, preset, LOUDNESS_FREQ, LOUDNESS_GATE_MS);
+    let window = (DEFAULT_RENDER_RATE as u64 * LOUDNESS_WINDOW_MS / 1000) as usize;
+    let loudest = (0..samples.len().saturating_sub(window).max(1))
+        .step_by(window / 4)
+        .map(|start| {
+            let stretch = &samples[start..(start + window).min(samples.len())];
+            stretch.iter().map(|s| s * s).sum::<f32>() / window as f32
+        })
+        .fold(0.0, f32::max);
+    gain_to_db(loudest.sqrt())
+}
+
+/// `preset` with its level set to play at `LOUDNESS_TARGET_DB`, as far
+/// as full scale allows, so presets compare at an even volume
+/// rather than at the levels they were written with
+pub fn loudness_matched(preset: &FMParams) -> FMParams {
+    let mut matched = preset.clone();
+    let loudness = loudness_db(preset);
+    if loudness.is_finite() {
+        matched.level_db = (preset.level_db + LOUDNESS_TARGET_DB - loudness).clamp(MIN_LEVEL_DB, 0.0);
+    }
+    matched
+}
+
+/// Normalization applied to a rendered buffer before export
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalize {
+    /// Scale so the loudest sample hits full scale
+    Peak,
+    /// Scale so the loudest sample hits the given level in dBFS
+    Dbfs(f32),
+}
+
+impl Normalize {
+    /// Parse `peak` or a level such as `-1dBFS`
+    pub fn parse(s: &str) -> Option<Self> {
+        let lower = s.to_lowercase();
+        if lower == "peak" {
+            return Some(Normalize::Peak);
+        }
+        let level = lower.strip_suffix("dbfs")?.trim().parse::<f32>().ok()?;
+        if level <= 0.0 {
+            Some(Normalize::Dbfs(level))
+        } else {
+            None
+        }
+    }
+
+    /// Target peak as a linear gain
+    pub fn target_peak(&self) -> f32 {
+        match self {
+            Normalize::Peak => 1.0,
+            Normalize::Dbfs(db) => db_to_gain(*db),
+        }
+    }
+}
+
+/// Largest absolute sample value in the buffer
+pub fn peak(buffer: &[f32]) -> f32 {
+    buffer.iter().fold(0.0, |max, s| max.max(s.abs()))
+}
+
+/// Scale the buffer in place to the requested level, returning the gain applied
+pub fn normalize(buffer: &mut [f32], mode: Normalize) -> f32 {
+    let current = peak(buffer);
+    if current <= 0.0 {
+        return 1.0;
+    }
+    let gain = mode.target_peak() / current;
+    for sample in buffer.iter_mut() {
+        *sample *= gain;
+    }
+    gain
+}
+
+/// 64-bit FNV-1a hash, used for stable content hashes
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x0100_0000_01B3))
+}
+
+/// Render a melody deterministically and hash the result.
+///
+/// The hash covers the 16-bit export (rendering, dither, and WAV encoding),
+/// so it changes whenever a DSP change alters the audible output but not
+/// for sub-LSB float noise between platforms. `seed` is the dither seed;
+/// the random LFO and spread follow the preset's own `lfo_seed` and
+/// `spread_seed`, so a preset always hashes the same.
+pub fn render_hash(sample_rate: u32, seed: u64, preset: &FMParams, melody: &[(&str, u64)]) -> u64 {
+    let samples = render_melody(sample_rate as f32, preset, melody);
+    fnv1a(&wav::encode_wav_seeded(sample_rate, &samples, BitDepth::Int16, seed))
+}
@@ -0,0 +1,201 @@
+// src/resample.rs - Sample-rate conversion between the engine and its outputs
+
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+use crate::synth_core::{SampleSource, MAX_OUTPUTS};
+
+/// Zero crossings of the sinc kernel on each side of its centre; more is a
+/// sharper low-pass at the cost of more work per sample
+const ZERO_CROSSINGS: f32 = 16.0;
+
+/// Kernel table entries per input sample, interpolated between
+const TABLE_RES: usize = 128;
+
+/// Cutoff as a fraction of the lower rate's Nyquist frequency, leaving room
+/// for the filter to roll off before it
+const ROLLOFF: f32 = 0.95;
+
+/// Channels a live resampler carries: left and right of every output a
+/// source can have
+pub const OUTPUT_CHANNELS: usize = 2 * MAX_OUTPUTS;
+
+/// A windowed-sinc converter of `C`-channel frames from one sample rate to
+/// another. It pulls input frames as it needs them, so a source running at
+/// the engine rate can feed an output at any other rate; equal rates pass
+/// frames straight through.
+pub struct Resampler<const C: usize> {
+    /// Input frames per output frame
+    step: f64,
+    passthrough: bool,
+    /// Input frames either side of an output frame that reach it
+    reach: usize,
+    half_width: f32,
+    /// The kernel from its centre outwards, `TABLE_RES` entries per input
+    /// frame
+    table: Vec<f32>,
+    /// Input frames still in reach, oldest first
+    history: VecDeque<[f32; C]>,
+    /// Where the next output frame falls, in input frames from the front
+    /// of `history`
+    position: f64,
+}
+
+impl<const C: usize> Resampler<C> {
+    pub fn new(from: u32, to: u32) -> Self {
+        // Downsampling lowers the cutoff below the output's Nyquist
+        // frequency and widens the kernel to match
+        let cutoff = (to as f32 / from.max(1) as f32).min(1.0) * ROLLOFF;
+        let half_width = ZERO_CROSSINGS / cutoff;
+        let table = (0..=(half_width * TABLE_RES as f32).ceil() as usize + 1)
+            .map(|i| {
+                let x = i as f32 / TABLE_RES as f32;
+                let sinc = if x == 0.0 { 1.0 } else { (PI * cutoff * x).sin() / (PI * cutoff * x) };
+                // Blackman window over the kernel's width
+                let u = (x / half_width).min(1.0);
+                let window = 0.42 + 0.5 * (PI * u).cos() + 0.08 * (2.0 * PI * u).cos();
+                cutoff * sinc * window
+            })
+            .collect();
+        let reach = half_width.ceil() as usize;
+        Self {
+            step: from as f64 / to.max(1) as f64,
+            passthrough: from == to,
+            reach,
+            half_width,
+            table,
+            // Silence before the first input frame
+            history: VecDeque::from(vec![[0.0; C]; reach]),
+            position: reach as f64,
+        }
+    }
+
+    /// Kernel weight `x` input frames from the centre
+    fn kernel(&self, x: f32) -> f32 {
+        let x = x.abs();
+        if x >= self.half_width {
+            return 0.0;
+        }
+        let scaled = x * TABLE_RES as f32;
+        let i = scaled as usize;
+        let frac = scaled - i as f32;
+        self.table[i] + (self.table[i + 1] - self.table[i]) * frac
+    }
+
+    /// The next output frame, pulling input frames from `source` as needed
+    pub fn next_frame(&mut self, mut source: impl FnMut() -> [f32; C]) -> [f32; C] {
+        if self.passthrough {
+            return source();
+        }
+        let centre = self.position.floor() as usize;
+        while self.history.len() <= centre + self.reach {
+            self.history.push_back(source());
+        }
+        let frac = self.position.fract() as f32;
+        let mut out = [0.0; C];
+        for i in centre + 1 - self.reach..=centre + self.reach {
+            let weight = self.kernel(frac + centre as f32 - i as f32);
+            for (out, sample) in out.iter_mut().zip(self.history[i]) {
+                *out += weight * sample;
+            }
+        }
+        self.position += self.step;
+        // Drop frames that have fallen out of reach of the next output frame
+        while self.position >= self.reach as f64 {
+            self.history.pop_front();
+            self.position -= 1.0;
+        }
+        out
+    }
+}
+
+/// Convert a mono buffer from `from` Hz to `to` Hz, keeping its length in
+/// time
+pub fn resample(samples: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to {
+        return samples.to_vec();
+    }
+    resample_iter(samples.iter().copied(), from, to).collect()
+}
+
+/// `resample` for mono samples arriving as they're rendered, such as a
+/// `render::RenderStream`, with the same result
+pub fn resample_iter(mut samples: impl Iterator<Item = f32>, from: u32, to: u32) -> impl Iterator<Item = f32> {
+    let mut resampler = Resampler::<1>::new(from, to);
+    let (mut read, mut ended, mut written) = (0u64, false, 0u64);
+    std::iter::from_fn(move || {
+        let frame = resampler.next_frame(|| match samples.next() {
+            Some(sample) => {
+                read += 1;
+                [sample]
+            }
+            // The kernel reaches past the end into silence
+            None => {
+                ended = true;
+                [0.0]
+            }
+        });
+        // Once the input's length is known, so is the output's
+        if ended && written >= (read as f64 * to as f64 / from as f64).round() as u64 {
+            return None;
+        }
+        written += 1;
+        Some(frame[0])
+    })
+}
+
+/// A source running at the engine rate as seen through a resampler by an
+/// output at the device rate. A stream calls only one of `next_sample`,
+/// `next_frame`, and `next_outputs`, so they share the resampler; the first
+/// two use only its first channels.
+pub struct Resampled<'a, S> {
+    source: &'a mut S,
+    resampler: &'a mut Resampler<OUTPUT_CHANNELS>,
+}
+
+impl<'a, S: SampleSource> Resampled<'a, S> {
+    pub fn new(source: &'a mut S, resampler: &'a mut Resampler<OUTPUT_CHANNELS>) -> Self {
+        Self { source, resampler }
+    }
+}
+
+impl<S: SampleSource> SampleSource for Resampled<'_, S> {
+    fn next_sample(&mut self) -> f32 {
+        let source = &mut *self.source;
+        self.resampler.next_frame(|| {
+            let mut frame = [0.0; OUTPUT_CHANNELS];
+            frame[0] = source.next_sample();
+            frame
+        })[0]
+    }
+
+    fn next_frame(&mut self) -> [f32; 2] {
+        let source = &mut *self.source;
+        let frame = self.resampler.next_frame(|| {
+            let mut frame = [0.0; OUTPUT_CHANNELS];
+            frame[..2].copy_from_slice(&source.next_frame());
+            frame
+        });
+        [frame[0], frame[1]]
+    }
+
+    fn next_outputs(&mut self) -> [[f32; 2]; MAX_OUTPUTS] {
+        let source = &mut *self.source;
+        let frame = self.resampler.next_frame(|| {
+            let outputs = source.next_outputs();
+            std::array::from_fn(|i| outputs[i / 2][i % 2])
+        });
+        std::array::from_fn(|i| [frame[2 * i], frame[2 * i + 1]])
+    }
+
+    /// The source stays at the engine rate
+    fn set_sample_rate(&mut self, _sample_rate: f32) {}
+
+    fn set_input_level(&mut self, level: f32) {
+        self.source.set_input_level(level);
+    }
+
+    fn set_external(&mut self, sample: f32) {
+        self.source.set_external(sample);
+    }
+}
@@ -0,0 +1,461 @@
+// src/resynth.rs - Fitting an FM preset to a recorded sound
+
+use crate::analysis::{self, Sideband};
+use crate::synth_core::{gain_to_db, FMParams, MAX_ENVELOPE_TIME, MAX_MODULATION_INDEX, MIN_LEVEL_DB, REFERENCE_FREQ};
+
+/// Frames the amplitude envelope is measured in
+const FRAME_SECONDS: f32 = 0.01;
+
+/// Spectrum windows, rounded up to a power of two samples
+const WINDOW_SECONDS: f32 = 0.05;
+
+/// Level, relative to the peak, below which the recording counts as silent
+/// (-40 dB)
+const SILENCE: f32 = 0.01;
+
+/// Sustain level, relative to the peak, above which a sound counts as held
+/// rather than struck, so its final fall can be a release
+const HELD: f32 = 0.05;
+
+/// Places the release is tried starting at, spread over the fade
+const RELEASE_STARTS: usize = 20;
+
+/// Strongest spectral peaks each tried as the carrier, and used to suggest
+/// frequency ratios
+const PEAKS: usize = 16;
+
+/// Peaks weaker than this, relative to the strongest, are ignored (-30 dB)
+const PEAK_FLOOR: f64 = 1e-3;
+
+/// Power ratio within which two peaks count as equally strong, so possibly
+/// a pair of sidebands either side of the carrier (3 dB)
+const PAIR_MATCH: f64 = 2.0;
+
+/// Ratios always tried, on top of those the peaks suggest
+const SIMPLE_RATIOS: [f64; 14] = [0.5, 1.0, 1.41, 1.5, 2.0, 2.5, 3.0, 3.5, 4.0, 5.0, 6.0, 7.0, 8.0, 11.0];
+
+/// Range of frequency ratios tried
+const MIN_RATIO: f64 = 0.05;
+const MAX_RATIO: f64 = 16.0;
+
+/// Modulation depths (beta) tabulated, and the coarse search's stride
+/// through them
+const MAX_BETA: f64 = 16.0;
+const BETA_STEP: f64 = 0.05;
+const COARSE_STRIDE: usize = 5;
+
+/// Highest frequency the spectra are compared up to
+const MAX_BAND_HZ: f64 = 16000.0;
+
+/// Narrowest band compared, in FFT bins: a semitone is narrower than a
+/// window's main lobe at low frequencies
+const MIN_BAND_BINS: f64 = 3.0;
+
+/// Bins either side of a sideband its window's response is spread over
+/// when predicting a spectrum (the Hann main lobe and its first sidelobes)
+const RESPONSE_BINS: isize = 4;
+
+/// Most windows the index envelope is measured over, and the level
+/// (relative to the peak) past which the sound is too quiet to measure
+const INDEX_WINDOWS: usize = 8;
+const INDEX_FLOOR: f32 = 0.1;
+
+/// Index sustain above which the brightness is steady enough not to need
+/// an index envelope
+const STEADY_INDEX: f32 = 0.8;
+
+/// Steps of the envelope fits: decay times from `MIN_FIT_TIME` to
+/// `MAX_ENVELOPE_TIME`, spaced evenly in ratio, and sustain levels
+const DECAY_STEPS: usize = 64;
+const SUSTAIN_STEPS: usize = 50;
+const MIN_FIT_TIME: f32 = 0.005;
+
+/// Most points an envelope is fitted to; longer ones are averaged down
+const FIT_POINTS: usize = 200;
+
+/// A preset fitted to a recording
+#[derive(Clone, Debug)]
+pub struct Resynthesis {
+    /// The fitted preset, at the usual reference pitch: played at `pitch`
+    /// it sounds like the recording
+    pub params: FMParams,
+    /// The recording's carrier frequency in Hz
+    pub pitch: f32,
+    /// How closely the fitted spectrum matches the recording's, from 0
+    /// (nothing alike) to 1 (the same), in semitone bands
+    pub similarity: f32,
+    /// Length of the recording from its onset to where it falls silent, in
+    /// seconds
+    pub duration: f32,
+}
+
+/// Fit a two-operator FM preset to a short recording, such as a single
+/// bell hit. The carrier, frequency ratio, and modulation index are searched
+/// for the spectrum closest to the recording's just after its peak; the
+/// amplitude envelope is fitted to its level, and an index envelope to how
+/// its spectrum narrows as it fades. `None` if the recording is silent.
+pub fn resynthesize(samples: &[f32], sample_rate: u32) -> Option<Resynthesis> {
+    let rate = sample_rate as f32;
+    let frame = ((rate * FRAME_SECONDS) as usize).max(1);
+    let frame_seconds = frame as f32 / rate;
+    let levels: Vec<f32> = samples.chunks(frame).map(rms).collect();
+    let (peak_frame, peak) = levels.iter().copied().enumerate().max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if peak <= 0.0 {
+        return None;
+    }
+    let onset = levels.iter().position(|&level| level >= peak * SILENCE)?;
+    let end = levels.iter().rposition(|&level| level >= peak * SILENCE)? + 1;
+
+    // The amplitude envelope, from the peak on
+    let fade: Vec<f32> = levels[peak_frame..end].iter().map(|level| level / peak).collect();
+    let attack = (peak_frame - onset) as f32 * frame_seconds;
+    let (decay, sustain, release) = fit_fade(&fade, frame_seconds, true);
+
+    let window = ((rate * WINDOW_SECONDS) as usize).next_power_of_two();
+    let bands = Bands::new(sample_rate, window);
+    let table = BesselTable::new();
+    let start = (peak_frame * frame).min(samples.len());
+    let spectrum = analysis::windowed_power_spectrum(&samples[start..], window);
+    let measured = bands.measure(&spectrum);
+    let fit = search(&bands, &table, &spectrum, &measured)?;
+
+    // Brightness over the fade, as the best depth for the same carrier
+    // and ratio in windows spread down to `INDEX_FLOOR`
+    let bright_end = fade.iter().rposition(|&level| level >= INDEX_FLOOR).unwrap_or(0) * frame;
+    let hop = (bright_end / INDEX_WINDOWS).max(window / 2);
+    let depths: Vec<f32> = (0..INDEX_WINDOWS)
+        .map(|i| start + i * hop)
+        .take_while(|&at| at == start || at + window <= start + bright_end)
+        .map(|at| {
+            let measured = bands.measure(&analysis::windowed_power_spectrum(&samples[at.min(samples.len())..], window));
+            let best = (0..=max_depth(&table, fit.ratio))
+                .map(|beta| (beta, fit.score_depth(&bands, &table, &measured, beta)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map_or(fit.beta, |(beta, _)| beta);
+            table.beta(best) as f32
+        })
+        .collect();
+    let peak_depth = depths.iter().copied().fold(table.beta(fit.beta) as f32, f32::max);
+    let index_curve: Vec<f32> = depths.iter().map(|depth| depth / peak_depth.max(f32::EPSILON)).collect();
+    let (index_decay, index_sustain, _) = fit_fade(&index_curve, hop as f32 / rate, false);
+    let index_env = depths.len() > 1 && index_sustain < STEADY_INDEX;
+
+    let defaults = FMParams::default();
+    let ratio = fit.ratio as f32;
+    let params = FMParams {
+        carrier_freq: REFERENCE_FREQ,
+        modulator_freq: REFERENCE_FREQ * ratio,
+        modulation_index: (peak_depth * ratio).min(MAX_MODULATION_INDEX),
+        // A sine at full envelope has an RMS level of its amplitude over root 2
+        level_db: gain_to_db(peak * std::f32::consts::SQRT_2).clamp(MIN_LEVEL_DB, 0.0),
+        phase_reset: true,
+        attack,
+        decay,
+        sustain,
+        release: release.unwrap_or(defaults.release),
+        index_env,
+        index_attack: if index_env { attack } else { defaults.index_attack },
+        index_decay: if index_env { index_decay } else { defaults.index_decay },
+        index_sustain: if index_env { index_sustain } else { defaults.index_sustain },
+        index_release: release.filter(|_| index_env).unwrap_or(defaults.index_release),
+        ..defaults
+    }
+    .clamped();
+    Some(Resynthesis {
+        params,
+        pitch: fit.carrier as f32,
+        similarity: fit.score as f32,
+        duration: (end - onset) as f32 * frame_seconds,
+    })
+}
+
+/// RMS level of a stretch of samples
+fn rms(samples: &[f32]) -> f32 {
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len().max(1) as f32).sqrt()
+}
+
+/// The decay time, sustain level, and (with `release`, for a held sound)
+/// release time of the engine's linear envelope stages that best fit
+/// `levels`, sampled every `step` seconds falling from 1, by least squares
+/// over a grid
+fn fit_fade(levels: &[f32], step: f32, release: bool) -> (f32, f32, Option<f32>) {
+    let chunk = levels.len().div_ceil(FIT_POINTS).max(1);
+    let levels: Vec<f32> = levels.chunks(chunk).map(|c| c.iter().sum::<f32>() / c.len() as f32).collect();
+    let step = step * chunk as f32;
+    let n = levels.len();
+    // Where the release starts, as a frame; `n` for none
+    let mut starts = vec![n];
+    if release {
+        starts.extend((1..RELEASE_STARTS).map(|i| i * n / RELEASE_STARTS));
+    }
+
+    let mut best = (f32::INFINITY, MIN_FIT_TIME, levels.last().copied().unwrap_or(0.0), n);
+    let mut head_error = vec![0.0; n + 1];
+    for i in 0..DECAY_STEPS {
+        let decay = MIN_FIT_TIME * (MAX_ENVELOPE_TIME / MIN_FIT_TIME).powf(i as f32 / (DECAY_STEPS - 1) as f32);
+        for j in 0..=SUSTAIN_STEPS {
+            let sustain = j as f32 / SUSTAIN_STEPS as f32;
+            for (k, level) in levels.iter().enumerate() {
+                let fit = sustain.max(1.0 - (1.0 - sustain) * k as f32 * step / decay);
+                head_error[k + 1] = head_error[k] + (fit - level).powi(2);
+            }
+            // The release falls from the sustain level to silence at the end
+            for &start in starts.iter().filter(|&&start| start == n || sustain >= HELD) {
+                let error = head_error[start] + levels[start..].iter()
+                    .enumerate()
+                    .map(|(k, level)| (sustain * (1.0 - k as f32 / (n - start) as f32) - level).powi(2))
+                    .sum::<f32>();
+                if error < best.0 {
+                    best = (error, decay, sustain, start);
+                }
+            }
+        }
+    }
+    let release = (best.3 < n).then(|| (n - best.3) as f32 * step);
+    (best.1, best.2, release)
+}
+
+/// J_k(beta) for every tabulated depth, `BETA_STEP` apart, and every order
+/// the sidebands of those depths need
+struct BesselTable {
+    rows: Vec<Vec<f64>>,
+}
+
+impl BesselTable {
+    fn new() -> Self {
+        let orders = (MAX_BETA + 20.0).ceil() as i32;
+        let rows = (0..=(MAX_BETA / BETA_STEP).round() as usize)
+            .map(|i| (0..=orders).map(|k| analysis::bessel_j(k, i as f64 * BETA_STEP)).collect())
+            .collect();
+        Self { rows }
+    }
+
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn beta(&self, i: usize) -> f64 {
+        i as f64 * BETA_STEP
+    }
+
+    /// J_k of depth `i`, using J_-k = (-1)^k J_k
+    fn get(&self, i: usize, k: i32) -> f64 {
+        let j = self.rows[i][k.unsigned_abs() as usize];
+        if k < 0 && k % 2 != 0 { -j } else { j }
+    }
+
+    fn sidebands(&self, carrier: f64, ratio: f64, i: usize, nyquist: f64) -> Vec<Sideband> {
+        analysis::sidebands_with(carrier, carrier * ratio, self.beta(i), nyquist, |k| self.get(i, k))
+    }
+}
+
+/// Frequency bands spectra are compared in: a semitone wide, or
+/// `MIN_BAND_BINS` where that's wider
+struct Bands {
+    bin_hz: f64,
+    nyquist: f64,
+    /// First FFT bin of each band, and the bin past the last
+    edges: Vec<usize>,
+    /// The band of each FFT bin below the last edge, if it's in one
+    band_of_bin: Vec<Option<usize>>,
+}
+
+impl Bands {
+    fn new(sample_rate: u32, window: usize) -> Self {
+        let bin_hz = sample_rate as f64 / window as f64;
+        let nyquist = sample_rate as f64 / 2.0;
+        let top = MAX_BAND_HZ.min(nyquist);
+        let mut edges = vec![(20.0 / bin_hz).ceil().max(1.0) as usize];
+        let mut edge = edges[0] as f64 * bin_hz;
+        while edge < top {
+            edge = (edge * 2f64.powf(1.0 / 12.0)).max(edge + MIN_BAND_BINS * bin_hz).min(top);
+            edges.push((edge / bin_hz).round() as usize);
+        }
+        let band_of_bin = (0..edges[edges.len() - 1])
+            .map(|bin| edges.partition_point(|&edge| edge <= bin).checked_sub(1))
+            .collect();
+        Self { bin_hz, nyquist, edges, band_of_bin }
+    }
+
+    /// Share of a power spectrum's energy in each band
+    fn measure(&self, spectrum: &[f64]) -> Vec<f64> {
+        let energy: Vec<f64> = self.edges.windows(2)
+            .map(|band| spectrum[band[0].min(spectrum.len())..band[1].min(spectrum.len())].iter().sum())
+            .collect();
+        normalized(energy)
+    }
+
+    /// Share of the sidebands' energy in each band, each spread over the
+    /// bins around it as a Hann window spreads it in a measured spectrum
+    fn predict(&self, sidebands: &[Sideband]) -> Vec<f64> {
+        let mut energy = vec![0.0; self.edges.len() - 1];
+        for sideband in sidebands {
+            let position = sideband.freq / self.bin_hz;
+            let centre = position.round() as isize;
+            for bin in centre - RESPONSE_BINS..=centre + RESPONSE_BINS {
+                let band = usize::try_from(bin).ok().and_then(|bin| self.band_of_bin.get(bin).copied().flatten());
+                if let Some(band) = band {
+                    energy[band] += (sideband.amplitude * hann_response(bin as f64 - position)).powi(2);
+                }
+            }
+        }
+        normalized(energy)
+    }
+}
+
+/// Magnitude of a Hann window's response `offset` bins from a sinusoid,
+/// relative to its peak
+fn hann_response(offset: f64) -> f64 {
+    let x = std::f64::consts::PI * offset;
+    match offset.abs() {
+        d if d < 1e-9 => 1.0,
+        d if (d - 1.0).abs() < 1e-9 => 0.5,
+        d => x.sin() / x / (1.0 - d * d),
+    }
+}
+
+fn normalized(mut energy: Vec<f64>) -> Vec<f64> {
+    let total: f64 = energy.iter().sum();
+    if total > 0.0 {
+        energy.iter_mut().for_each(|e| *e /= total);
+    }
+    energy
+}
+
+/// Overlap of two band energy distributions (the Bhattacharyya
+/// coefficient): 1 when they're the same, 0 when they share nothing
+fn similarity(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a * b).sqrt()).sum()
+}
+
+/// A point in the search, with its score
+#[derive(Clone, Copy, Debug)]
+struct Fit {
+    carrier: f64,
+    ratio: f64,
+    /// Depth, as a row of the `BesselTable`
+    beta: usize,
+    score: f64,
+}
+
+impl Fit {
+    fn scored(bands: &Bands, table: &BesselTable, measured: &[f64], carrier: f64, ratio: f64, beta: usize) -> Self {
+        let predicted = bands.predict(&table.sidebands(carrier, ratio, beta, bands.nyquist));
+        Self { carrier, ratio, beta, score: similarity(measured, &predicted) }
+    }
+
+    /// Score of this carrier and ratio at another depth
+    fn score_depth(&self, bands: &Bands, table: &BesselTable, measured: &[f64], beta: usize) -> f64 {
+        Self::scored(bands, table, measured, self.carrier, self.ratio, beta).score
+    }
+}
+
+/// The carrier, ratio, and depth whose sidebands best match `measured`: a
+/// coarse search over the peaks of `spectrum` and the ratios they suggest,
+/// then a finer one around the best of it
+fn search(bands: &Bands, table: &BesselTable, spectrum: &[f64], measured: &[f64]) -> Option<Fit> {
+    let peaks = spectral_peaks(spectrum, bands.bin_hz);
+    let max_beta = |ratio: f64| max_depth(table, ratio);
+    let freqs: Vec<f64> = peaks.iter().map(|&(freq, _)| freq).collect();
+    let mut best: Option<Fit> = None;
+    for carrier in candidate_carriers(&peaks) {
+        for ratio in candidate_ratios(carrier, &freqs) {
+            for beta in (0..=max_beta(ratio)).step_by(COARSE_STRIDE) {
+                let fit = Fit::scored(bands, table, measured, carrier, ratio, beta);
+                if best.is_none_or(|best| fit.score > best.score) {
+                    best = Some(fit);
+                }
+            }
+        }
+    }
+
+    // Nudge each coordinate in turn while that helps, in ever smaller steps
+    let mut best = best?;
+    for step in [0.01, 0.003, 0.001] {
+        loop {
+            let Fit { carrier, ratio, beta, .. } = best;
+            let neighbours = [
+                (carrier * (1.0 + step), ratio, beta),
+                (carrier * (1.0 - step), ratio, beta),
+                (carrier, ratio * (1.0 + step), beta),
+                (carrier, ratio * (1.0 - step), beta),
+                (carrier, ratio, (beta + 1).min(max_beta(ratio))),
+                (carrier, ratio, beta.saturating_sub(1)),
+            ];
+            let better = neighbours.into_iter()
+                .filter(|&(_, ratio, _)| (MIN_RATIO..=MAX_RATIO).contains(&ratio))
+                .map(|(carrier, ratio, beta)| Fit::scored(bands, table, measured, carrier, ratio, beta))
+                .max_by(|a, b| a.score.total_cmp(&b.score))
+                .filter(|fit| fit.score > best.score);
+            match better {
+                Some(fit) => best = fit,
+                None => break,
+            }
+        }
+    }
+    Some(best)
+}
+
+/// Deepest tabulated depth whose modulation index stays in range at `ratio`
+fn max_depth(table: &BesselTable, ratio: f64) -> usize {
+    ((MAX_MODULATION_INDEX as f64 / ratio / BETA_STEP) as usize).min(table.len() - 1)
+}
+
+/// Frequencies and powers of the strongest peaks of a power spectrum,
+/// strongest first, refined between bins by parabolic interpolation
+fn spectral_peaks(spectrum: &[f64], bin_hz: f64) -> Vec<(f64, f64)> {
+    let strongest = spectrum.iter().copied().fold(0.0, f64::max);
+    let mut peaks: Vec<(f64, f64)> = (2..spectrum.len().saturating_sub(2))
+        .filter(|&i| spectrum[i] >= strongest * PEAK_FLOOR && spectrum[i] > 0.0)
+        // The highest bin of its main lobe
+        .filter(|&i| (i - 2..=i + 2).all(|j| j == i || spectrum[j] < spectrum[i]))
+        .map(|i| {
+            let (a, b, c) = (spectrum[i - 1].max(1e-30).ln(), spectrum[i].ln(), spectrum[i + 1].max(1e-30).ln());
+            let offset = 0.5 * (a - c) / (a - 2.0 * b + c);
+            ((i as f64 + offset.clamp(-0.5, 0.5)) * bin_hz, spectrum[i])
+        })
+        .collect();
+    peaks.sort_by(|a, b| b.1.total_cmp(&a.1));
+    peaks.truncate(PEAKS);
+    peaks
+}
+
+/// Carrier frequencies worth trying: each peak, and the middle of each pair
+/// of equally strong peaks, as sidebands of the same order either side of
+/// the carrier are. That finds a carrier the modulation has all but
+/// cancelled. A lower sideband reflected around 0 Hz puts the carrier at
+/// half the pair's difference instead.
+fn candidate_carriers(peaks: &[(f64, f64)]) -> Vec<f64> {
+    let mut carriers: Vec<f64> = peaks.iter().map(|&(freq, _)| freq).collect();
+    for (i, &(freq, power)) in peaks.iter().enumerate() {
+        for &(other, other_power) in &peaks[i + 1..] {
+            if power.max(other_power) <= power.min(other_power) * PAIR_MATCH {
+                carriers.extend([(freq + other) / 2.0, (freq - other).abs() / 2.0]);
+            }
+        }
+    }
+    carriers.retain(|&carrier| carrier > 0.0);
+    carriers.sort_by(f64::total_cmp);
+    carriers.dedup_by(|a, b| (*a - *b).abs() < *b * 0.005);
+    carriers
+}
+
+/// Frequency ratios worth trying for `carrier`: the simple ones, those
+/// putting a sideband on each peak, and the spacings between peaks (the
+/// modulator frequency, for neighbouring sidebands)
+fn candidate_ratios(carrier: f64, peaks: &[f64]) -> Vec<f64> {
+    let mut ratios: Vec<f64> = SIMPLE_RATIOS.to_vec();
+    for (i, &peak) in peaks.iter().enumerate() {
+        ratios.push((peak - carrier).abs() / carrier);
+        // A lower sideband reflected around 0 Hz
+        ratios.push((peak + carrier) / carrier);
+        ratios.extend(peaks[i + 1..].iter().map(|&other| (peak - other).abs() / carrier));
+    }
+    // Sidebands only fold onto each other at exactly a simple ratio, so it
+    // stands in for any near it
+    let near_simple = |ratio: &f64| SIMPLE_RATIOS.iter().any(|simple| (ratio - simple).abs() < simple * 0.005);
+    ratios.retain(|ratio| (MIN_RATIO..=MAX_RATIO).contains(ratio) && (SIMPLE_RATIOS.contains(ratio) || !near_simple(ratio)));
+    ratios.sort_by(f64::total_cmp);
+    ratios.dedup_by(|a, b| (*a - *b).abs() < *b * 0.005);
+    ratios
+}
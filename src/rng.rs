@@ -0,0 +1,32 @@
+/// Small seedable pseudo-random generator (xorshift64*)
+///
+/// All randomness in the engine goes through this type so renders are
+/// reproducible for a given seed; `render::render_hash` relies on this.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift, so remap it
+        Self { state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform value in [0.0, 1.0)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform value in [-1.0, 1.0)
+    pub fn next_bipolar(&mut self) -> f32 {
+        self.next_f32() * 2.0 - 1.0
+    }
+}
@@ -0,0 +1,921 @@
+// src/sequencer.rs - Sample-clocked note sequencing for playback and rendering
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::drums::{self, Drums, Pattern};
+use crate::effects::{EffectChain, EffectSettings};
+use crate::render;
+use crate::synth_core::{FMParams, MonoSynth, NotePriority, PolySynth, SampleSource, SineTable, MAX_OUTPUTS};
+use crate::synth_data::{self, note_freq, parse_articulation, LEGATO_GATE, MIN_GATE};
+
+/// A note starting or ending `at` a position in a sequence; `note` indexes
+/// the sequence's resolved note parameters
+#[derive(Clone, Copy, Debug)]
+struct Event {
+    at: Duration,
+    note: usize,
+    on: bool,
+    /// Dropped once it has fired, rather than repeating on the next loop pass
+    once: bool,
+}
+
+/// How far a legato melody note overlaps the next, so the mono engine
+/// sees the next note-on while the previous note is still held
+pub const LEGATO_OVERLAP: Duration = Duration::from_millis(10);
+
+/// A continuous pitch change from a note's own pitch to `to`, starting
+/// with the note and taking `time`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slide {
+    pub to: f32,
+    pub time: Duration,
+}
+
+/// Beats per bar when counting bars, in 4/4
+pub const BEATS_PER_BAR: f32 = 4.0;
+
+/// Samples between tempo map updates; the tempo is constant within a block
+const TEMPO_BLOCK: u64 = 64;
+
+/// Slowest and fastest tempos a tempo map can set
+const MIN_BPM: f32 = 12.0;
+const MAX_BPM: f32 = 1200.0;
+
+/// A point of a tempo map: the tempo from the start of `bar` (counting from
+/// 1, in 4/4 at the written tempo), changing there at once or, with `ramp`,
+/// reached by a steady change from the point before
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct TempoPoint {
+    pub bar: f32,
+    pub bpm: f32,
+    #[serde(default)]
+    pub ramp: bool,
+}
+
+/// Tempo changes through a sequence, such as a ritardando over the last two
+/// bars. Before the first point the tempo is as written
+/// (`synth_data::MELODY_BPM`); tempos are relative to it, so a change of
+/// speed scales the whole map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TempoMap {
+    points: Vec<TempoPoint>,
+}
+
+impl TempoMap {
+    /// A map through `points` in bar order, with tempos clamped to 12 -
+    /// 1200 BPM; `None` if there are none
+    pub fn new(mut points: Vec<TempoPoint>) -> Option<Self> {
+        points.retain(|p| p.bar.is_finite() && p.bpm.is_finite());
+        for point in &mut points {
+            point.bar = point.bar.max(1.0);
+            point.bpm = point.bpm.clamp(MIN_BPM, MAX_BPM);
+        }
+        // Stable, so a jump written as two points at one bar keeps its order
+        points.sort_by(|a, b| a.bar.total_cmp(&b.bar));
+        (!points.is_empty()).then_some(Self { points })
+    }
+
+    /// Tempo at a (fractional) bar
+    pub fn bpm_at(&self, bar: f32) -> f32 {
+        let mut from = (1.0, synth_data::MELODY_BPM);
+        for point in &self.points {
+            if point.bar <= bar {
+                from = (point.bar, point.bpm);
+            } else {
+                if point.ramp {
+                    let t = (bar - from.0) / (point.bar - from.0);
+                    return from.1 + (point.bpm - from.1) * t;
+                }
+                break;
+            }
+        }
+        from.1
+    }
+
+    /// Speed relative to the written tempo at `position` seconds into the
+    /// score
+    fn speed_at(&self, position: f64) -> f32 {
+        let bar_secs = BEATS_PER_BAR as f64 * 60.0 / synth_data::MELODY_BPM as f64;
+        self.bpm_at((position / bar_secs) as f32 + 1.0) / synth_data::MELODY_BPM
+    }
+}
+
+/// One voice of a round: the preset it plays, how far it's transposed, and
+/// the output it plays through (`Sequence::set_output`)
+#[derive(Clone, Debug)]
+pub struct RoundPart {
+    pub preset: FMParams,
+    pub semitones: i32,
+    pub output: usize,
+}
+
+/// Settings for playing a sequence on the monophonic engine
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mono {
+    /// Change pitch without retriggering when notes overlap
+    pub legato: bool,
+    /// Slide between notes over this long
+    pub glide: Duration,
+    /// Which of several held notes sounds
+    pub priority: NotePriority,
+}
+
+/// Timed notes for the sequencer: a melody, a held note or chord, or any
+/// arrangement of them
+#[derive(Clone, Debug, Default)]
+pub struct Sequence {
+    /// Frequency, fully resolved parameters, slide, and output of each note
+    notes: Vec<(f32, FMParams, Option<Slide>, usize)>,
+    events: Vec<Event>,
+    length: Duration,
+    tail: Duration,
+    /// Play on the monophonic engine instead of the polyphonic one
+    mono: Option<Mono>,
+    /// Master bus effects, from the first note whose preset has any
+    effects: Option<EffectSettings>,
+    /// Play dry whatever the presets ask for
+    dry: bool,
+    /// Voices of the polyphonic engine; `render::MELODY_VOICES` if unset
+    voices: Option<usize>,
+    /// Play through a sine lookup table rather than `f32::sin`
+    sine_table: bool,
+    tempo_map: Option<TempoMap>,
+    /// Output that notes added from now on play through
+    output: usize,
+    /// Gate of unmarked notes added from now on, whatever their presets'
+    gate: Option<f32>,
+    /// Drum pattern repeating through the whole sequence, its output, and
+    /// the length of a step
+    drums: Option<(Pattern, usize, Duration)>,
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A melody with each note gated by its articulation, or the preset's
+    /// gate when unmarked
+    pub fn melody(preset: &FMParams, melody: &[(&str, u64)]) -> Self {
+        let mut sequence = Self::new();
+        sequence.append_melody(preset, melody);
+        sequence
+    }
+
+    /// A round (canon): every part plays the melody `repeats` times, each
+    /// entering `entry` after the one before, so the parts overlap like
+    /// voices singing Frère Jacques. Each part plays through its own
+    /// `output`.
+    pub fn round(parts: &[RoundPart], melody: &[(&str, u64)], entry: Duration, repeats: u32) -> Self {
+        let mut sequence = Self::new();
+        // Enough voices for every part's notes and releases
+        sequence.set_voices(render::MELODY_VOICES * parts.len().max(1));
+        for (i, part) in parts.iter().enumerate() {
+            let transposed = synth_data::transpose(melody, part.semitones);
+            let notes: Vec<(&str, u64)> = transposed.iter().map(|(note, duration)| (note.as_str(), *duration)).collect();
+            let mut at = entry * i as u32;
+            sequence.set_output(part.output);
+            for _ in 0..repeats.max(1) {
+                at = sequence.add_melody(at, &part.preset, &notes);
+            }
+        }
+        sequence
+    }
+
+    /// Voices for the polyphonic engine, for sequences with more
+    /// overlapping notes than a melody
+    pub fn set_voices(&mut self, voices: usize) {
+        self.voices = Some(voices.max(1));
+    }
+
+    /// Play the notes added from now on through output `output` (from 0,
+    /// up to `MAX_OUTPUTS - 1`), which an output stream's channel map can
+    /// send to its own speakers. Each output has its own engine and effects.
+    pub fn set_output(&mut self, output: usize) {
+        self.output = output.min(MAX_OUTPUTS - 1);
+    }
+
+    /// Outputs the notes play through: one more than the highest in use
+    pub fn outputs(&self) -> usize {
+        let drums = self.drums.as_ref().map_or(1, |drums| drums.1 + 1);
+        self.notes.iter().map(|note| note.3 + 1).max().unwrap_or(1).max(drums)
+    }
+
+    /// The sequence split into one sequence per output in use (a track),
+    /// each on output 0, and always at least one. Every track keeps the whole sequence's length,
+    /// release tail, tempo, and engine settings, so the tracks render to
+    /// the same number of samples and, summed in order, to the same
+    /// samples as the whole.
+    pub fn tracks(&self) -> Vec<Sequence> {
+        (0..self.outputs())
+            .filter(|&output| {
+                output == 0
+                    || self.notes.iter().any(|note| note.3 == output)
+                    || self.drums.as_ref().is_some_and(|drums| drums.1 == output)
+            })
+            .map(|output| {
+                let mut track = Sequence {
+                    length: self.length,
+                    tail: self.tail,
+                    mono: self.mono,
+                    effects: self.effects,
+                    dry: self.dry,
+                    voices: self.voices,
+                    sine_table: self.sine_table,
+                    tempo_map: self.tempo_map.clone(),
+                    gate: self.gate,
+                    drums: self.drums.as_ref()
+                        .filter(|drums| drums.1 == output)
+                        .map(|(pattern, _, step)| (pattern.clone(), 0, *step)),
+                    ..Sequence::new()
+                };
+                let mut index = vec![None; self.notes.len()];
+                for (i, (freq, params, slide, _)) in self.notes.iter().enumerate().filter(|(_, note)| note.3 == output) {
+                    index[i] = Some(track.notes.len());
+                    track.notes.push((*freq, params.clone(), *slide, 0));
+                }
+                track.events = self.events.iter()
+                    .filter_map(|&event| Some(Event { note: index[event.note]?, ..event }))
+                    .collect();
+                track
+            })
+            .collect()
+    }
+
+    /// Follow a tempo map rather than playing at a steady speed
+    pub fn set_tempo_map(&mut self, tempo_map: Option<TempoMap>) {
+        self.tempo_map = tempo_map;
+    }
+
+    /// Play on the monophonic engine. Set it before adding melodies: with
+    /// legato, their notes are tied into the next whatever their
+    /// articulation.
+    pub fn set_mono(&mut self, mono: Option<Mono>) {
+        self.mono = mono;
+    }
+
+    pub fn mono(&self) -> Option<Mono> {
+        self.mono
+    }
+
+    /// Hold unmarked notes added from now on for `gate` of their length
+    /// rather than their presets' gates; `None` goes back to the presets'
+    pub fn set_gate(&mut self, gate: Option<f32>) {
+        self.gate = gate.map(|gate| gate.clamp(MIN_GATE, LEGATO_GATE));
+    }
+
+    /// Repeat a drum pattern from the start to the end of the sequence,
+    /// through the output notes are being added to, at `speed` times the
+    /// written tempo (to match a melody arranged at that speed); `None`
+    /// removes it
+    pub fn set_drums(&mut self, pattern: Option<Pattern>, speed: f32) {
+        self.drums = pattern.map(|pattern| (pattern, self.output, drums::step_length().div_f32(speed)));
+        if self.drums.is_some() {
+            self.tail = self.tail.max(drums::tail());
+        }
+    }
+
+    /// Skip the presets' master bus effects (reverb, stereo width)
+    pub fn set_dry(&mut self, dry: bool) {
+        self.dry = dry;
+    }
+
+    /// Compute sines from one shared `SineTable` instead of `f32::sin`:
+    /// cheaper per voice, at a small cost in accuracy
+    pub fn set_sine_table(&mut self, sine_table: bool) {
+        self.sine_table = sine_table;
+    }
+
+    /// Add a melody starting at the current end of the sequence. Each note
+    /// is held for the gate of its articulation; one marked to slide (`C4~`)
+    /// is held for its whole length while its pitch moves to the next
+    /// note's, and legato notes (`C4_`) overlap the next.
+    pub fn append_melody(&mut self, preset: &FMParams, melody: &[(&str, u64)]) {
+        self.add_melody(self.length, preset, melody);
+    }
+
+    /// Add a melody starting `at` a position, which may overlap what's
+    /// already there, returning where it ends
+    pub fn add_melody(&mut self, at: Duration, preset: &FMParams, melody: &[(&str, u64)]) -> Duration {
+        let legato = self.mono.is_some_and(|mono| mono.legato);
+        let length_before = self.length;
+        let mut start = at;
+        for (i, &(note, duration)) in melody.iter().enumerate() {
+            let freq = note_freq(note);
+            let length = Duration::from_millis(duration);
+            let (_, articulation) = parse_articulation(note);
+            let slide_to = melody.get(i + 1)
+                .map(|&(next, _)| note_freq(next))
+                .filter(|&to| articulation.slide && to > 0.0);
+            if freq > 0.0 {
+                let unmarked = self.gate.unwrap_or(preset.gate);
+                let gate = if legato || articulation.is_legato(unmarked) {
+                    length + LEGATO_OVERLAP
+                } else if slide_to.is_some() {
+                    length
+                } else {
+                    Duration::from_millis((duration as f64 * articulation.gate(unmarked) as f64) as u64)
+                };
+                let accented = articulation.accent.then(|| preset.accented());
+                self.note(start, gate, freq, accented.as_ref().unwrap_or(preset));
+                if let Some(to) = slide_to {
+                    self.slide_last(Slide { to, time: length });
+                }
+            }
+            start += length;
+        }
+        // A legato overlap past the last note doesn't lengthen the sequence
+        self.length = length_before.max(start);
+        start
+    }
+
+    /// Slide the most recently added note
+    pub fn slide_last(&mut self, slide: Slide) {
+        if let Some(note) = self.notes.last_mut() {
+            note.2 = Some(slide);
+        }
+    }
+
+    /// Hold `freq` from `at` for `gate`, scaling the preset for the note
+    pub fn note(&mut self, at: Duration, gate: Duration, freq: f32, preset: &FMParams) {
+        self.tone(at, gate, freq, preset.for_note(freq));
+    }
+
+    /// Hold `freq` with parameters already resolved for it
+    pub fn tone(&mut self, at: Duration, gate: Duration, freq: f32, params: FMParams) {
+        self.tail = self.tail.max(Duration::from_millis(render::tail_ms(&params)));
+        if self.effects.is_none() && !params.effects().is_dry() {
+            self.effects = Some(params.effects());
+        }
+        let note = self.notes.len();
+        self.notes.push((freq, params, None, self.output));
+        self.events.push(Event { at, note, on: true, once: false });
+        self.events.push(Event { at: at + gate, note, on: false, once: false });
+        self.length = self.length.max(at + gate);
+    }
+
+    /// Add silence at the end of the sequence
+    pub fn rest(&mut self, duration: Duration) {
+        self.length += duration;
+    }
+
+    /// Time from the start to the end of the last note or rest
+    pub fn length(&self) -> Duration {
+        self.length
+    }
+}
+
+/// The engine a sequence plays on
+enum Engine {
+    Poly(PolySynth),
+    Mono(Box<MonoSynth>),
+}
+
+impl Engine {
+    fn note_on_with(&mut self, freq: f32, params: FMParams) {
+        match self {
+            Engine::Poly(synth) => synth.note_on_with(freq, params),
+            Engine::Mono(synth) => synth.note_on_with(freq, params),
+        }
+    }
+
+    fn note_off(&mut self, freq: f32) {
+        match self {
+            Engine::Poly(synth) => synth.note_off(freq),
+            Engine::Mono(synth) => synth.note_off(freq),
+        }
+    }
+
+    fn slide(&mut self, freq: f32, to: f32, seconds: f32) {
+        match self {
+            Engine::Poly(synth) => synth.slide(freq, to, seconds),
+            Engine::Mono(synth) => synth.slide(freq, to, seconds),
+        }
+    }
+
+    fn set_tempo(&mut self, bpm: f32) {
+        match self {
+            Engine::Poly(synth) => synth.set_tempo(bpm),
+            Engine::Mono(synth) => synth.set_tempo(bpm),
+        }
+    }
+
+    fn set_input_level(&mut self, level: f32) {
+        match self {
+            Engine::Poly(synth) => synth.set_input_level(level),
+            Engine::Mono(synth) => synth.set_input_level(level),
+        }
+    }
+
+    fn set_external(&mut self, sample: f32) {
+        match self {
+            Engine::Poly(synth) => synth.set_external(sample),
+            Engine::Mono(synth) => synth.set_external(sample),
+        }
+    }
+
+    fn all_notes_off(&mut self) {
+        match self {
+            Engine::Poly(synth) => synth.all_notes_off(),
+            Engine::Mono(synth) => synth.all_notes_off(),
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        match self {
+            Engine::Poly(synth) => synth.is_idle(),
+            Engine::Mono(synth) => synth.is_idle(),
+        }
+    }
+
+    /// Voices sounding, and the most that can
+    fn voices(&self) -> (usize, usize) {
+        match self {
+            Engine::Poly(synth) => (synth.active_voices(), synth.max_voices()),
+            Engine::Mono(synth) => (usize::from(!synth.is_idle()), 1),
+        }
+    }
+
+    fn sample_rate(&self) -> f32 {
+        match self {
+            Engine::Poly(synth) => synth.sample_rate(),
+            Engine::Mono(synth) => synth.sample_rate(),
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        match self {
+            Engine::Poly(synth) => synth.next_sample(),
+            Engine::Mono(synth) => synth.next_sample(),
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        match self {
+            Engine::Poly(synth) => synth.set_sample_rate(sample_rate),
+            Engine::Mono(synth) => synth.set_sample_rate(sample_rate),
+        }
+    }
+}
+
+/// Plays a `Sequence` on a `PolySynth` (or a `MonoSynth` for mono sequences), timed by the samples it renders.
+///
+/// The score position advances by `speed` seconds per second of output (times
+/// the tempo map's speed, if any), so note lengths are exact to the sample,
+/// the tempo can change mid-sequence, and live playback and offline renders
+/// produce the same notes.
+pub struct Sequencer {
+    /// An engine for each output of the sequence
+    synths: Vec<Engine>,
+    sequence: Sequence,
+    /// Index of the next event to fire
+    next: usize,
+    /// Samples rendered (while not paused) since the last rebase
+    samples: u64,
+    /// Score position, in seconds, at the last rebase
+    base: f64,
+    speed: f32,
+    /// Speed set by the tempo map at the current block; 1.0 without one
+    tempo: f32,
+    paused: bool,
+    looping: bool,
+    /// Master bus effects for each output, if the sequence has any
+    effects: Vec<EffectChain>,
+    /// Score position at which every voice had gone idle past the last
+    /// note, for `has_ended`
+    quiet_since: Option<f64>,
+    /// The drum engine, if the sequence has a pattern
+    drums: Option<Drums>,
+    /// Pattern step (counting from the start) last played, so each plays
+    /// once
+    drum_step: Option<u64>,
+}
+
+impl Sequencer {
+    pub fn new(sample_rate: f32, mut sequence: Sequence, looping: bool) -> Self {
+        // Stable, so simultaneous events keep the order they were added in
+        sequence.events.sort_by_key(|e| e.at);
+        let outputs = sequence.outputs();
+        let sine_table = sequence.sine_table.then(|| Arc::new(SineTable::default()));
+        let synths = (0..outputs)
+            .map(|_| match sequence.mono {
+                Some(mono) => {
+                    let mut synth = MonoSynth::new(sample_rate);
+                    synth.set_legato(mono.legato);
+                    synth.set_glide(mono.glide.as_secs_f32());
+                    synth.set_priority(mono.priority);
+                    synth.set_sine_table(sine_table.clone());
+                    Engine::Mono(Box::new(synth))
+                }
+                None => {
+                    let mut synth = PolySynth::new(sample_rate, sequence.voices.unwrap_or(render::MELODY_VOICES));
+                    synth.set_sine_table(sine_table.clone());
+                    Engine::Poly(synth)
+                }
+            })
+            .collect();
+        let effects = sequence.effects
+            .filter(|_| !sequence.dry)
+            .map(|settings| (0..outputs).map(|_| EffectChain::new(sample_rate, settings)).collect())
+            .unwrap_or_default();
+        let drums = sequence.drums.as_ref().map(|_| Drums::new(sample_rate));
+        Self {
+            effects,
+            synths,
+            drums,
+            drum_step: None,
+            sequence,
+            next: 0,
+            samples: 0,
+            base: 0.0,
+            speed: 1.0,
+            tempo: 1.0,
+            paused: false,
+            looping,
+            quiet_since: None,
+        }
+    }
+
+    /// Score seconds per second of output
+    fn rate(&self) -> f32 {
+        self.speed * self.tempo
+    }
+
+    /// Current score position in seconds
+    fn score(&self) -> f64 {
+        self.base + self.samples as f64 * self.rate() as f64 / self.synths[0].sample_rate() as f64
+    }
+
+    /// Follow the tempo map to the current position
+    fn update_tempo(&mut self) {
+        if let Some(tempo) = self.sequence.tempo_map.as_ref().map(|map| map.speed_at(self.score())) {
+            if tempo != self.tempo {
+                self.rebase();
+                self.tempo = tempo;
+                self.sync_tempo();
+            }
+        }
+    }
+
+    /// Tell the engines the playing tempo, for tempo-synced modulation
+    fn sync_tempo(&mut self) {
+        let bpm = synth_data::MELODY_BPM * self.rate();
+        self.synths.iter_mut().for_each(|synth| synth.set_tempo(bpm));
+    }
+
+    fn all_notes_off(&mut self) {
+        self.synths.iter_mut().for_each(Engine::all_notes_off);
+    }
+
+    /// Fold the rendered samples into the base position, before anything
+    /// that changes how samples map to score time
+    fn rebase(&mut self) {
+        self.base = self.score();
+        self.samples = 0;
+    }
+
+    /// Hold the score position and release the sounding notes
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.all_notes_off();
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Jump to `position`, releasing the sounding notes
+    pub fn seek(&mut self, position: Duration) {
+        let position = position.min(self.sequence.length);
+        self.all_notes_off();
+        self.base = position.as_secs_f64();
+        self.samples = 0;
+        self.next = self.sequence.events.partition_point(|e| e.at < position);
+        // A step already under way when seeking into it is skipped
+        self.drum_step = self.sequence.drums.as_ref().and_then(|&(_, _, step)| {
+            let steps = position.as_secs_f64() / step.as_secs_f64();
+            (steps.fract() > 1e-9).then_some(steps as u64)
+        });
+        self.update_tempo();
+    }
+
+    /// Play faster (`speed` > 1) or slower from the current position on
+    pub fn set_speed(&mut self, speed: f32) {
+        self.rebase();
+        self.speed = speed;
+        self.sync_tempo();
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Speed the tempo map sets at the current position, on top of `speed`
+    /// (1.0 without a map)
+    pub fn tempo(&self) -> f32 {
+        self.tempo
+    }
+
+    /// Current position within the sequence
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.score()).min(self.sequence.length)
+    }
+
+    pub fn length(&self) -> Duration {
+        self.sequence.length
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Play `take` from the current position, on top of the sequence. Its
+    /// times are real time, so notes keep their length at any speed. In a
+    /// looping sequence, notes running past the end wrap to the start. With
+    /// `keep`, the notes become part of the sequence and sound again on
+    /// every loop pass (overdubbing); otherwise they play once.
+    pub fn overdub(&mut self, mut take: Sequence, keep: bool) {
+        take.events.sort_by_key(|e| e.at);
+        let length = self.sequence.length;
+        let now = self.score();
+        let wrap = self.looping && !length.is_zero();
+        // A loop that has reached its end but not yet wrapped starts over
+        // on the next sample, so the take starts with it
+        let pending_wrap = wrap && now >= length.as_secs_f64();
+        let start = if wrap { now % length.as_secs_f64() } else { now };
+        let start = Duration::from_secs_f64(start);
+
+        let first = self.sequence.notes.len();
+        self.sequence.notes.extend(take.notes);
+        for event in take.events {
+            // Held no longer than one pass, so a note's end never passes its start
+            let offset = if wrap { event.at.min(length.saturating_sub(Duration::from_millis(1))) } else { event.at };
+            let mut at = start + offset.mul_f32(self.rate());
+            if wrap && at >= length {
+                at -= length;
+            }
+            let event = Event { at, note: first + event.note, on: event.on, once: !keep };
+
+            // Notes starting now go at the head of the queue to fire on the
+            // next sample; the rest wait their turn, and those behind the
+            // playhead (wrapped round) join the fired events until the next pass
+            let now_event = offset.is_zero() && !pending_wrap;
+            let index = if now_event {
+                self.next
+            } else {
+                self.sequence.events.partition_point(|e| e.at <= at)
+            };
+            if !now_event && (pending_wrap || at < start) {
+                self.next += 1;
+            }
+            self.sequence.events.insert(index, event);
+        }
+        if !wrap {
+            self.sequence.length = self.sequence.length.max(Duration::from_secs_f64(now) + take.length);
+        }
+    }
+
+    /// Add `take` at the end of the sequence, to play once, for sequences
+    /// that grow while they play (`jam`). Unlike `overdub`, its times are
+    /// score times from the current end, so it follows on without a gap
+    /// whenever it's added before the end is reached. Outputs it uses that
+    /// the sequence started without stay silent.
+    pub fn extend(&mut self, mut take: Sequence) {
+        take.events.sort_by_key(|e| e.at);
+        let start = self.sequence.length;
+        // Fired notes are never played again, so keep only those still to come
+        self.compact();
+        let first = self.sequence.notes.len();
+        self.sequence.notes.extend(take.notes);
+        for event in take.events {
+            let event = Event { at: start + event.at, note: first + event.note, on: event.on, once: true };
+            // Anything already due (added late) fires on the next sample
+            let index = self.sequence.events.partition_point(|e| e.at <= event.at).max(self.next);
+            self.sequence.events.insert(index, event);
+        }
+        self.sequence.length = start + take.length;
+        self.sequence.tail = self.sequence.tail.max(take.tail);
+        self.quiet_since = None;
+    }
+
+    /// Drop the notes no event refers to any more
+    fn compact(&mut self) {
+        let mut used = vec![false; self.sequence.notes.len()];
+        for event in &self.sequence.events {
+            used[event.note] = true;
+        }
+        // New index of each kept note
+        let remap: Vec<usize> = used.iter()
+            .scan(0, |kept, &used| {
+                let index = *kept;
+                *kept += used as usize;
+                Some(index)
+            })
+            .collect();
+        let mut used = used.into_iter();
+        self.sequence.notes.retain(|_| used.next().unwrap_or(false));
+        for event in &mut self.sequence.events {
+            event.note = remap[event.note];
+        }
+    }
+
+    /// Remove every note, releasing those sounding; the length, position,
+    /// and looping carry on
+    pub fn clear(&mut self) {
+        self.all_notes_off();
+        self.sequence.events.clear();
+        self.sequence.notes.clear();
+        self.next = 0;
+    }
+
+    /// Voices sounding and the most that can, over every output's engine.
+    /// A monophonic engine counts as one voice.
+    pub fn voices(&self) -> (usize, usize) {
+        self.synths.iter().map(Engine::voices).fold((0, 0), |(active, max), (a, m)| (active + a, max + m))
+    }
+
+    /// Resize each output's polyphonic engine to `voices` (see
+    /// `PolySynth::set_max_voices`); monophonic engines are unchanged
+    pub fn set_max_voices(&mut self, voices: usize) {
+        self.sequence.set_voices(voices);
+        for synth in &mut self.synths {
+            if let Engine::Poly(synth) = synth {
+                synth.set_max_voices(voices);
+            }
+        }
+    }
+
+    /// True once every voice has finished its release
+    pub fn is_idle(&self) -> bool {
+        self.synths.iter().all(Engine::is_idle) && self.drums.as_ref().is_none_or(Drums::is_idle)
+    }
+
+    /// Whether live playback has ended: past the last note with every voice
+    /// idle, and any reverb tail rung out after them. Unlike `is_finished`
+    /// it follows the voices themselves rather than an estimate of their
+    /// release.
+    pub fn has_ended(&self) -> bool {
+        let ringing = self.effects.first().map_or(Duration::ZERO, EffectChain::tail);
+        !self.looping && self.quiet_since.is_some_and(|at| self.score() >= at + ringing.as_secs_f64())
+    }
+
+    /// Note when playback past the last note goes quiet, or stops being so
+    /// (after a seek or an overdub)
+    fn update_quiet(&mut self) {
+        let score = self.score();
+        let quiet = score >= self.sequence.length.as_secs_f64() && self.is_idle();
+        self.quiet_since = if quiet { self.quiet_since.or(Some(score)) } else { None };
+    }
+
+    /// Score position where the last note, its release, and any reverb
+    /// tail have played, going by the release's length
+    pub fn end(&self) -> Duration {
+        let ringing = self.effects.first().map_or(Duration::ZERO, EffectChain::tail);
+        self.sequence.length + self.sequence.tail + ringing
+    }
+
+    /// Whether playback has reached `end`, so renders end at a set time
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.score() >= self.end().as_secs_f64()
+    }
+
+    /// How far playback has got toward `end` (0.0 - 1.0), in score time
+    pub fn progress(&self) -> f32 {
+        let end = self.end().as_secs_f64();
+        if end <= 0.0 {
+            return 1.0;
+        }
+        (self.score() / end).clamp(0.0, 1.0) as f32
+    }
+
+    /// Fire every event due by the current sample; events land on the
+    /// nearest sample to their exact time
+    fn fire_events(&mut self) {
+        let now = self.score();
+        let due = now + 0.5 * self.rate() as f64 / self.synths[0].sample_rate() as f64;
+        while let Some(&event) = self.sequence.events.get(self.next) {
+            if event.at.as_secs_f64() > due {
+                break;
+            }
+            let (freq, params, slide, output) = &self.sequence.notes[event.note];
+            // Overdubbed notes may name an output the sequence didn't start with
+            let rate = self.rate();
+            let last = self.synths.len() - 1;
+            let synth = &mut self.synths[(*output).min(last)];
+            if event.on {
+                synth.note_on_with(*freq, params.clone());
+                if let Some(slide) = slide {
+                    // Slides keep their length in score time, like the notes
+                    synth.slide(*freq, slide.to, slide.time.as_secs_f32() / rate);
+                }
+            } else {
+                synth.note_off(*freq);
+            }
+            if event.once {
+                self.sequence.events.remove(self.next);
+            } else {
+                self.next += 1;
+            }
+        }
+
+        let length = self.sequence.length.as_secs_f64();
+        if self.looping && self.next == self.sequence.events.len() && now >= length {
+            self.rebase();
+            self.base -= length;
+            self.next = 0;
+        }
+    }
+
+    /// Move the clock on by a sample, firing the events due
+    fn advance(&mut self) {
+        if !self.paused {
+            if self.samples.is_multiple_of(TEMPO_BLOCK) {
+                self.update_tempo();
+                self.update_quiet();
+            }
+            self.fire_events();
+            self.fire_drums();
+            self.samples += 1;
+        }
+    }
+
+    /// Strike the drums of the pattern step starting at the current
+    /// sample, landing on the nearest sample as events do
+    fn fire_drums(&mut self) {
+        let now = self.score();
+        if now >= self.sequence.length.as_secs_f64() {
+            return;
+        }
+        let due = now + 0.5 * self.rate() as f64 / self.synths[0].sample_rate() as f64;
+        let (Some(drums), Some((pattern, _, step_length))) = (&mut self.drums, &self.sequence.drums) else {
+            return;
+        };
+        let step = (due / step_length.as_secs_f64()) as u64;
+        if self.drum_step != Some(step) {
+            self.drum_step = Some(step);
+            for (kind, velocity) in pattern.hits(step as usize) {
+                drums.hit(kind, velocity);
+            }
+        }
+    }
+
+    /// The drums' next sample, silence without a pattern
+    fn drum_sample(&mut self) -> f32 {
+        self.drums.as_mut().map_or(0.0, Drums::next_sample)
+    }
+}
+
+impl SampleSource for Sequencer {
+    /// Every output mixed, dry
+    fn next_sample(&mut self) -> f32 {
+        self.advance();
+        self.synths.iter_mut().map(Engine::next_sample).sum::<f32>() + self.drum_sample()
+    }
+
+    /// Every output mixed, each through its own effects
+    fn next_frame(&mut self) -> [f32; 2] {
+        self.next_outputs().iter().fold([0.0; 2], |[l, r], [left, right]| [l + left, r + right])
+    }
+
+    fn next_outputs(&mut self) -> [[f32; 2]; MAX_OUTPUTS] {
+        self.advance();
+        let mut outputs = [[0.0; 2]; MAX_OUTPUTS];
+        for (i, (synth, out)) in self.synths.iter_mut().zip(&mut outputs).enumerate() {
+            let sample = synth.next_sample();
+            *out = match self.effects.get_mut(i) {
+                Some(effects) => effects.process(sample),
+                None => [sample, sample],
+            };
+        }
+        // Drums play dry, past the output's effects
+        if let Some(&(_, output, _)) = self.sequence.drums.as_ref() {
+            let drums = self.drum_sample();
+            outputs[output].iter_mut().for_each(|out| *out += drums);
+        }
+        outputs
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.rebase();
+        self.synths.iter_mut().for_each(|synth| synth.set_sample_rate(sample_rate));
+        if let Some(drums) = &mut self.drums {
+            drums.set_sample_rate(sample_rate);
+        }
+        self.effects.iter_mut().for_each(|effects| effects.set_sample_rate(sample_rate));
+    }
+
+    fn set_input_level(&mut self, level: f32) {
+        self.synths.iter_mut().for_each(|synth| synth.set_input_level(level));
+    }
+
+    fn set_external(&mut self, sample: f32) {
+        self.synths.iter_mut().for_each(|synth| synth.set_external(sample));
+    }
+}
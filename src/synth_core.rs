@@ -1,12 +1,211 @@
 use std::f32::consts::PI;
+use std::fmt;
+use std::sync::Arc;
 
-/// FM Synthesizer parameters
-#[derive(Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+use crate::effects::{EffectSettings, MAX_STEREO_DELAY, MAX_STEREO_WIDTH};
+use crate::lfo::{Lfo, LfoShape, MAX_LFO_RATE};
+use crate::operator::{RateLevel, RateLevelState, MAX_RATE_LEVEL};
+use crate::rng::Rng;
+use crate::synth_data::{DEFAULT_GATE, LEGATO_GATE, MELODY_BPM, MIN_GATE};
+
+/// Highest modulation index accepted by `FMParams::validate`
+pub const MAX_MODULATION_INDEX: f32 = 20.0;
+
+/// Highest carrier or modulator frequency accepted by `FMParams::validate`
+pub const MAX_FREQ: f32 = 20_000.0;
+
+/// Highest key scaling amount accepted by `FMParams::validate`
+pub const MAX_KEY_SCALING: f32 = 2.0;
+
+/// Longest attack, decay, or release time accepted by `FMParams::validate` (seconds)
+pub const MAX_ENVELOPE_TIME: f32 = 30.0;
+
+/// Deepest LFO pitch modulation accepted by `FMParams::validate` (cents)
+pub const MAX_LFO_PITCH: f32 = 1200.0;
+
+/// Pitch wander of a voice at full `drift` (cents either way)
+pub const MAX_DRIFT_CENTS: f32 = 10.0;
+
+/// Level wander of a voice at full `drift` (fraction either way, about 1 dB)
+const DRIFT_LEVEL: f32 = 0.12;
+
+/// Rates of the smoothed noise behind drift (Hz); unrelated so pitch and
+/// level don't move together
+const DRIFT_PITCH_RATE: f32 = 0.37;
+const DRIFT_LEVEL_RATE: f32 = 0.23;
+
+/// Widest random detune `spread` gives a new note (cents either way)
+pub const MAX_SPREAD_CENTS: f32 = 50.0;
+
+/// Longest tempo-synced tremolo cycle, in beats
+pub const MAX_TREMOLO_BEATS: f32 = 16.0;
+
+/// How far full audio input raises the modulation index, at most
+/// (`input_index`; 1 doubles it)
+pub const MAX_INPUT_INDEX: f32 = 4.0;
+
+/// Time the input level takes to reach a voice, smoothing the steps
+/// between output buffers (seconds)
+const INPUT_SMOOTHING: f32 = 0.005;
+
+/// Amplitude boost of an accented melody note (about +3 dB)
+pub const ACCENT_GAIN: f32 = 1.4;
+
+/// Quietest preset `level_db` accepted by `FMParams::validate`; a level
+/// this low is silence
+pub const MIN_LEVEL_DB: f32 = -120.0;
+
+/// Fade applied to a sounding voice before it's stolen for a new note (seconds)
+pub const STEAL_FADE: f32 = 0.005;
+
+/// Note frequency the presets are defined at (A4 at concert pitch). Presets
+/// are scaled from it by frequency, so they follow `synth_data::set_a4`.
+pub const REFERENCE_FREQ: f32 = 440.0;
+
+/// Operator waveform
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    #[default]
+    Sine,
+    /// Band-limited square (polyBLEP)
+    Square,
+    /// Band-limited rising sawtooth (polyBLEP)
+    Saw,
+}
+
+impl Waveform {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "sine" | "sin" => Some(Waveform::Sine),
+            "square" | "sqr" => Some(Waveform::Square),
+            "saw" | "sawtooth" => Some(Waveform::Saw),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Waveform::Sine => "sine",
+            Waveform::Square => "square",
+            Waveform::Saw => "saw",
+        }
+    }
+
+    /// Fourier series up to `harmonics`, as the cosine (`real`) and sine
+    /// (`imag`) terms a WebAudio `PeriodicWave` takes; index 0 is DC. The
+    /// phase matches the native oscillator.
+    pub fn fourier_series(&self, harmonics: usize) -> (Vec<f32>, Vec<f32>) {
+        let real = vec![0.0; harmonics + 1];
+        let mut imag = vec![0.0; harmonics + 1];
+        for (k, term) in imag.iter_mut().enumerate().skip(1) {
+            let k = k as f32;
+            *term = match self {
+                Waveform::Sine if k == 1.0 => 1.0,
+                Waveform::Sine => 0.0,
+                Waveform::Square if k % 2.0 == 1.0 => 4.0 / (PI * k),
+                Waveform::Square => 0.0,
+                // Rising ramp from -1 at phase 0
+                Waveform::Saw => -2.0 / (PI * k),
+            };
+        }
+        (real, imag)
+    }
+}
+
+/// PolyBLEP residual for a discontinuity at phase 0, with `t` the phase in
+/// [0, 1) and `dt` the phase increment per sample
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let t = t / dt;
+        t + t - t * t - 1.0
+    } else if t > 1.0 - dt {
+        let t = (t - 1.0) / dt;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// FM Synthesizer parameters (missing fields deserialize to their defaults)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, remote = "Self")]
 pub struct FMParams {
     pub carrier_freq: f32,      // Carrier frequency in Hz
     pub modulator_freq: f32,    // Modulator frequency in Hz
     pub modulation_index: f32,  // Modulation depth
-    pub amplitude: f32,         // Output amplitude (0.0 - 1.0)
+    pub level_db: f32,          // Output level in dB (MIN_LEVEL_DB - 0.0)
+    pub key_scaling: f32,       // Index reduction per octave above A4 (0 = off)
+    pub key_scaling_low: bool,  // Also boost the index for notes below A4
+    pub phase_reset: bool,      // Restart oscillator phases at note-on (else free-running)
+    pub legato_retrigger: bool, // Retriggering a held note keeps its envelope going
+    pub carrier_wave: Waveform,   // Carrier waveform
+    pub modulator_wave: Waveform, // Modulator waveform
+    pub attack: f32,            // Envelope attack time in seconds
+    pub decay: f32,             // Envelope decay time in seconds
+    pub sustain: f32,           // Envelope sustain level (0.0 - 1.0)
+    pub release: f32,           // Envelope release time in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub carrier_env: Option<RateLevel>, // DX7 rate/level envelope for the carrier, in place of the ADSR
+    pub gate: f32,              // Fraction of its length an unmarked melody note is held (1 = legato)
+    pub lfo_shape: LfoShape,    // LFO waveform
+    pub lfo_rate: f32,          // LFO rate in Hz
+    pub lfo_pitch: f32,         // LFO pitch depth in cents (0 = off)
+    pub lfo_index: f32,         // LFO modulation index depth, as a fraction of the index (0 = off)
+    pub lfo_seed: u64,          // Seed for the random LFO shapes
+    pub index_env: bool,        // Shape the modulation index with its own envelope
+    pub index_attack: f32,      // Index envelope attack time in seconds
+    pub index_decay: f32,       // Index envelope decay time in seconds
+    pub index_sustain: f32,     // Index envelope sustain level (0.0 - 1.0)
+    pub index_release: f32,     // Index envelope release time in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modulator_env: Option<RateLevel>, // DX7 rate/level envelope scaling the index, in place of the index envelope
+    pub velocity_index: f32,    // How much softer playing lowers the modulation index (0 = off, 1 = in proportion)
+    pub sub_level: f32,         // Sub-oscillator level relative to the carrier (0 = off)
+    pub sub_octave: u8,         // Sub-oscillator octaves below the carrier (1 or 2)
+    pub sub_wave: Waveform,     // Sub-oscillator waveform
+    pub stereo_delay: f32,      // Master bus Haas delay in ms for the right channel (0 = off)
+    pub stereo_width: f32,      // Master bus stereo width (0 = mono, 1 = as delayed, 2 = extra wide)
+    pub reverb_mix: f32,        // Master bus reverb level (0 = dry)
+    pub reverb_size: f32,       // Master bus reverb room size (0.0 - 1.0)
+    pub drift: f32,             // Slow random pitch and level wander per voice (0 = off, 1 = analog-unstable)
+    pub spread: f32,            // Random detune of each new note in cents, either way (0 = off)
+    pub spread_seed: u64,       // Seed for the spread's detunes
+    pub vibrato_rate: f32,      // Vibrato rate in Hz
+    pub vibrato_depth: f32,     // Vibrato depth in cents (0 = off)
+    pub vibrato_delay: f32,     // Seconds a note is held straight before vibrato starts
+    pub vibrato_fade: f32,      // Seconds vibrato takes to fade in to full depth
+    pub tremolo_rate: f32,      // Tremolo rate in Hz, when not synced
+    pub tremolo_depth: f32,     // How far tremolo dips the level (0 = off, 1 = to silence)
+    pub tremolo_beats: f32,     // Tremolo cycle in beats of the playing tempo (0 = free at tremolo_rate)
+    pub input_duck: f32,        // How far the audio input's level ducks the voice (0 = off, 1 = to silence)
+    pub input_index: f32,       // How far the audio input's level raises the modulation index (1 doubles it)
+    pub ext_index: f32,         // Modulation index of the external modulator (audio input or WAV file; 0 = off)
+}
+
+impl Serialize for FMParams {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        FMParams::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FMParams {
+    /// Presets saved before `level_db` carry a linear `amplitude` instead
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Stored {
+            amplitude: Option<f32>,
+            #[serde(flatten, with = "FMParams")]
+            params: FMParams,
+        }
+        let Stored { amplitude, mut params } = Stored::deserialize(deserializer)?;
+        if let Some(amplitude) = amplitude {
+            params.set_gain(amplitude);
+        }
+        Ok(params)
+    }
 }
 
 impl Default for FMParams {
@@ -15,9 +214,451 @@ impl Default for FMParams {
             carrier_freq: 440.0,
             modulator_freq: 220.0,
             modulation_index: 2.0,
-            amplitude: 0.3,
+            level_db: -10.5,
+            key_scaling: 0.0,
+            key_scaling_low: false,
+            phase_reset: false,
+            legato_retrigger: false,
+            carrier_wave: Waveform::Sine,
+            modulator_wave: Waveform::Sine,
+            attack: 0.01,
+            decay: 0.1,
+            sustain: 0.7,
+            release: 0.5,
+            carrier_env: None,
+            gate: DEFAULT_GATE,
+            lfo_shape: LfoShape::Sine,
+            lfo_rate: 5.0,
+            lfo_pitch: 0.0,
+            lfo_index: 0.0,
+            lfo_seed: 1,
+            index_env: false,
+            index_attack: 0.0,
+            index_decay: 0.5,
+            index_sustain: 0.3,
+            index_release: 0.5,
+            modulator_env: None,
+            velocity_index: 0.0,
+            sub_level: 0.0,
+            sub_octave: 1,
+            sub_wave: Waveform::Sine,
+            stereo_delay: 0.0,
+            stereo_width: 1.0,
+            reverb_mix: 0.0,
+            reverb_size: 0.5,
+            drift: 0.0,
+            spread: 0.0,
+            spread_seed: 1,
+            vibrato_rate: 5.5,
+            vibrato_depth: 0.0,
+            vibrato_delay: 0.0,
+            vibrato_fade: 0.0,
+            tremolo_rate: 5.0,
+            tremolo_depth: 0.0,
+            tremolo_beats: 0.0,
+            input_duck: 0.0,
+            input_index: 0.0,
+            ext_index: 0.0,
+        }
+    }
+}
+
+/// Reason an `FMParams` value is outside the supported range
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamError {
+    /// A parameter is NaN or infinite
+    NotFinite(&'static str),
+    /// A frequency is negative or above `MAX_FREQ`
+    FrequencyOutOfRange(&'static str, f32),
+    /// Modulation index is negative or above `MAX_MODULATION_INDEX`
+    IndexOutOfRange(f32),
+    /// Output level is below `MIN_LEVEL_DB` or above 0 dB
+    LevelOutOfRange(f32),
+    /// Key scaling is negative or above `MAX_KEY_SCALING`
+    KeyScalingOutOfRange(f32),
+    /// An envelope time is negative or above `MAX_ENVELOPE_TIME`
+    EnvelopeTimeOutOfRange(&'static str, f32),
+    /// Sustain level is outside 0.0 - 1.0
+    SustainOutOfRange(f32),
+    /// Velocity to index depth is outside 0.0 - 1.0
+    VelocityIndexOutOfRange(f32),
+    /// Sub-oscillator level is outside 0.0 - 1.0
+    SubLevelOutOfRange(f32),
+    /// Sub-oscillator is not 1 or 2 octaves down
+    SubOctaveOutOfRange(u8),
+    /// Stereo delay or width is negative or above its maximum
+    StereoOutOfRange(&'static str, f32, f32),
+    /// An LFO, vibrato, or tremolo setting is negative or above its maximum
+    LfoOutOfRange(&'static str, f32, f32),
+    /// Any other setting is outside `min` - `max`
+    OutOfRange { name: &'static str, value: f32, min: f32, max: f32 },
+    /// Drift amount is outside 0.0 - 1.0
+    DriftOutOfRange(f32),
+    /// Note gate is outside `MIN_GATE` - 1.0
+    GateOutOfRange(f32),
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamError::NotFinite(name) => write!(f, "{} must be a finite number", name),
+            ParamError::FrequencyOutOfRange(name, value) => {
+                write!(f, "{} {} Hz is outside 0 - {} Hz", name, value, MAX_FREQ)
+            }
+            ParamError::IndexOutOfRange(value) => {
+                write!(f, "modulation index {} is outside 0 - {}", value, MAX_MODULATION_INDEX)
+            }
+            ParamError::LevelOutOfRange(value) => write!(f, "level {} dB is outside {} - 0 dB", value, MIN_LEVEL_DB),
+            ParamError::KeyScalingOutOfRange(value) => {
+                write!(f, "key scaling {} is outside 0 - {}", value, MAX_KEY_SCALING)
+            }
+            ParamError::EnvelopeTimeOutOfRange(name, value) => {
+                write!(f, "{} {} s is outside 0 - {} s", name, value, MAX_ENVELOPE_TIME)
+            }
+            ParamError::SustainOutOfRange(value) => write!(f, "sustain {} is outside 0.0 - 1.0", value),
+            ParamError::VelocityIndexOutOfRange(value) => {
+                write!(f, "velocity index {} is outside 0.0 - 1.0", value)
+            }
+            ParamError::SubLevelOutOfRange(value) => write!(f, "sub-oscillator level {} is outside 0.0 - 1.0", value),
+            ParamError::SubOctaveOutOfRange(value) => {
+                write!(f, "sub-oscillator octave {} must be 1 or 2", value)
+            }
+            ParamError::StereoOutOfRange(name, value, max) => write!(f, "{} {} is outside 0 - {}", name, value, max),
+            ParamError::LfoOutOfRange(name, value, max) => write!(f, "{} {} is outside 0 - {}", name, value, max),
+            ParamError::OutOfRange { name, value, min, max } => {
+                write!(f, "{} {} is outside {} - {}", name, value, min, max)
+            }
+            ParamError::DriftOutOfRange(value) => write!(f, "drift {} is outside 0.0 - 1.0", value),
+            ParamError::GateOutOfRange(value) => write!(f, "gate {} is outside {} - {}", value, MIN_GATE, LEGATO_GATE),
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+impl FMParams {
+    /// Check every parameter is within its supported range
+    pub fn validate(&self) -> Result<(), ParamError> {
+        let fields = [
+            ("carrier frequency", self.carrier_freq),
+            ("modulator frequency", self.modulator_freq),
+            ("modulation index", self.modulation_index),
+            ("level", self.level_db),
+            ("key scaling", self.key_scaling),
+            ("attack", self.attack),
+            ("decay", self.decay),
+            ("release", self.release),
+            ("sustain", self.sustain),
+        ];
+        for (name, value) in fields {
+            if !value.is_finite() {
+                return Err(ParamError::NotFinite(name));
+            }
+        }
+        for (name, value) in &fields[..2] {
+            if !(0.0..=MAX_FREQ).contains(value) {
+                return Err(ParamError::FrequencyOutOfRange(name, *value));
+            }
+        }
+        if !(0.0..=MAX_MODULATION_INDEX).contains(&self.modulation_index) {
+            return Err(ParamError::IndexOutOfRange(self.modulation_index));
+        }
+        if !(MIN_LEVEL_DB..=0.0).contains(&self.level_db) {
+            return Err(ParamError::LevelOutOfRange(self.level_db));
+        }
+        if !(0.0..=MAX_KEY_SCALING).contains(&self.key_scaling) {
+            return Err(ParamError::KeyScalingOutOfRange(self.key_scaling));
+        }
+        for (name, value) in &fields[5..8] {
+            if !(0.0..=MAX_ENVELOPE_TIME).contains(value) {
+                return Err(ParamError::EnvelopeTimeOutOfRange(name, *value));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.sustain) {
+            return Err(ParamError::SustainOutOfRange(self.sustain));
+        }
+        let index_times = [
+            ("index attack", self.index_attack),
+            ("index decay", self.index_decay),
+            ("index release", self.index_release),
+        ];
+        for (name, value) in index_times {
+            if !value.is_finite() {
+                return Err(ParamError::NotFinite(name));
+            }
+            if !(0.0..=MAX_ENVELOPE_TIME).contains(&value) {
+                return Err(ParamError::EnvelopeTimeOutOfRange(name, value));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.index_sustain) {
+            return Err(ParamError::SustainOutOfRange(self.index_sustain));
+        }
+        for (name, value) in [("vibrato delay", self.vibrato_delay), ("vibrato fade", self.vibrato_fade)] {
+            if !value.is_finite() {
+                return Err(ParamError::NotFinite(name));
+            }
+            if !(0.0..=MAX_ENVELOPE_TIME).contains(&value) {
+                return Err(ParamError::EnvelopeTimeOutOfRange(name, value));
+            }
+        }
+        if !self.velocity_index.is_finite() {
+            return Err(ParamError::NotFinite("velocity index"));
+        }
+        if !(0.0..=1.0).contains(&self.velocity_index) {
+            return Err(ParamError::VelocityIndexOutOfRange(self.velocity_index));
+        }
+        if !self.sub_level.is_finite() {
+            return Err(ParamError::NotFinite("sub-oscillator level"));
+        }
+        if !(0.0..=1.0).contains(&self.sub_level) {
+            return Err(ParamError::SubLevelOutOfRange(self.sub_level));
+        }
+        if !(1..=2).contains(&self.sub_octave) {
+            return Err(ParamError::SubOctaveOutOfRange(self.sub_octave));
+        }
+        let stereo = [
+            ("stereo delay", self.stereo_delay, MAX_STEREO_DELAY),
+            ("stereo width", self.stereo_width, MAX_STEREO_WIDTH),
+        ];
+        for (name, value, max) in stereo {
+            if !value.is_finite() {
+                return Err(ParamError::NotFinite(name));
+            }
+            if !(0.0..=max).contains(&value) {
+                return Err(ParamError::StereoOutOfRange(name, value, max));
+            }
+        }
+        let lfo = [
+            ("LFO rate", self.lfo_rate, MAX_LFO_RATE),
+            ("LFO pitch depth", self.lfo_pitch, MAX_LFO_PITCH),
+            ("LFO index depth", self.lfo_index, 1.0),
+            ("vibrato rate", self.vibrato_rate, MAX_LFO_RATE),
+            ("vibrato depth", self.vibrato_depth, MAX_LFO_PITCH),
+            ("tremolo rate", self.tremolo_rate, MAX_LFO_RATE),
+            ("tremolo depth", self.tremolo_depth, 1.0),
+            ("tremolo beats", self.tremolo_beats, MAX_TREMOLO_BEATS),
+        ];
+        for (name, value, max) in lfo {
+            if !value.is_finite() {
+                return Err(ParamError::NotFinite(name));
+            }
+            if !(0.0..=max).contains(&value) {
+                return Err(ParamError::LfoOutOfRange(name, value, max));
+            }
+        }
+        let others = [
+            ("reverb mix", self.reverb_mix, 1.0),
+            ("reverb size", self.reverb_size, 1.0),
+            ("analog spread", self.spread, MAX_SPREAD_CENTS),
+            ("input duck", self.input_duck, 1.0),
+            ("input index depth", self.input_index, MAX_INPUT_INDEX),
+            ("external modulation index", self.ext_index, MAX_MODULATION_INDEX),
+        ];
+        for (name, value, max) in others {
+            if !value.is_finite() {
+                return Err(ParamError::NotFinite(name));
+            }
+            if !(0.0..=max).contains(&value) {
+                return Err(ParamError::OutOfRange { name, value, min: 0.0, max });
+            }
+        }
+        if !self.drift.is_finite() {
+            return Err(ParamError::NotFinite("drift"));
+        }
+        if !(0.0..=1.0).contains(&self.drift) {
+            return Err(ParamError::DriftOutOfRange(self.drift));
+        }
+        if !self.gate.is_finite() {
+            return Err(ParamError::NotFinite("gate"));
+        }
+        let rate_levels = [("carrier envelope", self.carrier_env), ("modulator envelope", self.modulator_env)];
+        for (name, envelope) in rate_levels {
+            let Some(envelope) = envelope else { continue };
+            if let Some(&value) = envelope.rates.iter().chain(&envelope.levels).find(|&&v| v > MAX_RATE_LEVEL) {
+                return Err(ParamError::OutOfRange { name, value: value as f32, min: 0.0, max: MAX_RATE_LEVEL as f32 });
+            }
+        }
+        if !(MIN_GATE..=LEGATO_GATE).contains(&self.gate) {
+            return Err(ParamError::GateOutOfRange(self.gate));
+        }
+        Ok(())
+    }
+
+    /// Copy of these parameters forced into the supported ranges.
+    /// Non-finite values fall back to the defaults.
+    pub fn clamped(&self) -> Self {
+        let defaults = Self::default();
+        let clamp = |value: f32, fallback: f32, max: f32| {
+            if value.is_finite() { value.clamp(0.0, max) } else { fallback }
+        };
+        Self {
+            carrier_freq: clamp(self.carrier_freq, defaults.carrier_freq, MAX_FREQ),
+            modulator_freq: clamp(self.modulator_freq, defaults.modulator_freq, MAX_FREQ),
+            modulation_index: clamp(self.modulation_index, defaults.modulation_index, MAX_MODULATION_INDEX),
+            level_db: if self.level_db.is_finite() { self.level_db.clamp(MIN_LEVEL_DB, 0.0) } else { defaults.level_db },
+            key_scaling: clamp(self.key_scaling, defaults.key_scaling, MAX_KEY_SCALING),
+            attack: clamp(self.attack, defaults.attack, MAX_ENVELOPE_TIME),
+            decay: clamp(self.decay, defaults.decay, MAX_ENVELOPE_TIME),
+            sustain: clamp(self.sustain, defaults.sustain, 1.0),
+            release: clamp(self.release, defaults.release, MAX_ENVELOPE_TIME),
+            gate: clamp(self.gate, defaults.gate, LEGATO_GATE).max(MIN_GATE),
+            lfo_rate: clamp(self.lfo_rate, defaults.lfo_rate, MAX_LFO_RATE),
+            lfo_pitch: clamp(self.lfo_pitch, defaults.lfo_pitch, MAX_LFO_PITCH),
+            lfo_index: clamp(self.lfo_index, defaults.lfo_index, 1.0),
+            index_attack: clamp(self.index_attack, defaults.index_attack, MAX_ENVELOPE_TIME),
+            index_decay: clamp(self.index_decay, defaults.index_decay, MAX_ENVELOPE_TIME),
+            index_sustain: clamp(self.index_sustain, defaults.index_sustain, 1.0),
+            index_release: clamp(self.index_release, defaults.index_release, MAX_ENVELOPE_TIME),
+            velocity_index: clamp(self.velocity_index, defaults.velocity_index, 1.0),
+            sub_level: clamp(self.sub_level, defaults.sub_level, 1.0),
+            sub_octave: self.sub_octave.clamp(1, 2),
+            stereo_delay: clamp(self.stereo_delay, defaults.stereo_delay, MAX_STEREO_DELAY),
+            stereo_width: clamp(self.stereo_width, defaults.stereo_width, MAX_STEREO_WIDTH),
+            reverb_mix: clamp(self.reverb_mix, defaults.reverb_mix, 1.0),
+            reverb_size: clamp(self.reverb_size, defaults.reverb_size, 1.0),
+            drift: clamp(self.drift, defaults.drift, 1.0),
+            spread: clamp(self.spread, defaults.spread, MAX_SPREAD_CENTS),
+            vibrato_rate: clamp(self.vibrato_rate, defaults.vibrato_rate, MAX_LFO_RATE),
+            vibrato_depth: clamp(self.vibrato_depth, defaults.vibrato_depth, MAX_LFO_PITCH),
+            vibrato_delay: clamp(self.vibrato_delay, defaults.vibrato_delay, MAX_ENVELOPE_TIME),
+            vibrato_fade: clamp(self.vibrato_fade, defaults.vibrato_fade, MAX_ENVELOPE_TIME),
+            tremolo_rate: clamp(self.tremolo_rate, defaults.tremolo_rate, MAX_LFO_RATE),
+            tremolo_depth: clamp(self.tremolo_depth, defaults.tremolo_depth, 1.0),
+            tremolo_beats: clamp(self.tremolo_beats, defaults.tremolo_beats, MAX_TREMOLO_BEATS),
+            input_duck: clamp(self.input_duck, defaults.input_duck, 1.0),
+            input_index: clamp(self.input_index, defaults.input_index, MAX_INPUT_INDEX),
+            ext_index: clamp(self.ext_index, defaults.ext_index, MAX_MODULATION_INDEX),
+            carrier_env: self.carrier_env.map(RateLevel::clamped),
+            modulator_env: self.modulator_env.map(RateLevel::clamped),
+            ..self.clone()
+        }
+    }
+
+    /// Envelope level (0.0 - 1.0) `t` seconds into a held note: the linear
+    /// attack and decay stages, then sustain
+    pub fn held_level(&self, t: f32) -> f32 {
+        if t < self.attack {
+            t / self.attack
+        } else if t < self.attack + self.decay {
+            1.0 - (1.0 - self.sustain) * (t - self.attack) / self.decay
+        } else {
+            self.sustain
+        }
+    }
+
+    /// The master bus effects this preset asks for
+    pub fn effects(&self) -> EffectSettings {
+        EffectSettings {
+            reverb_mix: self.reverb_mix,
+            reverb_size: self.reverb_size,
+            stereo_delay: self.stereo_delay,
+            stereo_width: self.stereo_width,
+        }
+    }
+
+    /// Parameters for a note played at `velocity` (0.0 - 1.0): softer notes
+    /// are quieter and, by the preset's `velocity_index`, less bright. Full
+    /// velocity leaves the preset unchanged.
+    pub fn for_velocity(&self, velocity: f32) -> Self {
+        let velocity = velocity.clamp(0.0, 1.0);
+        Self {
+            level_db: (self.level_db + gain_to_db(velocity)).max(MIN_LEVEL_DB),
+            modulation_index: self.modulation_index * (1.0 - self.velocity_index * (1.0 - velocity)),
+            ..self.clone()
+        }
+    }
+
+    /// Parameters for an accented note: louder by `ACCENT_GAIN` (up to full
+    /// level) and, by the preset's `velocity_index`, brighter
+    pub fn accented(&self) -> Self {
+        let boost = ACCENT_GAIN - 1.0;
+        Self {
+            level_db: (self.level_db + gain_to_db(ACCENT_GAIN)).min(0.0),
+            modulation_index: (self.modulation_index * (1.0 + self.velocity_index * boost)).min(MAX_MODULATION_INDEX),
+            ..self.clone()
         }
     }
+
+    /// `level_db` as a linear gain; `MIN_LEVEL_DB` and below are silent
+    pub fn gain(&self) -> f32 {
+        if self.level_db <= MIN_LEVEL_DB { 0.0 } else { db_to_gain(self.level_db) }
+    }
+
+    /// Set `level_db` from a linear gain, at most full scale
+    pub fn set_gain(&mut self, gain: f32) {
+        self.level_db = gain_to_db(gain).clamp(MIN_LEVEL_DB, 0.0);
+    }
+
+    /// Scale the level by a linear gain `factor`, at most full scale
+    pub fn scale_gain(&mut self, factor: f32) {
+        self.level_db = (self.level_db + gain_to_db(factor)).clamp(MIN_LEVEL_DB, 0.0);
+    }
+
+    /// Parameters for playing a note at `freq`: carrier and modulator are
+    /// scaled from `REFERENCE_FREQ` and the modulation index is key scaled
+    pub fn for_note(&self, freq: f32) -> Self {
+        let freq_ratio = freq / REFERENCE_FREQ;
+        let mut index_scale = freq_ratio.powf(-self.key_scaling);
+        if freq_ratio < 1.0 && !self.key_scaling_low {
+            index_scale = 1.0;
+        }
+        Self {
+            carrier_freq: self.carrier_freq * freq_ratio,
+            modulator_freq: self.modulator_freq * freq_ratio,
+            modulation_index: (self.modulation_index * index_scale).min(MAX_MODULATION_INDEX),
+            ..self.clone()
+        }
+    }
+}
+
+/// Default number of points in a `SineTable`
+pub const DEFAULT_SINE_TABLE_SIZE: usize = 4096;
+
+/// Interpolated sine lookup table, a cheaper alternative to `f32::sin`.
+///
+/// Larger tables are more accurate (linear interpolation error falls with the
+/// square of the size) at the cost of cache footprint. A table is immutable,
+/// so one `Arc<SineTable>` can be shared by every voice.
+pub struct SineTable {
+    size: usize,
+    mask: usize,
+    table: Vec<f32>,
+}
+
+impl SineTable {
+    /// Build a table; the size is rounded up to a power of two
+    pub fn new(size: usize) -> Self {
+        let size = size.max(4).next_power_of_two();
+        // One guard point so interpolation never needs to wrap
+        let table = (0..=size)
+            .map(|i| (2.0 * PI * i as f32 / size as f32).sin())
+            .collect();
+        Self { size, mask: size - 1, table }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Sine of a phase given in cycles (any value, wrapped to one period)
+    #[inline]
+    pub fn lookup(&self, phase: f32) -> f32 {
+        let pos = phase * self.size as f32;
+        // Truncate, then step down for negative positions to get the floor
+        let whole = pos as i32;
+        let whole = whole - (pos < whole as f32) as i32;
+        let frac = pos - whole as f32;
+        let index = whole as usize & self.mask;
+        let a = self.table[index];
+        let b = self.table[index + 1];
+        a + (b - a) * frac
+    }
+}
+
+impl Default for SineTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_SINE_TABLE_SIZE)
+    }
 }
 
 /// FM Synthesizer oscillator
@@ -25,7 +666,19 @@ pub struct FMOscillator {
     sample_rate: f32,
     carrier_phase: f32,
     modulator_phase: f32,
+    sub_phase: f32,
     params: FMParams,
+    /// `params.gain()`, worked out once when the parameters are set
+    gain: f32,
+    sine_table: Option<Arc<SineTable>>,
+    /// Frequency multiplier for glides and bends (1.0 = as set)
+    pitch: f32,
+    /// Frequency and modulation index multipliers from the LFO and the
+    /// index envelope
+    mod_pitch: f32,
+    mod_index: f32,
+    /// Current sample of the external modulator, for `ext_index`
+    external: f32,
 }
 
 impl FMOscillator {
@@ -34,32 +687,120 @@ impl FMOscillator {
             sample_rate,
             carrier_phase: 0.0,
             modulator_phase: 0.0,
+            sub_phase: 0.0,
+            gain: params.gain(),
             params,
+            sine_table: None,
+            pitch: 1.0,
+            mod_pitch: 1.0,
+            mod_index: 1.0,
+            external: 0.0,
+        }
+    }
+
+    /// Use a lookup table instead of `f32::sin` (faster, slightly less accurate),
+    /// or `None` for the exact path
+    pub fn set_sine_table(&mut self, table: Option<Arc<SineTable>>) {
+        self.sine_table = table;
+    }
+
+    #[inline]
+    fn sine(&self, phase: f32) -> f32 {
+        match &self.sine_table {
+            Some(table) => table.lookup(phase),
+            None => (2.0 * PI * phase).sin(),
+        }
+    }
+
+    /// One sample of `waveform` at `phase` (cycles), band-limited for a
+    /// phase increment of `dt` cycles per sample
+    #[inline]
+    fn wave(&self, waveform: Waveform, phase: f32, dt: f32) -> f32 {
+        // Wrapping a tiny negative phase can round up to exactly 1.0
+        let t = || phase - phase.floor();
+        let dt = dt.abs().min(0.5);
+        match waveform {
+            Waveform::Sine => self.sine(phase),
+            Waveform::Saw => {
+                let t = t();
+                2.0 * t - 1.0 - poly_blep(t, dt)
+            }
+            Waveform::Square => {
+                let t = t();
+                let naive = if t < 0.5 { 1.0 } else { -1.0 };
+                naive + poly_blep(t, dt) - poly_blep((t + 0.5).fract(), dt)
+            }
         }
     }
 
     pub fn next_sample(&mut self) -> f32 {
-        let modulator = (2.0 * PI * self.modulator_phase).sin();
-        let modulated_freq = self.params.carrier_freq * 
-            (1.0 + self.params.modulation_index * modulator);
-        let carrier = (2.0 * PI * self.carrier_phase).sin();
+        let carrier_freq = self.params.carrier_freq * self.pitch * self.mod_pitch;
+        let modulator_freq = self.params.modulator_freq * self.pitch * self.mod_pitch;
+        let modulation_index = self.params.modulation_index * self.mod_index;
+        let modulator = self.wave(
+            self.params.modulator_wave,
+            self.modulator_phase,
+            modulator_freq / self.sample_rate,
+        );
+        // The external signal modulates the carrier alongside the modulator,
+        // shaped by the same LFO and index envelope
+        let external = self.params.ext_index * self.mod_index * self.external;
+        let modulated_freq = carrier_freq * 
+            (1.0 + modulation_index * modulator + external);
+        let carrier = self.wave(self.params.carrier_wave, self.carrier_phase, modulated_freq / self.sample_rate);
         
         self.carrier_phase += modulated_freq / self.sample_rate;
-        self.modulator_phase += self.params.modulator_freq / self.sample_rate;
+        self.modulator_phase += modulator_freq / self.sample_rate;
         
-        if self.carrier_phase >= 1.0 {
-            self.carrier_phase -= 1.0;
-        }
-        if self.modulator_phase >= 1.0 {
-            self.modulator_phase -= 1.0;
+        // Deep or external modulation can push the carrier's frequency
+        // below zero, so wrap both ways to keep the phase in [0, 1)
+        self.carrier_phase -= self.carrier_phase.floor();
+        self.modulator_phase -= self.modulator_phase.floor();
+
+        let mut out = carrier;
+        if self.params.sub_level > 0.0 {
+            // Follows the unmodulated carrier pitch, an octave or two down
+            let dt = carrier_freq / (1 << self.params.sub_octave) as f32 / self.sample_rate;
+            out += self.params.sub_level * self.wave(self.params.sub_wave, self.sub_phase, dt);
+            self.sub_phase += dt;
+            self.sub_phase -= self.sub_phase.floor();
         }
         
-        carrier * self.params.amplitude
+        out * self.gain
     }
 
     pub fn set_params(&mut self, params: FMParams) {
+        self.gain = params.gain();
         self.params = params;
     }
+
+    /// Restart carrier and modulator at phase zero
+    pub fn reset_phase(&mut self) {
+        self.carrier_phase = 0.0;
+        self.modulator_phase = 0.0;
+        self.sub_phase = 0.0;
+    }
+
+    /// Scale both operator frequencies, e.g. for a glide
+    pub fn set_pitch(&mut self, ratio: f32) {
+        self.pitch = ratio;
+    }
+
+    /// Apply per-sample modulation: a frequency ratio and a modulation
+    /// index scale
+    pub fn set_modulation(&mut self, pitch: f32, index: f32) {
+        self.mod_pitch = pitch;
+        self.mod_index = index;
+    }
+
+    /// The external modulator's current sample (about -1.0 - 1.0)
+    pub fn set_external(&mut self, sample: f32) {
+        self.external = sample;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
 }
 
 /// ADSR Envelope state
@@ -70,6 +811,8 @@ pub enum EnvelopeState {
     Decay,
     Sustain,
     Release,
+    /// Quick fade from the current level before a stolen voice restarts
+    Damp,
 }
 
 /// ADSR Envelope generator
@@ -82,6 +825,8 @@ pub struct Envelope {
     state: EnvelopeState,
     level: f32,
     time: f32,
+    /// Level the release or `Damp` fade started from
+    fade_from: f32,
 }
 
 impl Envelope {
@@ -95,37 +840,91 @@ impl Envelope {
             state: EnvelopeState::Idle,
             level: 0.0,
             time: 0.0,
+            fade_from: 0.0,
         }
     }
 
+    /// Take the attack, decay, sustain, and release settings of a patch
+    pub fn set_adsr(&mut self, params: &FMParams) {
+        self.attack = params.attack;
+        self.decay = params.decay;
+        self.sustain = params.sustain;
+        self.release = params.release;
+    }
+
+    /// Open and close like a gate, fading over `STEAL_FADE`, for a patch's
+    /// `carrier_env` to shape
+    pub fn set_gate(&mut self) {
+        self.attack = STEAL_FADE;
+        self.decay = 0.0;
+        self.sustain = 1.0;
+        self.release = STEAL_FADE;
+    }
+
+    /// Take the modulation index envelope settings of a patch
+    pub fn set_index_adsr(&mut self, params: &FMParams) {
+        self.attack = params.index_attack;
+        self.decay = params.index_decay;
+        self.sustain = params.index_sustain;
+        self.release = params.index_release;
+    }
+
+    /// Start the attack from the current level, so retriggering a
+    /// sounding note doesn't click
     pub fn trigger(&mut self) {
         self.state = EnvelopeState::Attack;
-        self.time = 0.0;
+        self.time = self.level.clamp(0.0, 1.0) * self.attack;
+    }
+
+    /// Retrigger for legato playing: a held note carries on from where its
+    /// envelope is, and only a released or idle one starts a new attack
+    pub fn retrigger_legato(&mut self) {
+        if !self.is_held() {
+            self.trigger();
+        }
     }
 
+    /// Fade quickly to silence from the current level (over `STEAL_FADE`)
+    pub fn damp(&mut self) {
+        if self.state != EnvelopeState::Idle {
+            self.state = EnvelopeState::Damp;
+            self.fade_from = self.level;
+            self.time = 0.0;
+        }
+    }
+
+    /// Fade out from the current level over the release time, so a note
+    /// let go during its attack or decay doesn't jump to the sustain level
     pub fn release(&mut self) {
         if self.state != EnvelopeState::Idle {
             self.state = EnvelopeState::Release;
+            self.fade_from = self.level;
             self.time = 0.0;
         }
     }
 
     pub fn process(&mut self) -> f32 {
         let dt = 1.0 / self.sample_rate;
+        // Zero-length stages complete immediately instead of dividing by zero
+        let (attack, decay, release) = (
+            self.attack.max(f32::MIN_POSITIVE),
+            self.decay.max(f32::MIN_POSITIVE),
+            self.release.max(f32::MIN_POSITIVE),
+        );
         
         match self.state {
             EnvelopeState::Idle => {
                 self.level = 0.0;
             }
             EnvelopeState::Attack => {
-                self.level = self.time / self.attack;
+                self.level = self.time / attack;
                 if self.time >= self.attack {
                     self.state = EnvelopeState::Decay;
                     self.time = 0.0;
                 }
             }
             EnvelopeState::Decay => {
-                self.level = 1.0 - ((1.0 - self.sustain) * (self.time / self.decay));
+                self.level = 1.0 - ((1.0 - self.sustain) * (self.time / decay));
                 if self.time >= self.decay {
                     self.state = EnvelopeState::Sustain;
                     self.time = 0.0;
@@ -135,48 +934,893 @@ impl Envelope {
                 self.level = self.sustain;
             }
             EnvelopeState::Release => {
-                self.level = self.sustain * (1.0 - (self.time / self.release));
+                self.level = self.fade_from * (1.0 - (self.time / release));
                 if self.time >= self.release {
                     self.state = EnvelopeState::Idle;
                     self.level = 0.0;
                 }
             }
+            EnvelopeState::Damp => {
+                self.level = self.fade_from * (1.0 - self.time / STEAL_FADE).max(0.0);
+                if self.time >= STEAL_FADE {
+                    self.state = EnvelopeState::Idle;
+                    self.level = 0.0;
+                }
+            }
         }
         
         self.time += dt;
         self.level
     }
+
+    /// Current envelope output level
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    /// True once the release has finished (or before the first trigger)
+    pub fn is_idle(&self) -> bool {
+        self.state == EnvelopeState::Idle
+    }
+
+    /// True from the trigger until the release
+    pub fn is_held(&self) -> bool {
+        matches!(self.state, EnvelopeState::Attack | EnvelopeState::Decay | EnvelopeState::Sustain)
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
 }
 
 /// FM Synthesizer with envelope
 pub struct FMSynth {
     oscillator: FMOscillator,
     envelope: Envelope,
+    /// Scales the modulation index when the patch's `index_env` is on
+    index_envelope: Envelope,
+    /// Progress of the patch's `carrier_env` and `modulator_env`, for
+    /// patches that have them. `envelope` then only gates the carrier's.
+    carrier_levels: RateLevelState,
+    modulator_levels: RateLevelState,
+    lfo: Lfo,
+    /// Smoothed noise wandering the pitch and level when the patch drifts
+    drift_pitch: Lfo,
+    drift_level: Lfo,
+    /// Picks each new note's detune when the patch has `spread`, from the
+    /// patch's `spread_seed` mixed with `voice_seed`
+    spread_rng: Rng,
+    spread_seed: u64,
+    voice_seed: u64,
+    /// Pitch ratio of the current note's detune
+    spread_pitch: f32,
+    /// Restarts with each note, so vibrato can wait and fade in
+    vibrato: Lfo,
+    /// Seconds since note-on, for the vibrato's delay and fade
+    vibrato_time: f32,
+    tremolo: Lfo,
+    /// Tempo tremolo syncs to, in BPM
+    tempo: f32,
+    /// Audio input level (0.0 - 1.0) as the voice hears it, easing toward
+    /// `input_target`
+    input_level: f32,
+    input_target: f32,
+    sample_rate: f32,
 }
 
 impl FMSynth {
     pub fn new(sample_rate: f32, params: FMParams) -> Self {
+        let mut envelope = Envelope::new(sample_rate);
+        match params.carrier_env {
+            Some(_) => envelope.set_gate(),
+            None => envelope.set_adsr(&params),
+        }
+        let mut index_envelope = Envelope::new(sample_rate);
+        index_envelope.set_index_adsr(&params);
         Self {
-            oscillator: FMOscillator::new(sample_rate, params.clone()),
-            envelope: Envelope::new(sample_rate),
+            index_envelope,
+            carrier_levels: RateLevelState::new(&params.carrier_env.unwrap_or_default()),
+            modulator_levels: RateLevelState::new(&params.modulator_env.unwrap_or_default()),
+            lfo: Lfo::new(sample_rate, params.lfo_shape, params.lfo_rate, params.lfo_seed),
+            drift_pitch: Lfo::new(sample_rate, LfoShape::SmoothRandom, DRIFT_PITCH_RATE, 1),
+            drift_level: Lfo::new(sample_rate, LfoShape::SmoothRandom, DRIFT_LEVEL_RATE, 2),
+            spread_rng: Rng::new(params.spread_seed),
+            spread_seed: params.spread_seed,
+            voice_seed: 0,
+            spread_pitch: 1.0,
+            vibrato: Lfo::new(sample_rate, LfoShape::Sine, params.vibrato_rate, 1),
+            vibrato_time: 0.0,
+            tremolo: Lfo::new(sample_rate, LfoShape::Sine, tremolo_rate(&params, MELODY_BPM), 1),
+            tempo: MELODY_BPM,
+            input_level: 0.0,
+            input_target: 0.0,
+            sample_rate,
+            oscillator: FMOscillator::new(sample_rate, params),
+            envelope,
+        }
+    }
+
+    /// Vibrato depth (0.0 - 1.0) `vibrato_time` into a note: nothing during
+    /// the delay, then a linear fade in
+    fn vibrato_gain(&self) -> f32 {
+        let params = &self.oscillator.params;
+        let t = self.vibrato_time - params.vibrato_delay;
+        if t < 0.0 {
+            0.0
+        } else if t < params.vibrato_fade {
+            t / params.vibrato_fade
+        } else {
+            1.0
         }
     }
 
     pub fn next_sample(&mut self) -> f32 {
+        let params = &self.oscillator.params;
+        let (cents, depth, drift) = (params.lfo_pitch, params.lfo_index, params.drift);
+        let (carrier_env, modulator_env) = (params.carrier_env, params.modulator_env);
+        let index_env = params.index_env || modulator_env.is_some();
+        let vibrato = params.vibrato_depth;
+        let (duck, input_index) = (params.input_duck, params.input_index);
+        if duck > 0.0 || input_index > 0.0 {
+            let step = (1.0 / (INPUT_SMOOTHING * self.sample_rate)).min(1.0);
+            self.input_level += (self.input_target - self.input_level) * step;
+        }
+        let (lfo, spread) = (cents != 0.0 || depth != 0.0, params.spread > 0.0);
+        if lfo || index_env || drift > 0.0 || spread || vibrato > 0.0 || input_index > 0.0 {
+            let (mut pitch, mut index) = (self.spread_pitch, 1.0);
+            if lfo {
+                let value = self.lfo.next_value();
+                pitch *= (value * cents / 1200.0).exp2();
+                index = 1.0 + value * depth;
+            }
+            if index_env {
+                index *= match &modulator_env {
+                    Some(envelope) => self.modulator_levels.process(envelope, self.sample_rate),
+                    None => self.index_envelope.process(),
+                };
+            }
+            if drift > 0.0 {
+                pitch *= (self.drift_pitch.next_value() * drift * MAX_DRIFT_CENTS / 1200.0).exp2();
+            }
+            if vibrato > 0.0 {
+                let gain = self.vibrato_gain();
+                // The LFO waits too, so vibrato starts from the note's own pitch
+                if gain > 0.0 {
+                    pitch *= (self.vibrato.next_value() * vibrato * gain / 1200.0).exp2();
+                }
+                if gain < 1.0 {
+                    self.vibrato_time += 1.0 / self.sample_rate;
+                }
+            }
+            if input_index > 0.0 {
+                index *= 1.0 + self.input_level * input_index;
+            }
+            self.oscillator.set_modulation(pitch, index);
+        }
         let osc_out = self.oscillator.next_sample();
-        let env_out = self.envelope.process();
+        let mut env_out = self.envelope.process();
+        if let Some(envelope) = &carrier_env {
+            env_out *= self.carrier_levels.process(envelope, self.sample_rate);
+            // An L4 above 0 would hold the note until the next one, as on
+            // the DX7, so the gate closes once the release settles there
+            if self.carrier_levels.is_settled(envelope) && self.envelope.is_held() {
+                self.envelope.release();
+            }
+        }
+        let tremolo = self.oscillator.params.tremolo_depth;
+        if tremolo > 0.0 {
+            // Dips below the envelope, never above, so tremolo can't clip
+            env_out *= 1.0 - tremolo * 0.5 * (1.0 - self.tremolo.next_value());
+        }
+        if duck > 0.0 {
+            env_out *= 1.0 - duck * self.input_level;
+        }
+        if drift > 0.0 {
+            return osc_out * env_out * (1.0 + self.drift_level.next_value() * drift * DRIFT_LEVEL);
+        }
         osc_out * env_out
     }
 
+    /// Give this voice its own drift and spread, so voices wander and
+    /// detune independently
+    pub fn set_drift_seed(&mut self, seed: u64) {
+        self.drift_pitch.set_seed(seed.wrapping_mul(2));
+        self.drift_level.set_seed(seed.wrapping_mul(2) + 1);
+        if seed != self.voice_seed {
+            self.voice_seed = seed;
+            self.reseed_spread(self.spread_seed);
+        }
+    }
+
+    fn reseed_spread(&mut self, seed: u64) {
+        self.spread_seed = seed;
+        self.spread_rng = Rng::new(seed ^ self.voice_seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    }
+
     pub fn note_on(&mut self) {
+        if self.oscillator.params.legato_retrigger && self.is_held() {
+            self.envelope.retrigger_legato();
+            self.index_envelope.retrigger_legato();
+            return;
+        }
+        // Resetting the phase of a voice that's still sounding would click
+        if self.oscillator.params.phase_reset && self.envelope.is_idle() {
+            self.oscillator.reset_phase();
+        }
+        // A fresh detune per note, kept through legato retriggers
+        let spread = self.oscillator.params.spread;
+        self.spread_pitch = match spread > 0.0 {
+            true => (self.spread_rng.next_bipolar() * spread / 1200.0).exp2(),
+            false => 1.0,
+        };
         self.envelope.trigger();
+        self.index_envelope.trigger();
+        self.carrier_levels.trigger();
+        self.modulator_levels.trigger();
+        self.vibrato.reset_phase();
+        self.vibrato_time = 0.0;
+        // Synced tremolo starts its cycle with the note, so it falls on the beat
+        if self.oscillator.params.tremolo_beats > 0.0 {
+            self.tremolo.reset_phase();
+        }
     }
 
     pub fn note_off(&mut self) {
-        self.envelope.release();
+        // A carrier_env closes its gate itself, once its release settles
+        if self.oscillator.params.carrier_env.is_none() {
+            self.envelope.release();
+        }
+        self.index_envelope.release();
+        self.carrier_levels.release();
+        self.modulator_levels.release();
+    }
+
+    /// Fade out over `STEAL_FADE`, ahead of reusing the voice
+    pub fn damp(&mut self) {
+        self.envelope.damp();
+    }
+
+    /// Scale the pitch without changing the patch, e.g. for a glide
+    pub fn set_pitch(&mut self, ratio: f32) {
+        self.oscillator.set_pitch(ratio);
     }
 
     pub fn set_params(&mut self, params: FMParams) {
+        match params.carrier_env {
+            Some(_) => self.envelope.set_gate(),
+            None => self.envelope.set_adsr(&params),
+        }
+        // A released note's gate, left open for a dropped carrier_env, closes
+        if params.carrier_env.is_none() && !self.carrier_levels.is_held() && self.envelope.is_held() {
+            self.envelope.release();
+        }
+        self.index_envelope.set_index_adsr(&params);
+        self.lfo.set_shape(params.lfo_shape);
+        self.lfo.set_rate(params.lfo_rate);
+        self.lfo.set_seed(params.lfo_seed);
+        if params.spread_seed != self.spread_seed {
+            self.reseed_spread(params.spread_seed);
+        }
+        self.vibrato.set_rate(params.vibrato_rate);
+        self.tremolo.set_rate(tremolo_rate(&params, self.tempo));
+        let modulated = params.lfo_pitch != 0.0
+            || params.lfo_index != 0.0
+            || params.index_env
+            || params.modulator_env.is_some()
+            || params.input_index > 0.0;
+        if params.spread <= 0.0 {
+            self.spread_pitch = 1.0;
+        }
+        if !modulated && params.drift <= 0.0 && params.spread <= 0.0 && params.vibrato_depth <= 0.0 {
+            self.oscillator.set_modulation(1.0, 1.0);
+        }
         self.oscillator.set_params(params);
     }
+
+    /// Follow a change of output rate (e.g. after switching devices)
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.oscillator.set_sample_rate(sample_rate);
+        self.envelope.set_sample_rate(sample_rate);
+        self.index_envelope.set_sample_rate(sample_rate);
+        self.lfo.set_sample_rate(sample_rate);
+        self.drift_pitch.set_sample_rate(sample_rate);
+        self.drift_level.set_sample_rate(sample_rate);
+        self.vibrato.set_sample_rate(sample_rate);
+        self.tremolo.set_sample_rate(sample_rate);
+        self.sample_rate = sample_rate;
+    }
+
+    /// Tempo for synced tremolo (`tremolo_beats`), in BPM
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.tempo = bpm;
+        self.tremolo.set_rate(tremolo_rate(&self.oscillator.params, bpm));
+    }
+
+    /// Level (0.0 - 1.0) of the audio input, for patches that route it
+    /// (`input_duck`, `input_index`)
+    pub fn set_input_level(&mut self, level: f32) {
+        self.input_target = level;
+    }
+
+    /// The external modulator's current sample, for patches with an
+    /// `ext_index`
+    pub fn set_external(&mut self, sample: f32) {
+        self.oscillator.set_external(sample);
+    }
+
+    /// Switch between the exact sine and a (possibly shared) lookup table
+    pub fn set_sine_table(&mut self, table: Option<Arc<SineTable>>) {
+        self.oscillator.set_sine_table(table);
+    }
+
+    /// Current amplitude envelope level
+    pub fn envelope_level(&self) -> f32 {
+        match self.oscillator.params.carrier_env {
+            Some(_) => self.envelope.level() * self.carrier_levels.gain(),
+            None => self.envelope.level(),
+        }
+    }
+
+    /// True while the note is sounding, including its release
+    pub fn is_active(&self) -> bool {
+        !self.envelope.is_idle()
+    }
+
+    /// True from note-on until note-off
+    pub fn is_held(&self) -> bool {
+        self.envelope.is_held() && self.carrier_levels.is_held()
+    }
+}
+
+/// Linear gain of a level in dB
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Level in dB of a linear gain; silence is negative infinity
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.log10()
+}
+
+/// Tremolo rate in Hz: free-running, or one cycle per `tremolo_beats` at
+/// `bpm`
+fn tremolo_rate(params: &FMParams, bpm: f32) -> f32 {
+    if params.tremolo_beats > 0.0 {
+        (bpm / 60.0 / params.tremolo_beats).min(MAX_LFO_RATE)
+    } else {
+        params.tremolo_rate
+    }
+}
+
+/// Most separate outputs (stereo buses) a source can render for an output
+/// stream's channel map to route, e.g. a track each to channels 1–2 and 3–4
+pub const MAX_OUTPUTS: usize = 4;
+
+/// A synth that renders mono samples for an output stream
+pub trait SampleSource: Send {
+    fn next_sample(&mut self) -> f32;
+
+    /// Left and right samples for stereo outputs; the mono sample on both
+    /// unless the source has a stereo stage
+    fn next_frame(&mut self) -> [f32; 2] {
+        let sample = self.next_sample();
+        [sample, sample]
+    }
+
+    /// The next frame of each of the source's outputs, for streams with a
+    /// channel map; sources with only one put `next_frame` on the first
+    fn next_outputs(&mut self) -> [[f32; 2]; MAX_OUTPUTS] {
+        let mut outputs = [[0.0; 2]; MAX_OUTPUTS];
+        outputs[0] = self.next_frame();
+        outputs
+    }
+
+    /// Follow a change of output rate (e.g. after switching devices)
+    fn set_sample_rate(&mut self, sample_rate: f32);
+
+    /// Level (0.0 - 1.0) of the audio input, set by the output stream
+    /// before each buffer; sources that can't use it ignore it
+    fn set_input_level(&mut self, _level: f32) {}
+
+    /// One sample of the external modulator (audio input or WAV file), set
+    /// by the output stream before each frame while there is one
+    fn set_external(&mut self, _sample: f32) {}
+}
+
+impl SampleSource for FMSynth {
+    fn next_sample(&mut self) -> f32 {
+        FMSynth::next_sample(self)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        FMSynth::set_sample_rate(self, sample_rate)
+    }
+
+    fn set_input_level(&mut self, level: f32) {
+        FMSynth::set_input_level(self, level)
+    }
+
+    fn set_external(&mut self, sample: f32) {
+        FMSynth::set_external(self, sample)
+    }
+}
+
+/// A pitch offset in octaves moving at a steady rate toward a target, for
+/// glides and slides
+#[derive(Clone, Copy, Debug, Default)]
+struct Bend {
+    value: f32,
+    target: f32,
+    /// Octaves moved per sample
+    step: f32,
+}
+
+impl Bend {
+    /// Move from the current offset to `target` over `samples`
+    fn ramp(&mut self, target: f32, samples: f32) {
+        self.target = target;
+        self.step = (target - self.value).abs() / samples.max(1.0);
+    }
+
+    fn ratio(&self) -> f32 {
+        self.value.exp2()
+    }
+
+    /// Advance one sample, returning the new frequency ratio if it moved
+    fn next(&mut self) -> Option<f32> {
+        if self.value == self.target {
+            return None;
+        }
+        self.value = if self.value > self.target {
+            (self.value - self.step).max(self.target)
+        } else {
+            (self.value + self.step).min(self.target)
+        };
+        Some(self.ratio())
+    }
+}
+
+/// One voice of a `PolySynth` and the note it is playing
+struct Voice {
+    synth: FMSynth,
+    freq: f32,
+    /// Slide away from `freq`, if the note has one
+    bend: Bend,
+    held: bool,
+    /// Note-on order, for stealing the oldest voice
+    started: u64,
+    /// Note waiting for the voice's previous note to be damped
+    pending: Option<FMParams>,
+}
+
+impl Voice {
+    /// Sounding, or about to start a pending note
+    fn is_active(&self) -> bool {
+        self.synth.is_active() || self.pending.is_some()
+    }
+
+    fn release(&mut self) {
+        // A note released before its voice was free never starts
+        if self.pending.take().is_none() {
+            self.synth.note_off();
+        }
+        self.held = false;
+    }
+}
+
+/// Largest voice pool the command line, config file, and bindings accept
+pub const MAX_VOICES: usize = 256;
+
+/// Polyphonic engine: a pool of `FMSynth` voices mixed together.
+/// Voices are summed without scaling, so callers playing many notes at once
+/// should lower each note's amplitude.
+pub struct PolySynth {
+    voices: Vec<Voice>,
+    sample_rate: f32,
+    notes_started: u64,
+    /// Sine table shared by every voice, `None` for `f32::sin`
+    sine_table: Option<Arc<SineTable>>,
+}
+
+impl PolySynth {
+    pub fn new(sample_rate: f32, max_voices: usize) -> Self {
+        let mut synth = Self { voices: Vec::new(), sample_rate, notes_started: 0, sine_table: None };
+        synth.set_max_voices(max_voices);
+        synth
+    }
+
+    /// An idle voice, the `index`th of the pool, with its own drift
+    fn voice(&self, index: usize) -> Voice {
+        let mut synth = FMSynth::new(self.sample_rate, FMParams::default());
+        synth.set_drift_seed(index as u64 + 1);
+        synth.set_sine_table(self.sine_table.clone());
+        Voice { synth, freq: 0.0, bend: Bend::default(), held: false, started: 0, pending: None }
+    }
+
+    /// Give every voice, and those added later, one shared lookup table in
+    /// place of `f32::sin` (see `FMSynth::set_sine_table`), or `None` to go
+    /// back to the exact path
+    pub fn set_sine_table(&mut self, table: Option<Arc<SineTable>>) {
+        for voice in &mut self.voices {
+            voice.synth.set_sine_table(table.clone());
+        }
+        self.sine_table = table;
+    }
+
+    /// Grow or shrink the voice pool to `max_voices` (at least 1). Shrinking
+    /// drops idle voices first, then those playing the oldest notes, which
+    /// are cut off at once.
+    pub fn set_max_voices(&mut self, max_voices: usize) {
+        let max_voices = max_voices.max(1);
+        while self.voices.len() > max_voices {
+            let index = match self.voices.iter().rposition(|v| !v.is_active()) {
+                Some(index) => index,
+                None => (0..self.voices.len()).min_by_key(|&i| self.voices[i].started).unwrap_or(0),
+            };
+            self.voices.remove(index);
+        }
+        while self.voices.len() < max_voices {
+            self.voices.push(self.voice(self.voices.len()));
+        }
+    }
+
+    /// Start `freq` with a preset (scaled with `FMParams::for_note`), taking
+    /// a free voice or stealing the oldest one
+    pub fn note_on(&mut self, freq: f32, preset: &FMParams) {
+        self.note_on_with(freq, preset.for_note(freq));
+    }
+
+    /// Start `freq` with parameters already resolved for that note. A
+    /// stolen voice is damped first, and the note starts once it's silent.
+    pub fn note_on_with(&mut self, freq: f32, params: FMParams) {
+        let index = match self.voices.iter().position(|v| !v.is_active()) {
+            Some(index) => index,
+            None => (0..self.voices.len()).min_by_key(|&i| self.voices[i].started).unwrap_or(0),
+        };
+        self.notes_started += 1;
+        let voice = &mut self.voices[index];
+        if voice.synth.is_active() && voice.synth.envelope_level() > 0.0 {
+            voice.synth.damp();
+            voice.pending = Some(params);
+        } else {
+            voice.pending = None;
+            voice.synth.set_params(params);
+            voice.synth.set_pitch(1.0);
+            voice.synth.note_on();
+        }
+        voice.freq = freq;
+        voice.bend = Bend::default();
+        voice.held = true;
+        voice.started = self.notes_started;
+    }
+
+    /// Release every held voice playing `freq`
+    pub fn note_off(&mut self, freq: f32) {
+        for voice in self.voices.iter_mut().filter(|v| v.held && v.freq == freq) {
+            voice.release();
+        }
+    }
+
+    /// Slide the held voices playing `freq` continuously to `to` over
+    /// `seconds`; the note keeps its name, so `note_off(freq)` still ends it
+    pub fn slide(&mut self, freq: f32, to: f32, seconds: f32) {
+        if freq <= 0.0 || to <= 0.0 {
+            return;
+        }
+        let samples = seconds * self.sample_rate;
+        for voice in self.voices.iter_mut().filter(|v| v.held && v.freq == freq) {
+            voice.bend.ramp((to / freq).log2(), samples);
+        }
+    }
+
+    pub fn all_notes_off(&mut self) {
+        for voice in self.voices.iter_mut().filter(|v| v.held) {
+            voice.release();
+        }
+    }
+
+    /// Size of the voice pool: the most notes that can sound at once
+    pub fn max_voices(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Number of voices still sounding
+    pub fn active_voices(&self) -> usize {
+        self.voices.iter().filter(|v| v.is_active()).count()
+    }
+
+    /// True once every voice has finished its release
+    pub fn is_idle(&self) -> bool {
+        self.voices.iter().all(|v| !v.is_active())
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let mut mix = 0.0;
+        for voice in &mut self.voices {
+            if !voice.synth.is_active() {
+                match voice.pending.take() {
+                    Some(params) => {
+                        voice.synth.set_params(params);
+                        voice.synth.set_pitch(voice.bend.ratio());
+                        voice.synth.note_on();
+                    }
+                    None => continue,
+                }
+            }
+            // A stolen voice keeps its old pitch while it's damped
+            if voice.pending.is_none() {
+                if let Some(ratio) = voice.bend.next() {
+                    voice.synth.set_pitch(ratio);
+                }
+            }
+            mix += voice.synth.next_sample();
+        }
+        mix
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        for voice in &mut self.voices {
+            voice.synth.set_sample_rate(sample_rate);
+        }
+    }
+
+    /// Tempo for synced tremolo, in BPM
+    pub fn set_tempo(&mut self, bpm: f32) {
+        for voice in &mut self.voices {
+            voice.synth.set_tempo(bpm);
+        }
+    }
+
+    /// Level (0.0 - 1.0) of the audio input, for patches that route it
+    pub fn set_input_level(&mut self, level: f32) {
+        for voice in &mut self.voices {
+            voice.synth.set_input_level(level);
+        }
+    }
+
+    /// The external modulator's current sample, for patches that use it
+    pub fn set_external(&mut self, sample: f32) {
+        for voice in &mut self.voices {
+            voice.synth.set_external(sample);
+        }
+    }
+}
+
+impl SampleSource for PolySynth {
+    fn next_sample(&mut self) -> f32 {
+        PolySynth::next_sample(self)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        PolySynth::set_sample_rate(self, sample_rate)
+    }
+
+    fn set_input_level(&mut self, level: f32) {
+        PolySynth::set_input_level(self, level)
+    }
+
+    fn set_external(&mut self, sample: f32) {
+        PolySynth::set_external(self, sample)
+    }
+}
+
+/// Which held note a `MonoSynth` plays
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum NotePriority {
+    /// The most recently pressed
+    #[default]
+    Last,
+    Highest,
+    Lowest,
+}
+
+impl NotePriority {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "last" | "latest" => Some(NotePriority::Last),
+            "high" | "highest" | "top" => Some(NotePriority::Highest),
+            "low" | "lowest" | "bottom" => Some(NotePriority::Lowest),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotePriority::Last => "last",
+            NotePriority::Highest => "highest",
+            NotePriority::Lowest => "lowest",
+        }
+    }
+}
+
+/// Monophonic engine for lead and bass lines: one voice, with the notes
+/// still held kept on a stack so releasing the sounding note returns to
+/// the next one by priority, as on classic monosynths
+pub struct MonoSynth {
+    synth: FMSynth,
+    /// Held notes in the order they were pressed
+    held: Vec<(f32, FMParams)>,
+    /// Note the voice is playing (or releasing)
+    current: Option<f32>,
+    /// Change pitch without retriggering while a note is held
+    legato: bool,
+    /// Time to slide from one note to the next (seconds)
+    glide: f32,
+    priority: NotePriority,
+    /// Offset from the note's pitch: the rest of a glide, or a slide
+    bend: Bend,
+    sample_rate: f32,
+}
+
+impl MonoSynth {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            synth: FMSynth::new(sample_rate, FMParams::default()),
+            held: Vec::new(),
+            current: None,
+            legato: true,
+            glide: 0.0,
+            priority: NotePriority::Last,
+            bend: Bend::default(),
+            sample_rate,
+        }
+    }
+
+    /// With legato on (the default), a note played while another is held
+    /// changes pitch without restarting the envelope; off, every note
+    /// retriggers
+    pub fn set_legato(&mut self, legato: bool) {
+        self.legato = legato;
+    }
+
+    /// Glide from one note to the next over `seconds` (0 = jump)
+    pub fn set_glide(&mut self, seconds: f32) {
+        self.glide = seconds.max(0.0);
+    }
+
+    /// Use a lookup table in place of `f32::sin` (see
+    /// `FMSynth::set_sine_table`)
+    pub fn set_sine_table(&mut self, table: Option<Arc<SineTable>>) {
+        self.synth.set_sine_table(table);
+    }
+
+    /// Which held note sounds: the last pressed (the default), or the
+    /// highest or lowest
+    pub fn set_priority(&mut self, priority: NotePriority) {
+        self.priority = priority;
+    }
+
+    /// Play `freq` with a preset (scaled with `FMParams::for_note`)
+    pub fn note_on(&mut self, freq: f32, preset: &FMParams) {
+        self.note_on_with(freq, preset.for_note(freq));
+    }
+
+    /// Hold `freq` with parameters already resolved for that note; it
+    /// sounds if it has priority over the other held notes
+    pub fn note_on_with(&mut self, freq: f32, params: FMParams) {
+        self.held.retain(|(held, _)| *held != freq);
+        self.held.push((freq, params));
+        self.update(Some(freq));
+    }
+
+    /// Release `freq`; if it was sounding and other notes are still held,
+    /// go back to the one with priority
+    pub fn note_off(&mut self, freq: f32) {
+        self.held.retain(|(held, _)| *held != freq);
+        if self.current == Some(freq) {
+            self.update(None);
+        }
+    }
+
+    /// Sound the held note with priority, or release the voice if none is
+    /// held. A note that keeps sounding is only restarted if it was just
+    /// `pressed` again.
+    fn update(&mut self, pressed: Option<f32>) {
+        let chosen = match self.priority {
+            NotePriority::Last => self.held.last(),
+            NotePriority::Highest => self.held.iter().max_by(|a, b| a.0.total_cmp(&b.0)),
+            NotePriority::Lowest => self.held.iter().min_by(|a, b| a.0.total_cmp(&b.0)),
+        };
+        match chosen.cloned() {
+            Some((freq, _)) if self.current == Some(freq) && pressed != Some(freq) && self.synth.is_held() => {}
+            Some((freq, params)) => self.sound(freq, params),
+            None => self.synth.note_off(),
+        }
+    }
+
+    pub fn all_notes_off(&mut self) {
+        self.held.clear();
+        self.synth.note_off();
+    }
+
+    /// Move the voice to `freq`, gliding from the current pitch and
+    /// retriggering unless this is a legato change
+    fn sound(&mut self, freq: f32, params: FMParams) {
+        let legato = self.legato && self.synth.is_held();
+        match self.current {
+            Some(from) if self.glide > 0.0 && self.synth.is_active() && freq > 0.0 => {
+                self.bend.value += (from / freq).log2();
+                self.bend.ramp(0.0, self.glide * self.sample_rate);
+            }
+            // Carry on from where a slide into this note got to
+            Some(from) if self.bend.value != 0.0 && self.synth.is_active() && freq > 0.0 => {
+                self.bend.value += (from / freq).log2();
+                self.bend.ramp(0.0, 1.0);
+            }
+            _ => self.bend = Bend::default(),
+        }
+        self.synth.set_params(params);
+        self.synth.set_pitch(self.bend.ratio());
+        if !legato {
+            self.synth.note_on();
+        }
+        self.current = Some(freq);
+    }
+
+    /// True once the voice has finished its release
+    pub fn is_idle(&self) -> bool {
+        !self.synth.is_active()
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// Slide the sounding note, if it is `freq`, continuously to `to` over
+    /// `seconds`
+    pub fn slide(&mut self, freq: f32, to: f32, seconds: f32) {
+        if self.current == Some(freq) && freq > 0.0 && to > 0.0 {
+            self.bend.ramp((to / freq).log2(), seconds * self.sample_rate);
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        if let Some(ratio) = self.bend.next() {
+            self.synth.set_pitch(ratio);
+        }
+        self.synth.next_sample()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.synth.set_sample_rate(sample_rate);
+    }
+
+    /// Tempo for synced tremolo, in BPM
+    pub fn set_tempo(&mut self, bpm: f32) {
+        self.synth.set_tempo(bpm);
+    }
+
+    /// Level (0.0 - 1.0) of the audio input, for patches that route it
+    pub fn set_input_level(&mut self, level: f32) {
+        self.synth.set_input_level(level);
+    }
+
+    /// The external modulator's current sample, for patches that use it
+    pub fn set_external(&mut self, sample: f32) {
+        self.synth.set_external(sample);
+    }
+}
+
+impl SampleSource for MonoSynth {
+    fn next_sample(&mut self) -> f32 {
+        MonoSynth::next_sample(self)
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        MonoSynth::set_sample_rate(self, sample_rate)
+    }
+
+    fn set_input_level(&mut self, level: f32) {
+        MonoSynth::set_input_level(self, level)
+    }
+
+    fn set_external(&mut self, sample: f32) {
+        MonoSynth::set_external(self, sample)
+    }
 }
@@ -1,8 +1,39 @@
-use crate::synth_core::FMParams;
+use serde::{Deserialize, Serialize};
 
-/// Note frequencies
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::macros::Macro;
+use crate::synth_core::{FMParams, REFERENCE_FREQ};
+
+/// Lowest and highest concert pitch accepted by `set_a4` (Hz)
+pub const MIN_A4: f32 = 380.0;
+pub const MAX_A4: f32 = 500.0;
+
+/// Concert pitch as `f32` bits, read by every note-to-frequency conversion
+static A4: AtomicU32 = AtomicU32::new(REFERENCE_FREQ.to_bits());
+
+/// Frequency of A4 that notes are tuned to (440 Hz unless changed)
+pub fn a4() -> f32 {
+    f32::from_bits(A4.load(Ordering::Relaxed))
+}
+
+/// Retune every note, e.g. to 442 or 432 Hz, clamped to `MIN_A4..=MAX_A4`.
+/// Notes already sounding keep their pitch.
+pub fn set_a4(freq: f32) {
+    let freq = if freq.is_finite() { freq.clamp(MIN_A4, MAX_A4) } else { REFERENCE_FREQ };
+    A4.store(freq.to_bits(), Ordering::Relaxed);
+}
+
+/// Note frequencies at the current tuning (see `set_a4`). A cent offset
+/// after the octave, as in `A4+14` or `E4-31`, bends the note, and
+/// articulation marks are ignored.
 pub fn note_freq(note: &str) -> f32 {
-    match note {
+    let (note, _) = parse_articulation(note);
+    let (name, cents) = split_cents(note);
+    if name.len() < note.len() {
+        return note_freq(name) * (cents / 1200.0).exp2();
+    }
+    let freq = match note {
         "C3" => 130.81, "C#3" => 138.59, "D3" => 146.83, "D#3" => 155.56, "E3" => 164.81,
         "F3" => 174.61, "F#3" => 185.00, "G3" => 196.00, "G#3" => 207.65, "A3" => 220.00,
         "A#3" => 233.08, "B3" => 246.94,
@@ -11,10 +42,159 @@ pub fn note_freq(note: &str) -> f32 {
         "A#4" => 466.16, "B4" => 493.88,
         "C5" => 523.25, "C#5" => 554.37, "D5" => 587.33, "D#5" => 622.25, "E5" => 659.25,
         "F5" => 698.46, "F#5" => 739.99, "G5" => 783.99, "G#5" => 830.61, "A5" => 880.00,
-        _ => 0.0, // Rest
+        // Outside the table, fall back to equal temperament; anything else is a rest
+        _ => return note_number(note).map_or(0.0, midi_to_freq),
+    };
+    // The table is at A440; at that tuning the ratio is exactly 1
+    freq * (a4() / REFERENCE_FREQ)
+}
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// MIDI note number of a name such as `C4`, `F#3`, or `Bb5` (60 = C4)
+pub fn note_number(note: &str) -> Option<i32> {
+    let mut chars = note.chars();
+    let letter = chars.next()?.to_ascii_uppercase();
+    let mut semitone = NOTE_NAMES.iter().position(|n| n.len() == 1 && n.starts_with(letter))? as i32;
+    let rest = chars.as_str();
+    let octave = if let Some(octave) = rest.strip_prefix('#') {
+        semitone += 1;
+        octave
+    } else if let Some(octave) = rest.strip_prefix('b') {
+        semitone -= 1;
+        octave
+    } else {
+        rest
+    };
+    Some(12 * (octave.parse::<i32>().ok()? + 1) + semitone)
+}
+
+/// Fraction of its length an unmarked melody note is held, leaving a
+/// short gap before the next, unless its preset (`FMParams::gate`) or the
+/// playback setting says otherwise
+pub const DEFAULT_GATE: f32 = 0.8;
+
+/// Shortest gate a note can have
+pub const MIN_GATE: f32 = 0.01;
+
+/// Gates of staccato (`.`) and legato (`_`) notes
+pub const STACCATO_GATE: f32 = 0.5;
+pub const LEGATO_GATE: f32 = 1.0;
+
+/// How a melody note is played, from marks after its name
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Articulation {
+    /// `~`: slide continuously into the next note's pitch
+    pub slide: bool,
+    /// Fraction of the note's length it's held for: `.` staccato, `_`
+    /// legato, or a percentage such as `@60%`
+    pub gate: Option<f32>,
+    /// `>`: play louder and brighter
+    pub accent: bool,
+}
+
+impl Articulation {
+    /// Fraction of its length the note is held: all of it for a slide,
+    /// `unmarked` (the preset's or playback's gate) when unmarked
+    pub fn gate(&self, unmarked: f32) -> f32 {
+        if self.slide {
+            LEGATO_GATE
+        } else {
+            self.gate.unwrap_or(unmarked)
+        }
+    }
+
+    /// Held into the next note, so a mono engine ties the two
+    pub fn is_legato(&self, unmarked: f32) -> bool {
+        !self.slide && self.gate(unmarked) >= LEGATO_GATE
     }
 }
 
+/// Split the articulation marks from the end of a melody note, in any
+/// order: `C4~` is (`C4`, slide), `E4.>` is (`E4`, staccato and accent),
+/// and `G4@65%` is (`G4`, held for 65%)
+pub fn parse_articulation(note: &str) -> (&str, Articulation) {
+    let mut articulation = Articulation::default();
+    let mut name = note;
+    loop {
+        let mut gate = None;
+        if let Some(rest) = name.strip_suffix('~') {
+            articulation.slide = true;
+            name = rest;
+        } else if let Some(rest) = name.strip_suffix('>') {
+            articulation.accent = true;
+            name = rest;
+        } else if let Some(rest) = name.strip_suffix('.') {
+            gate = Some(STACCATO_GATE);
+            name = rest;
+        } else if let Some(rest) = name.strip_suffix('_') {
+            gate = Some(LEGATO_GATE);
+            name = rest;
+        } else if let Some((rest, percent)) = name.rsplit_once('@')
+            .and_then(|(rest, p)| Some((rest, p.trim_end_matches('%').parse::<f32>().ok()?)))
+            .filter(|(_, p)| p.is_finite())
+        {
+            gate = Some((percent / 100.0).clamp(MIN_GATE, LEGATO_GATE));
+            name = rest;
+        } else {
+            break;
+        }
+        // The mark nearest the name wins
+        articulation.gate = gate.or(articulation.gate);
+    }
+    (name, articulation)
+}
+
+/// Split a note into its name and cent offset: `A4+14` is (`A4`, 14.0),
+/// `C-1-50` is (`C-1`, -50.0), and a note without one is (`note`, 0.0)
+pub fn split_cents(note: &str) -> (&str, f32) {
+    // The offset is the signed number after the octave's digits
+    let Some(start) = note.find(|c: char| c.is_ascii_digit()) else {
+        return (note, 0.0);
+    };
+    let end = note[start..].find(|c: char| !c.is_ascii_digit()).map_or(note.len(), |i| start + i);
+    let offset = &note[end..];
+    match offset.parse::<f32>() {
+        Ok(cents) if offset.starts_with(['+', '-']) && cents.is_finite() => (&note[..end], cents),
+        _ => (note, 0.0),
+    }
+}
+
+/// Name of a MIDI note number, spelled with sharps (60 = `C4`)
+pub fn note_name(number: i32) -> String {
+    format!("{}{}", NOTE_NAMES[number.rem_euclid(12) as usize], number.div_euclid(12) - 1)
+}
+
+/// Equal-tempered frequency of a MIDI note number (69 = A4) at the current
+/// tuning
+pub fn midi_to_freq(note: i32) -> f32 {
+    a4() * 2f32.powf((note - 69) as f32 / 12.0)
+}
+
+/// Tempo the built-in melodies are written at (500 ms per beat)
+pub const MELODY_BPM: f32 = 120.0;
+
+/// Rescale every note duration in a melody; `speed` 2.0 plays twice as fast
+pub fn change_tempo<N: Clone>(melody: &[(N, u64)], speed: f32) -> Vec<(N, u64)> {
+    melody.iter()
+        .map(|(note, duration)| (note.clone(), (*duration as f32 / speed).round() as u64))
+        .collect()
+}
+
+/// Shift every note of a melody by `semitones`, keeping cent offsets and
+/// articulation; rests are left alone
+pub fn transpose(melody: &[(&str, u64)], semitones: i32) -> Vec<(String, u64)> {
+    melody.iter()
+        .map(|&(note, duration)| {
+            let (name, _) = split_cents(parse_articulation(note).0);
+            match note_number(name) {
+                Some(number) => (note_name(number + semitones) + &note[name.len()..], duration),
+                None => (note.to_string(), duration),
+            }
+        })
+        .collect()
+}
+
 /// Preset definitions
 pub fn get_presets() -> Vec<(&'static str, FMParams)> {
     vec![
@@ -22,77 +202,167 @@ pub fn get_presets() -> Vec<(&'static str, FMParams)> {
             carrier_freq: 440.0,
             modulator_freq: 440.0,
             modulation_index: 7.0,
-            amplitude: 0.3,
+            level_db: -10.5,
+            key_scaling: 0.5,
+            phase_reset: true,
+            reverb_mix: 0.35,
+            reverb_size: 0.85,
+            ..FMParams::default()
         }),
         ("Bass", FMParams {
             carrier_freq: 110.0,
             modulator_freq: 110.0,
             modulation_index: 1.5,
-            amplitude: 0.5,
+            level_db: -6.0,
+            key_scaling: 0.3,
+            key_scaling_low: true,
+            phase_reset: true,
+            ..FMParams::default()
         }),
         ("Electric Piano", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 880.0,
             modulation_index: 3.0,
-            amplitude: 0.4,
+            level_db: -8.0,
+            key_scaling: 0.5,
+            key_scaling_low: true,
+            phase_reset: true,
+            // The vintage e-piano's tremolo, in eighth notes
+            tremolo_depth: 0.3,
+            tremolo_beats: 0.5,
+            ..FMParams::default()
         }),
         ("Brass", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 440.0,
             modulation_index: 2.5,
-            amplitude: 0.4,
+            level_db: -8.0,
+            ..FMParams::default()
         }),
         ("Organ", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 880.0,
             modulation_index: 1.0,
-            amplitude: 0.4,
+            level_db: -8.0,
+            tremolo_rate: 6.5,
+            tremolo_depth: 0.2,
+            ..FMParams::default()
         }),
         ("Synth Lead", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 1320.0,
             modulation_index: 4.0,
-            amplitude: 0.35,
+            level_db: -9.1,
+            key_scaling: 0.3,
+            ..FMParams::default()
         }),
         ("Marimba", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 440.0,
             modulation_index: 3.5,
-            amplitude: 0.4,
+            level_db: -8.0,
+            phase_reset: true,
+            ..FMParams::default()
         }),
         ("Strings", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 220.0,
             modulation_index: 0.8,
-            amplitude: 0.3,
+            level_db: -10.5,
+            drift: 0.4,
+            ..FMParams::default()
         }),
         ("Flute", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 440.0,
             modulation_index: 0.5,
-            amplitude: 0.25,
+            level_db: -12.0,
+            vibrato_depth: 12.0,
+            vibrato_delay: 0.35,
+            vibrato_fade: 0.4,
+            ..FMParams::default()
         }),
         ("Metallic", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 567.0,
             modulation_index: 9.0,
-            amplitude: 0.3,
+            level_db: -10.5,
+            key_scaling: 0.5,
+            ..FMParams::default()
         }),
         ("Glockenspiel", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 1760.0,
             modulation_index: 2.5,
-            amplitude: 0.3,
+            level_db: -10.5,
+            key_scaling: 0.3,
+            phase_reset: true,
+            ..FMParams::default()
         }),
         ("Wood Block", FMParams {
             carrier_freq: 440.0,
             modulator_freq: 300.0,
             modulation_index: 12.0,
-            amplitude: 0.4,
+            level_db: -8.0,
+            key_scaling: 0.4,
+            phase_reset: true,
+            ..FMParams::default()
         }),
     ]
 }
 
+/// Descriptive details of a preset, so shared banks document themselves.
+/// Every field is optional in preset files.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PresetMeta {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub author: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Lowest and highest notes the preset is voiced for, e.g. `["C2", "C4"]`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<(String, String)>,
+    /// Up to `MAX_MACROS` macro knobs; presets without any get generic ones
+    /// from `macros::preset_macros`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub macros: Vec<Macro>,
+}
+
+impl PresetMeta {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Metadata of the built-in presets, by name
+pub fn preset_meta(name: &str) -> PresetMeta {
+    let (description, tags, low, high): (&str, &[&str], &str, &str) = match name {
+        "Bell" => ("Struck bell with inharmonic overtones and a long reverb tail", &["bell", "percussive", "metallic"], "C4", "C7"),
+        "Bass" => ("Round, dry bass that stays bright in the low octaves", &["bass"], "C1", "C4"),
+        "Electric Piano" => ("Tine-style electric piano with a bright attack", &["keys", "piano"], "C2", "C6"),
+        "Brass" => ("Simple brass section; try it with --legato", &["brass", "lead"], "C3", "C6"),
+        "Organ" => ("Steady organ with an octave-up modulator", &["keys", "organ", "sustained"], "C2", "C6"),
+        "Synth Lead" => ("Buzzy lead from a 3:1 modulator", &["lead", "synth"], "C3", "C6"),
+        "Marimba" => ("Mallet tone with a woody attack", &["mallet", "percussive"], "C3", "C6"),
+        "Strings" => ("Soft string pad from a sub-octave modulator", &["pad", "strings", "sustained"], "C2", "C6"),
+        "Flute" => ("Soft, nearly pure flute", &["woodwind", "lead"], "C4", "C7"),
+        "Metallic" => ("Clangorous, inharmonic texture", &["metallic", "fx"], "C2", "C6"),
+        "Glockenspiel" => ("Bright, high mallet bells", &["bell", "mallet", "percussive"], "C5", "C8"),
+        "Wood Block" => ("Short knock with a dense FM spectrum", &["percussive", "drum"], "C3", "C6"),
+        _ => return PresetMeta::default(),
+    };
+    PresetMeta {
+        author: "fm_synth".to_string(),
+        description: description.to_string(),
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+        range: Some((low.to_string(), high.to_string())),
+        macros: Vec::new(),
+    }
+}
+
 /// Melody definitions
 pub fn get_melodies() -> Vec<(&'static str, Vec<(&'static str, u64)>)> {
     vec![
@@ -154,5 +424,16 @@ pub fn get_melodies() -> Vec<(&'static str, Vec<(&'static str, u64)>)> {
             ("F4", 150), ("A4", 150), ("C5", 150), ("F5", 150),
             ("C5", 150), ("A4", 150), ("F4", 300),
         ]),
+        // A round: each part enters two bars after the last
+        ("Frere Jacques", vec![
+            ("C4", 500), ("D4", 500), ("E4", 500), ("C4", 500),
+            ("C4", 500), ("D4", 500), ("E4", 500), ("C4", 500),
+            ("E4", 500), ("F4", 500), ("G4", 1000),
+            ("E4", 500), ("F4", 500), ("G4", 1000),
+            ("G4", 250), ("A4", 250), ("G4", 250), ("F4", 250), ("E4", 500), ("C4", 500),
+            ("G4", 250), ("A4", 250), ("G4", 250), ("F4", 250), ("E4", 500), ("C4", 500),
+            ("C4", 500), ("G3", 500), ("C4", 1000),
+            ("C4", 500), ("G3", 500), ("C4", 1000),
+        ]),
     ]
 }
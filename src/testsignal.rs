@@ -0,0 +1,222 @@
+// src/testsignal.rs - Calibrated test signals for checking outputs and analysis
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use crate::rng::Rng;
+use crate::synth_core::{db_to_gain, SampleSource};
+
+/// Level used when none is given: quiet enough to be safe on any speaker
+pub const DEFAULT_LEVEL_DB: f32 = -18.0;
+
+/// Fade at the start and end of a signal so it doesn't click
+const FADE: Duration = Duration::from_millis(10);
+
+/// RMS of the pink filter's output for uniform white noise in [-1, 1)
+const PINK_RMS: f32 = 1.763;
+
+/// What a `TestSignal` plays
+#[derive(Clone, Debug, PartialEq)]
+pub enum Signal {
+    /// A steady sine at one frequency
+    Sine(f32),
+    /// Exponential sine sweep between two frequencies over `length`, so
+    /// each octave takes the same time
+    Sweep { from: f32, to: f32, length: Duration },
+    /// Sine tones held for `step` each, an octave apart from `from` up to
+    /// `to`
+    Steps { from: f32, to: f32, step: Duration },
+    WhiteNoise,
+    /// Noise with equal power per octave (-3 dB/octave)
+    PinkNoise,
+}
+
+impl Signal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Sine(_) => "sine",
+            Signal::Sweep { .. } => "sweep",
+            Signal::Steps { .. } => "steps",
+            Signal::WhiteNoise => "white noise",
+            Signal::PinkNoise => "pink noise",
+        }
+    }
+
+    /// Frequencies of the tones of `Steps`
+    pub fn step_freqs(from: f32, to: f32) -> Vec<f32> {
+        std::iter::successors(Some(from), |f| Some(f * 2.0))
+            .take_while(|&f| f <= to * 1.001)
+            .collect()
+    }
+
+    /// How long the signal naturally lasts; `None` if it plays until stopped
+    fn natural_length(&self) -> Option<Duration> {
+        match self {
+            Signal::Sweep { length, .. } => Some(*length),
+            Signal::Steps { from, to, step } => Some(*step * Self::step_freqs(*from, *to).len() as u32),
+            _ => None,
+        }
+    }
+}
+
+/// Which output channels carry the signal
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Channels {
+    #[default]
+    Both,
+    Left,
+    Right,
+}
+
+impl Channels {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "both" | "stereo" => Some(Channels::Both),
+            "left" | "l" => Some(Channels::Left),
+            "right" | "r" => Some(Channels::Right),
+            _ => None,
+        }
+    }
+}
+
+/// A test signal at a calibrated level. `level_db` is the sine's peak in
+/// dBFS; noise is scaled to the same RMS as that sine, so every signal reads
+/// the same on an RMS meter.
+pub struct TestSignal {
+    signal: Signal,
+    amplitude: f32,
+    channels: Channels,
+    length: Option<Duration>,
+    sample_rate: f32,
+    /// Samples played so far
+    elapsed: u64,
+    phase: f32,
+    rng: Rng,
+    pink: [f32; 7],
+}
+
+impl TestSignal {
+    /// A signal lasting `length`, or its natural length when `None` (sines
+    /// and noise then play until stopped)
+    pub fn new(sample_rate: f32, signal: Signal, level_db: f32, length: Option<Duration>) -> Self {
+        Self {
+            length: length.or_else(|| signal.natural_length()),
+            signal,
+            amplitude: db_to_gain(level_db),
+            channels: Channels::Both,
+            sample_rate,
+            elapsed: 0,
+            phase: 0.0,
+            rng: Rng::new(1),
+            pink: [0.0; 7],
+        }
+    }
+
+    pub fn set_channels(&mut self, channels: Channels) {
+        self.channels = channels;
+    }
+
+    pub fn signal(&self) -> &Signal {
+        &self.signal
+    }
+
+    pub fn length(&self) -> Option<Duration> {
+        self.length
+    }
+
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.elapsed as f64 / self.sample_rate as f64)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.length.is_some_and(|length| self.position() >= length)
+    }
+
+    /// Frequency of the tone at `t` seconds, for the tonal signals
+    pub fn freq_at(&self, t: f32) -> Option<f32> {
+        match &self.signal {
+            Signal::Sine(freq) => Some(*freq),
+            Signal::Sweep { from, to, length } => {
+                Some(from * (to / from).powf((t / length.as_secs_f32().max(f32::EPSILON)).min(1.0)))
+            }
+            Signal::Steps { from, to, step } => {
+                let freqs = Signal::step_freqs(*from, *to);
+                let index = (t / step.as_secs_f32().max(f32::EPSILON)) as usize;
+                freqs.get(index.min(freqs.len().saturating_sub(1))).copied()
+            }
+            Signal::WhiteNoise | Signal::PinkNoise => None,
+        }
+    }
+
+    /// Gain ramping in over `FADE` at the start and out before the end
+    fn fade(&self, t: f32) -> f32 {
+        let fade = FADE.as_secs_f32();
+        let fade_in = (t / fade).min(1.0);
+        let fade_out = self.length.map_or(1.0, |length| ((length.as_secs_f32() - t) / fade).clamp(0.0, 1.0));
+        fade_in.min(fade_out)
+    }
+
+    /// Paul Kellet's pink filter over uniform white noise
+    fn pink(&mut self, white: f32) -> f32 {
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b.iter().sum::<f32>() + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink
+    }
+
+    /// Render the whole signal (`seconds` long if it has no length of its own)
+    pub fn render(&mut self, seconds: f32) -> Vec<f32> {
+        let length = self.length.map_or(seconds, |l| l.as_secs_f32());
+        let samples = (length * self.sample_rate).round() as usize;
+        (0..samples).map(|_| self.next_sample()).collect()
+    }
+}
+
+impl SampleSource for TestSignal {
+    fn next_sample(&mut self) -> f32 {
+        if self.is_finished() {
+            return 0.0;
+        }
+        let t = self.elapsed as f32 / self.sample_rate;
+        self.elapsed += 1;
+        // Noise is scaled to the RMS of a sine (peak / sqrt 2)
+        let rms = self.amplitude * std::f32::consts::FRAC_1_SQRT_2;
+        let sample = match self.freq_at(t) {
+            Some(freq) => {
+                let sample = (2.0 * PI * self.phase).sin() * self.amplitude;
+                self.phase = (self.phase + freq / self.sample_rate).fract();
+                sample
+            }
+            None => {
+                let white = self.rng.next_bipolar();
+                match self.signal {
+                    Signal::PinkNoise => self.pink(white) / PINK_RMS * rms,
+                    // Uniform noise has an RMS of 1 / sqrt 3
+                    _ => white * 3f32.sqrt() * rms,
+                }
+            }
+        };
+        sample * self.fade(t)
+    }
+
+    fn next_frame(&mut self) -> [f32; 2] {
+        let sample = self.next_sample();
+        match self.channels {
+            Channels::Both => [sample, sample],
+            Channels::Left => [sample, 0.0],
+            Channels::Right => [0.0, sample],
+        }
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: f32) {
+        // Keep the position in time, not in samples
+        self.elapsed = (self.elapsed as f64 * sample_rate as f64 / self.sample_rate as f64) as u64;
+        self.sample_rate = sample_rate;
+    }
+}
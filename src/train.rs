@@ -0,0 +1,238 @@
+// src/train.rs - Ear training: random intervals and chords to name
+
+use std::time::Duration;
+
+use crate::chord;
+use crate::rng::Rng;
+use crate::sequencer::Sequence;
+use crate::synth_core::FMParams;
+use crate::synth_data::midi_to_freq;
+
+/// Intervals up to an octave: short name, full name, semitones
+pub const INTERVALS: [(&str, &str, i32); 12] = [
+    ("m2", "minor second", 1),
+    ("M2", "major second", 2),
+    ("m3", "minor third", 3),
+    ("M3", "major third", 4),
+    ("P4", "perfect fourth", 5),
+    ("TT", "tritone", 6),
+    ("P5", "perfect fifth", 7),
+    ("m6", "minor sixth", 8),
+    ("M6", "major sixth", 9),
+    ("m7", "minor seventh", 10),
+    ("M7", "major seventh", 11),
+    ("P8", "octave", 12),
+];
+
+/// Chord qualities asked about by default: the four triads
+pub const DEFAULT_CHORDS: [&str; 4] = ["maj", "min", "dim", "aug"];
+
+/// Lowest and highest MIDI notes questions are played in (C3 - G5), so
+/// every preset sounds clearly
+const LOWEST_NOTE: i32 = 48;
+const HIGHEST_NOTE: i32 = 79;
+
+/// How a question's notes are played: one after the other, or together
+const MELODIC_NOTE: Duration = Duration::from_millis(700);
+const HARMONIC_NOTE: Duration = Duration::from_millis(1500);
+
+/// How interval questions are played
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Lower note first
+    Up,
+    /// Higher note first
+    Down,
+    /// Both at once
+    Harmonic,
+    /// Any of the above, at random
+    Mixed,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 4] = [Mode::Up, Mode::Down, Mode::Harmonic, Mode::Mixed];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Up => "up",
+            Mode::Down => "down",
+            Mode::Harmonic => "harmonic",
+            Mode::Mixed => "mixed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(s))
+    }
+}
+
+/// What a quiz asks to name
+#[derive(Clone, Debug)]
+enum Topic {
+    /// Indices into `INTERVALS`
+    Intervals(Vec<usize>, Mode),
+    /// Chord quality suffixes, as `chord` spells them
+    Chords(Vec<&'static str>),
+}
+
+/// One question: MIDI notes to play, in order (or together), and the index
+/// of the right answer among the quiz's `choices`
+#[derive(Clone, Debug)]
+pub struct Question {
+    pub notes: Vec<i32>,
+    pub harmonic: bool,
+    pub answer: usize,
+}
+
+impl Question {
+    /// The question's notes with `preset`; notes played together share a
+    /// single note's level
+    pub fn sequence(&self, preset: &FMParams) -> Sequence {
+        let mut sequence = Sequence::new();
+        if self.harmonic {
+            let mut voice = preset.clone();
+            voice.scale_gain((self.notes.len() as f32).sqrt().recip());
+            for &note in &self.notes {
+                sequence.note(Duration::ZERO, HARMONIC_NOTE, midi_to_freq(note), &voice);
+            }
+        } else {
+            for (i, &note) in self.notes.iter().enumerate() {
+                sequence.note(MELODIC_NOTE * i as u32, MELODIC_NOTE, midi_to_freq(note), preset);
+            }
+        }
+        sequence
+    }
+}
+
+/// Answers given so far, overall and for each choice
+#[derive(Clone, Debug, Default)]
+pub struct Score {
+    pub asked: u32,
+    pub correct: u32,
+    /// Times each choice was the answer, and how often it was named
+    pub by_choice: Vec<(u32, u32)>,
+}
+
+impl Score {
+    /// Share of questions answered correctly (0.0 - 1.0)
+    pub fn ratio(&self) -> f32 {
+        if self.asked == 0 { 0.0 } else { self.correct as f32 / self.asked as f32 }
+    }
+}
+
+/// A run of random questions on intervals or chord qualities, keeping score
+#[derive(Clone, Debug)]
+pub struct Quiz {
+    topic: Topic,
+    rng: Rng,
+    score: Score,
+}
+
+impl Quiz {
+    /// Intervals from `set` (indices into `INTERVALS`; every interval when
+    /// empty), played as `mode` says
+    pub fn intervals(seed: u64, set: Vec<usize>, mode: Mode) -> Self {
+        let set = match set.is_empty() {
+            true => (0..INTERVALS.len()).collect(),
+            false => set,
+        };
+        Self::new(seed, Topic::Intervals(set, mode))
+    }
+
+    /// Chords of the `qualities` given (`DEFAULT_CHORDS` when empty); a
+    /// quality `chord` doesn't know is skipped
+    pub fn chords(seed: u64, qualities: Vec<&'static str>) -> Self {
+        let mut qualities: Vec<&'static str> = qualities.into_iter().filter(|q| chord::quality_intervals(q).is_some()).collect();
+        if qualities.is_empty() {
+            qualities = DEFAULT_CHORDS.to_vec();
+        }
+        Self::new(seed, Topic::Chords(qualities))
+    }
+
+    fn new(seed: u64, topic: Topic) -> Self {
+        let choices = match &topic {
+            Topic::Intervals(set, _) => set.len(),
+            Topic::Chords(qualities) => qualities.len(),
+        };
+        Self { topic, rng: Rng::new(seed), score: Score { by_choice: vec![(0, 0); choices], ..Score::default() } }
+    }
+
+    /// Short names of the possible answers, in order
+    pub fn choices(&self) -> Vec<&'static str> {
+        match &self.topic {
+            Topic::Intervals(set, _) => set.iter().map(|&i| INTERVALS[i].0).collect(),
+            Topic::Chords(qualities) => qualities.clone(),
+        }
+    }
+
+    /// Long name of choice `index`: `major third`, or a chord quality
+    pub fn describe(&self, index: usize) -> String {
+        match &self.topic {
+            Topic::Intervals(set, _) => format!("{} ({})", INTERVALS[set[index]].1, INTERVALS[set[index]].0),
+            Topic::Chords(qualities) => format!("{} chord", qualities[index]),
+        }
+    }
+
+    /// A new random question, rooted so every note lies in C3 - G5
+    pub fn next_question(&mut self) -> Question {
+        let answer = self.pick(self.score.by_choice.len());
+        let intervals: Vec<i32> = match &self.topic {
+            Topic::Intervals(set, _) => vec![0, INTERVALS[set[answer]].2],
+            Topic::Chords(qualities) => chord::quality_intervals(qualities[answer]).unwrap_or(&[0]).to_vec(),
+        };
+        let span = intervals.iter().max().copied().unwrap_or(0);
+        let root = LOWEST_NOTE + self.pick((HIGHEST_NOTE - span - LOWEST_NOTE + 1).max(1) as usize) as i32;
+        let mut notes: Vec<i32> = intervals.iter().map(|i| root + i).collect();
+        let harmonic = match &self.topic {
+            Topic::Intervals(_, mode) => {
+                let mode = match mode {
+                    Mode::Mixed => [Mode::Up, Mode::Down, Mode::Harmonic][self.pick(3)],
+                    mode => *mode,
+                };
+                if mode == Mode::Down {
+                    notes.reverse();
+                }
+                mode == Mode::Harmonic
+            }
+            Topic::Chords(_) => true,
+        };
+        Question { notes, harmonic, answer }
+    }
+
+    /// The choice `input` names: a short or full name (`m3`, `minor third`),
+    /// or for intervals a number of semitones. Short names are
+    /// case-sensitive, as `m3` and `M3` differ.
+    pub fn parse_answer(&self, input: &str) -> Option<usize> {
+        let input = input.trim();
+        match &self.topic {
+            Topic::Intervals(set, _) => set.iter().position(|&i| {
+                let (short, long, semitones) = INTERVALS[i];
+                input == short || input.eq_ignore_ascii_case(long) || input.parse::<i32>().ok() == Some(semitones)
+            }),
+            Topic::Chords(qualities) => qualities.iter().position(|&q| {
+                input == q || (!input.is_empty() && chord::quality_intervals(input) == chord::quality_intervals(q))
+            }),
+        }
+    }
+
+    /// Score an answer to `question`, returning whether it was right
+    pub fn answer(&mut self, question: &Question, answer: usize) -> bool {
+        let correct = answer == question.answer;
+        self.score.asked += 1;
+        self.score.correct += u32::from(correct);
+        if let Some(tally) = self.score.by_choice.get_mut(question.answer) {
+            tally.0 += 1;
+            tally.1 += u32::from(correct);
+        }
+        correct
+    }
+
+    pub fn score(&self) -> &Score {
+        &self.score
+    }
+
+    /// A uniform index below `len`
+    fn pick(&mut self, len: usize) -> usize {
+        ((self.rng.next_f32() * len as f32) as usize).min(len.saturating_sub(1))
+    }
+}
@@ -0,0 +1,265 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::rng::Rng;
+
+/// Seed for the export dither so identical renders produce identical files
+pub const DITHER_SEED: u64 = 0x5EED_D17E;
+
+/// Sample format of an exported WAV file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitDepth {
+    /// 16-bit PCM with TPDF dither
+    Int16,
+    /// 24-bit PCM
+    Int24,
+    /// 32-bit IEEE float
+    Float32,
+}
+
+impl BitDepth {
+    /// Parse `16`, `24`, or `32`/`32f`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "16" => Some(BitDepth::Int16),
+            "24" => Some(BitDepth::Int24),
+            "32" | "32f" | "float" => Some(BitDepth::Float32),
+            _ => None,
+        }
+    }
+
+    pub fn bits(&self) -> u16 {
+        match self {
+            BitDepth::Int16 => 16,
+            BitDepth::Int24 => 24,
+            BitDepth::Float32 => 32,
+        }
+    }
+
+    fn format_tag(&self) -> u16 {
+        match self {
+            BitDepth::Int16 | BitDepth::Int24 => 1, // PCM
+            BitDepth::Float32 => 3,                 // IEEE float
+        }
+    }
+
+    /// Length of a WAV header for this format: 44 bytes for PCM, 58 for
+    /// float, which needs the longer `fmt ` chunk and a `fact` chunk
+    fn header_len(&self) -> u32 {
+        match self.format_tag() {
+            1 => 44,
+            _ => 58,
+        }
+    }
+}
+
+/// Encode mono samples as a WAV file in the given format
+pub fn encode_wav(sample_rate: u32, samples: &[f32], depth: BitDepth) -> Vec<u8> {
+    encode_wav_seeded(sample_rate, samples, depth, DITHER_SEED)
+}
+
+/// Encode mono samples as a WAV file, seeding the 16-bit dither explicitly
+pub fn encode_wav_seeded(sample_rate: u32, samples: &[f32], depth: BitDepth, dither_seed: u64) -> Vec<u8> {
+    let data_len = (samples.len() * depth.bits() as usize / 8) as u32;
+    let mut out = Vec::with_capacity(depth.header_len() as usize + data_len as usize);
+    out.extend_from_slice(&header(sample_rate, 1, depth, data_len));
+    encode_samples(&mut out, samples, depth, &mut Rng::new(dither_seed));
+    out
+}
+
+/// The header of a WAV file holding `data_len` bytes of samples. Float
+/// data isn't PCM, so as the spec asks its `fmt ` chunk has the (empty)
+/// extension size and a `fact` chunk gives the length in frames.
+fn header(sample_rate: u32, channels: u16, depth: BitDepth, data_len: u32) -> Vec<u8> {
+    let bits_per_sample = depth.bits();
+    let block_align = channels * bits_per_sample / 8;
+    let byte_rate = sample_rate * block_align as u32;
+    let pcm = depth.format_tag() == 1;
+
+    let mut out = Vec::with_capacity(depth.header_len() as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(depth.header_len() - 8).saturating_add(data_len).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&(if pcm { 16u32 } else { 18 }).to_le_bytes());
+    out.extend_from_slice(&depth.format_tag().to_le_bytes());
+    out.extend_from_slice(&channels.to_le_bytes());
+    out.extend_from_slice(&sample_rate.to_le_bytes());
+    out.extend_from_slice(&byte_rate.to_le_bytes());
+    out.extend_from_slice(&block_align.to_le_bytes());
+    out.extend_from_slice(&bits_per_sample.to_le_bytes());
+    if !pcm {
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(b"fact");
+        out.extend_from_slice(&4u32.to_le_bytes());
+        out.extend_from_slice(&(data_len / block_align.max(1) as u32).to_le_bytes());
+    }
+
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_len.to_le_bytes());
+    out
+}
+
+/// Append samples in the given format, drawing 16-bit dither from `rng`
+fn encode_samples(out: &mut Vec<u8>, samples: &[f32], depth: BitDepth, rng: &mut Rng) {
+    match depth {
+        BitDepth::Int16 => {
+            // TPDF dither: sum of two uniform values spans +/- 1 LSB
+            let scale = i16::MAX as f32;
+            for &sample in samples {
+                let dither = (rng.next_f32() - rng.next_f32()) / scale;
+                let value = ((sample + dither).clamp(-1.0, 1.0) * scale).round() as i16;
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        BitDepth::Int24 => {
+            let scale = 8_388_607.0;
+            for &sample in samples {
+                let value = (sample.clamp(-1.0, 1.0) * scale).round() as i32;
+                out.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+        }
+        BitDepth::Float32 => {
+            for &sample in samples {
+                out.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Write mono samples to a WAV file
+pub fn write_wav(path: &str, sample_rate: u32, samples: &[f32], depth: BitDepth) -> io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    file.write_all(&encode_wav(sample_rate, samples, depth))
+}
+
+/// Read a WAV file as mono samples (channels averaged) and its sample rate
+pub fn read_wav(path: &Path) -> io::Result<(u32, Vec<f32>)> {
+    decode_wav(&std::fs::read(path)?)
+}
+
+/// Decode a PCM (8, 16, 24, or 32-bit) or 32-bit float WAV file as mono
+/// samples, averaging its channels, and its sample rate
+pub fn decode_wav(bytes: &[u8]) -> io::Result<(u32, Vec<f32>)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a WAV file"));
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+    let mut format = None;
+    let mut data = None;
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let len = u32_at(pos + 4) as usize;
+        let body = pos + 8;
+        let end = body.saturating_add(len).min(bytes.len());
+        match &bytes[pos..pos + 4] {
+            b"fmt " if end - body >= 16 => {
+                let mut tag = u16_at(body);
+                // WAVE_FORMAT_EXTENSIBLE keeps the real format in its sub-format GUID
+                if tag == 0xFFFE && end - body >= 26 {
+                    tag = u16_at(body + 24);
+                }
+                format = Some((tag, u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+            }
+            b"data" => data = Some(&bytes[body..end]),
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        pos = body.saturating_add(len + (len & 1));
+    }
+    let (Some((tag, channels, sample_rate, bits)), Some(data)) = (format, data) else {
+        return Err(invalid("WAV file has no format or data"));
+    };
+    let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+        (1, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (1, 16) => |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0,
+        (1, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f32 / 8_388_608.0,
+        (1, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        _ => return Err(invalid(&format!("unsupported WAV format {} at {} bits", tag, bits))),
+    };
+    if channels == 0 || sample_rate == 0 {
+        return Err(invalid("WAV file has no channels"));
+    }
+    let width = bits as usize / 8;
+    let samples = data.chunks_exact(width * channels as usize)
+        .map(|frame| frame.chunks_exact(width).map(decode).sum::<f32>() / channels as f32)
+        .collect();
+    Ok((sample_rate, samples))
+}
+
+/// Streams interleaved samples into a WAV file as they arrive. The header's
+/// sizes are rewritten by `update_header` and `finish`, so a file cut off
+/// without `finish` still plays up to the last update.
+pub struct WavWriter {
+    file: File,
+    sample_rate: u32,
+    channels: u16,
+    depth: BitDepth,
+    rng: Rng,
+    /// Bytes of sample data written so far
+    data_len: u64,
+    buffer: Vec<u8>,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32, channels: u16, depth: BitDepth) -> io::Result<Self> {
+        let channels = channels.max(1);
+        let mut file = File::create(path)?;
+        file.write_all(&header(sample_rate, channels, depth, 0))?;
+        Ok(Self {
+            file,
+            sample_rate,
+            channels,
+            depth,
+            rng: Rng::new(DITHER_SEED),
+            data_len: 0,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Change the rate written in the header (before any samples, normally)
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Append interleaved samples, `channels` per frame
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.buffer.clear();
+        encode_samples(&mut self.buffer, samples, self.depth, &mut self.rng);
+        self.file.write_all(&self.buffer)?;
+        self.data_len += self.buffer.len() as u64;
+        Ok(())
+    }
+
+    /// Frames written so far
+    pub fn frames(&self) -> u64 {
+        self.data_len / (self.channels as u64 * self.depth.bits() as u64 / 8)
+    }
+
+    /// Rewrite the header for the samples written so far; files over 4 GiB
+    /// get the largest size the header can hold
+    pub fn update_header(&mut self) -> io::Result<()> {
+        let data_len = self.data_len.min((u32::MAX - (self.depth.header_len() - 8)) as u64) as u32;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header(self.sample_rate, self.channels, self.depth, data_len))?;
+        self.file.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+
+    /// Finalize the header and flush the file to disk, returning the frames written
+    pub fn finish(mut self) -> io::Result<u64> {
+        self.update_header()?;
+        self.file.sync_all()?;
+        Ok(self.frames())
+    }
+}
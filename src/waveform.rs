@@ -0,0 +1,208 @@
+use std::fmt::Write as _;
+
+/// Image format for waveform exports
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Svg,
+    Png,
+}
+
+impl ImageFormat {
+    /// Pick the format from a file extension, defaulting to SVG
+    pub fn from_path(path: &str) -> Self {
+        if path.to_lowercase().ends_with(".png") {
+            ImageFormat::Png
+        } else {
+            ImageFormat::Svg
+        }
+    }
+}
+
+/// Time-domain waveform plot of a rendered buffer
+pub struct WaveformImage<'a> {
+    pub width: u32,
+    pub height: u32,
+    samples: &'a [f32],
+    envelope: Option<&'a [f32]>,
+}
+
+const BACKGROUND: [u8; 3] = [0x0a, 0x0a, 0x0a];
+const WAVE_COLOR: [u8; 3] = [0x00, 0xff, 0x00];
+const ENVELOPE_COLOR: [u8; 3] = [0xff, 0xff, 0x00];
+const AXIS_COLOR: [u8; 3] = [0x33, 0x33, 0x33];
+
+impl<'a> WaveformImage<'a> {
+    pub fn new(width: u32, height: u32, samples: &'a [f32]) -> Self {
+        Self { width, height, samples, envelope: None }
+    }
+
+    /// Overlay an envelope curve (one value per sample) on the waveform
+    pub fn with_envelope(mut self, envelope: &'a [f32]) -> Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    /// Min/max sample value for each pixel column
+    fn columns(&self, data: &[f32]) -> Vec<(f32, f32)> {
+        (0..self.width as usize)
+            .map(|x| {
+                let start = x * data.len() / self.width as usize;
+                let end = ((x + 1) * data.len() / self.width as usize).max(start + 1).min(data.len());
+                data.get(start..end)
+                    .unwrap_or(&[])
+                    .iter()
+                    .fold((0.0f32, 0.0f32), |(lo, hi), &s| (lo.min(s), hi.max(s)))
+            })
+            .collect()
+    }
+
+    /// Map a sample value in [-1, 1] to a y pixel coordinate
+    fn y(&self, value: f32) -> f32 {
+        let half = self.height as f32 / 2.0;
+        half - value.clamp(-1.0, 1.0) * (half - 1.0)
+    }
+
+    pub fn encode(&self, format: ImageFormat) -> Vec<u8> {
+        match format {
+            ImageFormat::Svg => self.to_svg().into_bytes(),
+            ImageFormat::Png => self.to_png(),
+        }
+    }
+
+    pub fn to_svg(&self) -> String {
+        let color = |c: [u8; 3]| format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2]);
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            w = self.width,
+            h = self.height
+        );
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="{}"/>"#, color(BACKGROUND));
+        let mid = self.height as f32 / 2.0;
+        let _ = writeln!(
+            svg,
+            r#"<line x1="0" y1="{mid}" x2="{}" y2="{mid}" stroke="{}"/>"#,
+            self.width,
+            color(AXIS_COLOR)
+        );
+
+        let mut path = String::new();
+        for (x, (lo, hi)) in self.columns(self.samples).into_iter().enumerate() {
+            let _ = write!(path, "M{}.5 {:.1}V{:.1}", x, self.y(hi), self.y(lo) + 0.5);
+        }
+        let _ = writeln!(svg, r#"<path d="{}" stroke="{}" fill="none"/>"#, path, color(WAVE_COLOR));
+
+        if let Some(envelope) = self.envelope {
+            for sign in [1.0, -1.0] {
+                let points: Vec<String> = self.columns(envelope)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(x, (_, hi))| format!("{}.5,{:.1}", x, self.y(sign * hi)))
+                    .collect();
+                let _ = writeln!(
+                    svg,
+                    r#"<polyline points="{}" stroke="{}" fill="none"/>"#,
+                    points.join(" "),
+                    color(ENVELOPE_COLOR)
+                );
+            }
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    pub fn to_png(&self) -> Vec<u8> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut pixels = vec![BACKGROUND; width * height];
+        let mut plot = |x: usize, y: f32, color: [u8; 3]| {
+            let y = (y as usize).min(height - 1);
+            pixels[y * width + x] = color;
+        };
+
+        let mid = self.height as f32 / 2.0;
+        for x in 0..width {
+            plot(x, mid, AXIS_COLOR);
+        }
+        for (x, (lo, hi)) in self.columns(self.samples).into_iter().enumerate() {
+            let (top, bottom) = (self.y(hi) as usize, self.y(lo) as usize);
+            for y in top..=bottom {
+                plot(x, y as f32, WAVE_COLOR);
+            }
+        }
+        if let Some(envelope) = self.envelope {
+            for (x, (_, hi)) in self.columns(envelope).into_iter().enumerate() {
+                plot(x, self.y(hi), ENVELOPE_COLOR);
+                plot(x, self.y(-hi), ENVELOPE_COLOR);
+            }
+        }
+
+        // Each scanline is prefixed with filter type 0 (none)
+        let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+        for row in pixels.chunks(width) {
+            raw.push(0);
+            for pixel in row {
+                raw.extend_from_slice(pixel);
+            }
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); // 8-bit RGB, no interlace
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &ihdr);
+        png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap data in a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}